@@ -0,0 +1,25 @@
+//! Times a loop-heavy script that reads and writes globals on every
+//! iteration. Run it with `cargo bench --bench globals`.
+
+use std::time::Instant;
+
+use thrush::Thrush;
+
+const SCRIPT: &str = "var total = 0\nvar step = 1\nfun add(a, b) {\n    return a + b\n}\nfor i in 0..200000 {\n    total = add(total, step)\n}";
+
+const RUNS: u32 = 20;
+
+fn main() {
+    let mut thrush = Thrush::new();
+
+    thrush.exec(SCRIPT).unwrap();
+
+    let start = Instant::now();
+
+    for _ in 0..RUNS {
+        thrush.exec(SCRIPT).unwrap();
+    }
+
+    assert_eq!(thrush.globals().get::<i64>("total"), Ok(200000));
+    println!("globals: {:?} per run", start.elapsed() / RUNS);
+}
@@ -0,0 +1,25 @@
+//! Times a loop-heavy script that calls a method on an instance on every
+//! iteration. Run it with `cargo bench --bench methods`.
+
+use std::time::Instant;
+
+use thrush::Thrush;
+
+const SCRIPT: &str = "class Counter {\n    var total\n    fun add(step) {\n        self.total = self.total + step\n    }\n}\nvar counter = Counter()\ncounter.total = 0\nfor i in 0..200000 {\n    counter.add(1)\n}\nvar total = counter.total";
+
+const RUNS: u32 = 20;
+
+fn main() {
+    let mut thrush = Thrush::new();
+
+    thrush.exec(SCRIPT).unwrap();
+
+    let start = Instant::now();
+
+    for _ in 0..RUNS {
+        thrush.exec(SCRIPT).unwrap();
+    }
+
+    assert_eq!(thrush.globals().get::<i64>("total"), Ok(200000));
+    println!("methods: {:?} per run", start.elapsed() / RUNS);
+}
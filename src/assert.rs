@@ -0,0 +1,190 @@
+//! Equality assertions for script tests, exposed as the `assert_eq` and
+//! `assert_ne` globals.
+//!
+//! A failed assertion shows both values as [inspect] renders them, and when
+//! two strings, lists or maps span several lines, a line diff between them:
+//!
+//! ```text
+//! assertion failed: left == right
+//! left: [
+//!   1,
+//!   2,
+//! ]
+//! right: [
+//!   1,
+//!   3,
+//! ]
+//! diff:
+//!   [
+//!     1,
+//! -   2,
+//! +   3,
+//!   ]
+//! ```
+
+use std::fmt::Write;
+
+use crate::{inspect::inspect, repr::PrintLimits, scope::State, value::Value};
+
+/// The most lines either side of a diff can have before it is left out,
+/// since the diff takes time proportional to the product of the two.
+const MAX_DIFF_LINES: usize = 1000;
+
+/// Register the `assert_eq` and `assert_ne` globals.
+pub fn register(state: &mut State) {
+    state.add_function("assert_eq", |args| {
+        let (left, right, message) = operands("assert_eq", &args)?;
+
+        if left == right {
+            Ok(Value::Nil)
+        } else {
+            Err(failure("==", left, right, message))
+        }
+    });
+
+    state.add_function("assert_ne", |args| {
+        let (left, right, message) = operands("assert_ne", &args)?;
+
+        if left != right {
+            Ok(Value::Nil)
+        } else {
+            Err(failure("!=", left, right, message))
+        }
+    });
+}
+
+fn operands<'a>(
+    name: &str,
+    args: &'a [Value],
+) -> Result<(&'a Value, &'a Value, Option<&'a str>), String> {
+    match args {
+        [left, right] => Ok((left, right, None)),
+        [left, right, Value::String(message)] => Ok((left, right, Some(message))),
+        _ => Err(format!("{name} expects two values and an optional message")),
+    }
+}
+
+/// Describe a failed assertion that `left <op> right`.
+fn failure(op: &str, left: &Value, right: &Value, message: Option<&str>) -> String {
+    let limits = PrintLimits::default();
+    let (left_text, right_text) = (inspect(left, limits), inspect(right, limits));
+    let mut out = format!("assertion failed: left {op} right");
+
+    if let Some(message) = message {
+        let _ = write!(out, ": {message}");
+    }
+
+    let _ = write!(out, "\nleft: {left_text}\nright: {right_text}");
+
+    if op == "==" {
+        let diffed = match (left, right) {
+            (Value::String(left), Value::String(right)) => Some((left.as_str(), right.as_str())),
+            (Value::List(_), Value::List(_)) | (Value::Map(_), Value::Map(_)) => {
+                Some((left_text.as_str(), right_text.as_str()))
+            }
+            _ => None,
+        };
+
+        if let Some(diff) = diffed.and_then(|(left, right)| diff(left, right)) {
+            let _ = write!(out, "\ndiff:\n{diff}");
+        }
+    }
+
+    out
+}
+
+/// A line diff from `left` to `right`, where lines only in `left` start with
+/// `-`, lines only in `right` with `+` and shared lines with a space, or
+/// `None` if the texts are a single line each or too long to diff.
+fn diff(left: &str, right: &str) -> Option<String> {
+    let left = left.lines().collect::<Vec<_>>();
+    let right = right.lines().collect::<Vec<_>>();
+
+    if left.len().max(right.len()) < 2 || left.len().max(right.len()) > MAX_DIFF_LINES {
+        return None;
+    }
+
+    // The length of the longest common subsequence of each pair of suffixes.
+    let mut common = vec![vec![0usize; right.len() + 1]; left.len() + 1];
+
+    for i in (0..left.len()).rev() {
+        for j in (0..right.len()).rev() {
+            common[i][j] = if left[i] == right[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < left.len() || j < right.len() {
+        if i < left.len() && j < right.len() && left[i] == right[j] {
+            let _ = writeln!(out, "  {}", left[i]);
+            i += 1;
+            j += 1;
+        } else if j == right.len() || (i < left.len() && common[i + 1][j] >= common[i][j + 1]) {
+            let _ = writeln!(out, "- {}", left[i]);
+            i += 1;
+        } else {
+            let _ = writeln!(out, "+ {}", right[j]);
+            j += 1;
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Thrush;
+
+    #[test]
+    fn test_assert() {
+        let mut thrush = Thrush::new();
+
+        assert_eq!(
+            thrush.exec("assert_eq([1, \"a\"], [1, \"a\"])\nassert_ne(1, 1.5)"),
+            Ok(())
+        );
+        assert_eq!(
+            thrush
+                .exec("assert_eq(1, 2, \"sums\")")
+                .unwrap_err()
+                .to_string(),
+            "[E0301] line 1:17: assertion failed: left == right: sums\nleft: 1\nright: 2"
+        );
+        assert_eq!(
+            thrush.exec("assert_eq([1, 2], [1, 3])").unwrap_err().to_string(),
+            "[E0301] line 1:23: assertion failed: left == right\nleft: [\n  1,\n  2,\n]\nright: [\n  1,\n  3,\n]\ndiff:\n  [\n    1,\n-   2,\n+   3,\n  ]\n"
+        );
+        assert!(thrush
+            .exec("assert_eq(\"a\", \"a\\nb\")")
+            .unwrap_err()
+            .to_string()
+            .ends_with("diff:\n  a\n+ b\n"));
+        assert!(thrush
+            .exec("assert_ne(\"x\", \"x\")")
+            .unwrap_err()
+            .to_string()
+            .contains("left != right"));
+        assert!(thrush.exec("assert_eq(1)").is_err());
+    }
+
+    #[test]
+    fn test_assert_cycle() {
+        let mut thrush = Thrush::new();
+
+        assert_eq!(
+            thrush.exec("class Node { var next }\nvar n = Node()\nn.next = [1, n]\nvar m = Node()\nm.next = [1, m]\nassert_eq(n, m)\nassert_eq(n.next, m.next)"),
+            Ok(())
+        );
+        assert!(thrush
+            .exec("var o = Node()\no.next = [2, o]\nassert_eq(n.next, o.next)")
+            .unwrap_err()
+            .to_string()
+            .contains("diff:\n  [\n-   1,\n+   2,\n"));
+    }
+}
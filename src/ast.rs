@@ -1,53 +1,442 @@
+use std::ops::{Index, IndexMut};
+
+use crate::token::Span;
+
 /// An AST (abstract syntax tree).
-#[derive(Debug)]
+///
+/// Expressions are stored in a single arena and refer to their
+/// sub-expressions by [ExprId], so cloning an AST is a couple of vector copies
+/// rather than a deep walk, and large expressions can be dropped without
+/// recursing.
+#[derive(Debug, Clone, Default)]
 pub struct Ast {
     pub nodes: Vec<Stmt>,
+    pub exprs: Vec<Expr>,
+    /// Where each expression starts in the script, by id. Expressions that
+    /// weren't parsed from a script have the default span.
+    pub spans: Vec<Span>,
+    /// The comments in the script, in order, if it was lexed with
+    /// [Lexer::with_comments](crate::lexer::Lexer::with_comments).
+    pub comments: Vec<Comment>,
+}
+
+/// ASTs are equal if their statements and expressions are, wherever in the
+/// script they were parsed from and whatever comments it has.
+impl PartialEq for Ast {
+    fn eq(&self, other: &Self) -> bool {
+        self.nodes == other.nodes && self.exprs == other.exprs
+    }
+}
+
+/// A comment kept for tools such as formatters, which find the code it is
+/// about from its span.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    /// The text after the `//`.
+    pub text: String,
+    /// Where the `//` is.
+    pub span: Span,
+    /// Whether the comment follows code on its line, rather than having the
+    /// line to itself.
+    pub trailing: bool,
+}
+
+/// The index of an expression in its [Ast].
+///
+/// Expressions are never removed from an AST, so an id keeps referring to the
+/// same expression in clones of the AST and can be used to attach extra
+/// information to it, such as a source location.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct ExprId(pub u32);
+
+impl ExprId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Stmt {
+    /// A function declaration.
+    Fun(FunDecl),
     /// A class declaration.
     Class {
         name: String,
+        /// Where the `class` keyword is, which errors checking the class
+        /// against its traits report.
+        span: Span,
+        /// The traits named after a `:`, which the class is checked to
+        /// implement when it is declared.
+        traits: Vec<String>,
+        /// The classes named after `with`, whose methods are copied into the
+        /// class when it is declared.
+        mixins: Vec<String>,
+        fields: Vec<String>,
+        /// The fields declared with `static var` or `static const`, which
+        /// are stored on the class itself.
+        statics: Vec<StaticField>,
+        methods: Vec<FunDecl>,
+        /// The version declared in the class body, which is stored with
+        /// serialized instances so old data can be migrated.
+        version: Option<u32>,
+        /// The string literal at the start of the class body, if any.
+        doc: Option<String>,
+    },
+    /// A trait declaration, naming the methods a class must have to
+    /// implement it, with how many parameters each takes.
+    Trait {
+        name: String,
+        methods: Vec<(String, usize)>,
     },
     VarDecl {
         id: String,
-        init: Expr,
+        /// The type annotation after the name, if any.
+        ty: Option<String>,
+        init: ExprId,
+    },
+    /// A constant declaration, which can't be reassigned.
+    ConstDecl {
+        id: String,
+        ty: Option<String>,
+        init: ExprId,
+    },
+    /// A variable declared with `let`, which can't be reassigned.
+    LetDecl {
+        id: String,
+        ty: Option<String>,
+        init: ExprId,
+    },
+    /// An import of the module `path`, bound to the global `name`.
+    Import { path: String, name: String },
+    /// A declaration exported from its module.
+    Export(Box<Stmt>),
+    /// A return statement.
+    Return(ExprId),
+    /// A match statement, which runs the first arm whose pattern matches.
+    Match { value: ExprId, arms: Vec<MatchArm> },
+    /// A switch statement, which runs the case containing a value equal to
+    /// the switched value, or the default body if there is none.
+    Switch {
+        value: ExprId,
+        cases: Vec<SwitchCase>,
+        default: Option<Vec<Stmt>>,
+    },
+    /// A throw statement, which raises its value as an error.
+    Throw(ExprId),
+    /// A try statement, with a catch clause, a finally block, or both.
+    Try {
+        body: Vec<Stmt>,
+        catch: Option<Catch>,
+        finally: Option<Vec<Stmt>>,
+    },
+    /// A while loop, which can be named by a label for `break` and
+    /// `continue` in nested loops.
+    While {
+        label: Option<String>,
+        cond: ExprId,
+        body: Vec<Stmt>,
+    },
+    /// A for-in loop, which runs its body once for each value the iterable
+    /// produces.
+    For {
+        label: Option<String>,
+        var: String,
+        iterable: ExprId,
+        body: Vec<Stmt>,
+    },
+    /// A yield statement, which suspends the generator it is in.
+    Yield(ExprId),
+    /// A break out of the innermost loop, or the loop with the label.
+    Break(Option<String>),
+    /// A jump to the next iteration of the innermost loop, or the loop with
+    /// the label.
+    Continue(Option<String>),
+    /// A statement starting with a keyword claimed by the host, which
+    /// compiles it.
+    Host(HostStmt),
+    /// An assignment to several targets at once, such as `a, b = b, a`.
+    /// Every value is evaluated before any target is assigned, and a single
+    /// value is unpacked into the targets.
+    Assign {
+        targets: Vec<ExprId>,
+        values: Vec<ExprId>,
     },
     /// An expression.
-    Expr(Expr),
+    Expr(ExprId),
+}
+
+impl Ast {
+    /// Add `expr` to the arena.
+    pub fn add(&mut self, expr: Expr) -> ExprId {
+        self.add_at(expr, Span::default())
+    }
+
+    /// Add `expr`, which starts at `span`, to the arena.
+    pub fn add_at(&mut self, expr: Expr, span: Span) -> ExprId {
+        let id = ExprId(u32::try_from(self.exprs.len()).expect("too many expressions"));
+
+        self.exprs.push(expr);
+        self.spans.push(span);
+        id
+    }
+
+    /// Where the expression `id` starts in the script.
+    pub fn span(&self, id: ExprId) -> Span {
+        self.spans.get(id.index()).copied().unwrap_or_default()
+    }
+
+    /// Iterate over every expression in the AST with its id.
+    pub fn iter_exprs(&self) -> impl Iterator<Item = (ExprId, &Expr)> {
+        self.exprs
+            .iter()
+            .enumerate()
+            .map(|(index, expr)| (ExprId(index as u32), expr))
+    }
+
+    /// The paths of the modules imported at the top level.
+    pub fn imports(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().filter_map(|node| match node {
+            Stmt::Import { path, .. } => Some(path.as_str()),
+            _ => None,
+        })
+    }
+}
+
+impl Index<ExprId> for Ast {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.index()]
+    }
+}
+
+impl IndexMut<ExprId> for Ast {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expr {
+        &mut self.exprs[id.index()]
+    }
+}
+
+impl Stmt {
+    /// The name of the global a top-level statement declares, if any.
+    pub fn declared_name(&self) -> Option<&str> {
+        match self {
+            Stmt::Class { name, .. } | Stmt::Trait { name, .. } => Some(name),
+            Stmt::Fun(decl) => Some(&decl.name),
+            Stmt::VarDecl { id, .. } | Stmt::ConstDecl { id, .. } | Stmt::LetDecl { id, .. } => {
+                Some(id)
+            }
+            Stmt::Import { name, .. } => Some(name),
+            Stmt::Export(stmt) => stmt.declared_name(),
+            _ => None,
+        }
+    }
+}
+
+/// A field stored on a class rather than its instances.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StaticField {
+    pub name: String,
+    /// Evaluated once, when the class is declared.
+    pub init: ExprId,
+    /// Whether the field was declared with `const`, so it can't be assigned.
+    pub constant: bool,
+}
+
+/// A parameter that unpacks its argument into locals.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Destructure {
+    /// `(x, y)`, which unpacks a list of exactly that many items.
+    Items(Vec<String>),
+    /// `{host, port}`, which takes the values of those keys from a map.
+    Keys(Vec<String>),
+}
+
+impl Destructure {
+    /// The names the pattern binds, in order.
+    pub fn names(&self) -> &[String] {
+        match self {
+            Destructure::Items(names) | Destructure::Keys(names) => names,
+        }
+    }
+}
+
+/// A function or method declaration.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunDecl {
+    pub name: String,
+    pub params: Vec<String>,
+    /// The type annotation of each parameter, if it has one.
+    pub param_types: Vec<Option<String>>,
+    /// Whether the last parameter collects any extra arguments into a list.
+    pub variadic: bool,
+    /// The parameters that unpack their argument, by index, which are
+    /// named after their pattern in `params`.
+    pub unpack: Vec<(usize, Destructure)>,
+    /// The return type annotation after `->`, if any.
+    pub returns: Option<String>,
+    pub body: Vec<Stmt>,
+    pub kind: FunKind,
+    /// Whether the function was declared with `async`, so it can `await`.
+    pub is_async: bool,
+    /// The string literal at the start of the body, which is removed from
+    /// it, if any.
+    pub doc: Option<String>,
+    /// The decorators written before the declaration, outermost first. Each
+    /// is called with the function, innermost first, and the last result is
+    /// bound instead of the function.
+    pub decorators: Vec<ExprId>,
+}
+
+/// An arm of a match statement.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Vec<Stmt>,
+}
+
+/// A case of a switch statement.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SwitchCase {
+    /// The integer or string constants the case matches.
+    pub values: Vec<Lit>,
+    pub body: Vec<Stmt>,
+}
+
+/// The catch clause of a try statement, which binds the error to `name`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Catch {
+    pub name: String,
+    /// The class after the name in `catch (e: ParseError)`, which limits
+    /// the clause to errors that are instances of it.
+    pub class: Option<String>,
+    pub body: Vec<Stmt>,
+}
+
+/// A statement claimed by the host, such as `quest "Find the sword" { ... }`.
+///
+/// The keyword is followed by comma-separated arguments and an optional
+/// block.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HostStmt {
+    pub keyword: String,
+    pub args: Vec<ExprId>,
+    pub body: Option<Vec<Stmt>>,
+}
+
+/// A pattern in a match arm.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    /// `_`, which matches anything.
+    Wildcard,
+    /// A name, which matches anything and binds it.
+    Binding(String),
+    /// A literal, which matches equal values.
+    Literal(Lit),
+    /// `Point(x, y)`, which matches instances of a class and destructures
+    /// their fields in declaration order.
+    Class { name: String, fields: Vec<Pattern> },
 }
 
-#[derive(Debug, PartialEq)]
+/// The kind of a function declaration.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum FunKind {
+    /// A function declared outside of a class.
+    Function,
+    /// An instance method.
+    Method,
+    /// A method declared with `static`.
+    Static,
+    /// A property getter declared with `get`.
+    Getter,
+    /// A property setter declared with `set`.
+    Setter,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     /// An identifier
     Identifier(String),
+    /// The `self` keyword
+    Slf,
     /// A literal
     Literal(Lit),
     /// A binary expression
     BinExpr {
-        left: Box<Expr>,
-        right: Box<Expr>,
+        left: ExprId,
+        right: ExprId,
         op: BinOp,
     },
     /// An unary expression
-    UnaryExpr { value: Box<Expr>, op: BinOp },
-    Call { callee: Box<Expr>, args: Vec<Expr> },
+    UnaryExpr { value: ExprId, op: BinOp },
+    /// A call, with positional arguments followed by named arguments.
+    Call {
+        callee: ExprId,
+        args: Vec<ExprId>,
+        named: Vec<(String, ExprId)>,
+    },
     /// A dot expression. 
-    Dot { object: Box<Expr>, property: Box<Expr> },
-}
-
-impl Expr {
-    pub fn binary_expr(op: BinOp, left: Expr, right: Expr) -> Expr {
-        Expr::BinExpr {
-            left: Box::new(left),
-            right: Box::new(right),
-            op,
-        }
-    }
+    Dot { object: ExprId, property: ExprId },
+    /// A list literal.
+    List(Vec<ExprId>),
+    /// A map literal. Entries whose value is an [Expr::Spread] have an empty
+    /// key, and insert every entry of the spread map.
+    Map(Vec<(String, ExprId)>),
+    /// A `...value` argument of a call or item of a list or map literal,
+    /// expanded into the items of the value when it runs.
+    Spread(ExprId),
+    /// An assignment to a variable or property.
+    Assign { target: ExprId, value: ExprId },
+    /// An increment or decrement of a variable or property, which adds or
+    /// subtracts one as `op` says. `++i` gives the new value and `i++` the
+    /// old one, and the object of a property is only evaluated once.
+    Increment {
+        target: ExprId,
+        op: BinOp,
+        postfix: bool,
+    },
+    /// A `typeof` expression, giving the name of its operand's type.
+    TypeOf(ExprId),
+    /// An `await` expression, suspending the async function it is in until
+    /// its operand resolves.
+    Await(ExprId),
+    /// An anonymous function, such as `fun(x) { return x }`.
+    Function(Box<FunDecl>),
+    /// An `is` expression, checking whether a value is an instance of a class.
+    Is { value: ExprId, class: ExprId },
+    /// A range of integers, `start..end` or `start..end step n`, which
+    /// doesn't include `end`.
+    Range {
+        start: ExprId,
+        end: ExprId,
+        step: Option<ExprId>,
+    },
+    /// `if cond { ... } else { ... }`, whose value is the value of the
+    /// branch taken, or nil without an else branch.
+    If {
+        cond: ExprId,
+        then: Vec<Stmt>,
+        otherwise: Option<Vec<Stmt>>,
+    },
+    /// A block, whose value is the value of its last statement if that is
+    /// an expression, or nil otherwise.
+    Block(Vec<Stmt>),
+    /// An object literal, such as `object { var x = 1 fun get() {} }`,
+    /// creating the one instance of a class with its fields and methods.
+    Object {
+        fields: Vec<(String, ExprId)>,
+        methods: Vec<FunDecl>,
+    },
+    /// A tagged template. The tag is called with the text around the
+    /// interpolations and their values, so `parts` has one more item than
+    /// `values`.
+    Template {
+        tag: String,
+        parts: Vec<String>,
+        values: Vec<ExprId>,
+    },
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Lit {
     /// A string literal
     String(String),
@@ -57,12 +446,14 @@ pub enum Lit {
     Float(f64),
     /// A character literal
     Char(char),
+    /// A boolean literal
+    Bool(bool),
     /// A nil literal
     Nil,
 }
 
 /// A binary operator.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum BinOp {
     /// +
     Add,
@@ -74,8 +465,26 @@ pub enum BinOp {
     Div,
     /// %
     Rem,
+    /// <
+    Less,
+    /// <=
+    LessEqual,
+    /// >
+    Greater,
+    /// >=
+    GreaterEqual,
+    /// ==
+    Equal,
+    /// !=
+    NotEqual,
+    /// &&, which only evaluates its right operand if the left one is truthy.
+    And,
+    /// ||, which only evaluates its right operand if the left one is falsey.
+    Or,
     /// !
     Bang,
+    /// A custom operator, compiled to a call of its handler.
+    Custom(String),
 }
 
 impl BinOp {
@@ -86,7 +495,459 @@ impl BinOp {
             BinOp::Mul => "*",
             BinOp::Div => "/",
             BinOp::Rem => "%",
+            BinOp::Less => "<",
+            BinOp::LessEqual => "<=",
+            BinOp::Greater => ">",
+            BinOp::GreaterEqual => ">=",
+            BinOp::Equal => "==",
+            BinOp::NotEqual => "!=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
             BinOp::Bang => "!",
+            BinOp::Custom(symbol) => symbol,
         }
     }
 }
+
+/// Visits the statements and expressions of an [Ast], for lints, checkers
+/// and other tools that only care about some kinds of node.
+///
+/// Each method walks into the children of its node by default, so a visitor
+/// overrides the methods for the nodes it wants and calls the matching
+/// `walk_` function to keep going into their children.
+///
+/// ```
+/// use thrush::{ast::{self, Ast, Expr, ExprId, Visitor}, lexer::Lexer, parser::Parser};
+///
+/// /// Collect the names of the variables used.
+/// struct Names(Vec<String>);
+///
+/// impl Visitor for Names {
+///     fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+///         if let Expr::Identifier(name) = &ast[id] {
+///             self.0.push(name.clone());
+///         }
+///         ast::walk_expr(self, ast, id);
+///     }
+/// }
+///
+/// let ast = Parser::parse_ast(Lexer::tokenize("fun f(x) { return x + y }\nprint(f(z))")).unwrap();
+/// let mut names = Names(Vec::new());
+///
+/// ast::walk_ast(&mut names, &ast);
+///
+/// assert_eq!(names.0, ["x", "y", "print", "f", "z"]);
+/// ```
+pub trait Visitor {
+    fn visit_stmt(&mut self, ast: &Ast, stmt: &Stmt) {
+        walk_stmt(self, ast, stmt);
+    }
+
+    fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+        walk_expr(self, ast, id);
+    }
+
+    /// Visit a function, method or anonymous function.
+    fn visit_fun(&mut self, ast: &Ast, decl: &FunDecl) {
+        walk_fun(self, ast, decl);
+    }
+}
+
+/// Visit the top-level statements of `ast`.
+pub fn walk_ast<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast) {
+    walk_stmts(visitor, ast, &ast.nodes);
+}
+
+fn walk_stmts<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, stmts: &[Stmt]) {
+    for stmt in stmts {
+        visitor.visit_stmt(ast, stmt);
+    }
+}
+
+/// Visit the expressions, functions and statements directly inside `stmt`.
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, stmt: &Stmt) {
+    match stmt {
+        Stmt::Fun(decl) => visitor.visit_fun(ast, decl),
+        Stmt::Class {
+            statics, methods, ..
+        } => {
+            for field in statics {
+                visitor.visit_expr(ast, field.init);
+            }
+            for method in methods {
+                visitor.visit_fun(ast, method);
+            }
+        }
+        Stmt::VarDecl { init, .. } | Stmt::ConstDecl { init, .. } | Stmt::LetDecl { init, .. } => {
+            visitor.visit_expr(ast, *init)
+        }
+        Stmt::Export(stmt) => visitor.visit_stmt(ast, stmt),
+        Stmt::Return(expr) | Stmt::Throw(expr) | Stmt::Yield(expr) | Stmt::Expr(expr) => {
+            visitor.visit_expr(ast, *expr)
+        }
+        Stmt::Match { value, arms } => {
+            visitor.visit_expr(ast, *value);
+            for arm in arms {
+                walk_stmts(visitor, ast, &arm.body);
+            }
+        }
+        Stmt::Switch {
+            value,
+            cases,
+            default,
+        } => {
+            visitor.visit_expr(ast, *value);
+            for case in cases {
+                walk_stmts(visitor, ast, &case.body);
+            }
+            walk_stmts(visitor, ast, default.as_deref().unwrap_or_default());
+        }
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            walk_stmts(visitor, ast, body);
+            if let Some(catch) = catch {
+                walk_stmts(visitor, ast, &catch.body);
+            }
+            walk_stmts(visitor, ast, finally.as_deref().unwrap_or_default());
+        }
+        Stmt::While { cond, body, .. } => {
+            visitor.visit_expr(ast, *cond);
+            walk_stmts(visitor, ast, body);
+        }
+        Stmt::For { iterable, body, .. } => {
+            visitor.visit_expr(ast, *iterable);
+            walk_stmts(visitor, ast, body);
+        }
+        Stmt::Host(stmt) => {
+            for arg in &stmt.args {
+                visitor.visit_expr(ast, *arg);
+            }
+            walk_stmts(visitor, ast, stmt.body.as_deref().unwrap_or_default());
+        }
+        Stmt::Assign { targets, values } => {
+            for expr in targets.iter().chain(values) {
+                visitor.visit_expr(ast, *expr);
+            }
+        }
+        Stmt::Trait { .. } | Stmt::Import { .. } | Stmt::Break(_) | Stmt::Continue(_) => {}
+    }
+}
+
+/// Visit the sub-expressions, functions and statements directly inside the
+/// expression `id`, in the order they appear in the script.
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, id: ExprId) {
+    match &ast[id] {
+        Expr::BinExpr { left, right, .. } => {
+            visitor.visit_expr(ast, *left);
+            visitor.visit_expr(ast, *right);
+        }
+        Expr::Call {
+            callee,
+            args,
+            named,
+        } => {
+            visitor.visit_expr(ast, *callee);
+            for arg in args.iter().chain(named.iter().map(|(_, arg)| arg)) {
+                visitor.visit_expr(ast, *arg);
+            }
+        }
+        Expr::Dot { object, property } => {
+            visitor.visit_expr(ast, *object);
+            visitor.visit_expr(ast, *property);
+        }
+        Expr::List(items) | Expr::Template { values: items, .. } => {
+            for item in items {
+                visitor.visit_expr(ast, *item);
+            }
+        }
+        Expr::Map(entries) => {
+            for (_, value) in entries {
+                visitor.visit_expr(ast, *value);
+            }
+        }
+        Expr::UnaryExpr { value, .. }
+        | Expr::Spread(value)
+        | Expr::TypeOf(value)
+        | Expr::Await(value)
+        | Expr::Increment { target: value, .. } => visitor.visit_expr(ast, *value),
+        Expr::Assign { target, value } => {
+            visitor.visit_expr(ast, *target);
+            visitor.visit_expr(ast, *value);
+        }
+        Expr::Function(decl) => visitor.visit_fun(ast, decl),
+        Expr::Is { value, class } => {
+            visitor.visit_expr(ast, *value);
+            visitor.visit_expr(ast, *class);
+        }
+        Expr::Range { start, end, step } => {
+            visitor.visit_expr(ast, *start);
+            visitor.visit_expr(ast, *end);
+            if let Some(step) = step {
+                visitor.visit_expr(ast, *step);
+            }
+        }
+        Expr::If {
+            cond,
+            then,
+            otherwise,
+        } => {
+            visitor.visit_expr(ast, *cond);
+            walk_stmts(visitor, ast, then);
+            walk_stmts(visitor, ast, otherwise.as_deref().unwrap_or_default());
+        }
+        Expr::Block(stmts) => walk_stmts(visitor, ast, stmts),
+        Expr::Object { fields, methods } => {
+            for (_, value) in fields {
+                visitor.visit_expr(ast, *value);
+            }
+            for method in methods {
+                visitor.visit_fun(ast, method);
+            }
+        }
+        Expr::Identifier(_) | Expr::Slf | Expr::Literal(_) => {}
+    }
+}
+
+/// Visit the decorators and then the body of `decl`.
+pub fn walk_fun<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, decl: &FunDecl) {
+    for decorator in &decl.decorators {
+        visitor.visit_expr(ast, *decorator);
+    }
+    walk_stmts(visitor, ast, &decl.body);
+}
+
+/// Visits the statements and expressions of an [Ast] like a [Visitor], and
+/// can change them.
+///
+/// An expression can be changed through `ast[id]`. While the children of an
+/// expression are visited it is taken out of the AST, leaving nil in its
+/// place, and it is put back afterwards.
+pub trait VisitorMut {
+    fn visit_stmt(&mut self, ast: &mut Ast, stmt: &mut Stmt) {
+        walk_stmt_mut(self, ast, stmt);
+    }
+
+    fn visit_expr(&mut self, ast: &mut Ast, id: ExprId) {
+        walk_expr_mut(self, ast, id);
+    }
+
+    fn visit_fun(&mut self, ast: &mut Ast, decl: &mut FunDecl) {
+        walk_fun_mut(self, ast, decl);
+    }
+}
+
+/// Visit the top-level statements of `ast`, and change them.
+pub fn walk_ast_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut Ast) {
+    let mut nodes = std::mem::take(&mut ast.nodes);
+
+    walk_stmts_mut(visitor, ast, &mut nodes);
+    ast.nodes = nodes;
+}
+
+fn walk_stmts_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut Ast, stmts: &mut [Stmt]) {
+    for stmt in stmts {
+        visitor.visit_stmt(ast, stmt);
+    }
+}
+
+/// Visit the expressions, functions and statements directly inside `stmt`,
+/// like [walk_stmt].
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut Ast, stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Fun(decl) => visitor.visit_fun(ast, decl),
+        Stmt::Class {
+            statics, methods, ..
+        } => {
+            for field in statics {
+                visitor.visit_expr(ast, field.init);
+            }
+            for method in methods {
+                visitor.visit_fun(ast, method);
+            }
+        }
+        Stmt::VarDecl { init, .. } | Stmt::ConstDecl { init, .. } | Stmt::LetDecl { init, .. } => {
+            visitor.visit_expr(ast, *init)
+        }
+        Stmt::Export(stmt) => visitor.visit_stmt(ast, stmt),
+        Stmt::Return(expr) | Stmt::Throw(expr) | Stmt::Yield(expr) | Stmt::Expr(expr) => {
+            visitor.visit_expr(ast, *expr)
+        }
+        Stmt::Match { value, arms } => {
+            visitor.visit_expr(ast, *value);
+            for arm in arms {
+                walk_stmts_mut(visitor, ast, &mut arm.body);
+            }
+        }
+        Stmt::Switch {
+            value,
+            cases,
+            default,
+        } => {
+            visitor.visit_expr(ast, *value);
+            for case in cases {
+                walk_stmts_mut(visitor, ast, &mut case.body);
+            }
+            walk_stmts_mut(visitor, ast, default.as_deref_mut().unwrap_or_default());
+        }
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            walk_stmts_mut(visitor, ast, body);
+            if let Some(catch) = catch {
+                walk_stmts_mut(visitor, ast, &mut catch.body);
+            }
+            walk_stmts_mut(visitor, ast, finally.as_deref_mut().unwrap_or_default());
+        }
+        Stmt::While { cond, body, .. } => {
+            visitor.visit_expr(ast, *cond);
+            walk_stmts_mut(visitor, ast, body);
+        }
+        Stmt::For { iterable, body, .. } => {
+            visitor.visit_expr(ast, *iterable);
+            walk_stmts_mut(visitor, ast, body);
+        }
+        Stmt::Host(stmt) => {
+            for arg in &stmt.args {
+                visitor.visit_expr(ast, *arg);
+            }
+            walk_stmts_mut(visitor, ast, stmt.body.as_deref_mut().unwrap_or_default());
+        }
+        Stmt::Assign { targets, values } => {
+            for expr in targets.iter().chain(values.iter()) {
+                visitor.visit_expr(ast, *expr);
+            }
+        }
+        Stmt::Trait { .. } | Stmt::Import { .. } | Stmt::Break(_) | Stmt::Continue(_) => {}
+    }
+}
+
+/// Visit the sub-expressions, functions and statements directly inside the
+/// expression `id`, like [walk_expr].
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut Ast, id: ExprId) {
+    let mut expr = std::mem::replace(&mut ast[id], Expr::Literal(Lit::Nil));
+
+    match &mut expr {
+        Expr::BinExpr { left, right, .. } => {
+            visitor.visit_expr(ast, *left);
+            visitor.visit_expr(ast, *right);
+        }
+        Expr::Call {
+            callee,
+            args,
+            named,
+        } => {
+            visitor.visit_expr(ast, *callee);
+            for arg in args.iter().chain(named.iter().map(|(_, arg)| arg)) {
+                visitor.visit_expr(ast, *arg);
+            }
+        }
+        Expr::Dot { object, property } => {
+            visitor.visit_expr(ast, *object);
+            visitor.visit_expr(ast, *property);
+        }
+        Expr::List(items) | Expr::Template { values: items, .. } => {
+            for item in items {
+                visitor.visit_expr(ast, *item);
+            }
+        }
+        Expr::Map(entries) => {
+            for (_, value) in entries {
+                visitor.visit_expr(ast, *value);
+            }
+        }
+        Expr::UnaryExpr { value, .. }
+        | Expr::Spread(value)
+        | Expr::TypeOf(value)
+        | Expr::Await(value)
+        | Expr::Increment { target: value, .. } => visitor.visit_expr(ast, *value),
+        Expr::Assign { target, value } => {
+            visitor.visit_expr(ast, *target);
+            visitor.visit_expr(ast, *value);
+        }
+        Expr::Function(decl) => visitor.visit_fun(ast, decl),
+        Expr::Is { value, class } => {
+            visitor.visit_expr(ast, *value);
+            visitor.visit_expr(ast, *class);
+        }
+        Expr::Range { start, end, step } => {
+            visitor.visit_expr(ast, *start);
+            visitor.visit_expr(ast, *end);
+            if let Some(step) = step {
+                visitor.visit_expr(ast, *step);
+            }
+        }
+        Expr::If {
+            cond,
+            then,
+            otherwise,
+        } => {
+            visitor.visit_expr(ast, *cond);
+            walk_stmts_mut(visitor, ast, then);
+            walk_stmts_mut(visitor, ast, otherwise.as_deref_mut().unwrap_or_default());
+        }
+        Expr::Block(stmts) => walk_stmts_mut(visitor, ast, stmts),
+        Expr::Object { fields, methods } => {
+            for (_, value) in fields {
+                visitor.visit_expr(ast, *value);
+            }
+            for method in methods {
+                visitor.visit_fun(ast, method);
+            }
+        }
+        Expr::Identifier(_) | Expr::Slf | Expr::Literal(_) => {}
+    }
+
+    ast[id] = expr;
+}
+
+/// Visit the decorators and then the body of `decl`, like [walk_fun].
+pub fn walk_fun_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut Ast, decl: &mut FunDecl) {
+    for decorator in &decl.decorators {
+        visitor.visit_expr(ast, *decorator);
+    }
+    walk_stmts_mut(visitor, ast, &mut decl.body);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{lexer::Lexer, parser::Parser};
+
+    use super::{walk_ast_mut, walk_expr_mut, walk_stmt_mut, Ast, Expr, ExprId, Stmt, VisitorMut};
+
+    /// Renames the variable `a` to `c`, and turns throws into returns.
+    struct Rename;
+
+    impl VisitorMut for Rename {
+        fn visit_stmt(&mut self, ast: &mut Ast, stmt: &mut Stmt) {
+            if let Stmt::Throw(value) = stmt {
+                *stmt = Stmt::Return(*value);
+            }
+            walk_stmt_mut(self, ast, stmt);
+        }
+
+        fn visit_expr(&mut self, ast: &mut Ast, id: ExprId) {
+            if ast[id] == Expr::Identifier("a".into()) {
+                ast[id] = Expr::Identifier("c".into());
+            }
+            walk_expr_mut(self, ast, id);
+        }
+    }
+
+    #[test]
+    fn test_visitor_mut() {
+        let parse = |source| Parser::parse_ast(Lexer::tokenize(source)).unwrap();
+        let mut ast = parse("fun f(g) {\n    if a { throw a } else { g(fun() { a }) }\n}\nvar b = [a, ...a]\nvar k = object { var x = a }");
+
+        walk_ast_mut(&mut Rename, &mut ast);
+
+        assert_eq!(
+            ast,
+            parse("fun f(g) {\n    if c { return c } else { g(fun() { c }) }\n}\nvar b = [c, ...c]\nvar k = object { var x = c }")
+        );
+    }
+}
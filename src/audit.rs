@@ -0,0 +1,102 @@
+//! A record of the native functions a script called.
+//!
+//! Scripts can only affect the world through the natives a host gives them,
+//! so the list of native calls is a summary of everything a script did. Once
+//! [Thrush::enable_audit](crate::Thrush::enable_audit) is called, every call
+//! to a native function or method is logged with a short summary of its
+//! arguments, to review what an untrusted plugin did or to ask the user
+//! before granting it the same again.
+
+use std::time::SystemTime;
+
+use crate::{
+    repr::{PrintLimits, Repr},
+    value::Value,
+};
+
+/// The longest an argument summary can be, in characters.
+const MAX_SUMMARY: usize = 64;
+
+/// How much of a container argument is summarized.
+const LIMITS: PrintLimits = PrintLimits {
+    max_depth: 2,
+    max_items: 8,
+};
+
+/// A call to a native function made by a script.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AuditEntry {
+    /// The function's name, prefixed with the class name for methods.
+    pub name: String,
+    /// A summary of each argument, cut short if it is long.
+    pub args: Vec<String>,
+    /// When the call was made.
+    pub time: SystemTime,
+}
+
+impl AuditEntry {
+    pub(crate) fn new(name: String, args: &[Value]) -> Self {
+        Self {
+            name,
+            args: args.iter().map(summarize).collect(),
+            time: SystemTime::now(),
+        }
+    }
+}
+
+/// Format `value` like the REPL would, cut to [MAX_SUMMARY] characters.
+fn summarize(value: &Value) -> String {
+    let repr = Repr::new(value, LIMITS).to_string();
+
+    match repr.char_indices().nth(MAX_SUMMARY) {
+        Some((end, _)) => format!("{}...", &repr[..end]),
+        None => repr,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        value::{Class, Value},
+        Thrush,
+    };
+
+    #[test]
+    fn test_audit() {
+        let mut thrush = Thrush::new();
+        let file = Class::new("File");
+
+        file.add_method("read", |_, _| Value::String("".into()));
+        thrush.globals().add_function("open", |_| Ok(Value::Nil));
+        thrush.globals().add("File", Value::Class(file));
+
+        thrush.exec("open(\"a.txt\", 2)").unwrap();
+        assert!(thrush.audit_log().is_empty());
+
+        thrush.enable_audit();
+        thrush
+            .exec("var f = File()\nopen(\"b.txt\")\nf.read([1, 2, 3])")
+            .unwrap();
+        thrush
+            .exec(&format!("open(\"{}\")", "x".repeat(100)))
+            .unwrap();
+
+        let log = thrush.take_audit_log();
+        let calls = log
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.args.clone()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            calls[..2],
+            [
+                ("open", vec!["\"b.txt\"".to_string()]),
+                ("File.read", vec!["[1, 2, 3]".to_string()]),
+            ]
+        );
+        assert_eq!(calls[2].1[0].len(), 67);
+        assert!(calls[2].1[0].ends_with("xx..."));
+        assert!(log[0].time <= log[1].time);
+        assert!(thrush.audit_log().is_empty());
+    }
+}
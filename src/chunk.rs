@@ -1,25 +1,124 @@
-use crate::{instruction::Instruction, value::Callable};
+use std::collections::HashMap;
+
+use crate::{instruction::Instruction, token::Span, value::Value};
 
 #[derive(Debug, Default)]
 pub struct Chunk {
     pub instructions: Vec<Instruction>,
+    /// Where in the script each instruction was compiled from.
+    pub spans: Vec<Span>,
+    /// The names instructions refer to by index, each stored once.
     pub variables: Vec<Box<str>>,
+    pub constants: Vec<Value>,
+    pub tables: Vec<JumpTable>,
+    /// The index of each name in `variables`.
+    names: HashMap<Box<str>, usize>,
 }
 
 impl Chunk {
     pub fn new() -> Self {
         Self {
             instructions: Vec::new(),
+            spans: Vec::new(),
             variables: Vec::new(),
+            constants: Vec::new(),
+            tables: Vec::new(),
+            names: HashMap::new(),
         }
     }
 
+    /// Where the instruction at `ip` was compiled from, if that is known.
+    pub fn span(&self, ip: usize) -> Option<Span> {
+        self.spans
+            .get(ip)
+            .copied()
+            .filter(|span| *span != Span::default())
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// The index of the name `s` in `variables`, adding it unless it is
+    /// already there.
     pub fn add_variable<S: Into<Box<str>>>(&mut self, s: S) -> usize {
-        self.variables.push(s.into());
+        let name = s.into();
+
+        if let Some(&index) = self.names.get(&name) {
+            return index;
+        }
+
+        self.variables.push(name.clone());
+        self.names.insert(name, self.variables.len() - 1);
         self.variables.len() - 1
     }
+
+    pub fn add_table(&mut self, table: JumpTable) -> usize {
+        self.tables.push(table);
+        self.tables.len() - 1
+    }
 }
 
+/// The jump offsets of a switch over a dense range of integers.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct JumpTable {
+    /// The smallest case value.
+    pub min: i64,
+    /// The offset to jump by for each value from `min` upwards.
+    pub targets: Vec<usize>,
+    /// The offset to jump by for values without a case.
+    pub default: usize,
+}
+
+impl JumpTable {
+    /// The offset to jump by when switching on `value`.
+    pub fn offset(&self, value: &Value) -> usize {
+        let target = match value {
+            Value::Integer(v) => v
+                .checked_sub(self.min)
+                .and_then(|i| usize::try_from(i).ok())
+                .and_then(|i| self.targets.get(i)),
+            _ => None,
+        };
+
+        target.copied().unwrap_or(self.default)
+    }
+}
+
+/// A module loaded by an `import`.
+///
+/// A module's top-level declarations are stored as globals namespaced by the
+/// module's name, such as `math/vec::add`, so modules can't clash with each
+/// other or with the scripts importing them.
+#[derive(Debug, PartialEq, Default)]
 pub struct Module {
-    pub functions: Vec<Box<dyn Callable>>,
+    pub name: Box<str>,
+    /// The exported names, mapped to the globals holding their values.
+    pub exports: Exports,
+}
+
+/// The names exported by a module, mapped to the globals holding their values.
+pub type Exports = HashMap<Box<str>, Box<str>>;
+
+impl Module {
+    /// The name of the global that holds `name` in the module `module`.
+    pub fn global(module: &str, name: &str) -> String {
+        format!("{module}::{name}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Chunk;
+
+    #[test]
+    fn test_add_variable() {
+        let mut chunk = Chunk::new();
+
+        assert_eq!(chunk.add_variable("x"), 0);
+        assert_eq!(chunk.add_variable("y"), 1);
+        assert_eq!(chunk.add_variable("x"), 0);
+        assert_eq!(chunk.variables, vec!["x".into(), "y".into()]);
+    }
 }
@@ -0,0 +1,382 @@
+//! Unicode-aware case mapping and string collation, exposed as the `str` global.
+//!
+//! ```text
+//! str.casefold("Straße") == str.casefold("STRASSE")
+//! var names = str.sort(names, {ignore_case: true, numeric: true})
+//! var order = str.compare("İstanbul", "istanbul", {locale: "tr", ignore_case: true})
+//! ```
+//!
+//! Case mapping works on whole characters rather than bytes. The only locale
+//! specific rules are the Turkish and Azeri dotted and dotless `i`.
+//!
+//! Strings are collated in three levels, each only deciding ties left by the
+//! one before: first by their letters with case and accents ignored, so
+//! `"a" < "B"` and `"é" < "f"`, then by their accents, so `"e" < "é"`, and
+//! last by their case, with lowercase first.
+
+use std::{cmp::Ordering, iter::Peekable, str::Chars};
+
+use crate::{
+    scope::State,
+    value::{Class, Value},
+};
+
+/// Options accepted by `str.compare` and `str.sort`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Collation {
+    /// Treat strings that only differ in case as equal, rather than putting
+    /// lowercase first.
+    pub ignore_case: bool,
+    /// Compare runs of digits by their numeric value, so `"a2" < "a10"`.
+    pub numeric: bool,
+    /// Use Turkish/Azeri case mapping rules.
+    pub turkic: bool,
+}
+
+impl Collation {
+    fn from_value(value: Option<&Value>) -> Result<Self, String> {
+        let mut collation = Collation::default();
+
+        let map = match value {
+            Some(Value::Map(map)) => map.borrow(),
+            Some(value) => return Err(format!("expected a map of options, found '{value}'")),
+            None => return Ok(collation),
+        };
+
+        for (key, value) in map.iter() {
+            match (key, value) {
+                ("ignore_case", Value::Bool(v)) => collation.ignore_case = *v,
+                ("numeric", Value::Bool(v)) => collation.numeric = *v,
+                ("locale", Value::String(locale)) => collation.turkic = is_turkic(locale),
+                (key, value) => return Err(format!("invalid collation option {key}: {value}")),
+            }
+        }
+
+        Ok(collation)
+    }
+
+    /// Compare two strings by their letters, then their accents and then,
+    /// unless `ignore_case` is set, their case.
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        let (a_folded, b_folded) = (casefold(a, self.turkic), casefold(b, self.turkic));
+
+        self.compare_keys(&strip_accents(&a_folded), &strip_accents(&b_folded))
+            .then_with(|| self.compare_keys(&a_folded, &b_folded))
+            .then_with(|| match self.ignore_case {
+                true => Ordering::Equal,
+                false => compare_case(a, b),
+            })
+    }
+
+    /// Sort strings, breaking ties between strings that compare equal by
+    /// their characters so the result doesn't depend on the input order.
+    pub fn sort(&self, strings: &mut [String]) {
+        strings.sort_by(|a, b| self.compare(a, b).then_with(|| a.cmp(b)));
+    }
+
+    fn compare_keys(&self, a: &str, b: &str) -> Ordering {
+        if !self.numeric {
+            return a.cmp(b);
+        }
+
+        let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+
+        loop {
+            match (a.peek(), b.peek()) {
+                (None, None) => return Ordering::Equal,
+                (None, Some(_)) => return Ordering::Less,
+                (Some(_), None) => return Ordering::Greater,
+                (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                    let ordering = compare_digits(&digits(&mut a), &digits(&mut b));
+
+                    if ordering.is_ne() {
+                        return ordering;
+                    }
+                }
+                (Some(x), Some(y)) => {
+                    let ordering = x.cmp(y);
+
+                    if ordering.is_ne() {
+                        return ordering;
+                    }
+
+                    a.next();
+                    b.next();
+                }
+            }
+        }
+    }
+}
+
+/// Order strings that only differ in case, putting lowercase letters before
+/// uppercase ones at the first position where they differ.
+fn compare_case(a: &str, b: &str) -> Ordering {
+    let uppercase = |text: &str| text.chars().map(char::is_uppercase).collect::<Vec<_>>();
+
+    uppercase(a).cmp(&uppercase(b))
+}
+
+/// Remove the accents from lowercase latin letters, and combining marks, so
+/// `"é"` and `"e"` compare equal.
+fn strip_accents(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        let base = match c {
+            '\u{300}'..='\u{36f}' => continue,
+            'à'..='å' | 'ā' | 'ă' | 'ą' => "a",
+            'æ' => "ae",
+            'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+            'ð' | 'ď' | 'đ' => "d",
+            'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+            'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+            'ĥ' | 'ħ' => "h",
+            // `ı` is a letter of its own in Turkish, so it keeps its place.
+            'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' => "i",
+            'ĵ' => "j",
+            'ķ' => "k",
+            'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+            'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+            'ò'..='ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+            'œ' => "oe",
+            'ŕ' | 'ŗ' | 'ř' => "r",
+            'ś' | 'ŝ' | 'ş' | 'š' => "s",
+            'ţ' | 'ť' | 'ŧ' => "t",
+            'þ' => "th",
+            'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+            'ŵ' => "w",
+            'ý' | 'ÿ' | 'ŷ' => "y",
+            'ź' | 'ż' | 'ž' => "z",
+            c => {
+                stripped.push(c);
+                continue;
+            }
+        };
+
+        stripped.push_str(base);
+    }
+
+    stripped
+}
+
+fn is_turkic(locale: &str) -> bool {
+    let language = locale.split(['-', '_']).next().unwrap_or("");
+
+    language.eq_ignore_ascii_case("tr") || language.eq_ignore_ascii_case("az")
+}
+
+fn digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+
+    digits
+}
+
+/// Compare two runs of ASCII digits by value without parsing them.
+fn compare_digits(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// Lowercase `text`, using Turkish rules for `I` when `turkic` is set.
+pub fn lower(text: &str, turkic: bool) -> String {
+    if !turkic {
+        return text.to_lowercase();
+    }
+
+    text.chars()
+        .map(|c| match c {
+            'I' => "ı".into(),
+            'İ' => "i".into(),
+            c => c.to_lowercase().collect::<String>(),
+        })
+        .collect()
+}
+
+/// Uppercase `text`, using Turkish rules for `i` when `turkic` is set.
+pub fn upper(text: &str, turkic: bool) -> String {
+    if !turkic {
+        return text.to_uppercase();
+    }
+
+    text.chars()
+        .map(|c| match c {
+            'i' => "İ".into(),
+            c => c.to_uppercase().collect::<String>(),
+        })
+        .collect()
+}
+
+/// Fold the case of `text` for caseless comparison.
+///
+/// Unlike [lower], this also folds characters whose lowercase forms differ,
+/// so `"Straße"` and `"STRASSE"` fold to the same string.
+pub fn casefold(text: &str, turkic: bool) -> String {
+    let mut folded = String::with_capacity(text.len());
+
+    for c in lower(text, turkic).chars() {
+        match c {
+            'ß' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            'ſ' => folded.push('s'),
+            'ﬀ' => folded.push_str("ff"),
+            'ﬁ' => folded.push_str("fi"),
+            'ﬂ' => folded.push_str("fl"),
+            'ﬃ' => folded.push_str("ffi"),
+            'ﬄ' => folded.push_str("ffl"),
+            'ﬅ' | 'ﬆ' => folded.push_str("st"),
+            c => folded.push(c),
+        }
+    }
+
+    folded
+}
+
+fn string_arg<'a>(name: &str, args: &'a [Value], index: usize) -> Result<&'a str, String> {
+    match args.get(index) {
+        Some(Value::String(text)) => Ok(text),
+        Some(value) => Err(format!("str.{name} expects a string, found '{value}'")),
+        None => Err(format!("str.{name} expects a string")),
+    }
+}
+
+fn locale_arg(args: &[Value], index: usize) -> Result<bool, String> {
+    match args.get(index) {
+        Some(Value::String(locale)) => Ok(is_turkic(locale)),
+        Some(value) => Err(format!("expected a locale name, found '{value}'")),
+        None => Ok(false),
+    }
+}
+
+/// Register the `str` global.
+pub fn register(state: &mut State) {
+    let class = Class::new("str");
+
+    class.add_static_method("casefold", |args| {
+        let text = string_arg("casefold", &args, 0)?;
+
+        Ok(Value::String(casefold(text, locale_arg(&args, 1)?)))
+    });
+
+    class.add_static_method("lower", |args| {
+        let text = string_arg("lower", &args, 0)?;
+
+        Ok(Value::String(lower(text, locale_arg(&args, 1)?)))
+    });
+
+    class.add_static_method("upper", |args| {
+        let text = string_arg("upper", &args, 0)?;
+
+        Ok(Value::String(upper(text, locale_arg(&args, 1)?)))
+    });
+
+    class.add_static_method("compare", |args| {
+        let a = string_arg("compare", &args, 0)?;
+        let b = string_arg("compare", &args, 1)?;
+        let ordering = Collation::from_value(args.get(2))?.compare(a, b);
+
+        Ok(Value::Integer(ordering as i64))
+    });
+
+    class.add_static_method("sort", |args| {
+        let collation = Collation::from_value(args.get(1))?;
+        let mut strings = match args.first() {
+            Some(Value::List(list)) => list
+                .borrow()
+                .iter()
+                .map(|value| match value {
+                    Value::String(s) => Ok(s.clone()),
+                    value => Err(format!("str.sort expects strings, found '{value}'")),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => return Err("str.sort expects a list of strings".into()),
+        };
+
+        collation.sort(&mut strings);
+
+        Ok(Value::list(
+            strings.into_iter().map(Value::String).collect(),
+        ))
+    });
+
+    state.add("str", Value::Class(class));
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::Ordering;
+
+    use crate::{value::Value, Thrush};
+
+    use super::{casefold, lower, upper, Collation};
+
+    #[test]
+    fn test_case_mapping() {
+        assert_eq!(casefold("Straße", false), casefold("STRASSE", false));
+        assert_eq!(casefold("ΣΊΣΥΦΟΣ", false), casefold("σίσυφος", false));
+        assert_eq!(lower("ÀÉÎ", false), "àéî");
+        assert_eq!(lower("DİYARBAKIR", true), "diyarbakır");
+        assert_eq!(upper("istanbul", true), "İSTANBUL");
+        assert_eq!(upper("istanbul", false), "ISTANBUL");
+    }
+
+    #[test]
+    fn test_collation() {
+        let collation = Collation {
+            ignore_case: true,
+            numeric: true,
+            turkic: false,
+        };
+
+        assert_eq!(collation.compare("file2", "File10"), Ordering::Less);
+        assert_eq!(collation.compare("a007", "a7"), Ordering::Equal);
+        assert_eq!(collation.compare("ÉCOLE", "école"), Ordering::Equal);
+        assert_eq!(Collation::default().compare("b", "a"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_collation_levels() {
+        let collation = Collation::default();
+
+        assert_eq!(collation.compare("a", "B"), Ordering::Less);
+        assert_eq!(collation.compare("é", "f"), Ordering::Less);
+        assert_eq!(collation.compare("e", "é"), Ordering::Less);
+        assert_eq!(collation.compare("a", "A"), Ordering::Less);
+        assert_eq!(collation.compare("Été", "ete"), Ordering::Greater);
+        assert_eq!(collation.compare("œuvre", "oeuvre"), Ordering::Greater);
+
+        let mut words = [
+            "zebra", "Éclair", "apple", "Zoo", "eclair", "Apple", "ferme",
+        ]
+        .map(String::from);
+
+        collation.sort(&mut words);
+
+        assert_eq!(
+            words,
+            ["apple", "Apple", "eclair", "Éclair", "ferme", "zebra", "Zoo"]
+        );
+    }
+
+    #[test]
+    fn test_collation_script() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "var names = str.sort([\"item10\", \"Item2\", \"item1\"], {ignore_case: true, numeric: true})\nvar same = str.compare(\"I\", \"ı\", {locale: \"tr\", ignore_case: true})\nvar order = str.compare(\"a\", \"B\")",
+            )
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("names").unwrap().to_string(),
+            "[\"item1\", \"Item2\", \"item10\"]"
+        );
+        assert_eq!(thrush.globals().get::<i64>("same"), Ok(0));
+        assert_eq!(thrush.globals().get::<i64>("order"), Ok(-1));
+    }
+}
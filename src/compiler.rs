@@ -1,8 +1,26 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, mem,
+    rc::Rc,
+};
+
 use crate::{
-    ast::{Ast, BinOp, Expr, Lit, Stmt},
-    chunk::Chunk,
-    instruction::Instruction,
+    ast::{
+        Ast, BinOp, Catch, Destructure, Expr, ExprId, FunDecl, FunKind, HostStmt, Lit, MatchArm,
+        Pattern, Stmt, SwitchCase,
+    },
+    chunk::{Chunk, Exports, JumpTable, Module},
+    diagnostic::{did_you_mean, Error, ErrorCode},
+    hash::Hash,
+    instruction::{InstanceValue, Instruction},
+    lexer::{self, check_operator},
+    opt::{self, lit_value, value_lit},
+    parser::{nesting_error, Precedence, DEFAULT_MAX_DEPTH},
+    report::Diagnostic,
     scope::State,
+    token::Span,
+    value::{Capture, Function, ScriptFun, Trait, Value},
+    vm,
 };
 
 pub struct Class {
@@ -13,132 +31,2843 @@ impl Class {
     fn _new() -> Option<Class> {
         Some(Self { fields: 0 })
     }
-}
+}
+
+/// The fewest integer cases a switch needs to be compiled to a jump table.
+pub const JUMP_TABLE_MIN_CASES: usize = 3;
+
+/// A custom infix operator.
+#[derive(Debug, Clone)]
+pub struct Operator {
+    /// How tightly the operator binds its operands.
+    pub precedence: Precedence,
+    /// The function called with the operator's operands.
+    pub handler: Rc<Function>,
+}
+
+/// Compiles a statement claimed by the host.
+type CompileStatement = dyn Fn(&HostStmt, &mut Emitter) -> Result<(), String>;
+
+/// A host function that compiles the statements starting with a keyword.
+#[derive(Clone)]
+pub struct StatementHook(Rc<CompileStatement>);
+
+impl fmt::Debug for StatementHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StatementHook")
+    }
+}
+
+/// How much work the compiler does to make the code it emits run faster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptLevel {
+    /// Compile the script as written.
+    None,
+    /// Fold operations on literals, see [opt::fold].
+    Fold,
+    /// Also remove unreachable code and rewrite short sequences of
+    /// instructions, see [opt::optimize].
+    #[default]
+    Full,
+}
+
+/// Options that extend the language a script is compiled from, and control
+/// how it is compiled.
+#[derive(Debug, Clone)]
+pub struct CompilerOptions {
+    /// Custom infix operators, by symbol.
+    operators: HashMap<String, Operator>,
+    /// The hooks that compile host statements, by keyword.
+    statements: HashMap<String, StatementHook>,
+    /// The functions tagged templates call, by tag.
+    tags: HashMap<String, Rc<Function>>,
+    opt_level: OptLevel,
+    /// Whether instructions are given the position of the code they were
+    /// compiled from, which runtime errors are reported at.
+    debug_info: bool,
+    /// Whether assigning to an undeclared global declares it.
+    implicit_globals: bool,
+    /// How many constants a chunk can have, if there is a limit.
+    max_constants: Option<usize>,
+}
+
+impl Default for CompilerOptions {
+    fn default() -> Self {
+        Self {
+            operators: HashMap::new(),
+            statements: HashMap::new(),
+            tags: HashMap::new(),
+            opt_level: OptLevel::default(),
+            debug_info: true,
+            implicit_globals: false,
+            max_constants: None,
+        }
+    }
+}
+
+impl CompilerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how much the compiler optimizes, [OptLevel::Full] by default.
+    /// Lower levels compile faster, but the code runs slower.
+    pub fn with_opt_level(mut self, opt_level: OptLevel) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    /// Set whether instructions record where in the script they came from,
+    /// which is on by default. Without it, chunks are smaller, but runtime
+    /// errors don't say which line they happened on.
+    pub fn with_debug_info(mut self, debug_info: bool) -> Self {
+        self.debug_info = debug_info;
+        self
+    }
+
+    /// Let scripts assign to globals that nothing declares, which declares
+    /// them, rather than failing to compile. Reading such a global before
+    /// it is assigned gives nil.
+    pub fn with_implicit_globals(mut self, implicit_globals: bool) -> Self {
+        self.implicit_globals = implicit_globals;
+        self
+    }
+
+    /// Fail to compile scripts and functions that need more than
+    /// `max_constants` constants.
+    pub fn with_max_constants(mut self, max_constants: usize) -> Self {
+        self.max_constants = Some(max_constants);
+        self
+    }
+
+    /// Register the infix operator `symbol`, so `a <symbol> b` calls
+    /// `handler(a, b)`.
+    ///
+    /// The operator binds as tightly as the built-in operators of
+    /// `precedence`, and associates to the left.
+    ///
+    /// ```
+    /// use thrush::{compiler::CompilerOptions, parser::Precedence, value::Value};
+    ///
+    /// let mut options = CompilerOptions::new();
+    ///
+    /// options
+    ///     .operator("<>", Precedence::Sum, |a, b| Ok(Value::list(vec![a, b])))
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `symbol` can't be tokenized as an operator, see
+    /// [crate::lexer::check_operator], or if `precedence` is
+    /// [Precedence::None] or [Precedence::End].
+    pub fn operator<F>(
+        &mut self,
+        symbol: &str,
+        precedence: Precedence,
+        handler: F,
+    ) -> Result<(), String>
+    where
+        F: Fn(Value, Value) -> Result<Value, String> + 'static,
+    {
+        check_operator(symbol)?;
+
+        if matches!(precedence, Precedence::None | Precedence::End) {
+            return Err(format!("operators can't have precedence {precedence:?}"));
+        }
+
+        let handler = Function::new(symbol, move |args| {
+            let mut args = args.into_iter();
+
+            match (args.next(), args.next()) {
+                (Some(a), Some(b)) => handler(a, b),
+                _ => Err("expected two operands".into()),
+            }
+        })
+        .with_params(&["left", "right"]);
+
+        self.operators.insert(
+            symbol.into(),
+            Operator {
+                precedence,
+                handler: Rc::new(handler),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// The registered custom operators, by symbol.
+    pub fn operators(&self) -> impl Iterator<Item = (&str, &Operator)> {
+        self.operators
+            .iter()
+            .map(|(symbol, operator)| (symbol.as_str(), operator))
+    }
+
+    /// Claim `keyword` for statements compiled by `hook`.
+    ///
+    /// A host statement is the keyword, followed by comma-separated
+    /// arguments and an optional block:
+    ///
+    /// ```text
+    /// quest "Find the sword", 100 {
+    ///     var reward = "sword"
+    /// }
+    /// ```
+    ///
+    /// The hook is given the parsed [HostStmt] and an [Emitter] to compile
+    /// it with. The code it emits has to leave the stack as it found it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `keyword` is already a keyword or is not a valid
+    /// identifier.
+    pub fn statement<F>(&mut self, keyword: &str, hook: F) -> Result<(), String>
+    where
+        F: Fn(&HostStmt, &mut Emitter) -> Result<(), String> + 'static,
+    {
+        if lexer::keyword(keyword).is_some() {
+            return Err(format!("'{keyword}' is already a keyword"));
+        }
+
+        if !lexer::is_identifier(keyword) {
+            return Err(format!("'{keyword}' is not a valid identifier"));
+        }
+
+        self.statements
+            .insert(keyword.into(), StatementHook(Rc::new(hook)));
+
+        Ok(())
+    }
+
+    /// The keywords claimed by host statements.
+    pub fn host_keywords(&self) -> impl Iterator<Item = &str> {
+        self.statements.keys().map(String::as_str)
+    }
+
+    /// Register the tag `name`, so the template `name"..."` calls `handler`
+    /// with the template's text and the values interpolated into it.
+    ///
+    /// The text is split around the interpolations, so handlers can treat
+    /// values differently from the text, such as escaping them:
+    ///
+    /// ```
+    /// use thrush::{compiler::CompilerOptions, value::Value};
+    ///
+    /// let mut options = CompilerOptions::new();
+    ///
+    /// // sql"SELECT * FROM t WHERE id = ${id}" gives
+    /// // ["SELECT * FROM t WHERE id = ?", [id]]
+    /// options.tag("sql", |parts, values| {
+    ///     Ok(Value::list(vec![
+    ///         Value::String(parts.join("?")),
+    ///         Value::list(values),
+    ///     ]))
+    /// });
+    /// ```
+    pub fn tag<F>(&mut self, name: &str, handler: F)
+    where
+        F: Fn(Vec<String>, Vec<Value>) -> Result<Value, String> + 'static,
+    {
+        let handler = Function::new(name, move |args| match &args[..] {
+            [Value::List(parts), Value::List(values)] => {
+                let parts = parts
+                    .borrow()
+                    .iter()
+                    .map(|part| match part {
+                        Value::String(part) => part.clone(),
+                        part => part.to_string(),
+                    })
+                    .collect();
+
+                handler(parts, values.borrow().clone())
+            }
+            _ => Err("expected the parts and values of a template".into()),
+        })
+        .with_params(&["parts", "values"]);
+
+        self.tags.insert(name.into(), Rc::new(handler));
+    }
+}
+
+/// What the compiler knows about the declarations of the scripts it has
+/// compiled, which hosts keep between scripts so one can rely on what an
+/// earlier one declared, as [Thrush](crate::Thrush) does.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    /// The parameter names of the functions declared so far.
+    functions: HashMap<String, Vec<String>>,
+    /// The instance methods of the classes declared so far, by global, for
+    /// checking the classes that mix them in.
+    classes: HashMap<String, Vec<(FunKind, String)>>,
+    /// The values of constants initialized with literals, which references
+    /// are replaced with.
+    folded: HashMap<String, Lit>,
+}
+
+/// The part of the compiler given to hooks compiling host statements.
+pub struct Emitter<'c, 'a> {
+    compiler: &'c mut Compiler<'a>,
+}
+
+impl Emitter<'_, '_> {
+    /// The expression `id`, such as one of the statement's arguments.
+    pub fn get(&self, id: ExprId) -> &Expr {
+        &self.compiler.ast[id]
+    }
+
+    /// Compile `expr`, leaving its value on the stack.
+    pub fn expr(&mut self, expr: ExprId) -> Result<(), String> {
+        self.compiler.expr(expr)
+    }
+
+    /// Compile `stmts`, such as the statement's body.
+    pub fn statements(&mut self, stmts: &[Stmt]) -> Result<(), String> {
+        self.compiler.statements(stmts)
+    }
+
+    /// Emit `inst`.
+    pub fn emit(&mut self, inst: Instruction) {
+        self.compiler.emit_inst(inst);
+    }
+
+    /// Add `value` to the chunk's constants, for [Instruction::Constant].
+    pub fn constant(&mut self, value: Value) -> usize {
+        self.compiler.chunk.add_constant(value)
+    }
+
+    /// The slot of the global `name`, for instructions such as
+    /// [Instruction::GetGlobal]. The name counts as declared, since the hook
+    /// may be defining it.
+    pub fn global(&mut self, name: &str) -> usize {
+        self.compiler.declare(name)
+    }
+}
+
+pub struct Compiler<'a> {
+    state: &'a mut State,
+    chunk: Chunk,
+    /// The AST being compiled, which expression ids are resolved in.
+    ast: Rc<Ast>,
+    /// Names of the local slots in the function being compiled.
+    locals: Vec<String>,
+    /// The locals declared with `var` that are in scope, by slot.
+    vars: Vec<Var>,
+    /// The slots of the locals that closures capture, which have to be
+    /// closed before they go out of scope.
+    captured: HashSet<usize>,
+    /// The variables of enclosing functions the function being compiled
+    /// uses, which its closures capture.
+    upvalues: Vec<Capture>,
+    /// The functions enclosing the one being compiled, innermost last.
+    enclosing: Vec<Enclosing>,
+    /// The warnings reported so far, see [Compiler::diagnostics].
+    warnings: Vec<Diagnostic>,
+    /// Whether a function body is being compiled.
+    in_function: bool,
+    /// Whether the function being compiled yields, making it a generator.
+    yields: bool,
+    /// Whether the function being compiled is async, so it can `await`.
+    in_async: bool,
+    /// The return type of the function being compiled, if it is annotated
+    /// with one, and how type errors describe its return value.
+    returns: Option<(String, String)>,
+    /// The finally blocks of the try blocks enclosing the code being
+    /// compiled, innermost last, which a return has to run on its way out.
+    tries: Vec<Option<Vec<Stmt>>>,
+    /// The loops enclosing the code being compiled, innermost last.
+    loops: Vec<Loop>,
+    /// Whether the values of an enclosing expression are on the stack above
+    /// the locals, as in the block of `f({ ... })`, so statements that add
+    /// locals or jump away can't be compiled.
+    temporaries: bool,
+    /// What is known about the declarations compiled so far.
+    symbols: Symbols,
+    /// The constants declared by the script being compiled, which are added
+    /// to the global state once it compiles.
+    constants: Vec<String>,
+    /// The variables declared with `let` by the script being compiled.
+    immutables: Vec<String>,
+    /// How deeply statements can be nested before compiling fails, rather
+    /// than overflowing the stack.
+    pub max_depth: usize,
+    depth: usize,
+    /// The module being compiled, whose top-level declarations are
+    /// namespaced by its name.
+    module: Option<String>,
+    /// The names declared at the top level of the module being compiled.
+    module_globals: HashSet<String>,
+    /// The globals declared by the script being compiled.
+    declared: HashSet<String>,
+    /// The slots of the globals declared by assigning to them, which are
+    /// defined as nil once the script compiles.
+    implicit: Vec<usize>,
+    /// The globals the script being compiled refers to that weren't defined
+    /// yet at the time, and where, for [Compiler::check_globals].
+    referenced: Vec<(String, Span)>,
+    /// The names exported so far, mapped to the globals that hold them.
+    exports: Exports,
+    /// The index of the instruction discarding the value of the last
+    /// top-level statement, if it is an expression.
+    last_value: Option<usize>,
+    /// Extensions to the language, such as custom operators.
+    options: CompilerOptions,
+    /// Where the expression being compiled starts, which the instructions
+    /// emitted for it and any error compiling it are given.
+    span: Span,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(state: &'a mut State) -> Self {
+        Self {
+            state,
+            chunk: Chunk::new(),
+            ast: Rc::default(),
+            locals: Vec::new(),
+            vars: Vec::new(),
+            captured: HashSet::new(),
+            upvalues: Vec::new(),
+            enclosing: Vec::new(),
+            warnings: Vec::new(),
+            in_function: false,
+            yields: false,
+            in_async: false,
+            returns: None,
+            tries: Vec::new(),
+            loops: Vec::new(),
+            temporaries: false,
+            symbols: Symbols::default(),
+            constants: Vec::new(),
+            immutables: Vec::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            depth: 0,
+            module: None,
+            module_globals: HashSet::new(),
+            declared: HashSet::new(),
+            implicit: Vec::new(),
+            referenced: Vec::new(),
+            exports: HashMap::new(),
+            last_value: None,
+            options: CompilerOptions::default(),
+            span: Span::default(),
+        }
+    }
+
+    /// Compile the language extensions in `options`.
+    pub fn with_options(mut self, options: CompilerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Compile the script as the module `name`.
+    pub fn with_module(mut self, name: &str) -> Self {
+        self.module = Some(name.into());
+        self
+    }
+
+    /// Compile the script knowing about the declarations in `symbols`, taken
+    /// from the compiler of a script that has already run.
+    pub fn with_symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Take what the compiler learned about the declarations of the
+    /// compiled script and those before it, see [Compiler::with_symbols].
+    pub fn symbols(&mut self) -> Symbols {
+        mem::take(&mut self.symbols)
+    }
+
+    /// Take the names exported by the compiled script, mapped to the globals
+    /// that hold them.
+    pub fn exports(&mut self) -> Exports {
+        mem::take(&mut self.exports)
+    }
+
+    /// Take the warnings reported about the compiled script, such as unused
+    /// variables, which don't stop it from compiling.
+    pub fn diagnostics(&mut self) -> Vec<Diagnostic> {
+        mem::take(&mut self.warnings)
+    }
+
+    fn warn(&mut self, code: ErrorCode, message: String, span: Span) {
+        self.warnings.push(Diagnostic::warning(
+            Error::new(code.error(message)).at(span),
+        ));
+    }
+
+    /// The global that holds `name`, namespacing names declared by a module.
+    fn global(&self, name: &str) -> String {
+        match &self.module {
+            Some(module) if self.module_globals.contains(name) => Module::global(module, name),
+            _ => name.into(),
+        }
+    }
+
+    /// The slot of the global `name` in the state, which the global
+    /// instructions refer to it by.
+    fn global_slot(&mut self, name: &str) -> usize {
+        let global = self.global(name);
+
+        self.state.slot(&global)
+    }
+
+    /// The slot of the global `name`, which the script being compiled
+    /// declares.
+    fn declare(&mut self, name: &str) -> usize {
+        self.declared.insert(name.into());
+        self.global_slot(name)
+    }
+
+    /// The slot of the global `name`, which the script being compiled refers
+    /// to. Unless it is defined by the time the script is compiled, that
+    /// is an error, see [Compiler::check_globals].
+    fn refer(&mut self, name: &str) -> usize {
+        let slot = self.global_slot(name);
+
+        if self.state.get_slot(slot).is_none() && !self.declared.contains(name) {
+            self.referenced.push((name.into(), self.span));
+        }
+
+        slot
+    }
+
+    /// The slot of the global `name`, which is assigned to. Unless something
+    /// else declares it, the assignment does, see
+    /// [CompilerOptions::with_implicit_globals].
+    fn implicit(&mut self, name: &str) -> usize {
+        let slot = self.declare(name);
+
+        if self.state.get_slot(slot).is_none() {
+            self.implicit.push(slot);
+        }
+
+        slot
+    }
+
+    /// Fail if the script compiled so far refers to a global that neither
+    /// it nor the host defines. Globals can be referred to before they are
+    /// declared, as long as the script declares them somewhere.
+    pub fn check_globals(&self) -> Result<(), Error> {
+        let undefined = self.referenced.iter().find(|(name, _)| {
+            !self.declared.contains(name) && !self.state.contains(&self.global(name))
+        });
+
+        let Some((name, span)) = undefined else {
+            return Ok(());
+        };
+
+        let candidates = self
+            .state
+            .names()
+            .filter(|name| !name.contains("::"))
+            .chain(self.declared.iter().map(String::as_str));
+
+        Err(Error::new(ErrorCode::UndefinedVariable.error(format!(
+            "undefined variable '{name}'{}",
+            did_you_mean(name, candidates)
+        )))
+        .at(*span))
+    }
+
+    /// Fail if `chunk` has more constants than the options allow.
+    fn check_constants(&self, chunk: &Chunk) -> Result<(), String> {
+        match self.options.max_constants {
+            Some(max) if chunk.constants.len() > max => Err(ErrorCode::ConstantLimit
+                .error(format!("too many constants (more than {max} in one chunk)"))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the statement being compiled is at the top level of the script.
+    fn at_top_level(&self) -> bool {
+        self.depth == 1
+    }
+
+    /// Set how deeply statements can be nested.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Run `f` one level of nesting deeper, failing if that exceeds the limit.
+    fn nested(&mut self, f: impl FnOnce(&mut Self) -> Result<(), String>) -> Result<(), String> {
+        if self.depth >= self.max_depth {
+            return Err(nesting_error(self.max_depth));
+        }
+
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+
+        result
+    }
+
+    pub fn emit_inst(&mut self, inst: Instruction) {
+        let span = if self.options.debug_info {
+            self.span
+        } else {
+            Span::default()
+        };
+
+        self.chunk.instructions.push(inst);
+        self.chunk.spans.push(span);
+    }
+
+    pub fn emit_return(&mut self) {
+        self.emit_inst(Instruction::Halt);
+    }
+
+    /// Emit a jump with a placeholder offset and return its index for [Compiler::patch_jump].
+    pub fn emit_jump(&mut self, inst: impl FnOnce(usize) -> Instruction) -> usize {
+        self.emit_inst(inst(0));
+
+        self.chunk.instructions.len() - 1
+    }
+
+    /// Point the jump at `index` to the next instruction to be emitted.
+    pub fn patch_jump(&mut self, index: usize) {
+        let target = self.chunk.instructions.len() - index - 1;
+
+        match &mut self.chunk.instructions[index] {
+            Instruction::Jump { offset }
+            | Instruction::JumpIfFalse { offset }
+            | Instruction::JumpIfFalseOrPop { offset }
+            | Instruction::JumpIfTrueOrPop { offset }
+            | Instruction::ForNext { offset, .. }
+            | Instruction::Try { offset } => *offset = target,
+            inst => unreachable!("cannot patch {inst:?}"),
+        }
+    }
+
+    /// Emit a jump back to the instruction at `start`.
+    pub fn emit_loop(&mut self, start: usize) {
+        let offset = self.chunk.instructions.len() + 1 - start;
+
+        self.emit_inst(Instruction::Loop { offset });
+    }
+
+    pub fn run(&mut self, ast: Ast) -> Result<Chunk, Error> {
+        if self.module.is_some() {
+            self.module_globals = ast
+                .nodes
+                .iter()
+                .filter_map(Stmt::declared_name)
+                .map(String::from)
+                .collect();
+        }
+
+        self.compile(ast)?;
+        self.check_globals()?;
+
+        Ok(self.finish())
+    }
+
+    /// Compile the statements of `ast` onto the end of the chunk being built.
+    ///
+    /// A script can be compiled a piece at a time by calling this for each
+    /// of its statements in turn, then [Compiler::finish].
+    pub fn compile(&mut self, mut ast: Ast) -> Result<(), Error> {
+        if self.options.opt_level != OptLevel::None {
+            opt::fold(&mut ast);
+        }
+
+        let ast = Rc::new(ast);
+        self.ast = Rc::clone(&ast);
+
+        for node in &ast.nodes {
+            // Statements without expressions, such as `break`, aren't given
+            // the position of the statement before them.
+            self.span = Span::default();
+            self.statement(node)
+                .and_then(|()| self.check_constants(&self.chunk))
+                .map_err(|err| Error::new(err).at(self.span))?;
+            self.last_value = matches!(node, Stmt::Expr(expr) if !is_statement_like(&ast[*expr]))
+                .then(|| self.chunk.instructions.len() - 1);
+        }
+
+        Ok(())
+    }
+
+    /// Finish the chunk like [Compiler::finish], but leave the value of the
+    /// last statement on the stack if it is an expression, or nil if not.
+    pub fn finish_with_value(&mut self) -> Chunk {
+        match self.last_value.take() {
+            Some(pop) if pop + 1 == self.chunk.instructions.len() => {
+                self.chunk.instructions.pop();
+                self.chunk.spans.pop();
+            }
+            _ => self.emit_inst(Instruction::LoadNil),
+        }
+
+        self.finish()
+    }
+
+    /// Finish the chunk being built and return it.
+    pub fn finish(&mut self) -> Chunk {
+        self.emit_return();
+        self.ast = Rc::default();
+
+        for name in self.constants.drain(..) {
+            self.state.add_constant(&name);
+        }
+
+        for name in self.immutables.drain(..) {
+            self.state.add_immutable(&name);
+        }
+
+        for slot in self.implicit.drain(..) {
+            if self.state.get_slot(slot).is_none() {
+                self.state.set_slot(slot, Value::Nil);
+            }
+        }
+
+        if self.options.opt_level == OptLevel::Full {
+            opt::optimize(&mut self.chunk);
+        }
+        mem::take(&mut self.chunk)
+    }
+
+    fn statement(&mut self, node: &Stmt) -> Result<(), String> {
+        if self.temporaries {
+            check_in_expression(node)?;
+        }
+
+        self.nested(|compiler| compiler.statement_body(node))
+    }
+
+    fn statement_body(&mut self, node: &Stmt) -> Result<(), String> {
+        match node {
+            Stmt::Class {
+                name,
+                span,
+                traits,
+                mixins,
+                fields,
+                statics,
+                methods,
+                version,
+                doc,
+            } => {
+                self.class(name, fields, methods, *version, doc.as_deref())?;
+
+                for field in statics {
+                    self.expr(field.init)?;
+
+                    let index = self.chunk.add_variable(field.name.as_str());
+
+                    self.emit_inst(Instruction::StaticField {
+                        index,
+                        constant: field.constant,
+                    });
+                }
+
+                self.mixins(name, mixins, methods)?;
+                self.span = *span;
+
+                for t in traits {
+                    self.identifier(t);
+                    self.emit_inst(Instruction::Implement);
+                }
+
+                let slot = self.declare(name);
+                self.emit_inst(Instruction::DefineGlobal { slot });
+
+                Ok(())
+            }
+            Stmt::Trait { name, methods } => self.trait_decl(name, methods),
+            Stmt::Fun(decl) => self.fun_declaration(decl),
+            Stmt::VarDecl { id, ty, init } => self.var_declartion(id, ty.as_deref(), *init),
+            Stmt::ConstDecl { id, ty, init } => self.const_declaration(id, ty.as_deref(), *init),
+            Stmt::LetDecl { id, ty, init } => self.let_declaration(id, ty.as_deref(), *init),
+            Stmt::Import { path, name } => self.import(path, name),
+            Stmt::Export(stmt) => self.export(stmt),
+            Stmt::Return(value) => self.return_stmt(*value),
+            Stmt::Match { value, arms } => self.match_stmt(*value, arms),
+            Stmt::Switch {
+                value,
+                cases,
+                default,
+            } => self.switch_stmt(*value, cases, default.as_deref()),
+            Stmt::Throw(value) => {
+                self.expr(*value)?;
+                // Report the thrown value where it starts, not where its
+                // last operand is.
+                self.span = self.ast.span(*value);
+                self.emit_inst(Instruction::Throw);
+
+                Ok(())
+            }
+            Stmt::Assign { targets, values } => self.multiple_assignment(targets, values),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => self.try_stmt(body, catch.as_ref(), finally.as_deref()),
+            Stmt::While { label, cond, body } => self.while_stmt(label.as_deref(), *cond, body),
+            Stmt::For {
+                label,
+                var,
+                iterable,
+                body,
+            } => self.for_stmt(label.as_deref(), var, *iterable, body),
+            Stmt::Yield(value) => self.yield_stmt(*value),
+            Stmt::Break(label) => self.jump_stmt(label.as_deref(), true),
+            Stmt::Continue(label) => self.jump_stmt(label.as_deref(), false),
+            Stmt::Host(stmt) => self.host_stmt(stmt),
+            Stmt::Expr(expr) => match &Rc::clone(&self.ast)[*expr] {
+                Expr::If {
+                    cond,
+                    then,
+                    otherwise,
+                } => self.if_stmt(*cond, then, otherwise.as_deref()),
+                Expr::Block(body) => self.statements(body),
+                _ => self.expression(*expr),
+            },
+        }
+    }
+
+    /// Compile an `if` in statement position, which leaves nothing on the
+    /// stack.
+    fn if_stmt(
+        &mut self,
+        cond: ExprId,
+        then: &[Stmt],
+        otherwise: Option<&[Stmt]>,
+    ) -> Result<(), String> {
+        self.expr(cond)?;
+
+        let other = self.emit_jump(|offset| Instruction::JumpIfFalse { offset });
+
+        self.statements(then)?;
+
+        let Some(otherwise) = otherwise else {
+            self.patch_jump(other);
+            return Ok(());
+        };
+
+        let end = self.emit_jump(|offset| Instruction::Jump { offset });
+
+        self.patch_jump(other);
+        self.statements(otherwise)?;
+        self.patch_jump(end);
+
+        Ok(())
+    }
+
+    /// Compile an `if` or block in expression position, leaving its value
+    /// on the stack.
+    fn value_expr(&mut self, expr: &Expr) -> Result<(), String> {
+        match expr {
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.expr(*cond)?;
+
+                let other = self.emit_jump(|offset| Instruction::JumpIfFalse { offset });
+
+                self.value_block(then)?;
+
+                let end = self.emit_jump(|offset| Instruction::Jump { offset });
+
+                self.patch_jump(other);
+
+                match otherwise {
+                    Some(otherwise) => self.value_block(otherwise)?,
+                    None => self.nil(),
+                }
+
+                self.patch_jump(end);
+
+                Ok(())
+            }
+            Expr::Block(body) => self.value_block(body),
+            _ => unreachable!("only ifs and blocks have statements"),
+        }
+    }
+
+    /// Compile `body`, leaving the value of its last statement on the stack
+    /// if that is an expression, or nil otherwise.
+    ///
+    /// The variables declared in `body` are popped from under the value.
+    fn value_block(&mut self, body: &[Stmt]) -> Result<(), String> {
+        let scope = self.locals.len();
+
+        self.check_reachable(body);
+
+        let result = match body.split_last() {
+            Some((Stmt::Expr(last), rest)) => rest
+                .iter()
+                .try_for_each(|stmt| self.statement(stmt))
+                .and_then(|()| self.expr(*last)),
+            _ => body
+                .iter()
+                .try_for_each(|stmt| self.statement(stmt))
+                .map(|()| self.nil()),
+        };
+
+        if self.locals.len() > scope {
+            self.close_upvalues(scope);
+            self.emit_inst(Instruction::SetLocal { slot: scope });
+            self.emit_inst(Instruction::Pop);
+            self.end_scope(scope + 1);
+            self.release_vars(scope);
+            self.captured.remove(&scope);
+            self.locals.pop();
+        }
+
+        result
+    }
+
+    /// Compile a host statement with the hook that claimed its keyword.
+    fn host_stmt(&mut self, stmt: &HostStmt) -> Result<(), String> {
+        let StatementHook(hook) = self
+            .options
+            .statements
+            .get(&stmt.keyword)
+            .cloned()
+            .ok_or_else(|| {
+                ErrorCode::Syntax.error(format!("unknown statement '{}'", stmt.keyword))
+            })?;
+
+        hook(stmt, &mut Emitter { compiler: self })
+    }
+
+    /// Compile a class body, leaving the class on the stack.
+    fn class(
+        &mut self,
+        name: &str,
+        fields: &[String],
+        methods: &[FunDecl],
+        version: Option<u32>,
+        doc: Option<&str>,
+    ) -> Result<(), String> {
+        let index = self.chunk.add_variable(name);
+
+        self.emit_inst(Instruction::Class { index });
+
+        if let Some(version) = version {
+            self.emit_inst(Instruction::Version { version });
+        }
+
+        if let Some(doc) = doc {
+            let index = self.chunk.add_constant(Value::String(doc.to_string()));
+
+            self.emit_inst(Instruction::Doc { index });
+        }
+
+        for field in fields {
+            let index = self.chunk.add_variable(field.as_str());
+            self.emit_inst(Instruction::Field { index });
+        }
+
+        self.methods(methods)?;
+
+        //self.class = Class::new();
+
+        //self.state.add_class(name);
+
+        Ok(())
+    }
+
+    /// Copy the methods of `mixins` into the class `name` on top of the
+    /// stack, failing if two of them, or a mixin and the class, define a
+    /// method with the same name.
+    fn mixins(&mut self, name: &str, mixins: &[String], methods: &[FunDecl]) -> Result<(), String> {
+        let mut defined = methods
+            .iter()
+            .filter(|method| method.kind != FunKind::Static)
+            .map(|method| ((method.kind, method.name.clone()), name.to_string()))
+            .collect::<HashMap<_, _>>();
+
+        for mixin in mixins {
+            let mixed = self.class_methods(mixin).ok_or_else(|| {
+                ErrorCode::UndefinedVariable.error(format!(
+                    "mixin '{mixin}' must be a class declared before '{name}'"
+                ))
+            })?;
+
+            for method in mixed {
+                if let Some(owner) = defined.insert(method.clone(), mixin.clone()) {
+                    return Err(ErrorCode::TypeMismatch.error(format!(
+                        "'{}' from mixin '{mixin}' conflicts with the one in '{owner}'",
+                        method.1
+                    )));
+                }
+            }
+
+            self.identifier(mixin);
+            self.emit_inst(Instruction::Mixin);
+        }
+
+        self.symbols
+            .classes
+            .insert(self.global(name), defined.into_keys().collect());
+
+        Ok(())
+    }
+
+    /// The instance methods, getters and setters of the class `name`,
+    /// declared earlier in this script or by one that has already run.
+    fn class_methods(&self, name: &str) -> Option<Vec<(FunKind, String)>> {
+        let global = self.global(name);
+
+        if let Some(methods) = self.symbols.classes.get(&global) {
+            return Some(methods.clone());
+        }
+
+        let class = match self.state.contains(&global) {
+            true => self.state.get::<Value>(&global).ok()?,
+            false => return None,
+        };
+        let Value::Class(class) = class else {
+            return None;
+        };
+        let tables = [
+            (FunKind::Method, &class.methods),
+            (FunKind::Getter, &class.getters),
+            (FunKind::Setter, &class.setters),
+        ];
+
+        Some(
+            tables
+                .into_iter()
+                .flat_map(|(kind, table)| {
+                    table
+                        .borrow()
+                        .keys()
+                        .map(|name| (kind, name.to_string()))
+                        .collect::<Vec<_>>()
+                })
+                .collect(),
+        )
+    }
+
+    /// Compile a trait declaration, which defines a global holding the trait
+    /// as a constant.
+    fn trait_decl(&mut self, name: &str, methods: &[(String, usize)]) -> Result<(), String> {
+        let methods = methods
+            .iter()
+            .map(|(method, arity)| (method.as_str().into(), *arity))
+            .collect();
+        let index = self
+            .chunk
+            .add_constant(Value::Trait(Rc::new(Trait::new(name, methods))));
+
+        self.emit_inst(Instruction::Constant { index });
+
+        let slot = self.declare(name);
+        self.emit_inst(Instruction::DefineGlobal { slot });
+
+        Ok(())
+    }
+
+    /// Add `methods` to the class on top of the stack.
+    fn methods(&mut self, methods: &[FunDecl]) -> Result<(), String> {
+        for method in methods {
+            let fun = self.function(method)?;
+            let name = self.chunk.add_variable(method.name.as_str());
+
+            self.decorated(method, fun)?;
+
+            self.emit_inst(match method.kind {
+                FunKind::Method | FunKind::Function => Instruction::Method { index: name },
+                FunKind::Static => Instruction::StaticMethod { index: name },
+                FunKind::Getter => Instruction::Getter { index: name },
+                FunKind::Setter => Instruction::Setter { index: name },
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Compile an object literal to a class of its own, followed by the
+    /// values of its fields and the instruction that makes the instance.
+    fn object(
+        &mut self,
+        fields: &[(String, ExprId)],
+        methods: &[FunDecl],
+        work: &mut Vec<Work>,
+    ) -> Result<(), String> {
+        let index = self.chunk.add_variable("object");
+
+        self.emit_inst(Instruction::Class { index });
+
+        for (field, _) in fields {
+            let index = self.chunk.add_variable(field.as_str());
+
+            self.emit_inst(Instruction::Field { index });
+        }
+
+        self.methods(methods)?;
+
+        let mut steps = fields
+            .iter()
+            .map(|(_, init)| Work::Expr(*init))
+            .collect::<Vec<_>>();
+
+        steps.push(Work::Emit(Instruction::Object { len: fields.len() }));
+        schedule(work, steps);
+
+        Ok(())
+    }
+
+    /// Unpack the parameter in `slot` into a local for each name `pattern`
+    /// binds, above the parameters.
+    fn unpack_param(&mut self, slot: usize, pattern: &Destructure) {
+        match pattern {
+            Destructure::Items(names) => {
+                self.emit_inst(Instruction::GetLocal { slot });
+                self.emit_inst(Instruction::Unpack { len: names.len() });
+            }
+            Destructure::Keys(names) => {
+                for name in names {
+                    let index = self.chunk.add_variable(name.as_str());
+
+                    self.emit_inst(Instruction::GetLocal { slot });
+                    self.emit_inst(Instruction::GetKey { index });
+                }
+            }
+        }
+
+        self.locals.extend(pattern.names().iter().cloned());
+    }
+
+    /// Compile a function body into its own chunk.
+    fn function(&mut self, decl: &FunDecl) -> Result<Rc<ScriptFun>, String> {
+        // slot 0 holds the receiver, or the function itself for static methods
+        // and functions.
+        let receiver = match decl.kind {
+            FunKind::Static | FunKind::Function => "",
+            FunKind::Method | FunKind::Getter | FunKind::Setter => "self",
+        };
+        let locals = std::iter::once(receiver.to_string())
+            .chain(decl.params.iter().cloned())
+            .collect();
+
+        let enclosing = mem::take(&mut self.chunk);
+        self.enclosing.push(Enclosing {
+            locals: mem::replace(&mut self.locals, locals),
+            vars: mem::take(&mut self.vars),
+            captured: mem::take(&mut self.captured),
+            upvalues: mem::take(&mut self.upvalues),
+        });
+        let enclosing_in_function = mem::replace(&mut self.in_function, true);
+        let enclosing_yields = mem::replace(&mut self.yields, false);
+        let enclosing_async = mem::replace(&mut self.in_async, decl.is_async);
+        let returns = decl
+            .returns
+            .clone()
+            .map(|ty| (ty, format!("the return value of '{}'", decl.name)));
+        let enclosing_returns = mem::replace(&mut self.returns, returns);
+        let enclosing_tries = mem::take(&mut self.tries);
+        let enclosing_loops = mem::take(&mut self.loops);
+        let enclosing_temporaries = mem::replace(&mut self.temporaries, false);
+
+        for (slot, (param, ty)) in decl.params.iter().zip(&decl.param_types).enumerate() {
+            if let Some(ty) = ty {
+                self.emit_inst(Instruction::GetLocal { slot: slot + 1 });
+                self.check_type(ty, &format!("parameter '{param}' of '{}'", decl.name));
+                self.emit_inst(Instruction::Pop);
+            }
+        }
+
+        for (index, pattern) in &decl.unpack {
+            self.unpack_param(index + 1, pattern);
+        }
+
+        self.check_reachable(&decl.body);
+
+        let result = decl.body.iter().try_for_each(|stmt| self.statement(stmt));
+
+        self.release_vars(0);
+        self.nil();
+        self.check_return();
+        self.emit_inst(Instruction::Return);
+
+        let mut chunk = mem::replace(&mut self.chunk, enclosing);
+        let result = result.and_then(|()| self.check_constants(&chunk));
+
+        if self.options.opt_level == OptLevel::Full {
+            opt::optimize(&mut chunk);
+        }
+        let outer = self.enclosing.pop().expect("the enclosing function");
+        self.locals = outer.locals;
+        self.vars = outer.vars;
+        self.captured = outer.captured;
+        let captures = mem::replace(&mut self.upvalues, outer.upvalues);
+        self.in_function = enclosing_in_function;
+        self.in_async = enclosing_async;
+        self.returns = enclosing_returns;
+        self.tries = enclosing_tries;
+        self.loops = enclosing_loops;
+        self.temporaries = enclosing_temporaries;
+
+        let generator = mem::replace(&mut self.yields, enclosing_yields);
+
+        result?;
+
+        let arity = decl.params.len() - decl.variadic as usize;
+        let fun = ScriptFun::new(decl.name.as_str(), arity, chunk);
+
+        Ok(Rc::new(ScriptFun {
+            variadic: decl.variadic,
+            params: decl
+                .params
+                .iter()
+                .map(|param| param.as_str().into())
+                .collect(),
+            generator,
+            is_async: decl.is_async,
+            doc: decl.doc.as_deref().map(Into::into),
+            captures,
+            ..fun
+        }))
+    }
+
+    fn fun_declaration(&mut self, decl: &FunDecl) -> Result<(), String> {
+        if self.in_function {
+            return Err(ErrorCode::NestedFunction.error(format!(
+                "cannot declare function '{}' inside another function",
+                decl.name
+            )));
+        }
+
+        // A decorated function is replaced by whatever its decorators return,
+        // so its parameters aren't known.
+        if decl.decorators.is_empty() {
+            self.symbols
+                .functions
+                .insert(decl.name.clone(), decl.params.clone());
+        } else {
+            self.symbols.functions.remove(&decl.name);
+        }
+
+        let fun = self.function(decl)?;
+        let slot = self.declare(&decl.name);
+
+        self.decorated(decl, fun)?;
+        self.emit_inst(Instruction::DefineGlobal { slot });
+
+        Ok(())
+    }
+
+    /// Push `fun` passed through the decorators of its declaration.
+    fn decorated(&mut self, decl: &FunDecl, fun: Rc<ScriptFun>) -> Result<(), String> {
+        for decorator in &decl.decorators {
+            self.expr(*decorator)?;
+        }
+
+        self.closure(fun);
+
+        for _ in &decl.decorators {
+            self.emit_inst(Instruction::Call { argc: 1 });
+        }
+
+        Ok(())
+    }
+
+    /// Push `fun`, making a closure of it if it uses the variables of the
+    /// functions enclosing it.
+    fn closure(&mut self, fun: Rc<ScriptFun>) {
+        let captures = !fun.captures.is_empty();
+        let index = self.chunk.add_constant(Value::ScriptFun(fun));
+
+        if captures {
+            self.emit_inst(Instruction::MakeClosure { index });
+        } else {
+            self.emit_inst(Instruction::Constant { index });
+        }
+    }
+
+    /// Check that the value on top of the stack has the type `ty` when the
+    /// code runs, describing it as `what` if it doesn't.
+    fn check_type(&mut self, ty: &str, what: &str) {
+        let ty = self.chunk.add_variable(ty);
+        let what = self.chunk.add_variable(what);
+
+        self.emit_inst(Instruction::CheckType { ty, what });
+    }
+
+    /// Check the value about to be returned against the function's return
+    /// type, if it has one.
+    fn check_return(&mut self) {
+        if let Some((ty, what)) = self.returns.clone() {
+            self.check_type(&ty, &what);
+        }
+    }
+
+    fn var_declartion(&mut self, id: &str, ty: Option<&str>, init: ExprId) -> Result<(), String> {
+        self.check_not_constant(id)?;
+        self.expr(init)?;
+
+        if let Some(ty) = ty {
+            self.check_type(ty, &format!("'{id}'"));
+        }
+
+        // Variables declared in a function or block live in a local slot
+        // until the end of the block.
+        if !self.at_top_level() {
+            let span = self.ast.span(init);
+
+            if self.resolve_local(id).is_some() {
+                self.warn(
+                    ErrorCode::ShadowedName,
+                    format!("'{id}' shadows a variable declared before it"),
+                    span,
+                );
+            }
+
+            self.vars.push(Var {
+                slot: self.locals.len(),
+                name: id.into(),
+                span,
+                read: false,
+                assigned: false,
+            });
+            self.locals.push(id.into());
+            return Ok(());
+        }
+
+        let slot = self.declare(id);
+        self.emit_inst(Instruction::DefineGlobal { slot });
+
+        Ok(())
+    }
+
+    fn const_declaration(
+        &mut self,
+        id: &str,
+        ty: Option<&str>,
+        init: ExprId,
+    ) -> Result<(), String> {
+        self.immutable_declaration(id, ty, init)?;
+        self.constants.push(self.global(id));
+
+        Ok(())
+    }
+
+    fn let_declaration(&mut self, id: &str, ty: Option<&str>, init: ExprId) -> Result<(), String> {
+        self.immutable_declaration(id, ty, init)?;
+        self.immutables.push(self.global(id));
+
+        Ok(())
+    }
+
+    /// Define a global that can't be reassigned, so reads of it can be
+    /// replaced with its initializer if that is known at compile time.
+    fn immutable_declaration(
+        &mut self,
+        id: &str,
+        ty: Option<&str>,
+        init: ExprId,
+    ) -> Result<(), String> {
+        self.check_not_constant(id)?;
+
+        let folded = self.const_eval(init);
+
+        match &folded {
+            Some(lit) => self.literal(lit),
+            None => self.expr(init)?,
+        }
+
+        if let Some(ty) = ty {
+            self.check_type(ty, &format!("'{id}'"));
+        }
+
+        let slot = self.declare(id);
+        self.emit_inst(Instruction::DefineGlobal { slot });
+
+        if let Some(lit) = folded {
+            self.symbols.folded.insert(id.into(), lit);
+        }
+
+        Ok(())
+    }
+
+    /// Evaluate `expr` if its value is known at compile time, which it is
+    /// when it only does arithmetic on literals and folded constants, and
+    /// the arithmetic succeeds. Errors are left to be raised at runtime.
+    fn const_eval(&self, expr: ExprId) -> Option<Lit> {
+        let ast = Rc::clone(&self.ast);
+        let mut work = vec![Work::Expr(expr)];
+        let mut values = Vec::new();
+
+        // Like [Compiler::expr], this goes by an explicit work stack so a
+        // long chain of operators can't overflow the native stack.
+        while let Some(step) = work.pop() {
+            match step {
+                Work::Expr(expr) => match &ast[expr] {
+                    Expr::Literal(lit) => values.push(lit_value(lit)?),
+                    Expr::Identifier(name) if self.resolve_local(name).is_none() => {
+                        values.push(lit_value(self.symbols.folded.get(name)?)?)
+                    }
+                    Expr::BinExpr { op, left, right } => {
+                        let inst = opt::binary_instruction(op)?;
+
+                        schedule(
+                            &mut work,
+                            vec![Work::Expr(*left), Work::Expr(*right), Work::Emit(inst)],
+                        );
+                    }
+                    Expr::UnaryExpr { op, value } => {
+                        let inst = match op {
+                            BinOp::Sub => Instruction::Neg,
+                            BinOp::Bang => Instruction::Not,
+                            _ => return None,
+                        };
+
+                        schedule(&mut work, vec![Work::Expr(*value), Work::Emit(inst)]);
+                    }
+                    _ => return None,
+                },
+                Work::Emit(inst @ (Instruction::Neg | Instruction::Not)) => {
+                    let value = values.pop()?;
+
+                    values.push(vm::unary(inst, value).ok()?);
+                }
+                Work::Emit(inst) => {
+                    let b = values.pop()?;
+                    let a = values.pop()?;
+
+                    values.push(vm::binary(inst, a, b).ok()?);
+                }
+                Work::ShortCircuit { .. } | Work::Land => return None,
+            }
+        }
+
+        value_lit(values.pop()?)
+    }
+
+    fn check_not_constant(&self, name: &str) -> Result<(), String> {
+        let global = self.global(name);
+
+        if self.state.is_constant(&global) || self.constants.contains(&global) {
+            Err(ErrorCode::AssignToConstant.error(format!("cannot assign to constant '{name}'")))
+        } else if self.state.is_immutable(&global) || self.immutables.contains(&global) {
+            Err(ErrorCode::AssignToConstant.error(format!(
+                "cannot assign to '{name}', which is declared with let"
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn import(&mut self, path: &str, name: &str) -> Result<(), String> {
+        if !self.at_top_level() {
+            return Err(ErrorCode::Import.error("modules can only be imported at the top level"));
+        }
+
+        let module = self.state.module(path).ok_or_else(|| {
+            ErrorCode::Import.error(format!("module \"{path}\" has not been loaded"))
+        })?;
+
+        self.check_not_constant(name)?;
+
+        let constant = self.chunk.add_constant(Value::Module(module));
+        let slot = self.declare(name);
+
+        self.emit_inst(Instruction::Constant { index: constant });
+        self.emit_inst(Instruction::DefineGlobal { slot });
+
+        Ok(())
+    }
+
+    fn export(&mut self, stmt: &Stmt) -> Result<(), String> {
+        if !self.at_top_level() {
+            return Err("declarations can only be exported at the top level".into());
+        }
+
+        self.statement_body(stmt)?;
+
+        if let Some(name) = stmt.declared_name() {
+            self.exports.insert(name.into(), self.global(name).into());
+        }
+
+        Ok(())
+    }
+
+    fn return_stmt(&mut self, value: ExprId) -> Result<(), String> {
+        if !self.in_function {
+            return Err(ErrorCode::ReturnOutsideFunction.error("cannot return from top-level code"));
+        }
+
+        self.expr(value)?;
+        self.check_return();
+
+        // The returned value is kept in a hidden local while the finally
+        // blocks run.
+        self.locals.push(String::new());
+
+        let result = self.leave_tries(0);
+
+        self.locals.pop();
+        result?;
+
+        self.emit_inst(Instruction::Return);
+
+        Ok(())
+    }
+
+    fn yield_stmt(&mut self, value: ExprId) -> Result<(), String> {
+        if !self.in_function {
+            return Err(ErrorCode::YieldOutsideFunction.error("cannot yield from top-level code"));
+        }
+
+        if self.in_async {
+            return Err(
+                ErrorCode::YieldOutsideFunction.error("cannot yield from an async function")
+            );
+        }
+
+        self.expr(value)?;
+        self.emit_inst(Instruction::Yield);
+        self.yields = true;
+
+        Ok(())
+    }
+
+    /// Leave the enclosing try blocks until only `depth` are left, running
+    /// their finally blocks on the way out.
+    fn leave_tries(&mut self, depth: usize) -> Result<(), String> {
+        let tries = self.tries.clone();
+        let mut result = Ok(());
+
+        while self.tries.len() > depth {
+            self.emit_inst(Instruction::EndTry);
+
+            if let Some(Some(finally)) = self.tries.pop() {
+                result = self.statements(&finally);
+
+                if result.is_err() {
+                    break;
+                }
+            }
+        }
+
+        self.tries = tries;
+
+        result
+    }
+
+    fn while_stmt(
+        &mut self,
+        label: Option<&str>,
+        cond: ExprId,
+        body: &[Stmt],
+    ) -> Result<(), String> {
+        let start = self.chunk.instructions.len();
+
+        self.expr(cond)?;
+
+        let exit = self.emit_jump(|offset| Instruction::JumpIfFalse { offset });
+
+        self.loops.push(Loop {
+            label: label.map(String::from),
+            start,
+            breaks: Vec::new(),
+            locals: self.locals.len(),
+            tries: self.tries.len(),
+        });
+
+        let result = self.statements(body);
+        let lp = self.loops.pop().expect("the loop being compiled");
+
+        result?;
+
+        self.emit_loop(start);
+        self.patch_jump(exit);
+
+        for jump in lp.breaks {
+            self.patch_jump(jump);
+        }
+
+        Ok(())
+    }
+
+    /// Compile a for loop.
+    ///
+    /// The iterable and its cursor live in two hidden locals while the loop
+    /// runs, and the loop variable is pushed above them on each iteration.
+    fn for_stmt(
+        &mut self,
+        label: Option<&str>,
+        var: &str,
+        iterable: ExprId,
+        body: &[Stmt],
+    ) -> Result<(), String> {
+        self.expr(iterable)?;
+        self.emit_inst(Instruction::Iter);
+        self.locals.extend([String::new(), String::new()]);
+
+        let slot = self.locals.len() - 2;
+        let start = self.chunk.instructions.len();
+        let exit = self.emit_jump(|offset| Instruction::ForNext { slot, offset });
+
+        self.loops.push(Loop {
+            label: label.map(String::from),
+            start,
+            breaks: Vec::new(),
+            locals: self.locals.len(),
+            tries: self.tries.len(),
+        });
+        self.locals.push(var.into());
+
+        let result = self.statements(body);
+        let lp = self.loops.pop().expect("the loop being compiled");
+
+        // Each iteration's closures capture a variable of their own.
+        self.close_upvalues(lp.locals);
+        self.captured.retain(|slot| *slot < lp.locals);
+        self.locals.truncate(lp.locals);
+        result?;
+
+        self.emit_inst(Instruction::Pop);
+        self.emit_loop(start);
+        self.patch_jump(exit);
+
+        for jump in lp.breaks {
+            self.patch_jump(jump);
+        }
+
+        self.emit_inst(Instruction::Pop);
+        self.emit_inst(Instruction::Pop);
+        self.locals.truncate(slot);
+
+        Ok(())
+    }
+
+    /// Compile `break`, or `continue` if `is_break` is false, leaving the
+    /// try blocks and popping the locals inside the loop it jumps out of.
+    fn jump_stmt(&mut self, label: Option<&str>, is_break: bool) -> Result<(), String> {
+        let keyword = if is_break { "break" } else { "continue" };
+        let index = match label {
+            Some(label) => self
+                .loops
+                .iter()
+                .rposition(|lp| lp.label.as_deref() == Some(label)),
+            None => self.loops.len().checked_sub(1),
+        };
+
+        let Some(index) = index else {
+            return Err(ErrorCode::InvalidJump.error(match label {
+                Some(label) => {
+                    let labels = self.loops.iter().filter_map(|lp| lp.label.as_deref());
+
+                    format!(
+                        "no loop labeled '{label}' encloses this {keyword}{}",
+                        did_you_mean(label, labels)
+                    )
+                }
+                None => format!("cannot {keyword} outside of a loop"),
+            }));
+        };
+
+        self.leave_tries(self.loops[index].tries)?;
+        self.close_upvalues(self.loops[index].locals);
+
+        for _ in self.loops[index].locals..self.locals.len() {
+            self.emit_inst(Instruction::Pop);
+        }
+
+        if is_break {
+            let jump = self.emit_jump(|offset| Instruction::Jump { offset });
+
+            self.loops[index].breaks.push(jump);
+        } else {
+            self.emit_loop(self.loops[index].start);
+        }
+
+        Ok(())
+    }
+
+    /// Compile a try statement.
+    ///
+    /// The finally block is compiled inline on every way out of the
+    /// statement: after the body or the catch clause, before a return from
+    /// either, and in a handler that rethrows any error they raise.
+    fn try_stmt(
+        &mut self,
+        body: &[Stmt],
+        catch: Option<&Catch>,
+        finally: Option<&[Stmt]>,
+    ) -> Result<(), String> {
+        let handler = self.emit_jump(|offset| Instruction::Try { offset });
+
+        self.guarded(body, finally)?;
+        self.emit_inst(Instruction::EndTry);
+        self.statements(finally.unwrap_or_default())?;
+
+        let end = self.emit_jump(|offset| Instruction::Jump { offset });
+        self.patch_jump(handler);
+
+        match (catch, finally) {
+            (Some(catch), None) => self.catch(catch, None)?,
+            (Some(catch), Some(finally)) => {
+                let handler = self.emit_jump(|offset| Instruction::Try { offset });
+
+                self.catch(catch, Some(finally))?;
+                self.emit_inst(Instruction::EndTry);
+                self.statements(finally)?;
+
+                let caught = self.emit_jump(|offset| Instruction::Jump { offset });
+
+                // The error caught first is still on the stack below the
+                // one raised by the catch clause.
+                self.patch_jump(handler);
+                self.locals.push(String::new());
+
+                let result = self.rethrow(finally);
+
+                self.locals.pop();
+                result?;
+                self.patch_jump(caught);
+            }
+            (None, Some(finally)) => self.rethrow(finally)?,
+            (None, None) => unreachable!("a try statement needs a catch or finally"),
+        }
+
+        self.patch_jump(end);
+
+        Ok(())
+    }
+
+    /// Compile statements inside a try block with the finally block `finally`.
+    fn guarded(&mut self, stmts: &[Stmt], finally: Option<&[Stmt]>) -> Result<(), String> {
+        self.tries.push(finally.map(<[Stmt]>::to_vec));
+
+        let result = self.statements(stmts);
+
+        self.tries.pop();
+
+        result
+    }
+
+    /// Compile a catch clause, binding the error on top of the stack to its
+    /// name for the duration of the body.
+    ///
+    /// A clause limited to a class raises any other error again, which runs
+    /// `finally` on the way out if there is one.
+    fn catch(&mut self, catch: &Catch, finally: Option<&[Stmt]>) -> Result<(), String> {
+        self.locals.push(catch.name.clone());
+
+        let other = catch.class.as_deref().map(|class| {
+            self.identifier(&catch.name);
+            self.identifier(class);
+            self.emit_inst(Instruction::IsInstance);
+            self.emit_jump(|offset| Instruction::JumpIfFalse { offset })
+        });
+
+        let result = match finally {
+            Some(_) => self.guarded(&catch.body, finally),
+            None => self.statements(&catch.body),
+        };
+
+        self.end_scope(self.locals.len() - 1);
+
+        if let Some(other) = other {
+            let end = self.emit_jump(|offset| Instruction::Jump { offset });
+
+            self.patch_jump(other);
+            self.emit_inst(Instruction::Throw);
+            self.patch_jump(end);
+        }
+
+        result
+    }
+
+    /// Run `finally` with the error on top of the stack kept in a hidden
+    /// local, then raise the error again.
+    fn rethrow(&mut self, finally: &[Stmt]) -> Result<(), String> {
+        self.locals.push(String::new());
+
+        let result = self.statements(finally);
+
+        self.locals.pop();
+        result?;
+
+        self.emit_inst(Instruction::Throw);
+
+        Ok(())
+    }
+
+    /// Compile a match into a chain of pattern tests, each jumping to the
+    /// next arm when it fails.
+    ///
+    /// The matched value lives in a hidden local while the arms run, and each
+    /// arm's bindings are pushed above it as locals once its tests pass.
+    fn match_stmt(&mut self, value: ExprId, arms: &[MatchArm]) -> Result<(), String> {
+        self.expr(value)?;
+        self.locals.push(String::new());
+
+        let slot = self.locals.len() - 1;
+        let mut ends = Vec::new();
+
+        for arm in arms {
+            let mut fails = Vec::new();
+
+            self.pattern_tests(&arm.pattern, slot, &mut Vec::new(), &mut fails);
+
+            let bindings = self.locals.len();
+            self.pattern_bindings(&arm.pattern, slot, &mut Vec::new());
+
+            self.check_reachable(&arm.body);
+
+            let result = arm.body.iter().try_for_each(|stmt| self.statement(stmt));
+
+            self.end_scope(bindings);
+            result?;
+
+            ends.push(self.emit_jump(|offset| Instruction::Jump { offset }));
+
+            for fail in fails {
+                self.patch_jump(fail);
+            }
+        }
+
+        for end in ends {
+            self.patch_jump(end);
+        }
+
+        self.emit_inst(Instruction::Pop);
+        self.locals.pop();
+
+        Ok(())
+    }
+
+    /// Compile a switch, using a jump table when the cases are dense integers
+    /// and a chain of comparisons otherwise.
+    fn switch_stmt(
+        &mut self,
+        value: ExprId,
+        cases: &[SwitchCase],
+        default: Option<&[Stmt]>,
+    ) -> Result<(), String> {
+        let mut seen = Vec::new();
+
+        for lit in cases.iter().flat_map(|case| &case.values) {
+            if seen.contains(&lit) {
+                let lit = match lit {
+                    Lit::String(s) => format!("\"{s}\""),
+                    Lit::Integer(v) => v.to_string(),
+                    lit => format!("{lit:?}"),
+                };
+
+                return Err(ErrorCode::DuplicateCase.error(format!("duplicate switch case {lit}")));
+            }
+
+            seen.push(lit);
+        }
+
+        let integers = seen
+            .iter()
+            .map(|lit| match lit {
+                Lit::Integer(v) => Some(*v),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+
+        match integers.as_deref().and_then(table_range) {
+            Some(min) => self.switch_table(value, cases, default, min),
+            None => self.switch_compare(value, cases, default),
+        }
+    }
+
+    fn switch_table(
+        &mut self,
+        value: ExprId,
+        cases: &[SwitchCase],
+        default: Option<&[Stmt]>,
+        min: i64,
+    ) -> Result<(), String> {
+        self.expr(value)?;
+
+        let index = self.chunk.add_table(JumpTable::default());
+        self.emit_inst(Instruction::JumpTable { index });
+
+        let jump = self.chunk.instructions.len() - 1;
+        let mut targets = Vec::new();
+        let mut ends = Vec::new();
+
+        for case in cases {
+            let target = self.chunk.instructions.len() - jump - 1;
+
+            for lit in &case.values {
+                if let Lit::Integer(v) = lit {
+                    let i = (v - min) as usize;
+
+                    if targets.len() <= i {
+                        targets.resize(i + 1, None);
+                    }
+
+                    targets[i] = Some(target);
+                }
+            }
+
+            self.statements(&case.body)?;
+            ends.push(self.emit_jump(|offset| Instruction::Jump { offset }));
+        }
+
+        let fallback = self.chunk.instructions.len() - jump - 1;
+
+        self.statements(default.unwrap_or_default())?;
+
+        for end in ends {
+            self.patch_jump(end);
+        }
+
+        self.chunk.tables[index] = JumpTable {
+            min,
+            targets: targets
+                .into_iter()
+                .map(|target| target.unwrap_or(fallback))
+                .collect(),
+            default: fallback,
+        };
+
+        Ok(())
+    }
+
+    /// Compile a switch into a comparison against each case value in turn,
+    /// keeping the switched value in a hidden local.
+    fn switch_compare(
+        &mut self,
+        value: ExprId,
+        cases: &[SwitchCase],
+        default: Option<&[Stmt]>,
+    ) -> Result<(), String> {
+        self.expr(value)?;
+        self.locals.push(String::new());
+
+        let slot = self.locals.len() - 1;
+        let mut ends = Vec::new();
+
+        for case in cases {
+            let mut matched = Vec::new();
+            let mut fail = None;
+
+            for lit in &case.values {
+                if let Some(fail) = fail.take() {
+                    matched.push(self.emit_jump(|offset| Instruction::Jump { offset }));
+                    self.patch_jump(fail);
+                }
+
+                self.emit_inst(Instruction::GetLocal { slot });
+                self.literal(lit);
+                self.emit_inst(Instruction::Equal);
+                fail = Some(self.emit_jump(|offset| Instruction::JumpIfFalse { offset }));
+            }
+
+            for jump in matched {
+                self.patch_jump(jump);
+            }
+
+            self.statements(&case.body)?;
+            ends.push(self.emit_jump(|offset| Instruction::Jump { offset }));
+
+            if let Some(fail) = fail {
+                self.patch_jump(fail);
+            }
+        }
+
+        self.statements(default.unwrap_or_default())?;
+
+        for end in ends {
+            self.patch_jump(end);
+        }
+
+        self.emit_inst(Instruction::Pop);
+        self.locals.pop();
+
+        Ok(())
+    }
+
+    /// Compile `stmts` as a block, popping the variables declared in it at
+    /// the end.
+    fn statements(&mut self, stmts: &[Stmt]) -> Result<(), String> {
+        let scope = self.locals.len();
+
+        self.check_reachable(stmts);
+
+        let result = stmts.iter().try_for_each(|stmt| self.statement(stmt));
+
+        self.end_scope(scope);
+
+        result
+    }
+
+    /// Pop the locals declared since there were `scope` of them.
+    fn end_scope(&mut self, scope: usize) {
+        self.release_vars(scope);
+        self.close_upvalues(scope);
+        self.captured.retain(|slot| *slot < scope);
+
+        for _ in scope..self.locals.len() {
+            self.emit_inst(Instruction::Pop);
+        }
+
+        self.locals.truncate(scope);
+    }
+
+    /// Emit the tests for `pattern` against the part of the local `slot` at
+    /// `path`, jumping away if any of them fail.
+    fn pattern_tests(
+        &mut self,
+        pattern: &Pattern,
+        slot: usize,
+        path: &mut Vec<usize>,
+        fails: &mut Vec<usize>,
+    ) {
+        match pattern {
+            Pattern::Wildcard | Pattern::Binding(_) => {}
+            Pattern::Literal(lit) => {
+                self.pattern_value(slot, path);
+                self.literal(lit);
+                self.emit_inst(Instruction::Equal);
+                fails.push(self.emit_jump(|offset| Instruction::JumpIfFalse { offset }));
+            }
+            Pattern::Class { name, fields } => {
+                self.pattern_value(slot, path);
+                self.identifier(name);
+                self.emit_inst(Instruction::IsInstance);
+                fails.push(self.emit_jump(|offset| Instruction::JumpIfFalse { offset }));
+
+                for (i, field) in fields.iter().enumerate() {
+                    path.push(i);
+                    self.pattern_tests(field, slot, path, fails);
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    /// Push the values bound by `pattern` as new locals.
+    fn pattern_bindings(&mut self, pattern: &Pattern, slot: usize, path: &mut Vec<usize>) {
+        match pattern {
+            Pattern::Binding(name) => {
+                self.pattern_value(slot, path);
+                self.locals.push(name.clone());
+            }
+            Pattern::Class { fields, .. } => {
+                for (i, field) in fields.iter().enumerate() {
+                    path.push(i);
+                    self.pattern_bindings(field, slot, path);
+                    path.pop();
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) => {}
+        }
+    }
+
+    /// Push the field at `path` inside the local `slot`.
+    fn pattern_value(&mut self, slot: usize, path: &[usize]) {
+        self.emit_inst(Instruction::GetLocal { slot });
+
+        for &slot in path {
+            self.emit_inst(Instruction::GetField { slot });
+        }
+    }
+
+    fn expression(&mut self, expr: ExprId) -> Result<(), String> {
+        self.expr(expr)?;
+
+        self.emit_inst(Instruction::Pop);
+
+        Ok(())
+    }
+
+    /// Compile an expression, leaving its value on the stack.
+    ///
+    /// Sub-expressions are compiled from an explicit work stack rather than
+    /// by recursion, so long chains of operators, calls or properties can't
+    /// overflow the native stack.
+    fn expr(&mut self, expr: ExprId) -> Result<(), String> {
+        let ast = Rc::clone(&self.ast);
+
+        self.span = ast.span(expr);
+
+        // An if or block that is the whole expression runs with nothing
+        // above the locals, so any statement can be compiled in it.
+        if is_statement_like(&ast[expr]) {
+            return self.value_expr(&ast[expr]);
+        }
+
+        let mut work = vec![Work::Expr(expr)];
+        // The jumps of the `&&` and `||` being compiled, innermost last.
+        let mut jumps = Vec::new();
+
+        while let Some(step) = work.pop() {
+            match step {
+                Work::Expr(expr) => {
+                    self.span = ast.span(expr);
+                    self.lower(&ast[expr], &mut work)?
+                }
+                Work::Emit(inst) => self.emit_inst(inst),
+                Work::ShortCircuit { or: true } => {
+                    jumps.push(self.emit_jump(|offset| Instruction::JumpIfTrueOrPop { offset }))
+                }
+                Work::ShortCircuit { or: false } => {
+                    jumps.push(self.emit_jump(|offset| Instruction::JumpIfFalseOrPop { offset }))
+                }
+                Work::Land => self.patch_jump(jumps.pop().expect("a jump to land")),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compile the leaves of `expr` directly, and schedule its
+    /// sub-expressions and the instructions that follow them on `work`.
+    fn lower(&mut self, expr: &Expr, work: &mut Vec<Work>) -> Result<(), String> {
+        match expr {
+            Expr::Dot { object, property } => self.dot_expr(*object, *property, work),
+            Expr::Literal(lit) => self.literal(lit),
+            Expr::BinExpr {
+                op: BinOp::Custom(symbol),
+                left,
+                right,
+            } => self.custom_operator(symbol, *left, *right, work)?,
+            Expr::BinExpr {
+                op: op @ (BinOp::And | BinOp::Or),
+                left,
+                right,
+            } => schedule(
+                work,
+                vec![
+                    Work::Expr(*left),
+                    Work::ShortCircuit {
+                        or: *op == BinOp::Or,
+                    },
+                    Work::Expr(*right),
+                    Work::Land,
+                ],
+            ),
+            Expr::BinExpr { op, left, right } => self.binary_expr(op, *left, *right, work),
+            Expr::Identifier(ident) => self.identifier(ident),
+            Expr::Slf => self.slf()?,
+            Expr::Call {
+                callee,
+                args,
+                named,
+            } => self.call(*callee, args, named, work)?,
+            Expr::Assign { target, value } => self.assign(*target, *value, work)?,
+            Expr::Increment {
+                target,
+                op,
+                postfix,
+            } => self.increment(*target, op, *postfix, work)?,
+            Expr::List(items) => self.list(items, work),
+            Expr::Map(entries) => self.map(entries, work),
+            Expr::Spread(_) => {
+                return Err(ErrorCode::Syntax
+                    .error("'...' can only spread call arguments and list or map items"))
+            }
+            Expr::Template { tag, parts, values } => self.template(tag, parts, values, work)?,
+            Expr::TypeOf(value) => schedule(
+                work,
+                vec![Work::Expr(*value), Work::Emit(Instruction::TypeOf)],
+            ),
+            Expr::Await(value) => {
+                if !self.in_async {
+                    return Err(ErrorCode::AwaitOutsideAsync
+                        .error("cannot await outside of an async function"));
+                }
+
+                schedule(
+                    work,
+                    vec![Work::Expr(*value), Work::Emit(Instruction::Await)],
+                )
+            }
+            Expr::Function(decl) => {
+                let fun = self.function(decl)?;
+
+                self.closure(fun);
+            }
+            Expr::Is { value, class } => schedule(
+                work,
+                vec![
+                    Work::Expr(*value),
+                    Work::Expr(*class),
+                    Work::Emit(Instruction::IsInstance),
+                ],
+            ),
+            Expr::Range { start, end, step } => {
+                let mut steps = vec![Work::Expr(*start), Work::Expr(*end)];
+
+                steps.extend(step.map(Work::Expr));
+                steps.push(Work::Emit(Instruction::Range {
+                    step: step.is_some(),
+                }));
+                schedule(work, steps)
+            }
+            Expr::Object { fields, methods } => self.object(fields, methods, work)?,
+            Expr::If { .. } | Expr::Block(_) => {
+                let temporaries = mem::replace(&mut self.temporaries, true);
+                let result = self.value_expr(expr);
+
+                self.temporaries = temporaries;
+                result?
+            }
+            Expr::UnaryExpr { value, op } => self.unary_expr(op, *value, work),
+        }
+
+        Ok(())
+    }
+
+    fn binary_expr(&mut self, op: &BinOp, left: ExprId, right: ExprId, work: &mut Vec<Work>) {
+        let inst = opt::binary_instruction(op).expect("a binary operator with an instruction");
+
+        schedule(
+            work,
+            vec![Work::Expr(left), Work::Expr(right), Work::Emit(inst)],
+        );
+    }
+
+    fn unary_expr(&mut self, op: &BinOp, value: ExprId, work: &mut Vec<Work>) {
+        // A negated number literal is pushed already negated.
+        match (op, &self.ast[value]) {
+            (BinOp::Sub, Expr::Literal(Lit::Integer(v))) if *v != i64::MIN => {
+                return self.integer(-v)
+            }
+            (BinOp::Sub, Expr::Literal(Lit::Float(v))) => return self.float(-v),
+            _ => {}
+        }
+
+        let inst = match op {
+            BinOp::Sub => Instruction::Neg,
+            BinOp::Bang => Instruction::Not,
+            // `+x` leaves a number as it is, so it only checks that it is one.
+            BinOp::Add => Instruction::CheckType {
+                ty: self.chunk.add_variable("Number"),
+                what: self.chunk.add_variable("the operand of unary +"),
+            },
+            _ => unreachable!("not a unary operator"),
+        };
+
+        schedule(work, vec![Work::Expr(value), Work::Emit(inst)]);
+    }
+
+    /// Compile a custom operator to a call of its handler.
+    fn custom_operator(
+        &mut self,
+        symbol: &str,
+        left: ExprId,
+        right: ExprId,
+        work: &mut Vec<Work>,
+    ) -> Result<(), String> {
+        let operator = self
+            .options
+            .operators
+            .get(symbol)
+            .ok_or_else(|| ErrorCode::Syntax.error(format!("unknown operator '{symbol}'")))?;
+        let index = self
+            .chunk
+            .add_constant(Value::Function(Rc::clone(&operator.handler)));
+
+        schedule(
+            work,
+            vec![
+                Work::Emit(Instruction::Constant { index }),
+                Work::Expr(left),
+                Work::Expr(right),
+                Work::Emit(Instruction::Call { argc: 2 }),
+            ],
+        );
+
+        Ok(())
+    }
+
+    /// Compile a tagged template to a call of its tag with a list of its
+    /// text and a list of its values.
+    fn template(
+        &mut self,
+        tag: &str,
+        parts: &[String],
+        values: &[ExprId],
+        work: &mut Vec<Work>,
+    ) -> Result<(), String> {
+        let handler = self.options.tags.get(tag).ok_or_else(|| {
+            ErrorCode::UndefinedVariable.error(format!("undefined template tag '{tag}'"))
+        })?;
+        let index = self.chunk.add_constant(Value::Function(Rc::clone(handler)));
+
+        self.emit_inst(Instruction::Constant { index });
+
+        for part in parts {
+            self.string(part);
+        }
+
+        self.emit_inst(Instruction::List { len: parts.len() });
+
+        let mut steps: Vec<_> = values.iter().copied().map(Work::Expr).collect();
+
+        steps.push(Work::Emit(Instruction::List { len: values.len() }));
+        steps.push(Work::Emit(Instruction::Call { argc: 2 }));
+
+        schedule(work, steps);
+
+        Ok(())
+    }
+
+    fn dot_expr(&mut self, object: ExprId, property: ExprId, work: &mut Vec<Work>) {
+        let mut steps = vec![Work::Expr(object)];
+
+        if let Expr::Identifier(name) = &self.ast[property] {
+            let index = self.chunk.add_variable(name.to_string());
+            steps.push(Work::Emit(Instruction::GetProperty { index }));
+        }
+
+        schedule(work, steps);
+    }
+
+    fn assign(
+        &mut self,
+        target: ExprId,
+        value: ExprId,
+        work: &mut Vec<Work>,
+    ) -> Result<(), String> {
+        let ast = Rc::clone(&self.ast);
+
+        match &ast[target] {
+            Expr::Identifier(name) => {
+                let inst = self.set_variable(name)?;
+
+                schedule(work, vec![Work::Expr(value), Work::Emit(inst)]);
+            }
+            Expr::Dot { object, property } => {
+                let Expr::Identifier(name) = &self.ast[*property] else {
+                    return Err(ErrorCode::InvalidAssignment.error("expected a property name"));
+                };
+
+                let index = self.chunk.add_variable(name.as_str());
+
+                schedule(
+                    work,
+                    vec![
+                        Work::Expr(*object),
+                        Work::Expr(value),
+                        Work::Emit(Instruction::SetProperty { index }),
+                    ],
+                );
+            }
+            _ => return Err(ErrorCode::InvalidAssignment.error("invalid assignment target")),
+        }
+
+        Ok(())
+    }
+
+    /// Compile an increment or decrement of `target`. The old value is kept
+    /// under the new one while it is stored if `postfix` is set, and the
+    /// object of a property is copied rather than evaluated again.
+    fn increment(
+        &mut self,
+        target: ExprId,
+        op: &BinOp,
+        postfix: bool,
+        work: &mut Vec<Work>,
+    ) -> Result<(), String> {
+        let ast = Rc::clone(&self.ast);
+        let inst = opt::binary_instruction(op).expect("an increment to add or subtract");
+        let mut steps = Vec::new();
+
+        match &ast[target] {
+            Expr::Identifier(name) => {
+                let set = self.set_variable(name)?;
+
+                self.identifier(name);
+
+                if postfix {
+                    steps.push(Work::Emit(Instruction::Dup { depth: 0 }));
+                }
+
+                steps.extend([
+                    Work::Emit(Instruction::integer(1)),
+                    Work::Emit(inst),
+                    Work::Emit(set),
+                ]);
+            }
+            Expr::Dot { object, property } => {
+                let Expr::Identifier(name) = &ast[*property] else {
+                    return Err(ErrorCode::InvalidAssignment.error("expected a property name"));
+                };
+                let index = self.chunk.add_variable(name.as_str());
+
+                steps.extend([
+                    Work::Expr(*object),
+                    Work::Emit(Instruction::Dup { depth: 0 }),
+                    Work::Emit(Instruction::GetProperty { index }),
+                ]);
+
+                // Leave the old value under the object: `old object old`.
+                if postfix {
+                    steps.extend([
+                        Work::Emit(Instruction::Swap),
+                        Work::Emit(Instruction::Dup { depth: 1 }),
+                    ]);
+                }
+
+                steps.extend([
+                    Work::Emit(Instruction::integer(1)),
+                    Work::Emit(inst),
+                    Work::Emit(Instruction::SetProperty { index }),
+                ]);
+            }
+            _ => {
+                return Err(
+                    ErrorCode::InvalidAssignment.error("invalid increment or decrement target")
+                )
+            }
+        }
+
+        if postfix {
+            steps.push(Work::Emit(Instruction::Pop));
+        }
+
+        schedule(work, steps);
+
+        Ok(())
+    }
+
+    /// The instruction that sets the variable `name` to the value on top of
+    /// the stack.
+    fn set_variable(&mut self, name: &str) -> Result<Instruction, String> {
+        if let Some(slot) = self.resolve_local(name) {
+            self.access_local(slot, false);
+            return Ok(Instruction::SetLocal { slot });
+        }
+
+        if let Some(index) = self.resolve_upvalue(name) {
+            return Ok(Instruction::SetUpvalue { index });
+        }
+
+        self.check_not_constant(name)?;
+
+        let slot = if self.options.implicit_globals {
+            self.implicit(name)
+        } else {
+            self.refer(name)
+        };
+
+        Ok(Instruction::SetGlobal { slot })
+    }
+
+    /// Compile an assignment to several targets. Every value is pushed, or
+    /// the single value unpacked, before the targets are assigned from the
+    /// top of the stack down, so the last target is assigned first.
+    fn multiple_assignment(&mut self, targets: &[ExprId], values: &[ExprId]) -> Result<(), String> {
+        for value in values {
+            self.expr(*value)?;
+        }
+
+        if values.len() == 1 {
+            self.emit_inst(Instruction::Unpack { len: targets.len() });
+        }
+
+        let ast = Rc::clone(&self.ast);
+
+        for target in targets.iter().rev() {
+            match &ast[*target] {
+                Expr::Identifier(name) => {
+                    let inst = self.set_variable(name)?;
+
+                    self.emit_inst(inst);
+                }
+                Expr::Dot { object, property } => {
+                    let Expr::Identifier(name) = &ast[*property] else {
+                        return Err(ErrorCode::InvalidAssignment.error("expected a property name"));
+                    };
+                    let index = self.chunk.add_variable(name.as_str());
+
+                    self.expr(*object)?;
+                    self.emit_inst(Instruction::Swap);
+                    self.emit_inst(Instruction::SetProperty { index });
+                }
+                _ => return Err(ErrorCode::InvalidAssignment.error("invalid assignment target")),
+            }
+
+            self.emit_inst(Instruction::Pop);
+        }
+
+        Ok(())
+    }
+
+    fn list(&mut self, items: &[ExprId], work: &mut Vec<Work>) {
+        let steps = self.spread_list(items);
+
+        schedule(work, steps);
+    }
+
+    /// The steps that build a list of `items`.
+    ///
+    /// The items before the first spread item are collected into the list,
+    /// which is then extended with each spread value and each run of items
+    /// between them in turn.
+    fn spread_list(&self, items: &[ExprId]) -> Vec<Work> {
+        let spread = |item: &ExprId| matches!(self.ast[*item], Expr::Spread(_));
+        let first = items.iter().position(spread).unwrap_or(items.len());
+        let mut steps = items[..first]
+            .iter()
+            .copied()
+            .map(Work::Expr)
+            .collect::<Vec<_>>();
+        let mut rest = &items[first..];
+
+        steps.push(Work::Emit(Instruction::List { len: first }));
+
+        while let Some((item, tail)) = rest.split_first() {
+            if let Expr::Spread(value) = self.ast[*item] {
+                steps.push(Work::Expr(value));
+                rest = tail;
+            } else {
+                let len = rest.iter().position(spread).unwrap_or(rest.len());
+
+                steps.extend(rest[..len].iter().copied().map(Work::Expr));
+                steps.push(Work::Emit(Instruction::List { len }));
+                rest = &rest[len..];
+            }
+
+            steps.push(Work::Emit(Instruction::Extend));
+        }
+
+        steps
+    }
+
+    /// Compile a map literal, built from its spread entries like a list in
+    /// [Compiler::spread_list].
+    fn map(&mut self, entries: &[(String, ExprId)], work: &mut Vec<Work>) {
+        let ast = Rc::clone(&self.ast);
+        let spread = |(_, value): &(String, ExprId)| matches!(ast[*value], Expr::Spread(_));
+        let first = entries.iter().position(spread).unwrap_or(entries.len());
+        let mut steps = self.map_entries(&entries[..first]);
+        let mut rest = &entries[first..];
+
+        while let Some(((_, value), tail)) = rest.split_first() {
+            if let Expr::Spread(value) = ast[*value] {
+                steps.push(Work::Expr(value));
+                rest = tail;
+            } else {
+                let len = rest.iter().position(spread).unwrap_or(rest.len());
+
+                steps.extend(self.map_entries(&rest[..len]));
+                rest = &rest[len..];
+            }
+
+            steps.push(Work::Emit(Instruction::Extend));
+        }
+
+        schedule(work, steps);
+    }
 
-pub struct Compiler<'a> {
-    _state: &'a mut State,
-    chunk: Chunk,
-}
+    /// The steps that collect `entries` into a map.
+    fn map_entries(&mut self, entries: &[(String, ExprId)]) -> Vec<Work> {
+        let mut steps = Vec::new();
 
-impl<'a> Compiler<'a> {
-    pub fn new(state: &'a mut State) -> Self {
-        Self {
-            _state: state,
-            chunk: Chunk::new(),
+        for (key, value) in entries {
+            let index = self.chunk.add_constant(Value::String(key.clone()));
+
+            steps.push(Work::Emit(Instruction::Constant { index }));
+            steps.push(Work::Expr(*value));
         }
-    }
 
-    pub fn emit_inst(&mut self, inst: Instruction) {
-        self.chunk.instructions.push(inst);
+        steps.push(Work::Emit(Instruction::Map { len: entries.len() }));
+        steps
     }
 
-    pub fn emit_return(&mut self) {
-        self.emit_inst(Instruction::Halt);
-    }
+    fn call(
+        &mut self,
+        expr: ExprId,
+        args: &[ExprId],
+        named: &[(String, ExprId)],
+        work: &mut Vec<Work>,
+    ) -> Result<(), String> {
+        self.check_named_args(expr, named)?;
 
-    pub fn run(&mut self, ast: Ast) -> Result<Chunk, String> {
-        for node in ast.nodes {
-            match &node {
-                Stmt::Class { name } => self.class(name),
-                Stmt::VarDecl { id, init } => self.var_declartion(id, init),
-                Stmt::Expr(expr) => self.expression(expr),
+        if args
+            .iter()
+            .any(|arg| matches!(self.ast[*arg], Expr::Spread(_)))
+        {
+            if !named.is_empty() {
+                return Err(ErrorCode::NamedArgument
+                    .error("cannot spread arguments into a call with named arguments"));
             }
+
+            let mut steps = vec![Work::Expr(expr)];
+
+            steps.extend(self.spread_list(args));
+            steps.push(Work::Emit(Instruction::CallSpread));
+            schedule(work, steps);
+
+            return Ok(());
         }
 
-        self.emit_return();
+        let ast = Rc::clone(&self.ast);
+        let method = match &ast[expr] {
+            Expr::Dot { object, property } if named.is_empty() => match &ast[*property] {
+                Expr::Identifier(name) => Some((*object, name)),
+                _ => None,
+            },
+            _ => None,
+        };
 
-        Ok(Chunk {
-            instructions: self.chunk.instructions.clone(),
-            variables: self.chunk.variables.clone(),
-        })
+        if let Some((object, name)) = method {
+            let index = self.chunk.add_variable(name.as_str());
+            let mut steps = vec![Work::Expr(object)];
+
+            steps.extend(args.iter().copied().map(Work::Expr));
+            steps.push(Work::Emit(Instruction::CallMethod {
+                index,
+                hash: Hash::of(name.as_str()),
+                argc: args.len() as u32,
+            }));
+            schedule(work, steps);
+
+            return Ok(());
+        }
+
+        let mut steps = vec![Work::Expr(expr)];
+
+        steps.extend(
+            args.iter()
+                .chain(named.iter().map(|(_, arg)| arg))
+                .copied()
+                .map(Work::Expr),
+        );
+        let argc = args.len() + named.len();
+
+        if named.is_empty() {
+            steps.push(Work::Emit(Instruction::Call { argc }));
+        } else {
+            let names = named
+                .iter()
+                .map(|(name, _)| Value::String(name.clone()))
+                .collect();
+            let names = self.chunk.add_constant(Value::list(names));
+
+            steps.push(Work::Emit(Instruction::CallNamed { argc, names }));
+        }
+
+        schedule(work, steps);
+
+        Ok(())
     }
 
-    fn class(&mut self, name: &str) {
-        let index = self.chunk.add_variable(name);
+    /// Reject named arguments that a statically known function doesn't accept.
+    ///
+    /// Arguments are still matched to parameters at runtime, since the
+    /// callee may be reassigned by the time the call runs.
+    fn check_named_args(&self, callee: ExprId, named: &[(String, ExprId)]) -> Result<(), String> {
+        let Expr::Identifier(name) = &self.ast[callee] else {
+            return Ok(());
+        };
 
-        self.emit_inst(Instruction::Class { index });
-        self.emit_inst(Instruction::DefineGlobal { index });
+        if named.is_empty() || self.resolve_local(name).is_some() {
+            return Ok(());
+        }
 
-        //self.class = Class::new();
+        let params = match self.symbols.functions.get(name) {
+            Some(params) => params.iter().map(String::as_str).collect::<Vec<_>>(),
+            None => return Ok(()),
+        };
 
-        //self.state.add_class(name);
+        match named
+            .iter()
+            .find(|(arg, _)| !params.contains(&arg.as_str()))
+        {
+            Some((arg, _)) => {
+                let hint = did_you_mean(arg, &params);
+
+                Err(ErrorCode::NamedArgument
+                    .error(format!("'{name}' has no parameter named '{arg}'{hint}")))
+            }
+            None => Ok(()),
+        }
     }
 
-    fn var_declartion(&mut self, id: &str, init: &Expr) {
-        self.expr(init);
-        
-        let index = self.chunk.add_variable(id);
-        self.emit_inst(Instruction::DefineGlobal { index });
+    fn identifier(&mut self, name: &str) {
+        if let Some(slot) = self.resolve_local(name) {
+            self.access_local(slot, true);
+            self.emit_inst(Instruction::GetLocal { slot });
+        } else if let Some(index) = self.resolve_upvalue(name) {
+            self.emit_inst(Instruction::GetUpvalue { index });
+        } else if let Some(lit) = self.symbols.folded.get(name).cloned() {
+            self.literal(&lit);
+        } else {
+            let slot = self.refer(name);
+            self.emit_inst(Instruction::GetGlobal { slot });
+        }
     }
 
-    fn expression(&mut self, expr: &Expr) {
-        self.expr(expr);
+    fn slf(&mut self) -> Result<(), String> {
+        match self.resolve_local("self") {
+            Some(slot) => {
+                self.emit_inst(Instruction::GetLocal { slot });
 
-        self.emit_inst(Instruction::Pop);
-    }
+                Ok(())
+            }
+            None if self.resolve_upvalue("self").is_some() => {
+                self.identifier("self");
 
-    fn expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Dot { object, property } => self.dot_expr(object, property),
-            Expr::Literal(lit) => self.literal(lit),
-            Expr::BinExpr { op, left, right } => self.binary_expr(op, left, right),
-            Expr::Identifier(ident) => self.identifier(ident),
-            Expr::Call { callee, .. } => self.call(callee),
-            Expr::UnaryExpr { .. } => todo!(),
+                Ok(())
+            }
+            None => {
+                Err(ErrorCode::SelfOutsideMethod.error("cannot use 'self' outside of a method"))
+            }
         }
     }
 
-    fn binary_expr(&mut self, _op: &BinOp, left: &Expr, right: &Expr) {
-        // PUSH 2
-        self.expr(left);
-        // PUSH 1
-        self.expr(right);
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.locals.iter().rposition(|local| local == name)
+    }
+
+    /// The index of the upvalue of the function being compiled that holds
+    /// `name`, if it is a local of an enclosing function.
+    fn resolve_upvalue(&mut self, name: &str) -> Option<usize> {
+        self.capture(self.enclosing.len(), name)
+    }
+
+    /// Resolve `name` as an upvalue of the function `depth` functions deep,
+    /// adding upvalues to it and the functions between it and the one that
+    /// declares `name` as needed.
+    fn capture(&mut self, depth: usize, name: &str) -> Option<usize> {
+        let outer = depth.checked_sub(1)?;
+        let capture = match self.enclosing[outer]
+            .locals
+            .iter()
+            .rposition(|local| local == name)
+        {
+            Some(slot) => {
+                let function = &mut self.enclosing[outer];
+
+                function.captured.insert(slot);
+
+                // Closures may read the variable whenever they are called.
+                if let Some(var) = function.vars.iter_mut().find(|var| var.slot == slot) {
+                    var.read = true;
+                }
 
-        // add
-        //let hash = Hash::of(op.into_string());
+                Capture {
+                    local: true,
+                    index: slot,
+                }
+            }
+            None => Capture {
+                local: false,
+                index: self.capture(outer, name)?,
+            },
+        };
+
+        let upvalues = match self.enclosing.get_mut(depth) {
+            Some(function) => &mut function.upvalues,
+            None => &mut self.upvalues,
+        };
 
-        // GET_PROP
-        //self.emit_inst(Instruction::GetProperty { name: hash });
+        Some(
+            match upvalues.iter().position(|upvalue| *upvalue == capture) {
+                Some(index) => index,
+                None => {
+                    upvalues.push(capture);
+                    upvalues.len() - 1
+                }
+            },
+        )
+    }
 
-        // CALL
-        self.emit_inst(Instruction::Call);
+    /// Close the upvalues capturing the locals from `scope` on, before they
+    /// are popped.
+    fn close_upvalues(&mut self, scope: usize) {
+        let closing = (scope..self.locals.len()).filter(|slot| self.captured.contains(slot));
 
-        // 1 + 2 -> 1.add(2)
-        // PUSH 2
-        // PUSH 1
-        // OP_GET add
-        // OP_CALL
-        //
-        //self.emit_inst(Instruction::CallInstance { hash, args: 2 });
+        if let Some(slot) = closing.min() {
+            self.emit_inst(Instruction::CloseUpvalue { slot });
+        }
     }
 
-    fn dot_expr(&mut self, object: &Expr, property: &Expr) {
-        self.expr(object);
+    /// Record that the local `slot` is read, or assigned if `read` is false.
+    fn access_local(&mut self, slot: usize, read: bool) {
+        if let Some(var) = self.vars.iter_mut().rev().find(|var| var.slot == slot) {
+            if read {
+                var.read = true;
+            } else {
+                var.assigned = true;
+            }
+        }
+    }
 
-        if let Expr::Identifier(name) = property {
-            let index = self.chunk.add_variable(name.to_string());
-            self.emit_inst(Instruction::GetProperty { index });
+    /// Warn about the variables in the slots from `scope` on that are never
+    /// read, as they go out of scope. Names starting with `_` are exempt.
+    fn release_vars(&mut self, scope: usize) {
+        let start = self.vars.partition_point(|var| var.slot < scope);
+
+        for var in self.vars.split_off(start) {
+            if var.read || var.name.starts_with('_') {
+                continue;
+            }
+
+            let (code, message) = if var.assigned {
+                (
+                    ErrorCode::UnreadAssignment,
+                    format!("'{}' is assigned but never read", var.name),
+                )
+            } else {
+                (
+                    ErrorCode::UnusedVariable,
+                    format!("unused variable '{}'", var.name),
+                )
+            };
+
+            self.warn(code, message, var.span);
         }
     }
 
-    fn call(&mut self, expr: &Expr) {
-        self.expr(expr);
+    /// Warn about the first statement of `stmts` that follows a return,
+    /// break, continue or throw, as it can never run.
+    fn check_reachable(&mut self, stmts: &[Stmt]) {
+        let jump = stmts.iter().position(|stmt| {
+            matches!(
+                stmt,
+                Stmt::Return(_) | Stmt::Break(_) | Stmt::Continue(_) | Stmt::Throw(_)
+            )
+        });
 
-        self.emit_inst(Instruction::Call);
+        if let Some(stmt) = jump.and_then(|jump| stmts.get(jump + 1)) {
+            let span = self.statement_span(stmt);
+
+            self.warn(ErrorCode::UnreachableCode, "unreachable code".into(), span);
+        }
     }
 
-    fn identifier(&mut self, name: &str) {
-        let index = self.chunk.add_variable(name);
-        self.emit_inst(Instruction::GetGlobal { index });
+    /// Where `stmt` starts, going by its first expression, or the expression
+    /// compiled last if it has none, like `break`.
+    fn statement_span(&self, stmt: &Stmt) -> Span {
+        let expr = match stmt {
+            Stmt::VarDecl { init, .. }
+            | Stmt::ConstDecl { init, .. }
+            | Stmt::LetDecl { init, .. } => Some(*init),
+            Stmt::Return(expr) | Stmt::Throw(expr) | Stmt::Yield(expr) | Stmt::Expr(expr) => {
+                Some(*expr)
+            }
+            Stmt::Match { value, .. } | Stmt::Switch { value, .. } => Some(*value),
+            Stmt::While { cond, .. } => Some(*cond),
+            Stmt::For { iterable, .. } => Some(*iterable),
+            Stmt::Assign { targets, .. } => targets.first().copied(),
+            _ => None,
+        };
+
+        expr.map_or(self.span, |expr| self.ast.span(expr))
     }
 
     fn literal(&mut self, lit: &Lit) {
         match lit {
             Lit::Integer(v) => self.integer(*v),
-            Lit::Float(_) => todo!(),
+            Lit::Float(v) => self.float(*v),
             Lit::Char(_) => todo!(),
+            Lit::Bool(v) => self.emit_inst(Instruction::Push {
+                value: InstanceValue::Bool(*v),
+            }),
             Lit::Nil => self.nil(),
-            Lit::String(_) => todo!(),
+            Lit::String(v) => self.string(v),
         }
     }
 
@@ -146,27 +2875,130 @@ impl<'a> Compiler<'a> {
         self.emit_inst(Instruction::integer(v));
     }
 
-    fn _float(&mut self) {}
+    fn float(&mut self, v: f64) {
+        self.emit_inst(Instruction::Push {
+            value: InstanceValue::Float(v),
+        });
+    }
 
-    fn _string(&mut self) {}
+    fn string(&mut self, v: &str) {
+        let index = self.chunk.add_constant(Value::String(v.into()));
+        self.emit_inst(Instruction::Constant { index });
+    }
 
     fn nil(&mut self) {
         self.emit_inst(Instruction::LoadNil);
     }
 }
 
+/// A loop enclosing the code being compiled.
+struct Loop {
+    label: Option<String>,
+    /// The first instruction of the loop's condition, which `continue` jumps to.
+    start: usize,
+    /// The jumps emitted by `break`, which are patched to the end of the loop.
+    breaks: Vec<usize>,
+    /// How many locals there were when the loop started.
+    locals: usize,
+    /// How many try blocks the loop is inside.
+    tries: usize,
+}
+
+/// A variable declared with `var` in a function or block, which is warned
+/// about if it is never read.
+struct Var {
+    slot: usize,
+    name: String,
+    /// Where its initializer starts.
+    span: Span,
+    read: bool,
+    /// Whether it is assigned after it is declared.
+    assigned: bool,
+}
+
+/// The state of a function whose body contains the function being compiled.
+struct Enclosing {
+    locals: Vec<String>,
+    vars: Vec<Var>,
+    captured: HashSet<usize>,
+    upvalues: Vec<Capture>,
+}
+
+/// A step of compiling an expression.
+enum Work {
+    /// Compile an expression.
+    Expr(ExprId),
+    /// Emit an instruction, after the steps scheduled before it.
+    Emit(Instruction),
+    /// Emit the jump of `&&`, or of `||` if `or` is set, over its right
+    /// operand, keeping the left one as the value if the jump is taken.
+    ShortCircuit { or: bool },
+    /// Point the innermost jump of a `&&` or `||` here.
+    Land,
+}
+
+/// Whether `expr` is an if or a block, which are compiled as statements
+/// unless their value is used.
+fn is_statement_like(expr: &Expr) -> bool {
+    matches!(expr, Expr::If { .. } | Expr::Block(_))
+}
+
+/// Fail if `node` can't be compiled in a block whose value is one part of
+/// a larger expression.
+fn check_in_expression(node: &Stmt) -> Result<(), String> {
+    let what = match node {
+        Stmt::For { .. } => "a for loop",
+        Stmt::Match { .. } => "a match",
+        Stmt::Switch { .. } => "a switch",
+        Stmt::Try { .. } => "a try statement",
+        Stmt::VarDecl { .. } => "a var declaration",
+        Stmt::Return(_) => "return",
+        Stmt::Break(_) => "break",
+        Stmt::Continue(_) => "continue",
+        _ => return Ok(()),
+    };
+
+    Err(ErrorCode::Syntax.error(format!(
+        "{what} can't be used in a block inside a larger expression"
+    )))
+}
+
+/// Schedule `steps` to run in order before the rest of `work`.
+fn schedule(work: &mut Vec<Work>, steps: Vec<Work>) {
+    work.extend(steps.into_iter().rev());
+}
+
+/// The smallest of `values` if they are dense enough to switch on with a
+/// jump table, with at least [JUMP_TABLE_MIN_CASES] values filling at least
+/// half of the table.
+fn table_range(values: &[i64]) -> Option<i64> {
+    let min = *values.iter().min()?;
+    let max = *values.iter().max()?;
+    let span = max.checked_sub(min)?.checked_add(1)?;
+
+    (values.len() >= JUMP_TABLE_MIN_CASES && span <= 2 * values.len() as i64).then_some(min)
+}
+
 #[cfg(test)]
 mod test {
     use crate::{
+        ast::{Expr, Lit},
+        hash::Hash,
         instruction::{InstanceValue, Instruction},
         lexer::Lexer,
-        parser,
+        parser::{self, Precedence},
         scope::State,
+        value::Value,
+        Thrush,
     };
 
+    use super::{CompilerOptions, OptLevel};
+
     #[test]
     fn compile_literal() {
-        let ast = parser::Parser::new(Lexer::tokenize("1")).parse().unwrap();
+        let ast = parser::Parser::new(Lexer::tokenize("var x = 1"))
+            .parse()
+            .unwrap();
         let scope = &mut State::new();
 
         let mut compiler = super::Compiler::new(scope);
@@ -180,4 +3012,467 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn compile_function() {
+        let ast = parser::Parser::new(Lexer::tokenize("fun add(a, b) {\n    return a + b\n}"))
+            .parse()
+            .unwrap();
+        let scope = &mut State::new();
+
+        let chunk = super::Compiler::new(scope).run(ast).unwrap();
+        let Some(fun) = chunk.constants.iter().find_map(|constant| match constant {
+            Value::ScriptFun(fun) => Some(fun),
+            _ => None,
+        }) else {
+            panic!("expected a function constant");
+        };
+
+        assert_eq!(&*fun.name, "add");
+        assert_eq!(fun.arity, 2);
+        assert_eq!(fun.params, [Box::from("a"), Box::from("b")]);
+        assert_eq!(fun.chunk.instructions.last(), Some(&Instruction::Return));
+        assert!(!chunk.instructions.contains(&Instruction::Return));
+    }
+
+    #[test]
+    fn compile_jumps() {
+        let scope = &mut State::new();
+        let mut compiler = super::Compiler::new(scope);
+
+        compiler.emit_inst(Instruction::LoadNil);
+
+        let jump = compiler.emit_jump(|offset| Instruction::JumpIfFalse { offset });
+
+        compiler.emit_inst(Instruction::LoadNil);
+        compiler.emit_inst(Instruction::Pop);
+        compiler.emit_loop(0);
+        compiler.patch_jump(jump);
+
+        assert_eq!(
+            compiler.chunk.instructions,
+            [
+                Instruction::LoadNil,
+                Instruction::JumpIfFalse { offset: 3 },
+                Instruction::LoadNil,
+                Instruction::Pop,
+                Instruction::Loop { offset: 5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_switch() {
+        let compile = |source: &str| {
+            let ast = parser::Parser::new(Lexer::tokenize(source))
+                .parse()
+                .unwrap();
+            let scope = &mut State::new();
+
+            for name in ["x", "a", "b", "c"] {
+                scope.add(name, Value::Nil);
+            }
+
+            super::Compiler::new(scope).run(ast).unwrap()
+        };
+
+        let dense = compile("switch x {\n    1 => a\n    2 => b\n    4 => c\n}");
+        let sparse = compile("switch x {\n    1 => a\n    2 => b\n    40 => c\n}");
+
+        assert_eq!(dense.instructions[1], Instruction::JumpTable { index: 0 });
+        assert_eq!(dense.tables[0].targets.len(), 4);
+        assert_eq!(dense.tables[0].targets[2], dense.tables[0].default);
+        assert!(sparse.tables.is_empty());
+    }
+
+    #[test]
+    fn compile_locals() {
+        let ast = parser::Parser::new(Lexer::tokenize("if x {\n    var y = 1\n    y = y + 1\n}"))
+            .parse()
+            .unwrap();
+        let scope = &mut State::new();
+        scope.add("x", Value::Nil);
+
+        let chunk = super::Compiler::new(scope).run(ast).unwrap();
+
+        assert_eq!(chunk.instructions[3], Instruction::GetLocal { slot: 0 });
+        assert_eq!(chunk.instructions[6], Instruction::SetLocal { slot: 0 });
+        assert_eq!(chunk.instructions[8], Instruction::Pop);
+        assert!(!chunk
+            .instructions
+            .iter()
+            .any(|inst| matches!(inst, Instruction::DefineGlobal { .. })));
+
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("var x = 1\nfun sum(items) {\n    var total = 0\n    for item in items {\n        var doubled = item * 2\n        total = total + doubled\n        match doubled {\n            6 => {\n                break\n            }\n            _ => {}\n        }\n    }\n    return total\n}\nvar total = sum([1, 2, 3, 4])\nif true {\n    var x = 2\n    var inner = x\n    total = total + inner\n}\nvar block = {\n    var a = 3\n    var b = 4\n    a * b\n}")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("total"), "14");
+        assert_eq!(global("x"), "1");
+        assert_eq!(global("block"), "12");
+        assert!(!thrush.globals().contains("doubled"));
+        assert!(!thrush.globals().contains("inner"));
+        assert!(thrush
+            .exec("print({ var a = 1\n a })")
+            .unwrap_err()
+            .to_string()
+            .contains("a var declaration can't be used in a block inside a larger expression"));
+    }
+
+    #[test]
+    fn compile_undefined() {
+        let mut thrush = Thrush::new();
+
+        thrush.globals().add("lives", 3);
+        thrush
+            .exec("fun start() {\n    return later() + lives\n}\nfun later() {\n    return 1\n}\nvar total = start()")
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<i64>("total"), Ok(4));
+        assert_eq!(
+            thrush
+                .exec("var total = totl + 1")
+                .map_err(|err| err.to_string()),
+            Err("[E0001] line 1:13: undefined variable 'totl'; did you mean 'total'?".into())
+        );
+        assert_eq!(
+            thrush
+                .exec("fun reset() {\n    score = 0\n}")
+                .map_err(|err| err.to_string()),
+            Err("[E0001] line 2:5: undefined variable 'score'".into())
+        );
+        assert!(!thrush.globals().contains("reset"));
+    }
+
+    #[test]
+    fn compile_call_method() {
+        let ast = parser::Parser::new(Lexer::tokenize("x.m(1, 2)\nx.m"))
+            .parse()
+            .unwrap();
+        let scope = &mut State::new();
+        scope.add("x", Value::Nil);
+
+        let chunk = super::Compiler::new(scope).run(ast).unwrap();
+
+        assert_eq!(
+            chunk.instructions[3],
+            Instruction::CallMethod {
+                index: 0,
+                hash: Hash::of("m"),
+                argc: 2
+            }
+        );
+        assert_eq!(chunk.instructions[6], Instruction::GetProperty { index: 0 });
+    }
+
+    #[test]
+    fn compile_across_execs() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("fun area(width, height) {\n    return width * height\n}\nvar ran = false")
+            .unwrap();
+
+        assert_eq!(
+            thrush
+                .exec("ran = true\narea(widht: 2, height: 3)")
+                .map_err(|err| err.to_string()),
+            Err(
+                "[E0103] line 2:1: 'area' has no parameter named 'widht'; did you mean 'width'?"
+                    .into()
+            )
+        );
+        assert_eq!(thrush.globals().get::<bool>("ran"), Ok(false));
+    }
+
+    #[test]
+    fn compile_warnings() {
+        let mut thrush = Thrush::new();
+        let mut warnings = |script| {
+            thrush.exec(script).unwrap();
+            thrush
+                .warnings()
+                .iter()
+                .map(|warning| warning.message.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            warnings("fun area(w, h) {\n    var unit = 1\n    var _spare = 2\n    var w = w * 2\n    var last = 0\n    last = w * h\n    return w * h\n    unit = 3\n}"),
+            vec![
+                "[W0002] line 8:5: unreachable code",
+                "[W0003] line 4:13: 'w' shadows a variable declared before it",
+                "[W0004] line 2:16: 'unit' is assigned but never read",
+                "[W0004] line 5:16: 'last' is assigned but never read",
+            ]
+        );
+        assert_eq!(
+            warnings("var n = 0\nwhile true {\n    var step = 1\n    n = n + step\n    if true {\n        var left = 3\n    }\n    break\n}"),
+            vec!["[W0001] line 6:20: unused variable 'left'"]
+        );
+        assert!(warnings("var total = 0\nfun add(x) {\n    var sum = total\n    sum = sum + x\n    return sum\n}").is_empty());
+    }
+
+    #[test]
+    fn compile_const() {
+        let ast = parser::Parser::new(Lexer::tokenize("const PI = 3.5\nvar x = PI"))
+            .parse()
+            .unwrap();
+        let scope = &mut State::new();
+
+        let chunk = super::Compiler::new(scope).run(ast).unwrap();
+
+        assert_eq!(
+            chunk.instructions[2],
+            Instruction::Push {
+                value: InstanceValue::Float(3.5)
+            }
+        );
+        assert!(scope.is_constant("PI"));
+    }
+
+    #[test]
+    fn compile_const_expression() {
+        let ast = parser::Parser::new(Lexer::tokenize(
+            "const SIZE = 8 * 8\nlet AREA = SIZE * 2 + 0.5\nconst NAME = \"grid\" + \"-\" * 2\nconst BAD = 1 / 0\nvar x = SIZE",
+        ))
+        .parse()
+        .unwrap();
+        let scope = &mut State::new();
+
+        let chunk = super::Compiler::new(scope).run(ast).unwrap();
+
+        assert_eq!(chunk.instructions[0], Instruction::integer(64));
+        assert_eq!(
+            chunk.instructions[2],
+            Instruction::Push {
+                value: InstanceValue::Float(128.5)
+            }
+        );
+        assert_eq!(chunk.instructions[4], Instruction::Constant { index: 0 });
+        assert_eq!(chunk.constants[0], Value::String("grid--".into()));
+        assert!(matches!(chunk.instructions[8], Instruction::Div));
+        assert_eq!(chunk.instructions[10], Instruction::integer(64));
+
+        let mut thrush = Thrush::new();
+
+        assert_eq!(
+            thrush
+                .exec("const LIMIT = 9223372036854775807 + 1")
+                .map_err(|err| err.to_string()),
+            Err("[E0205] line 1:37: integer overflow in 9223372036854775807 + 1".into())
+        );
+    }
+
+    #[test]
+    fn compile_negative_literal() {
+        let ast = parser::Parser::new(Lexer::tokenize("var x = -5\nvar y = -2.5\nvar z = -x"))
+            .parse()
+            .unwrap();
+        let scope = &mut State::new();
+
+        let chunk = super::Compiler::new(scope).run(ast).unwrap();
+
+        assert_eq!(chunk.instructions[0], Instruction::integer(-5));
+        assert_eq!(
+            chunk.instructions[2],
+            Instruction::Push {
+                value: InstanceValue::Float(-2.5)
+            }
+        );
+        assert_eq!(chunk.instructions[5], Instruction::Neg);
+    }
+
+    #[test]
+    fn compile_folded() {
+        let ast = parser::Parser::new(Lexer::tokenize(
+            "var day = 60 * 60 * 24\nvar name = \"thr\" + \"ush\"\nvar f = x * (1 + 1)",
+        ))
+        .parse()
+        .unwrap();
+        let scope = &mut State::new();
+        scope.add("x", Value::Nil);
+
+        let chunk = super::Compiler::new(scope).run(ast).unwrap();
+
+        assert_eq!(chunk.instructions[0], Instruction::integer(86400));
+        assert!(matches!(
+            chunk.instructions[1],
+            Instruction::DefineGlobal { .. }
+        ));
+        assert_eq!(chunk.constants[0], Value::String("thrush".into()));
+        assert_eq!(chunk.instructions[5], Instruction::integer(2));
+        assert_eq!(chunk.instructions[6], Instruction::Mul);
+    }
+
+    #[test]
+    fn compile_options() {
+        let ast = parser::Parser::new(Lexer::tokenize("var day = 60 * 24"))
+            .parse()
+            .unwrap();
+        let scope = &mut State::new();
+        let options = CompilerOptions::new().with_opt_level(OptLevel::None);
+
+        let chunk = super::Compiler::new(scope)
+            .with_options(options)
+            .run(ast)
+            .unwrap();
+
+        assert_eq!(chunk.instructions[2], Instruction::Mul);
+
+        let mut thrush = Thrush::new();
+
+        thrush.set_compiler_options(CompilerOptions::new().with_debug_info(false));
+        assert_eq!(
+            thrush
+                .exec("var s = \"a\" * -2")
+                .map_err(|err| err.to_string()),
+            Err("[E0205] can't repeat a string -2 times".into())
+        );
+
+        thrush.set_compiler_options(CompilerOptions::new().with_implicit_globals(true));
+        thrush
+            .exec("fun bump() {\n    count = count + 1\n}\ncount = 1\nbump()\nunset = unset")
+            .unwrap();
+        assert_eq!(thrush.globals().get::<i64>("count"), Ok(2));
+        assert_eq!(thrush.globals().get::<Value>("unset"), Ok(Value::Nil));
+
+        thrush.set_compiler_options(CompilerOptions::new().with_max_constants(1));
+        assert_eq!(
+            thrush
+                .exec("var a = \"x\"\nvar b = \"y\"")
+                .map_err(|err| err.to_string()),
+            Err("[E0404] line 2:9: too many constants (more than 1 in one chunk)".into())
+        );
+        assert!(thrush
+            .exec("fun greet() {\n    return [\"hello\", \"there\"]\n}")
+            .unwrap_err()
+            .to_string()
+            .starts_with("[E0404]"));
+    }
+
+    #[test]
+    fn compile_long_chain() {
+        let source = format!("x{}", ".y".repeat(100_000));
+        let ast = parser::Parser::new(Lexer::tokenize(&source))
+            .parse()
+            .unwrap();
+        let scope = &mut State::new();
+        scope.add("x", Value::Nil);
+
+        let chunk = super::Compiler::new(scope).run(ast).unwrap();
+
+        assert_eq!(chunk.instructions.len(), 100_003);
+        assert!(matches!(
+            chunk.instructions[100_000],
+            Instruction::GetProperty { .. }
+        ));
+    }
+
+    #[test]
+    fn compile_custom_operator() {
+        let mut options = super::CompilerOptions::new();
+        let show = |symbol: &'static str| {
+            move |a: Value, b: Value| Ok(Value::String(format!("({a} {symbol} {b})")))
+        };
+
+        options.operator("<>", Precedence::Sum, show("<>")).unwrap();
+        options
+            .operator("^^", Precedence::Term, show("^^"))
+            .unwrap();
+
+        assert!(options.operator("=", Precedence::Sum, show("=")).is_err());
+        assert!(options.operator("@", Precedence::End, show("@")).is_err());
+
+        let mut thrush = Thrush::new();
+
+        thrush.set_compiler_options(options);
+        thrush.exec("var x = 1 <> 2 ^^ 3 <> 4").unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<String>("x"),
+            Ok("((1 <> (2 ^^ 3)) <> 4)".into())
+        );
+    }
+
+    #[test]
+    fn compile_host_statement() {
+        let mut options = super::CompilerOptions::new();
+
+        options
+            .statement("quest", |stmt, emitter| {
+                let [title, xp] = stmt.args[..] else {
+                    return Err("quest expects a title and experience".into());
+                };
+
+                if !matches!(emitter.get(title), Expr::Literal(Lit::String(_))) {
+                    return Err("quest titles must be strings".into());
+                }
+
+                let slot = emitter.global("on_quest");
+
+                emitter.emit(Instruction::GetGlobal { slot });
+                emitter.expr(title)?;
+                emitter.expr(xp)?;
+                emitter.emit(Instruction::Call { argc: 2 });
+                emitter.emit(Instruction::Pop);
+                emitter.statements(stmt.body.as_deref().unwrap_or_default())
+            })
+            .unwrap();
+
+        assert!(options.statement("while", |_, _| Ok(())).is_err());
+
+        let mut thrush = Thrush::new();
+
+        thrush.set_compiler_options(options);
+        thrush
+            .exec("var log = nil\nvar reward = nil\nfun on_quest(title, xp) {\n    log = [title, xp]\n}\nquest \"Find the sword\", 100 {\n    reward = \"sword\"\n}")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("log").unwrap().to_string(),
+            "[\"Find the sword\", 100]"
+        );
+        assert_eq!(thrush.globals().get::<String>("reward"), Ok("sword".into()));
+        assert!(thrush
+            .exec("quest 1, 2")
+            .unwrap_err()
+            .to_string()
+            .contains("quest titles must be strings"));
+    }
+
+    #[test]
+    fn compile_template() {
+        let mut options = super::CompilerOptions::new();
+
+        options.tag("sql", |parts, values| {
+            Ok(Value::list(vec![
+                Value::String(parts.join("?")),
+                Value::list(values),
+            ]))
+        });
+
+        let mut thrush = Thrush::new();
+
+        thrush.set_compiler_options(options);
+        thrush
+            .exec(
+                "var id = 7\nvar q = sql\"SELECT * FROM t WHERE id = ${id} AND name = ${\"bob\"}\"",
+            )
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("q").unwrap().to_string(),
+            "[\"SELECT * FROM t WHERE id = ? AND name = ?\", [7, \"bob\"]]"
+        );
+        assert!(thrush
+            .exec("var q = html\"<b>\"")
+            .unwrap_err()
+            .to_string()
+            .contains("undefined template tag 'html'"));
+    }
 }
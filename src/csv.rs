@@ -0,0 +1,278 @@
+//! CSV and TSV parsing for scripts, exposed as the `csv` global.
+//!
+//! ```text
+//! var rows = csv.parse(text, {headers: true})
+//! var tsv = csv.write(rows, {delimiter: "\t"})
+//! ```
+
+use crate::{
+    scope::State,
+    value::{Class, Map, Value},
+};
+
+/// Options accepted by `csv.parse` and `csv.write`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CsvOptions {
+    /// Whether the first record holds the column names.
+    pub headers: bool,
+    /// The character separating fields.
+    pub delimiter: char,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            headers: false,
+            delimiter: ',',
+        }
+    }
+}
+
+impl CsvOptions {
+    fn from_value(value: Option<&Value>) -> Result<Self, String> {
+        let mut options = CsvOptions::default();
+
+        let map = match value {
+            Some(Value::Map(map)) => map.borrow(),
+            Some(value) => return Err(format!("expected a map of options, found '{value}'")),
+            None => return Ok(options),
+        };
+
+        for (key, value) in map.iter() {
+            match (key, value) {
+                ("headers", Value::Bool(headers)) => options.headers = *headers,
+                ("delimiter", Value::String(delimiter)) if delimiter.chars().count() == 1 => {
+                    options.delimiter = delimiter.chars().next().unwrap();
+                }
+                (key, value) => return Err(format!("invalid csv option {key}: {value}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Split `text` into records of fields.
+///
+/// Fields may be quoted with `"`, in which case they can contain delimiters,
+/// newlines, and doubled `""` quotes.
+pub fn parse_records(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    let mut quoted = false;
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                c => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+
+                    field.push(c);
+                }
+            }
+
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                line += 1;
+            }
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(format!("unterminated quoted field on line {line}"));
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// Parse `text` into a list of lists, or a list of maps when `headers` is set.
+pub fn parse(text: &str, options: &CsvOptions) -> Result<Value, String> {
+    let mut records = parse_records(text, options.delimiter)?.into_iter();
+
+    if !options.headers {
+        let rows = records
+            .map(|record| Value::list(record.into_iter().map(Value::String).collect()))
+            .collect();
+
+        return Ok(Value::list(rows));
+    }
+
+    let headers = records.next().unwrap_or_default();
+    let rows = records
+        .map(|record| {
+            let mut fields = record.into_iter();
+            let map = headers
+                .iter()
+                .map(|header| {
+                    let value = fields.next().map_or(Value::Nil, Value::String);
+
+                    (header.as_str(), value)
+                })
+                .collect::<Map>();
+
+            Value::map(map)
+        })
+        .collect();
+
+    Ok(Value::list(rows))
+}
+
+/// Render a list of lists or a list of maps as CSV text.
+///
+/// Maps are written with a header row taken from the keys of the first row.
+pub fn write(rows: &Value, options: &CsvOptions) -> Result<String, String> {
+    let rows = match rows {
+        Value::List(rows) => rows.borrow(),
+        value => return Err(format!("expected a list of rows, found '{value}'")),
+    };
+
+    let mut out = String::new();
+    let headers = match rows.first() {
+        Some(Value::Map(map)) => {
+            let headers = map.borrow().keys().map(String::from).collect::<Vec<_>>();
+
+            write_record(&mut out, &headers, options.delimiter);
+            Some(headers)
+        }
+        _ => None,
+    };
+
+    for row in rows.iter() {
+        match (row, &headers) {
+            (Value::Map(map), Some(headers)) => {
+                let map = map.borrow();
+                let fields = headers
+                    .iter()
+                    .map(|header| map.get(header).map(field_string).unwrap_or_default())
+                    .collect::<Vec<_>>();
+
+                write_record(&mut out, &fields, options.delimiter);
+            }
+            (Value::List(fields), None) => {
+                let fields = fields.borrow().iter().map(field_string).collect::<Vec<_>>();
+
+                write_record(&mut out, &fields, options.delimiter);
+            }
+            (row, _) => {
+                return Err(format!(
+                    "expected every row to be a list or a map, found '{row}'"
+                ))
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn write_record(out: &mut String, fields: &[String], delimiter: char) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(delimiter);
+        }
+
+        if field.contains([delimiter, '"', '\n', '\r']) {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+
+    out.push('\n');
+}
+
+fn field_string(value: &Value) -> String {
+    match value {
+        Value::Nil => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// Register the `csv` global.
+pub fn register(state: &mut State) {
+    let class = Class::new("csv");
+
+    class.add_static_method("parse", |args| match args.first() {
+        Some(Value::String(text)) => parse(text, &CsvOptions::from_value(args.get(1))?),
+        _ => Err("csv.parse expects a string".into()),
+    });
+
+    class.add_static_method("write", |args| match args.first() {
+        Some(rows) => Ok(Value::String(write(
+            rows,
+            &CsvOptions::from_value(args.get(1))?,
+        )?)),
+        None => Err("csv.write expects a list of rows".into()),
+    });
+
+    state.add("csv", Value::Class(class));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{value::Value, Thrush};
+
+    use super::{parse_records, CsvOptions};
+
+    #[test]
+    fn test_parse_records() {
+        let records = parse_records("a,\"b,c\",\"say \"\"hi\"\"\"\r\n1,2,3\n", ',').unwrap();
+
+        assert_eq!(
+            records,
+            vec![vec!["a", "b,c", "say \"hi\""], vec!["1", "2", "3"]]
+        );
+        assert!(parse_records("\"open", ',').is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let options = CsvOptions {
+            headers: true,
+            delimiter: '\t',
+        };
+        let text = "name\tscore\nann\t3\nbob\t\"4\t5\"\n";
+        let rows = super::parse(text, &options).unwrap();
+
+        assert_eq!(super::write(&rows, &options).unwrap(), text);
+    }
+
+    #[test]
+    fn test_csv_script() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("var rows = csv.parse(\"x,y\\n1,2\", {headers: true})")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("rows").unwrap().to_string(),
+            "[{x: \"1\", y: \"2\"}]"
+        );
+    }
+}
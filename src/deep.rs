@@ -0,0 +1,280 @@
+//! Deep copies and merges of lists and maps, exposed as `copy.deep` and
+//! `map.merge`.
+//!
+//! ```text
+//! var settings = map.merge(defaults, overrides, {deep: true})
+//! var backup = copy.deep(settings)
+//! ```
+//!
+//! Lists and maps that appear more than once in a value, including inside
+//! themselves, are copied once, so a copy has the same shape as the
+//! original and copying a cycle terminates. Other values, such as instances
+//! and functions, are shared rather than copied.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    scope::State,
+    value::{Class, Map, Value},
+};
+
+/// The copies made so far of the lists or maps at each address.
+type Copies = HashMap<*const (), Value>;
+
+/// Copy `value` and every list and map inside it.
+pub fn deep_copy(value: &Value) -> Value {
+    copy(value, &mut Copies::new())
+}
+
+fn copy(value: &Value, copies: &mut Copies) -> Value {
+    match value {
+        Value::List(list) => {
+            let key = Rc::as_ptr(list) as *const ();
+
+            if let Some(copy) = copies.get(&key) {
+                return copy.clone();
+            }
+
+            let copied = Rc::new(RefCell::new(Vec::new()));
+
+            copies.insert(key, Value::List(Rc::clone(&copied)));
+
+            let items = list
+                .borrow()
+                .iter()
+                .map(|item| copy(item, copies))
+                .collect();
+
+            *copied.borrow_mut() = items;
+
+            Value::List(copied)
+        }
+        Value::Map(map) => {
+            let key = Rc::as_ptr(map) as *const ();
+
+            if let Some(copy) = copies.get(&key) {
+                return copy.clone();
+            }
+
+            let copied = Rc::new(RefCell::new(Map::new()));
+
+            copies.insert(key, Value::Map(Rc::clone(&copied)));
+
+            let entries = map
+                .borrow()
+                .iter()
+                .map(|(key, value)| (key, copy(value, copies)))
+                .collect();
+
+            *copied.borrow_mut() = entries;
+
+            Value::Map(copied)
+        }
+        value => value.clone(),
+    }
+}
+
+/// The merges made so far of the pairs of maps at each pair of addresses.
+type Merges = HashMap<(*const (), *const ()), Value>;
+
+/// Merge the entries of `b` into those of `a`, in a new map.
+///
+/// A shallow merge shares the values of both maps with the result. A deep
+/// merge copies them, and merges the values of keys that are maps in both.
+pub fn merge(a: &Rc<RefCell<Map>>, b: &Rc<RefCell<Map>>, deep: bool) -> Value {
+    if !deep {
+        let mut merged = a.borrow().clone();
+
+        for (key, value) in b.borrow().iter() {
+            merged.insert(key, value.clone());
+        }
+
+        return Value::map(merged);
+    }
+
+    merge_deep(a, b, &mut Merges::new(), &mut Copies::new())
+}
+
+fn merge_deep(
+    a: &Rc<RefCell<Map>>,
+    b: &Rc<RefCell<Map>>,
+    merges: &mut Merges,
+    copies: &mut Copies,
+) -> Value {
+    let key = (Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ());
+
+    if let Some(merged) = merges.get(&key) {
+        return merged.clone();
+    }
+
+    let merged = Rc::new(RefCell::new(Map::new()));
+
+    merges.insert(key, Value::Map(Rc::clone(&merged)));
+
+    for (key, value) in a.borrow().iter() {
+        let value = copy(value, copies);
+
+        merged.borrow_mut().insert(key, value);
+    }
+
+    for (key, value) in b.borrow().iter() {
+        let value = match (a.borrow().get(key), value) {
+            (Some(Value::Map(a)), Value::Map(b)) => merge_deep(a, b, merges, copies),
+            (_, value) => copy(value, copies),
+        };
+
+        merged.borrow_mut().insert(key, value);
+    }
+
+    Value::Map(merged)
+}
+
+/// Whether the options passed to `map.merge` ask for a deep merge.
+fn is_deep(options: Option<&Value>) -> Result<bool, String> {
+    let options = match options {
+        Some(Value::Map(options)) => options.borrow(),
+        Some(value) => return Err(format!("expected a map of options, found '{value}'")),
+        None => return Ok(false),
+    };
+    let mut deep = false;
+
+    for (key, value) in options.iter() {
+        match (key, value) {
+            ("deep", Value::Bool(v)) => deep = *v,
+            (key, value) => return Err(format!("invalid merge option {key}: {value}")),
+        }
+    }
+
+    Ok(deep)
+}
+
+/// Register the `copy` and `map` globals.
+pub fn register(state: &mut State) {
+    let copy = Class::new("copy");
+
+    copy.add_static_method("deep", |args| match args.as_slice() {
+        [value] => Ok(deep_copy(value)),
+        _ => Err("copy.deep expects a value".into()),
+    });
+
+    state.add("copy", Value::Class(copy));
+
+    let map = Class::new("map");
+
+    map.add_static_method("merge", |args| match args.as_slice() {
+        [Value::Map(a), Value::Map(b), options @ ..] if options.len() < 2 => {
+            Ok(merge(a, b, is_deep(options.first())?))
+        }
+        _ => Err("map.merge expects two maps and an optional map of options".into()),
+    });
+
+    state.add("map", Value::Class(map));
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use crate::{
+        value::{Map, Value},
+        Thrush,
+    };
+
+    #[test]
+    fn test_deep_copy() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("var original = {a: [1, {b: 2}]}\nvar shared = [original, original]\nvar copied = copy.deep(shared)")
+            .unwrap();
+
+        let (Ok(Value::List(shared)), Ok(Value::List(copied))) = (
+            thrush.globals().get::<Value>("shared"),
+            thrush.globals().get::<Value>("copied"),
+        ) else {
+            panic!("expected lists");
+        };
+        let ([Value::Map(original), _], [Value::Map(first), Value::Map(second)]) =
+            (&shared.borrow()[..], &copied.borrow()[..])
+        else {
+            panic!("expected maps");
+        };
+
+        assert_eq!(shared, copied);
+        assert!(!Rc::ptr_eq(original, first));
+        assert!(Rc::ptr_eq(first, second));
+
+        let cycle = Value::list(vec![Value::Integer(1)]);
+        let Value::List(items) = &cycle else {
+            unreachable!()
+        };
+
+        items.borrow_mut().push(cycle.clone());
+        thrush.globals().add("cycle", cycle.clone());
+        thrush.exec("var copied_cycle = copy.deep(cycle)").unwrap();
+
+        let Ok(Value::List(copied)) = thrush.globals().get::<Value>("copied_cycle") else {
+            panic!("expected a list");
+        };
+        let Value::List(inner) = copied.borrow()[1].clone() else {
+            panic!("expected a list");
+        };
+
+        assert!(Rc::ptr_eq(&copied, &inner));
+        assert!(!Rc::ptr_eq(&copied, items));
+
+        // Break the cycles so they are freed.
+        items.borrow_mut().clear();
+        copied.borrow_mut().clear();
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("var defaults = {size: {w: 1, h: 2}, title: \"a\"}\nvar overrides = {size: {h: 3}, debug: true}\nvar shallow = map.merge(defaults, overrides)\nvar deep = map.merge(defaults, overrides, {deep: true})")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(
+            global("shallow"),
+            "{size: {h: 3}, title: \"a\", debug: true}"
+        );
+        assert_eq!(
+            global("deep"),
+            "{size: {w: 1, h: 3}, title: \"a\", debug: true}"
+        );
+        assert_eq!(global("defaults"), "{size: {w: 1, h: 2}, title: \"a\"}");
+        assert!(thrush
+            .exec("var bad = map.merge({}, {}, {shallow: true})")
+            .unwrap_err()
+            .to_string()
+            .contains("invalid merge option shallow: true"));
+        assert!(thrush.exec("var bad = map.merge({}, [])").is_err());
+
+        let looped = Value::map(Map::new());
+        let Value::Map(entries) = &looped else {
+            unreachable!()
+        };
+
+        entries.borrow_mut().insert("me", looped.clone());
+        thrush.globals().add("looped", looped.clone());
+        thrush
+            .exec("var merged = map.merge(looped, looped, {deep: true})")
+            .unwrap();
+
+        let Ok(Value::Map(merged)) = thrush.globals().get::<Value>("merged") else {
+            panic!("expected a map");
+        };
+        let Some(Value::Map(me)) = merged.borrow().get("me").cloned() else {
+            panic!("expected a map");
+        };
+
+        assert!(Rc::ptr_eq(&merged, &me));
+
+        entries.borrow_mut().remove("me");
+        merged.borrow_mut().remove("me");
+    }
+}
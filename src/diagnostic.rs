@@ -0,0 +1,812 @@
+//! Stable error codes for compile and runtime diagnostics.
+//!
+//! Every error message produced by the parser, compiler and VM starts with
+//! its code, e.g. `[E0102] 'add' expected 2 arguments but got 1`, and
+//! `thrush explain E0102` prints a longer description of the error with an
+//! example of how to fix it. Warnings from the compiler, which don't stop a
+//! script from running, have codes too, e.g. `[W0001] unused variable 'x'`.
+
+use std::fmt::{self, Display};
+
+use crate::token::Span;
+
+/// The code of a diagnostic.
+///
+/// Codes are grouped by area: `E00xx` for names, `E01xx` for calls, `E02xx`
+/// for values and properties, `E03xx` for builtins, `E04xx` for syntax,
+/// `E09xx` for internal errors, and `W00xx` for warnings. A code is never
+/// reused once assigned.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorCode {
+    UndefinedVariable,
+    InvalidAssignment,
+    SelfOutsideMethod,
+    ReturnOutsideFunction,
+    NestedFunction,
+    DuplicateCase,
+    AssignToConstant,
+    InvalidJump,
+    YieldOutsideFunction,
+    AwaitOutsideAsync,
+    NotCallable,
+    WrongArity,
+    NamedArgument,
+    StackOverflow,
+    UndefinedProperty,
+    NoProperties,
+    InvalidMapKey,
+    TypeMismatch,
+    Arithmetic,
+    NotIterable,
+    ValueTooLarge,
+    TraitNotImplemented,
+    Native,
+    Uncaught,
+    Syntax,
+    InvalidAccessor,
+    NestingLimit,
+    ConstantLimit,
+    Import,
+    Internal,
+    UnusedVariable,
+    UnreachableCode,
+    ShadowedName,
+    UnreadAssignment,
+}
+
+impl ErrorCode {
+    /// Every error code, in order.
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::UndefinedVariable,
+        ErrorCode::InvalidAssignment,
+        ErrorCode::SelfOutsideMethod,
+        ErrorCode::ReturnOutsideFunction,
+        ErrorCode::NestedFunction,
+        ErrorCode::DuplicateCase,
+        ErrorCode::AssignToConstant,
+        ErrorCode::InvalidJump,
+        ErrorCode::YieldOutsideFunction,
+        ErrorCode::AwaitOutsideAsync,
+        ErrorCode::NotCallable,
+        ErrorCode::WrongArity,
+        ErrorCode::NamedArgument,
+        ErrorCode::StackOverflow,
+        ErrorCode::UndefinedProperty,
+        ErrorCode::NoProperties,
+        ErrorCode::InvalidMapKey,
+        ErrorCode::TypeMismatch,
+        ErrorCode::Arithmetic,
+        ErrorCode::NotIterable,
+        ErrorCode::ValueTooLarge,
+        ErrorCode::TraitNotImplemented,
+        ErrorCode::Native,
+        ErrorCode::Uncaught,
+        ErrorCode::Syntax,
+        ErrorCode::InvalidAccessor,
+        ErrorCode::NestingLimit,
+        ErrorCode::ConstantLimit,
+        ErrorCode::Import,
+        ErrorCode::Internal,
+        ErrorCode::UnusedVariable,
+        ErrorCode::UnreachableCode,
+        ErrorCode::ShadowedName,
+        ErrorCode::UnreadAssignment,
+    ];
+
+    /// The code as written in messages, e.g. `"E0102"`.
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorCode::UndefinedVariable => "E0001",
+            ErrorCode::InvalidAssignment => "E0002",
+            ErrorCode::SelfOutsideMethod => "E0003",
+            ErrorCode::ReturnOutsideFunction => "E0004",
+            ErrorCode::NestedFunction => "E0005",
+            ErrorCode::DuplicateCase => "E0006",
+            ErrorCode::AssignToConstant => "E0007",
+            ErrorCode::InvalidJump => "E0008",
+            ErrorCode::YieldOutsideFunction => "E0009",
+            ErrorCode::AwaitOutsideAsync => "E0010",
+            ErrorCode::NotCallable => "E0101",
+            ErrorCode::WrongArity => "E0102",
+            ErrorCode::NamedArgument => "E0103",
+            ErrorCode::StackOverflow => "E0104",
+            ErrorCode::UndefinedProperty => "E0201",
+            ErrorCode::NoProperties => "E0202",
+            ErrorCode::InvalidMapKey => "E0203",
+            ErrorCode::TypeMismatch => "E0204",
+            ErrorCode::Arithmetic => "E0205",
+            ErrorCode::NotIterable => "E0206",
+            ErrorCode::ValueTooLarge => "E0207",
+            ErrorCode::TraitNotImplemented => "E0208",
+            ErrorCode::Native => "E0301",
+            ErrorCode::Uncaught => "E0302",
+            ErrorCode::Syntax => "E0401",
+            ErrorCode::InvalidAccessor => "E0402",
+            ErrorCode::NestingLimit => "E0403",
+            ErrorCode::ConstantLimit => "E0404",
+            ErrorCode::Import => "E0501",
+            ErrorCode::Internal => "E0901",
+            ErrorCode::UnusedVariable => "W0001",
+            ErrorCode::UnreachableCode => "W0002",
+            ErrorCode::ShadowedName => "W0003",
+            ErrorCode::UnreadAssignment => "W0004",
+        }
+    }
+
+    /// Whether the code is for a warning rather than an error.
+    pub fn is_warning(self) -> bool {
+        self.code().starts_with('W')
+    }
+
+    /// Look up an error code from its written form, ignoring case.
+    pub fn from_code(code: &str) -> Option<Self> {
+        ErrorCode::ALL
+            .iter()
+            .copied()
+            .find(|c| c.code().eq_ignore_ascii_case(code))
+    }
+
+    /// Find the code at the start of an error message.
+    pub fn of_message(message: &str) -> Option<Self> {
+        let code = message.strip_prefix('[')?.split(']').next()?;
+
+        ErrorCode::from_code(code)
+    }
+
+    /// Prefix `message` with this code, unless it already has one.
+    pub fn error<M: Display>(self, message: M) -> String {
+        let message = message.to_string();
+
+        if ErrorCode::of_message(&message).is_some() {
+            message
+        } else {
+            format!("[{}] {message}", self.code())
+        }
+    }
+
+    /// A one-line summary of the error.
+    pub fn title(self) -> &'static str {
+        match self {
+            ErrorCode::UndefinedVariable => "undefined variable",
+            ErrorCode::InvalidAssignment => "invalid assignment target",
+            ErrorCode::SelfOutsideMethod => "`self` used outside of a method",
+            ErrorCode::ReturnOutsideFunction => "`return` used outside of a function",
+            ErrorCode::NestedFunction => "function declared inside another function",
+            ErrorCode::DuplicateCase => "duplicate switch case",
+            ErrorCode::AssignToConstant => "assignment to a constant",
+            ErrorCode::InvalidJump => "break or continue outside of a loop",
+            ErrorCode::YieldOutsideFunction => "`yield` used outside of a function",
+            ErrorCode::AwaitOutsideAsync => "`await` used outside of an async function",
+            ErrorCode::NotCallable => "value is not callable",
+            ErrorCode::WrongArity => "wrong number of arguments",
+            ErrorCode::NamedArgument => "named arguments don't match the parameters",
+            ErrorCode::StackOverflow => "too many nested calls",
+            ErrorCode::UndefinedProperty => "undefined property",
+            ErrorCode::NoProperties => "value has no properties",
+            ErrorCode::InvalidMapKey => "invalid map key",
+            ErrorCode::TypeMismatch => "value has the wrong type",
+            ErrorCode::Arithmetic => "arithmetic error",
+            ErrorCode::NotIterable => "value is not iterable",
+            ErrorCode::ValueTooLarge => "value too large to hand to the host",
+            ErrorCode::TraitNotImplemented => "class doesn't implement a trait it names",
+            ErrorCode::Native => "error in a builtin function",
+            ErrorCode::Uncaught => "uncaught exception",
+            ErrorCode::Syntax => "syntax error",
+            ErrorCode::InvalidAccessor => "invalid getter or setter",
+            ErrorCode::NestingLimit => "program too deeply nested",
+            ErrorCode::ConstantLimit => "too many constants",
+            ErrorCode::Import => "module could not be imported",
+            ErrorCode::Internal => "internal error",
+            ErrorCode::UnusedVariable => "unused variable",
+            ErrorCode::UnreachableCode => "unreachable code",
+            ErrorCode::ShadowedName => "variable shadows another",
+            ErrorCode::UnreadAssignment => "assigned value is never read",
+        }
+    }
+
+    /// An extended description of the error, with an example.
+    pub fn explanation(self) -> &'static str {
+        match self {
+            ErrorCode::UndefinedVariable => {
+                "A variable was used or assigned, but neither the script nor the host\n\
+                 declares it.\n\n\
+                 \x20   score = 10\n\n\
+                 Declare the variable with `var` first:\n\n\
+                 \x20   var score = 10"
+            }
+            ErrorCode::InvalidAssignment => {
+                "Only variables and properties can be assigned to.\n\n\
+                 \x20   1 = x\n\n\
+                 Put a variable or a property on the left of `=`:\n\n\
+                 \x20   x = 1\n\
+                 \x20   player.x = 1"
+            }
+            ErrorCode::SelfOutsideMethod => {
+                "`self` refers to the instance a method was called on, so it\n\
+                 can only be used inside a class's methods, getters and setters.\n\
+                 Static methods and functions have no `self`.\n\n\
+                 \x20   class Bird {\n\
+                 \x20       fun me() {\n\
+                 \x20           return self\n\
+                 \x20       }\n\
+                 \x20   }"
+            }
+            ErrorCode::ReturnOutsideFunction => {
+                "`return` ends the function it is in, so it can't be used in\n\
+                 top-level code. Move the code into a function:\n\n\
+                 \x20   fun answer() {\n\
+                 \x20       return 42\n\
+                 \x20   }"
+            }
+            ErrorCode::NestedFunction => {
+                "Functions can only be declared at the top level of a script.\n\
+                 Move the inner function out of the function containing it."
+            }
+            ErrorCode::DuplicateCase => {
+                "The same value appears in more than one case of a switch, so\n\
+                 only the first of those cases could ever run.\n\n\
+                 \x20   switch key {\n\
+                 \x20       1 => up()\n\
+                 \x20       1 => down()\n\
+                 \x20   }\n\n\
+                 Remove the value from all but one case."
+            }
+            ErrorCode::AssignToConstant => {
+                "A constant declared with `const`, or a variable declared with\n\
+                 `let`, was assigned to or declared again.\n\n\
+                 \x20   const LIVES = 3\n\
+                 \x20   LIVES = 2\n\n\
+                 Declare values that change with `var` instead:\n\n\
+                 \x20   var lives = 3\n\
+                 \x20   lives = 2"
+            }
+            ErrorCode::InvalidJump => {
+                "`break` and `continue` can only be used inside a loop, and a\n\
+                 label after them must name a loop enclosing them.\n\n\
+                 \x20   outer: while running {\n\
+                 \x20       while true {\n\
+                 \x20           break outer\n\
+                 \x20       }\n\
+                 \x20   }"
+            }
+            ErrorCode::YieldOutsideFunction => {
+                "`yield` suspends the generator it is in, and any function\n\
+                 containing it is a generator, so it can't be used in top-level\n\
+                 code. Move the code into a function:\n\n\
+                 \x20   fun countdown(n) {\n\
+                 \x20       while n {\n\
+                 \x20           yield n\n\
+                 \x20           n = n - 1\n\
+                 \x20       }\n\
+                 \x20   }\n\n\
+                 Async functions can't yield either, as they suspend at each\n\
+                 `await` instead."
+            }
+            ErrorCode::AwaitOutsideAsync => {
+                "`await` suspends the function it is in until a promise\n\
+                 resolves, which only async functions can do. Declare the\n\
+                 function with `async`:\n\n\
+                 \x20   async fun load(name) {\n\
+                 \x20       return await read_file(name)\n\
+                 \x20   }\n\n\
+                 Calling it returns a promise of its result."
+            }
+            ErrorCode::NotCallable => {
+                "Only functions, methods and classes can be called.\n\n\
+                 \x20   var x = 1\n\
+                 \x20   x()\n\n\
+                 Check that the value you are calling is a function."
+            }
+            ErrorCode::WrongArity => {
+                "A function was called with a different number of arguments\n\
+                 than it has parameters.\n\n\
+                 \x20   fun add(a, b) {\n\
+                 \x20       return a + b\n\
+                 \x20   }\n\
+                 \x20   add(1)\n\n\
+                 Pass one argument for each parameter:\n\n\
+                 \x20   add(1, 2)\n\n\
+                 Functions with a `...rest` parameter accept any number of\n\
+                 extra arguments."
+            }
+            ErrorCode::NamedArgument => {
+                "A named argument doesn't match a parameter of the function, was\n\
+                 given twice, or left a parameter without a value.\n\n\
+                 \x20   fun draw(x, y) {}\n\
+                 \x20   draw(x: 1, z: 2)\n\n\
+                 Use the function's parameter names, and give each one a value:\n\n\
+                 \x20   draw(x: 1, y: 2)"
+            }
+            ErrorCode::StackOverflow => {
+                "Functions called each other more deeply than the VM allows,\n\
+                 usually because a recursive function never stops calling itself.\n\n\
+                 \x20   fun count(n) {\n\
+                 \x20       return count(n + 1)\n\
+                 \x20   }\n\n\
+                 Give the recursion a case that returns without calling the\n\
+                 function again."
+            }
+            ErrorCode::UndefinedProperty => {
+                "An object doesn't have a field, method or static method with\n\
+                 the given name.\n\n\
+                 \x20   class Point {\n\
+                 \x20       var x\n\
+                 \x20   }\n\
+                 \x20   Point().y\n\n\
+                 Declare fields in the class body with `var`."
+            }
+            ErrorCode::NoProperties => {
+                "Properties can only be used on instances and classes.\n\n\
+                 \x20   var n = 1\n\
+                 \x20   n.x"
+            }
+            ErrorCode::InvalidMapKey => {
+                "Map keys must be strings.\n\n\
+                 \x20   {\"name\": 1}\n\
+                 \x20   {name: 1}"
+            }
+            ErrorCode::TypeMismatch => {
+                "A value was used where a value of a different type was expected,\n\
+                 such as matching against something that is not a class, or a\n\
+                 value that doesn't match its type annotation.\n\n\
+                 \x20   fun add(a: Int, b: Int) -> Int {\n\
+                 \x20       return a + b\n\
+                 \x20   }\n\
+                 \x20   add(1, \"2\")"
+            }
+            ErrorCode::Arithmetic => {
+                "An arithmetic operation has no result, such as dividing an\n\
+                 integer by zero or overflowing the range of integers.\n\n\
+                 \x20   var half = lives / 0\n\n\
+                 Check the divisor first, or use floats, which divide by zero\n\
+                 to infinity."
+            }
+            ErrorCode::NotIterable => {
+                "A for loop was given a value it can't iterate over. Lists,\n\
+                 maps, strings, bytes and generators can be iterated, as can\n\
+                 instances of classes with an `__iter` method returning one of\n\
+                 those.\n\n\
+                 \x20   for x in 10 {}\n\n\
+                 A generator also can't be resumed by a loop inside itself."
+            }
+            ErrorCode::ValueTooLarge => {
+                "The host asked for a value that is nested too deeply, holds too\n\
+                 many items or has a string that is too long, going by the limits\n\
+                 it set with `Thrush::set_host_limits`.\n\n\
+                 \x20   var grid = [...range(10000000)]\n\n\
+                 Send the host smaller values, or ask it to raise the limits."
+            }
+            ErrorCode::TraitNotImplemented => {
+                "A class names a trait after `:` but is missing one of the\n\
+                 trait's methods, or has it with a different number of\n\
+                 parameters.\n\n\
+                 \x20   trait Drawable {\n\
+                 \x20       fun draw(canvas)\n\
+                 \x20   }\n\
+                 \x20   class Sprite : Drawable {}\n\n\
+                 Define every method the trait names, taking the same\n\
+                 parameters:\n\n\
+                 \x20   class Sprite : Drawable {\n\
+                 \x20       fun draw(canvas) {}\n\
+                 \x20   }"
+            }
+            ErrorCode::Native => {
+                "A builtin or host-provided function reported an error, usually\n\
+                 because it was passed arguments of the wrong type. The message\n\
+                 describes what the function expected."
+            }
+            ErrorCode::Uncaught => {
+                "A value was thrown and no enclosing try statement caught it.\n\n\
+                 \x20   throw \"out of lives\"\n\n\
+                 Wrap the code in a try statement to handle the error:\n\n\
+                 \x20   try {\n\
+                 \x20       lose_life()\n\
+                 \x20   } catch (e) {\n\
+                 \x20       game_over(e)\n\
+                 \x20   }"
+            }
+            ErrorCode::Syntax => {
+                "The script could not be parsed. Check for missing brackets,\n\
+                 parentheses or separators near the reported token."
+            }
+            ErrorCode::InvalidAccessor => {
+                "Getters take no parameters and setters take exactly one.\n\n\
+                 \x20   class Box {\n\
+                 \x20       get size() {}\n\
+                 \x20       set size(value) {}\n\
+                 \x20   }"
+            }
+            ErrorCode::NestingLimit => {
+                "Expressions or blocks are nested more deeply than the parser and\n\
+                 compiler allow, for example thousands of parentheses:\n\n\
+                 \x20   ((((((((1))))))))\n\n\
+                 Split the expression up using variables. Hosts can raise the\n\
+                 limit with `Thrush::set_max_depth`."
+            }
+            ErrorCode::ConstantLimit => {
+                "A script or function needs more constants, such as strings and\n\
+                 numbers, than the host's compiler options allow. Split it into\n\
+                 smaller functions, or ask the host to raise the limit with\n\
+                 `CompilerOptions::with_max_constants`."
+            }
+            ErrorCode::Import => {
+                "A module named in an `import` couldn't be loaded. Either the\n\
+                 host's module loader didn't find it, no loader is set, the\n\
+                 module imports itself through a chain of other modules, or the\n\
+                 import isn't at the top level of the script.\n\n\
+                 \x20   import \"math/vec\"\n\
+                 \x20   import \"math/vec\" as v\n\n\
+                 Hosts provide module sources with `Thrush::set_module_loader`."
+            }
+            ErrorCode::Internal => {
+                "The VM reached a state that should be impossible. This is a bug\n\
+                 in Thrush; please report it with the script that caused it."
+            }
+            ErrorCode::UnusedVariable => {
+                "A variable declared in a function or block is never used.\n\n\
+                 \x20   fun area(w, h) {\n\
+                 \x20       var unit = \"cm\"\n\
+                 \x20       return w * h\n\
+                 \x20   }\n\n\
+                 Remove the variable, or start its name with `_` to keep it."
+            }
+            ErrorCode::UnreachableCode => {
+                "A statement follows a `return`, `break`, `continue` or `throw` in\n\
+                 the same block, so it can never run.\n\n\
+                 \x20   return score\n\
+                 \x20   print(score)\n\n\
+                 Move the statement before the jump, or remove it."
+            }
+            ErrorCode::ShadowedName => {
+                "A variable is declared with the name of a variable or parameter\n\
+                 that is already in scope, hiding it until the end of the block.\n\n\
+                 \x20   fun move(speed) {\n\
+                 \x20       var speed = speed * 2\n\
+                 \x20   }\n\n\
+                 Give the new variable a different name."
+            }
+            ErrorCode::UnreadAssignment => {
+                "A variable is assigned to, but its value is never read, so the\n\
+                 assignment has no effect.\n\n\
+                 \x20   var total = 0\n\
+                 \x20   total = count * 2\n\n\
+                 Use the variable, or remove it and the assignments to it."
+            }
+        }
+    }
+}
+
+/// The number of single character insertions, deletions, substitutions and
+/// swaps of adjacent characters needed to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+/// The candidate closest to `name`, if one is close enough to be a likely typo.
+///
+/// A candidate may differ by up to a third of the length of `name`, and at
+/// least one character. Ties go to the alphabetically first candidate so the
+/// suggestion doesn't depend on the order of the candidates.
+pub fn suggest<I>(name: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let max = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let candidate = candidate.as_ref();
+            let distance = edit_distance(name, candidate);
+
+            (candidate != name && distance <= max).then(|| (distance, candidate.to_string()))
+        })
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// A hint to append to an error about `name`, suggesting the closest of
+/// `candidates`, or an empty string if none are close.
+pub fn did_you_mean<I>(name: &str, candidates: I) -> String
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    match suggest(name, candidates) {
+        Some(candidate) => format!("; did you mean '{candidate}'?"),
+        None => String::new(),
+    }
+}
+
+/// An error that stopped a script from compiling or running.
+///
+/// It is written like the messages [ErrorCode::error] makes, with its
+/// position after the code, e.g. `[E0001] line 2:5: undefined variable
+/// 'score'`. A script with several syntax errors is reported at the first,
+/// with the others on lines of their own after its message.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Error {
+    /// The code of the error, unless it came from outside the language, such
+    /// as from a host's native function.
+    pub code: Option<ErrorCode>,
+    /// Where in the script the error happened, if that is known.
+    pub span: Option<Span>,
+    /// What went wrong, without the code or position.
+    pub message: String,
+}
+
+impl Error {
+    /// An error with the code `message` starts with, if it has one.
+    pub fn new<M: Display>(message: M) -> Self {
+        let message = message.to_string();
+
+        match ErrorCode::of_message(&message) {
+            Some(code) => Self {
+                code: Some(code),
+                span: None,
+                message: message[code.code().len() + 2..].trim_start().into(),
+            },
+            None => Self {
+                code: None,
+                span: None,
+                message,
+            },
+        }
+    }
+
+    /// Report the error at `span`, unless it already has a position or
+    /// `span` isn't known.
+    pub fn at(mut self, span: Span) -> Self {
+        if self.span.is_none() && span != Span::default() {
+            self.span = Some(span);
+        }
+
+        self
+    }
+
+    /// The error with the line of `source` it happened at, see [render].
+    pub fn render(&self, source: &str) -> String {
+        render(&self.to_string(), self.span, source)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(code) = self.code {
+            write!(f, "[{}] ", code.code())?;
+        }
+
+        if let Some(span) = self.span {
+            write!(f, "{span}: ")?;
+        }
+
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::new(message)
+    }
+}
+
+impl From<Error> for String {
+    fn from(err: Error) -> Self {
+        err.to_string()
+    }
+}
+
+/// Render `message` with the line of `source` at `span`, and carets under
+/// the code there:
+///
+/// ```text
+/// [E0001] line 2:5: undefined variable 'score'
+///   |
+/// 2 |     score = 0
+///   |     ^^^^^
+/// ```
+///
+/// Messages without a position, or whose line isn't in `source`, are given
+/// back as they are.
+pub fn render(message: &str, span: Option<Span>, source: &str) -> String {
+    let Some(span) = span else {
+        return message.into();
+    };
+    let Some(line) = span
+        .line
+        .checked_sub(1)
+        .and_then(|index| source.lines().nth(index))
+    else {
+        return message.into();
+    };
+
+    let start = span.column.saturating_sub(1);
+    // Keep tabs in the indent, so the carets line up however wide they are.
+    let indent = line
+        .chars()
+        .take(start)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let width = line
+        .chars()
+        .skip(start)
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .count()
+        .max(1);
+    let number = span.line.to_string();
+    let gutter = " ".repeat(number.len());
+
+    format!(
+        "{message}\n{gutter} |\n{number} | {line}\n{gutter} | {indent}{}",
+        "^".repeat(width)
+    )
+}
+
+/// Render the extended explanation of the code `code`, as printed by `thrush explain`.
+pub fn explain(code: &str) -> Option<String> {
+    let code = ErrorCode::from_code(code)?;
+
+    Some(format!(
+        "{}: {}\n\n{}\n",
+        code.code(),
+        code.title(),
+        code.explanation()
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{token::Span, Thrush};
+
+    use super::{edit_distance, explain, render, suggest, Error, ErrorCode};
+
+    #[test]
+    fn test_codes() {
+        for (i, code) in ErrorCode::ALL.iter().enumerate() {
+            assert_eq!(ErrorCode::from_code(code.code()), Some(*code));
+            assert!(ErrorCode::ALL[i + 1..]
+                .iter()
+                .all(|other| other.code() != code.code()));
+        }
+
+        assert_eq!(
+            ErrorCode::WrongArity.error(ErrorCode::Native.error("x")),
+            "[E0301] x"
+        );
+        assert_eq!(
+            Error::new("[E0301] x")
+                .at(Span { line: 1, column: 2 })
+                .at(Span { line: 3, column: 4 })
+                .to_string(),
+            "[E0301] line 1:2: x"
+        );
+        assert_eq!(Error::new("x").at(Span::default()).to_string(), "x");
+        assert!(explain("e0102").unwrap().starts_with("E0102: wrong number"));
+        assert_eq!(explain("E9999"), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "fun reset() {\n\tscore = 0\n}";
+        let err = Thrush::new().exec(source).unwrap_err();
+
+        assert_eq!(err.code, Some(ErrorCode::UndefinedVariable));
+        assert_eq!(err.span, Some(Span { line: 2, column: 2 }));
+        assert_eq!(err.message, "undefined variable 'score'");
+        assert_eq!(
+            err.render(source),
+            "[E0001] line 2:2: undefined variable 'score'\n  |\n2 | \tscore = 0\n  | \t^^^^^"
+        );
+        assert_eq!(
+            render(
+                "[E0401] line 1:9: expected an expression",
+                Some(Span { line: 1, column: 9 }),
+                "var x = +"
+            ),
+            "[E0401] line 1:9: expected an expression\n  |\n1 | var x = +\n  |         ^"
+        );
+        assert_eq!(
+            render("[E0301] bad argument", None, source),
+            "[E0301] bad argument"
+        );
+        assert_eq!(
+            render("oops", Some(Span { line: 7, column: 1 }), source),
+            "oops"
+        );
+    }
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(
+            suggest("plyer", ["enemy", "layer", "player"]),
+            Some("player".into())
+        );
+        assert_eq!(suggest("scroe", ["core", "score"]), Some("score".into()));
+        assert_eq!(suggest("ab", ["ab", "ba"]), Some("ba".into()));
+        assert_eq!(suggest("health", ["speed", "x"]), None);
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let mut thrush = Thrush::new();
+        let error = |thrush: &mut Thrush, script| thrush.exec(script).unwrap_err().to_string();
+
+        thrush
+            .exec(
+                "var player = 1\nclass Bird {\n    var wings\n    fun sing() {}\n}\nvar b = Bird()",
+            )
+            .unwrap();
+
+        assert_eq!(
+            error(&mut thrush, "playr"),
+            "[E0001] line 1:1: undefined variable 'playr'; did you mean 'player'?"
+        );
+        assert_eq!(
+            error(&mut thrush, "b.sng()"),
+            "[E0201] line 1:1: 'Bird' has no property named 'sng'; did you mean 'sing'?"
+        );
+        assert_eq!(
+            error(&mut thrush, "b.wing = 2"),
+            "[E0201] line 1:10: 'Bird' has no field named 'wing'; did you mean 'wings'?"
+        );
+        assert_eq!(
+            error(&mut thrush, "path.jion()"),
+            "[E0201] line 1:1: 'path' has no static method named 'jion'; did you mean 'join'?"
+        );
+        assert_eq!(
+            error(&mut thrush, "fun f(width) {}\nf(widht: 1)"),
+            "[E0103] line 2:1: 'f' has no parameter named 'widht'; did you mean 'width'?"
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_codes() {
+        let mut thrush = Thrush::new();
+        let code = |result: Result<(), Error>| result.unwrap_err().code;
+
+        assert_eq!(
+            code(thrush.exec("fun f(a) {}\nf()")),
+            Some(ErrorCode::WrongArity)
+        );
+        assert_eq!(
+            code(thrush.exec("x = 1")),
+            Some(ErrorCode::UndefinedVariable)
+        );
+        assert_eq!(
+            code(thrush.exec("self")),
+            Some(ErrorCode::SelfOutsideMethod)
+        );
+        assert_eq!(code(thrush.exec("path.ext(1)")), Some(ErrorCode::Native));
+        assert_eq!(code(thrush.exec("var = 1")), Some(ErrorCode::Syntax));
+    }
+}
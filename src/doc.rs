@@ -0,0 +1,56 @@
+//! Docstrings of classes and functions, exposed as the `doc` global.
+//!
+//! A string literal at the start of a class or function body documents it,
+//! and is kept on the class or function at runtime rather than compiled:
+//!
+//! ```text
+//! fun area(w, h) {
+//!     "The area of a w by h rectangle."
+//!     return w * h
+//! }
+//!
+//! var help = doc(area)
+//! ```
+//!
+//! Hosts can read the same text with [Value::doc].
+
+use crate::{scope::State, value::Value};
+
+/// Register the `doc` global.
+pub fn register(state: &mut State) {
+    state.add_function("doc", |args| match args.as_slice() {
+        [value] => Ok(value.doc().map_or(Value::Nil, Value::String)),
+        _ => Err("doc expects a single value".into()),
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{value::Value, Thrush};
+
+    #[test]
+    fn test_doc() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "class Point {\n    \"A point on the map.\"\n    var x\n    fun area() {\n        \"Points have no area.\"\n        return 0\n    }\n}\nfun plain() {\n    return \"not a docstring\"\n}\nvar class_doc = doc(Point)\nvar method_doc = doc(Point().area)\nvar plain_doc = doc(plain)\nvar value_doc = doc(1)",
+            )
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<String>("class_doc"),
+            Ok("A point on the map.".into())
+        );
+        assert_eq!(
+            thrush.globals().get::<String>("method_doc"),
+            Ok("Points have no area.".into())
+        );
+        assert_eq!(thrush.globals().get::<Value>("plain_doc"), Ok(Value::Nil));
+        assert_eq!(thrush.globals().get::<Value>("value_doc"), Ok(Value::Nil));
+
+        let point = thrush.globals().get::<Value>("Point").unwrap();
+
+        assert_eq!(point.doc().as_deref(), Some("A point on the map."));
+    }
+}
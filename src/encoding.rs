@@ -0,0 +1,367 @@
+//! Byte encodings and hash functions, exposed as the `encode`, `decode`
+//! and `hash` globals.
+//!
+//! ```text
+//! var text = encode.base64("hello")
+//! var bytes = decode.base64(text)
+//! var digest = hash.sha256(bytes)
+//! ```
+
+use std::rc::Rc;
+
+use crate::{
+    scope::State,
+    value::{Class, Value},
+};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode bytes as padded, standard-alphabet base64.
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+/// Decode standard-alphabet base64. Padding is optional.
+pub fn base64_decode(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim_end_matches('=');
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in text.bytes() {
+        let value = BASE64
+            .iter()
+            .position(|&b| b == c)
+            .ok_or_else(|| format!("invalid base64 character '{}'", c as char))?;
+
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+
+    if bits >= 6 {
+        return Err("invalid base64 length".into());
+    }
+
+    Ok(out)
+}
+
+/// Encode bytes as lowercase hex.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Decode hex, accepting either case.
+pub fn hex_decode(text: &str) -> Result<Vec<u8>, String> {
+    if !text.len().is_multiple_of(2) {
+        return Err("hex string must have an even length".into());
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("invalid hex at offset {i}"))
+        })
+        .collect()
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Pad a message to a multiple of 64 bytes, ending with its bit length.
+fn pad(data: &[u8], big_endian: bool) -> Vec<u8> {
+    let bits = (data.len() as u64).wrapping_mul(8);
+    let mut message = data.to_vec();
+
+    message.push(0x80);
+
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+
+    if big_endian {
+        message.extend_from_slice(&bits.to_be_bytes());
+    } else {
+        message.extend_from_slice(&bits.to_le_bytes());
+    }
+
+    message
+}
+
+/// Compute the SHA-256 digest of `data`.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    for block in pad(data, true).chunks(64) {
+        let mut w = [0u32; 64];
+
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let temp1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(maj);
+
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 32];
+
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+
+    digest
+}
+
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+/// Compute the MD5 digest of `data`.
+///
+/// MD5 is broken as a cryptographic hash; it is provided for checksums and
+/// compatibility with existing formats only.
+pub fn md5(data: &[u8]) -> [u8; 16] {
+    let k: Vec<u32> = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32)
+        .collect();
+    let mut h: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    for block in pad(data, false).chunks(64) {
+        let mut m = [0u32; 16];
+
+        for (i, word) in block.chunks(4).enumerate() {
+            m[i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        }
+
+        let [mut a, mut b, mut c, mut d] = h;
+
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+
+            let f = f.wrapping_add(a).wrapping_add(k[i]).wrapping_add(m[g]);
+
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(MD5_SHIFTS[i / 16 * 4 + i % 4]));
+        }
+
+        for (state, value) in h.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 16];
+
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+
+    digest
+}
+
+/// Get the bytes of a string or bytes argument.
+fn bytes_arg(name: &str, args: &[Value]) -> Result<Rc<[u8]>, String> {
+    match args {
+        [Value::Bytes(bytes)] => Ok(bytes.clone()),
+        [Value::String(string)] => Ok(string.as_bytes().into()),
+        _ => Err(format!("{name} expects a string or bytes")),
+    }
+}
+
+fn string_arg<'a>(name: &str, args: &'a [Value]) -> Result<&'a str, String> {
+    match args {
+        [Value::String(string)] => Ok(string),
+        _ => Err(format!("{name} expects a string")),
+    }
+}
+
+/// Register the `encode`, `decode` and `hash` globals.
+pub fn register(state: &mut State) {
+    let encode = Class::new("encode");
+
+    encode.add_static_method("base64", |args| {
+        Ok(Value::String(base64_encode(&bytes_arg(
+            "encode.base64",
+            &args,
+        )?)))
+    });
+    encode.add_static_method("hex", |args| {
+        Ok(Value::String(hex_encode(&bytes_arg("encode.hex", &args)?)))
+    });
+    encode.add_static_method("utf8", |args| {
+        Ok(Value::Bytes(
+            string_arg("encode.utf8", &args)?.as_bytes().into(),
+        ))
+    });
+
+    let decode = Class::new("decode");
+
+    decode.add_static_method("base64", |args| {
+        let bytes = base64_decode(string_arg("decode.base64", &args)?)?;
+
+        Ok(Value::Bytes(bytes.into()))
+    });
+    decode.add_static_method("hex", |args| {
+        let bytes = hex_decode(string_arg("decode.hex", &args)?)?;
+
+        Ok(Value::Bytes(bytes.into()))
+    });
+    decode.add_static_method("utf8", |args| {
+        let bytes = bytes_arg("decode.utf8", &args)?;
+
+        String::from_utf8(bytes.to_vec())
+            .map(Value::String)
+            .map_err(|_| "decode.utf8 expects valid utf-8".into())
+    });
+
+    let hash = Class::new("hash");
+
+    hash.add_static_method("sha256", |args| {
+        Ok(Value::String(hex_encode(&sha256(&bytes_arg(
+            "hash.sha256",
+            &args,
+        )?))))
+    });
+    hash.add_static_method("md5", |args| {
+        Ok(Value::String(hex_encode(&md5(&bytes_arg(
+            "hash.md5", &args,
+        )?))))
+    });
+
+    state.add("encode", Value::Class(encode));
+    state.add("decode", Value::Class(decode));
+    state.add("hash", Value::Class(hash));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Thrush;
+
+    use super::{base64_decode, base64_encode, hex_decode, hex_encode, md5, sha256};
+
+    #[test]
+    fn test_base64() {
+        for (plain, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(base64_encode(plain.as_bytes()), encoded);
+            assert_eq!(base64_decode(encoded).unwrap(), plain.as_bytes());
+        }
+
+        assert!(base64_decode("Zm9v!").is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex_encode(&[0, 15, 255]), "000fff");
+        assert_eq!(hex_decode("000FfF").unwrap(), [0, 15, 255]);
+        assert!(hex_decode("abc").is_err());
+    }
+
+    #[test]
+    fn test_digests() {
+        assert_eq!(
+            hex_encode(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex_encode(&sha256(&[b'a'; 64])),
+            "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb"
+        );
+        assert_eq!(hex_encode(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            hex_encode(&md5(b"The quick brown fox jumps over the lazy dog")),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+    }
+
+    #[test]
+    fn test_encoding_script() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("var s = decode.utf8(decode.base64(encode.base64(\"hi\")))\nvar h = hash.md5(s)")
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<String>("s"), Ok("hi".into()));
+        assert_eq!(
+            thrush.globals().get::<String>("h"),
+            Ok("49f68a5c8493ec2c0bf489821c21fc3b".into())
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! The built-in `Error` class, which scripts extend to declare their own
+//! kinds of error:
+//!
+//! ```text
+//! class ParseError : Error {}
+//!
+//! try {
+//!     throw ParseError("unexpected end of input")
+//! } catch (e: ParseError) {
+//!     print(e.stack)
+//! }
+//! ```
+//!
+//! An error has a `message` field, which is set to the argument it is
+//! created with, if any, and a `stack` field that is filled in with the
+//! functions that were running when it is thrown. A catch clause
+//! with a class only catches instances of that class or a class extending
+//! it, and lets anything else carry on to the enclosing try block.
+//!
+//! Errors raised by the interpreter itself are still caught as strings.
+
+use std::rc::Rc;
+
+use crate::{
+    scope::State,
+    value::{Class, Instance, Value},
+};
+
+/// Create the `Error` class.
+pub fn error_class() -> Rc<Class> {
+    let class = Class::new("Error");
+
+    class.add_field("message");
+    class.add_field("stack");
+    class.add_method("__init", init);
+
+    class
+}
+
+/// `Error(message)`, which classes extending `Error` inherit unless they
+/// define their own `__init`.
+fn init(error: Rc<Instance>, args: Vec<Value>) -> Value {
+    if let Some(message) = args.into_iter().next() {
+        let _ = error.set_field("message", message);
+    }
+
+    Value::Nil
+}
+
+/// Register the `Error` global.
+pub fn register(state: &mut State, class: Rc<Class>) {
+    state.add("Error", Value::Class(class));
+}
+
+/// Fill in the stack trace of an error that is being thrown, unless it
+/// already has one, where `calls` are the names of the functions running,
+/// outermost first.
+pub(crate) fn fill_stack(error: &Instance, calls: &[Box<str>]) {
+    if error.get_field("stack") != Some(Value::Nil) {
+        return;
+    }
+
+    let trace = calls
+        .iter()
+        .rev()
+        .map(|name| format!("at {name}"))
+        .chain(["at <script>".to_string()])
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let _ = error.set_field("stack", Value::String(trace));
+}
+
+/// The message to report when `error` is not caught.
+pub(crate) fn describe(error: &Instance) -> String {
+    match error.get_field("message") {
+        Some(Value::String(message)) => format!("{}: {message}", error.class.name),
+        _ => error.class.name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{value::Value, Thrush};
+
+    #[test]
+    fn test_error_class() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                r#"class ParseError : Error {}
+class LexError : ParseError {
+    var offset
+}
+fun lex() {
+    var err = LexError("bad token")
+    err.offset = 3
+    throw err
+}
+fun parse() { lex() }
+var caught = nil
+try {
+    parse()
+} catch (e: ParseError) {
+    caught = e
+}
+var stack = caught.stack
+var message = caught.message
+var is_error = caught is Error
+var cleaned = false
+fun rethrow() {
+    try {
+        throw Error()
+    } catch (e: ParseError) {
+    } finally {
+        cleaned = true
+    }
+}
+var base = nil
+try { rethrow() } catch (e: Error) { base = e.stack }
+var plain = nil
+try {
+    try {
+        throw "plain"
+    } catch (e: ParseError) {
+        plain = "wrong"
+    }
+} catch (e) {
+    plain = e
+}"#,
+            )
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("stack"), "at lex\nat parse\nat <script>");
+        assert_eq!(global("message"), "bad token");
+        assert_eq!(global("is_error"), "true");
+        assert_eq!(global("plain"), "plain");
+        assert_eq!(global("cleaned"), "true");
+        assert_eq!(global("base"), "at rethrow\nat <script>");
+        assert_eq!(
+            thrush
+                .exec("var err = ParseError()\nerr.message = \"oops\"\nthrow err")
+                .map_err(|err| err.to_string()),
+            Err("[E0302] line 3:7: ParseError: oops".into())
+        );
+        assert_eq!(
+            thrush
+                .exec(r#"throw ParseError("bad")"#)
+                .map_err(|err| err.to_string()),
+            Err("[E0302] line 1:7: ParseError: bad".into())
+        );
+        assert!(thrush
+            .exec("class Twice : ParseError, Error {}")
+            .unwrap_err()
+            .to_string()
+            .contains("can only extend one class"));
+    }
+}
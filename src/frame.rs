@@ -0,0 +1,65 @@
+//! Frame timing, exposed as the `frame` global.
+//!
+//! The host advances the clock through [Thrush::update](crate::Thrush::update),
+//! so scripts can do time-based logic without access to the wall clock.
+//! `frame.dt()` is the length of the last frame in seconds and
+//! `frame.number()` counts the frames so far.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    scope::State,
+    value::{Class, Value},
+};
+
+/// The timing of the current frame.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Frame {
+    /// The length of the last frame in seconds.
+    pub dt: f64,
+    /// The number of frames that have been started.
+    pub number: u64,
+}
+
+impl Frame {
+    /// Start a new frame that is `dt` seconds after the last one.
+    pub fn advance(&mut self, dt: f64) {
+        self.dt = dt;
+        self.number += 1;
+    }
+}
+
+/// Register the `frame` global, which reads from `frame`.
+pub fn register(state: &mut State, frame: Rc<RefCell<Frame>>) {
+    let class = Class::new("frame");
+    let dt = frame.clone();
+
+    class.add_static_method("dt", move |_| Ok(Value::Float(dt.borrow().dt)));
+    class.add_static_method("number", move |_| {
+        Ok(Value::Integer(frame.borrow().number as i64))
+    });
+
+    state.add("frame", Value::Class(class));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Thrush;
+
+    #[test]
+    fn test_frame() {
+        let mut thrush = Thrush::new();
+
+        thrush.exec("var n = frame.number()").unwrap();
+        assert_eq!(thrush.globals().get::<i64>("n"), Ok(0));
+
+        thrush.update(0.5).unwrap();
+        thrush.update(0.25).unwrap();
+        thrush
+            .exec("var dt = frame.dt()\nvar n = frame.number()")
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<f64>("dt"), Ok(0.25));
+        assert_eq!(thrush.globals().get::<i64>("n"), Ok(2));
+    }
+}
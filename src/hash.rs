@@ -1,13 +1,36 @@
 use std::{
-    collections::hash_map::DefaultHasher,
+    collections::{hash_map::DefaultHasher, HashMap},
     fmt::Debug,
-    hash::{self, Hasher},
+    hash::{self, BuildHasherDefault, Hasher},
 };
 
-// implement custom hasher?
-#[derive(PartialEq, Clone, Copy, hash::Hash)]
+#[derive(PartialEq, Eq, Clone, Copy, hash::Hash)]
 pub struct Hash(u64);
 
+/// A map keyed by [Hash]es, which uses them as they are rather than hashing
+/// them again.
+pub type HashTable<V> = HashMap<Hash, V, BuildHasherDefault<Prehashed>>;
+
+/// A [Hasher] for keys that are already hashes.
+#[derive(Default)]
+pub struct Prehashed(u64);
+
+impl Hasher for Prehashed {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(*byte);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 = value;
+    }
+}
+
 impl Hash {
     pub fn of<H: hash::Hash>(value: H) -> Self {
         let mut hasher = DefaultHasher::new();
@@ -32,4 +55,14 @@ mod test {
         assert_eq!(Hash::of("name"), Hash::of("name"));
         assert_ne!(Hash::of("a"), Hash::of("b"));
     }
+
+    #[test]
+    fn test_hash_table() {
+        let mut table = super::HashTable::default();
+
+        table.insert(Hash::of("add"), 1);
+
+        assert_eq!(table.get(&Hash::of("add")), Some(&1));
+        assert_eq!(table.get(&Hash::of("sub")), None);
+    }
 }
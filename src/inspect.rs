@@ -0,0 +1,186 @@
+//! Canonical, multi-line dumps of values, exposed as the `inspect` global.
+//!
+//! The output is stable across runs so it can be diffed or stored as a golden
+//! file: map keys are sorted, floats always keep their decimal point, and
+//! every element of a container sits on its own line.
+//!
+//! ```text
+//! {
+//!   name: "ann",
+//!   scores: [
+//!     1.0,
+//!     2.5,
+//!   ],
+//! }
+//! ```
+
+use std::collections::HashSet;
+
+use crate::{
+    repr::PrintLimits,
+    scope::State,
+    value::{Instance, Value},
+};
+
+const INDENT: &str = "  ";
+
+/// Render `value` as a canonical multi-line string.
+pub fn inspect(value: &Value, limits: PrintLimits) -> String {
+    let mut inspector = Inspector {
+        out: String::new(),
+        limits,
+        seen: HashSet::new(),
+        items: 0,
+    };
+
+    inspector.value(value, 0);
+    inspector.out
+}
+
+struct Inspector {
+    out: String,
+    limits: PrintLimits,
+    /// The addresses of the containers currently being inspected.
+    seen: HashSet<usize>,
+    /// The number of elements written so far.
+    items: usize,
+}
+
+impl Inspector {
+    fn value(&mut self, value: &Value, depth: usize) {
+        match value {
+            Value::String(v) => self.out.push_str(&format!("{v:?}")),
+            Value::Float(v) => self.out.push_str(&format!("{v:?}")),
+            Value::List(list) => {
+                let items = list.borrow();
+                let entries = items.iter().map(|item| (None, item)).collect::<Vec<_>>();
+
+                self.container(list.as_ptr() as usize, ("[", "]"), &entries, depth);
+            }
+            Value::Map(cell) => {
+                let map = cell.borrow();
+                let mut entries = map
+                    .iter()
+                    .map(|(key, value)| (Some(key), value))
+                    .collect::<Vec<_>>();
+
+                entries.sort_by_key(|(key, _)| *key);
+
+                self.container(cell.as_ptr() as usize, ("{", "}"), &entries, depth);
+            }
+            Value::Instance(instance) => self.instance(instance, depth),
+            value => self.out.push_str(&value.to_string()),
+        }
+    }
+
+    fn instance(&mut self, instance: &Instance, depth: usize) {
+        let fields = instance.class.fields.borrow();
+        let values = instance.fields();
+        let entries = fields
+            .iter()
+            .zip(values.iter())
+            .map(|(name, value)| (Some(&**name), value))
+            .collect::<Vec<_>>();
+
+        self.out.push_str(&instance.class.name);
+        self.out.push(' ');
+        self.container(
+            instance as *const Instance as usize,
+            ("{", "}"),
+            &entries,
+            depth,
+        );
+    }
+
+    fn container(
+        &mut self,
+        address: usize,
+        (open, close): (&str, &str),
+        entries: &[(Option<&str>, &Value)],
+        depth: usize,
+    ) {
+        if entries.is_empty() {
+            self.out.push_str(open);
+            self.out.push_str(close);
+            return;
+        }
+
+        if depth >= self.limits.max_depth || !self.seen.insert(address) {
+            self.out.push_str(&format!("{open}...{close}"));
+            return;
+        }
+
+        self.out.push_str(open);
+        self.out.push('\n');
+
+        for (key, value) in entries {
+            self.out.push_str(&INDENT.repeat(depth + 1));
+
+            if self.items >= self.limits.max_items {
+                self.out.push_str("...\n");
+                break;
+            }
+
+            self.items += 1;
+
+            if let Some(key) = key {
+                self.out.push_str(key);
+                self.out.push_str(": ");
+            }
+
+            self.value(value, depth + 1);
+            self.out.push_str(",\n");
+        }
+
+        self.seen.remove(&address);
+
+        self.out.push_str(&INDENT.repeat(depth));
+        self.out.push_str(close);
+    }
+}
+
+/// Register the `inspect` global.
+pub fn register(state: &mut State) {
+    state.add_function("inspect", |args| match args.as_slice() {
+        [value] => Ok(Value::String(inspect(value, PrintLimits::default()))),
+        _ => Err("inspect expects a single value".into()),
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{repr::PrintLimits, value::Value, Thrush};
+
+    use super::inspect;
+
+    #[test]
+    fn test_inspect() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("class P {\n    var x\n    var y\n}\nvar p = P()\np.x = 1.0\nvar v = {b: [], a: [p, \"s\"]}")
+            .unwrap();
+
+        let value = thrush.globals().get::<Value>("v").unwrap();
+
+        assert_eq!(
+            inspect(&value, PrintLimits::default()),
+            "{\n  a: [\n    P {\n      x: 1.0,\n      y: nil,\n    },\n    \"s\",\n  ],\n  b: [],\n}"
+        );
+    }
+
+    #[test]
+    fn test_inspect_cycle() {
+        let list = Value::list(vec![]);
+
+        if let Value::List(inner) = &list {
+            inner.borrow_mut().push(list.clone());
+        }
+
+        assert_eq!(inspect(&list, PrintLimits::default()), "[\n  [...],\n]");
+
+        if let Value::List(inner) = &list {
+            inner.borrow_mut().clear();
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::value::Value;
+use crate::{hash::Hash, value::Value};
 
 /// An instruction in a stack-based virtual machine.
 ///
@@ -10,20 +10,181 @@ pub enum Instruction {
     Push { value: InstanceValue },
     /// Pop a value off the stack.
     Pop,
+    /// Swap the top two values on the stack.
+    Swap,
+    /// Push a copy of the value `depth` slots down from the top of the stack.
+    Dup { depth: usize },
+    /// Pop a sequence and push its `len` items, in order.
+    Unpack { len: usize },
+    /// Pop a map and push the value of the key named by a variable.
+    GetKey { index: usize },
+    /// Push a value from the chunk's constant pool onto the stack.
+    Constant { index: usize },
     /// Construct a new class.
     Class { index: usize },
-    /// Call the value on top of the stack.
-    Call,
+    /// Declare a field on the class on top of the stack.
+    Field { index: usize },
+    /// Set the version of the class on top of the stack.
+    Version { version: u32 },
+    /// Set the docstring of the class on top of the stack to a string
+    /// constant.
+    Doc { index: usize },
+    /// Add the function on top of the stack as a method of the class below it.
+    Method { index: usize },
+    /// Add the function on top of the stack as a static method of the class below it.
+    StaticMethod { index: usize },
+    /// Pop a value and store it on the class below it as a static field,
+    /// which can't be assigned if it is `constant`.
+    StaticField { index: usize, constant: bool },
+    /// Add the function on top of the stack as a property getter of the class below it.
+    Getter { index: usize },
+    /// Add the function on top of the stack as a property setter of the class below it.
+    Setter { index: usize },
+    /// Pop `len` values and the class below them, and push an instance of
+    /// the class whose fields are set to the values in order.
+    Object { len: usize },
+    /// Pop a trait and check that the class below it implements it, or pop
+    /// a class and make the class below it extend it.
+    Implement,
+    /// Pop a class and copy its fields, methods, getters and setters into
+    /// the class below it.
+    Mixin,
+    /// Call the property named by the variable at `index` of the value
+    /// below the top `argc` arguments on the stack, like
+    /// [Instruction::GetProperty] followed by [Instruction::Call], but
+    /// without binding a method of an instance to it first. `hash` is the
+    /// hash of the name, which instances look their methods up by, and
+    /// `argc` is narrower than elsewhere to keep instructions small.
+    CallMethod { index: usize, hash: Hash, argc: u32 },
+    /// Call the value below the top `argc` arguments on the stack.
+    Call { argc: usize },
+    /// Like `Call`, but the last arguments are matched to parameters by the
+    /// names in the list constant at `names`.
+    CallNamed { argc: usize, names: usize },
+    /// Return from the current function with the value on top of the stack.
+    Return,
     /// Load a `nil` value onto the stack.
     LoadNil,
+    /// Collect the top `len` values on the stack into a list.
+    List { len: usize },
+    /// Collect the top `len` key-value pairs on the stack into a map.
+    Map { len: usize },
+    /// Pop a value and append its items to the list below it, or insert its
+    /// entries into the map below it.
+    Extend,
+    /// Call the value below the list on top of the stack with the list's
+    /// items as arguments.
+    CallSpread,
     /// Access a property from the instance on top of the stack.
     GetProperty { index: usize },
-    /// Define a new global.
-    DefineGlobal { index: usize },
-    /// Set a global's value to what's on top of the stack.
-    SetGlobal { index: usize },
-    /// Load a global onto the stack.
-    GetGlobal { index: usize },
+    /// Set a property on the instance below the value on top of the stack.
+    SetProperty { index: usize },
+    /// Define the global in `slot` of the state, see [State::slot].
+    ///
+    /// [State::slot]: crate::scope::State::slot
+    DefineGlobal { slot: usize },
+    /// Set the global in `slot` to what's on top of the stack.
+    SetGlobal { slot: usize },
+    /// Load the global in `slot` onto the stack.
+    GetGlobal { slot: usize },
+    /// Load a local from the current call frame onto the stack.
+    GetLocal { slot: usize },
+    /// Set a local in the current call frame to what's on top of the stack.
+    SetLocal { slot: usize },
+    /// Push a closure of the function constant at `index`, capturing the
+    /// upvalues it lists, see [Capture](crate::value::Capture).
+    MakeClosure { index: usize },
+    /// Load the upvalue at `index` of the running closure onto the stack.
+    GetUpvalue { index: usize },
+    /// Set the upvalue at `index` of the running closure to what's on top
+    /// of the stack.
+    SetUpvalue { index: usize },
+    /// Move the locals from `slot` up that closures captured off the stack,
+    /// before they go out of scope.
+    CloseUpvalue { slot: usize },
+    /// Push the sum of the locals in slots `a` and `b`, like two
+    /// [Instruction::GetLocal]s followed by [Instruction::Add].
+    AddLocals { a: usize, b: usize },
+    /// Replace the instance on top of the stack with the value of its field at `slot`.
+    GetField { slot: usize },
+    /// Pop two values and push their sum, or the strings joined together.
+    Add,
+    /// Pop two values and push their difference.
+    Sub,
+    /// Pop two values and push their product, or a string repeated a number of times.
+    Mul,
+    /// Pop two values and push their quotient.
+    Div,
+    /// Pop two values and push the remainder of dividing them.
+    Rem,
+    /// Pop a number and push it negated.
+    Neg,
+    /// Pop a value and push whether it is falsey.
+    Not,
+    /// Pop two values and push whether they are equal, comparing integers
+    /// and floats by their values.
+    Equal,
+    /// Pop two values and push whether they are not equal.
+    NotEqual,
+    /// Pop two numbers or strings and push whether the first is less than the second.
+    Less,
+    /// Pop two numbers or strings and push whether the first is less than or equal to the second.
+    LessEqual,
+    /// Pop two numbers or strings and push whether the first is greater than the second.
+    Greater,
+    /// Pop two numbers or strings and push whether the first is greater than or equal to the second.
+    GreaterEqual,
+    /// Pop a class and a value and push whether the value is an instance of the class.
+    IsInstance,
+    /// Pop a value and push the name of its type.
+    TypeOf,
+    /// Pop an end and a start, with a step on top of them if `step` is set,
+    /// and push an iterator over the integers in that range.
+    Range { step: bool },
+    /// Skip forward over the next `offset` instructions.
+    Jump { offset: usize },
+    /// Pop a value and skip forward over the next `offset` instructions if it is falsey.
+    JumpIfFalse { offset: usize },
+    /// Pop a value and skip forward over the next `offset` instructions if it is truthy.
+    JumpIfTrue { offset: usize },
+    /// Skip forward over the next `offset` instructions, leaving the value
+    /// on top of the stack, if it is falsey, or else pop it.
+    JumpIfFalseOrPop { offset: usize },
+    /// Skip forward over the next `offset` instructions, leaving the value
+    /// on top of the stack, if it is truthy, or else pop it.
+    JumpIfTrueOrPop { offset: usize },
+    /// Jump back over the previous `offset` instructions, including this one.
+    Loop { offset: usize },
+    /// Pop a value and skip forward by the offset the jump table at `index`
+    /// gives for it.
+    JumpTable { index: usize },
+    /// Start a try block whose handler is `offset` instructions ahead.
+    ///
+    /// If an error is raised before the matching `EndTry`, the stack is
+    /// unwound to its height here, the error is pushed, and execution
+    /// continues at the handler.
+    Try { offset: usize },
+    /// End the innermost try block.
+    EndTry,
+    /// Pop a value and raise it as an error.
+    Throw,
+    /// Replace the value on top of the stack with something a for loop can
+    /// iterate over, calling its `__iter` method if it has one, and push a
+    /// cursor for `ForNext` above it.
+    Iter,
+    /// Push the next value of the iterable in the local `slot`, whose cursor
+    /// is in the slot above it, or skip forward over the next `offset`
+    /// instructions if it has run out.
+    ForNext { slot: usize, offset: usize },
+    /// Suspend the current generator, producing the value on top of the stack.
+    Yield,
+    /// Replace the value on top of the stack with what it resolves to,
+    /// suspending the current async function until then if it is a pending
+    /// promise.
+    Await,
+    /// Fail unless the value on top of the stack has the type named by
+    /// variable `ty`, describing the value with variable `what`.
+    CheckType { ty: usize, what: usize },
     /// Halt the current VM.
     Halt,
 }
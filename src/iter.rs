@@ -0,0 +1,260 @@
+//! Lazy sequence utilities, exposed as the `range`, `zip`, `enumerate`,
+//! `take` and `chunk` globals.
+//!
+//! ```text
+//! for i in range(0, 100, 5) {}
+//! for i in 10..0 step 0 - 2 {}
+//! for pair in zip(names, scores) {}
+//! for entry in enumerate(lines) {}
+//! for batch in chunk(take(events(), 100), 10) {}
+//! ```
+//!
+//! Each returns an iterator that works out its items as a `for` loop asks
+//! for them, so they can wrap generators that never end. They accept
+//! anything a loop can go through: lists, maps, strings, bytes, generators,
+//! other iterators and instances with an `__iter` method.
+
+use std::rc::Rc;
+
+use crate::{
+    diagnostic::ErrorCode,
+    scope::State,
+    value::{Iter, Value},
+    vm::{Vm, VmError},
+};
+
+/// Where an iterator is in going through one of its arguments.
+struct Cursor {
+    iterable: Value,
+    position: usize,
+    done: bool,
+}
+
+impl Cursor {
+    fn new(vm: &mut Vm, value: Value) -> Result<Self, VmError> {
+        Ok(Self {
+            iterable: vm.iterable(value)?,
+            position: 0,
+            done: false,
+        })
+    }
+
+    /// Get the next item, or `None` from the first time there isn't one.
+    fn next(&mut self, vm: &mut Vm) -> Result<Option<Value>, VmError> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let next = vm.next_item(&self.iterable, &mut self.position)?;
+
+        self.done = next.is_none();
+
+        Ok(next)
+    }
+}
+
+fn error(message: &str) -> VmError {
+    VmError::new(ErrorCode::Native, message)
+}
+
+fn iter<F>(name: &str, next: F) -> Value
+where
+    F: FnMut(&mut Vm) -> Result<Option<Value>, VmError> + 'static,
+{
+    Value::Iter(Rc::new(Iter::new(name, next)))
+}
+
+/// An iterator over the integers from `start` up to, but not including,
+/// `end`, going `step` at a time. A negative step counts down.
+pub(crate) fn range(start: i64, end: i64, step: i64) -> Result<Value, String> {
+    if step == 0 {
+        return Err("range step can't be 0".into());
+    }
+
+    let mut next = Some(start);
+
+    Ok(iter("range", move |_| {
+        let Some(current) = next else {
+            return Ok(None);
+        };
+
+        if (step > 0 && current >= end) || (step < 0 && current <= end) {
+            return Ok(None);
+        }
+
+        // Stop rather than overflow past the largest or smallest integer.
+        next = current.checked_add(step);
+
+        Ok(Some(Value::Integer(current)))
+    }))
+}
+
+/// Register the sequence utility globals.
+pub fn register(state: &mut State) {
+    state.add_function("range", |args| match args.as_slice() {
+        [Value::Integer(end)] => range(0, *end, 1),
+        [Value::Integer(start), Value::Integer(end)] => range(*start, *end, 1),
+        [Value::Integer(start), Value::Integer(end), Value::Integer(step)] => {
+            range(*start, *end, *step)
+        }
+        _ => Err("range expects an end, a start and an end, or a start, an end and a step".into()),
+    });
+
+    state.add_reentrant_function("zip", |vm, args| {
+        if args.is_empty() {
+            return Err(error("zip expects at least one iterable"));
+        }
+
+        let mut cursors = args
+            .into_iter()
+            .map(|arg| Cursor::new(vm, arg))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Stop at the end of the shortest iterable.
+        Ok(iter("zip", move |vm| {
+            let mut items = Vec::with_capacity(cursors.len());
+
+            for cursor in &mut cursors {
+                match cursor.next(vm)? {
+                    Some(item) => items.push(item),
+                    None => return Ok(None),
+                }
+            }
+
+            Ok(Some(Value::list(items)))
+        }))
+    });
+
+    state.add_reentrant_function("enumerate", |vm, args| {
+        let mut cursor = match <[Value; 1]>::try_from(args) {
+            Ok([iterable]) => Cursor::new(vm, iterable)?,
+            Err(_) => return Err(error("enumerate expects an iterable")),
+        };
+        let mut index = 0;
+
+        Ok(iter("enumerate", move |vm| {
+            let Some(item) = cursor.next(vm)? else {
+                return Ok(None);
+            };
+
+            index += 1;
+
+            Ok(Some(Value::list(vec![Value::Integer(index - 1), item])))
+        }))
+    });
+
+    state.add_reentrant_function("take", |vm, args| {
+        let (mut cursor, mut left) = match <[Value; 2]>::try_from(args) {
+            Ok([iterable, Value::Integer(count)]) if count >= 0 => {
+                (Cursor::new(vm, iterable)?, count)
+            }
+            _ => return Err(error("take expects an iterable and a count of at least 0")),
+        };
+
+        // Stop before asking for an item past the count, so a generator
+        // doesn't run further than it needs to.
+        Ok(iter("take", move |vm| {
+            if left == 0 {
+                return Ok(None);
+            }
+
+            left -= 1;
+            cursor.next(vm)
+        }))
+    });
+
+    state.add_reentrant_function("chunk", |vm, args| {
+        let (mut cursor, size) = match <[Value; 2]>::try_from(args) {
+            Ok([iterable, Value::Integer(size)]) if size > 0 => {
+                (Cursor::new(vm, iterable)?, size as usize)
+            }
+            _ => return Err(error("chunk expects an iterable and a size of at least 1")),
+        };
+
+        // The last chunk holds whatever is left, so it can be shorter.
+        Ok(iter("chunk", move |vm| {
+            let mut items = Vec::with_capacity(size);
+
+            while items.len() < size {
+                match cursor.next(vm)? {
+                    Some(item) => items.push(item),
+                    None => break,
+                }
+            }
+
+            Ok((!items.is_empty()).then(|| Value::list(items)))
+        }))
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{value::Value, Thrush};
+
+    #[test]
+    fn test_iter() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("fun naturals() {\n    var n = 0\n    while true {\n        yield n\n        n = n + 1\n    }\n}\nvar r = [...range(3)]\nvar from = [...range(2, 5)]\nvar zipped = [...zip([1, 2, 3], \"ab\")]\nvar counted = [...enumerate([\"x\", \"y\"])]\nvar first = [...take(naturals(), 4)]\nvar batches = [...chunk(range(5), 2)]\nvar pairs = [...take(zip(naturals(), range(10, 20)), 2)]\nvar total = 0\nfor i in range(5) {\n    total = total + i\n}")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("r"), "[0, 1, 2]");
+        assert_eq!(global("from"), "[2, 3, 4]");
+        assert_eq!(global("zipped"), "[[1, \"a\"], [2, \"b\"]]");
+        assert_eq!(global("counted"), "[[0, \"x\"], [1, \"y\"]]");
+        assert_eq!(global("first"), "[0, 1, 2, 3]");
+        assert_eq!(global("batches"), "[[0, 1], [2, 3], [4]]");
+        assert_eq!(global("pairs"), "[[0, 10], [1, 11]]");
+        assert_eq!(global("total"), "10");
+
+        thrush
+            .exec("var it = range(2)\nvar typ = typeof it")
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<String>("typ"), Ok("iterator".into()));
+        assert_eq!(
+            thrush.globals().get::<Value>("it").unwrap().to_string(),
+            "<iterator range>"
+        );
+        assert!(thrush
+            .exec("var bad = zip([1], 2)")
+            .unwrap_err()
+            .to_string()
+            .contains("int '2' is not iterable"));
+        assert!(thrush.exec("var bad = chunk([1], 0)").is_err());
+        assert!(thrush.exec("var bad = take([1], 0 - 1)").is_err());
+    }
+
+    #[test]
+    fn test_range_step() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("var stepped = [...range(0, 10, 3)]\nvar down = [...range(5, 0, 0 - 2)]\nvar short = [...0..3]\nvar sum = 0\nfor i in 0..100 step 5 + 5 {\n    sum = sum + i\n}\nvar countdown = []\nfor i in 3..0 step 0 - 1 {\n    countdown = [...countdown, i]\n}\nvar empty = [...5..0]")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("stepped"), "[0, 3, 6, 9]");
+        assert_eq!(global("down"), "[5, 3, 1]");
+        assert_eq!(global("short"), "[0, 1, 2]");
+        assert_eq!(global("sum"), "450");
+        assert_eq!(global("countdown"), "[3, 2, 1]");
+        assert_eq!(global("empty"), "[]");
+        assert_eq!(
+            thrush
+                .exec("var bad = 0..5 step 0")
+                .map_err(|err| err.to_string()),
+            Err("[E0205] line 1:21: range step can't be 0".into())
+        );
+        assert!(thrush
+            .exec("var bad = 0..\"5\"")
+            .unwrap_err()
+            .to_string()
+            .contains("a range needs integers"));
+        assert!(thrush.exec("var bad = range(0, 5, 0)").is_err());
+    }
+}
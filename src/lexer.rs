@@ -1,4 +1,6 @@
-use crate::token::{Lit, Token, TokenKind, Keyword};
+use std::{cmp::Reverse, collections::HashMap, mem};
+
+use crate::token::{Keyword, Lit, Span, Token, TokenKind};
 
 /// Helper struct for reading a string.
 pub struct StringReader<'a> {
@@ -59,41 +61,168 @@ impl<'a> StringReader<'a> {
 }
 
 pub trait ToStringReader {
-    fn to_string_reader(&self) -> StringReader;
+    fn to_string_reader(&self) -> StringReader<'_>;
 }
 
 impl ToStringReader for &str {
-    fn to_string_reader(&self) -> StringReader {
+    fn to_string_reader(&self) -> StringReader<'_> {
         StringReader::new(self)
     }
 }
 
+/// The characters custom operators can be spelled with.
+const OPERATOR_CHARS: &str = "+-*/%!~.=:<>&|^@#$?";
+
+/// The built-in tokens a custom operator can't be spelled as.
+const RESERVED_SYMBOLS: [&str; 25] = [
+    "+", "++", "-", "--", "*", "/", "%", "!", "~", ".", "...", "=", "=>", "->", ":", ",", "@", "<",
+    "<=", ">", ">=", "==", "!=", "&&", "||",
+];
+
+/// Check that `symbol` can be registered as a custom operator.
+pub fn check_operator(symbol: &str) -> Result<(), String> {
+    if symbol.is_empty() || !symbol.chars().all(|c| OPERATOR_CHARS.contains(c)) {
+        return Err(format!(
+            "'{symbol}' is not a valid operator; operators are spelled with the characters {OPERATOR_CHARS}"
+        ));
+    }
+
+    if RESERVED_SYMBOLS.contains(&symbol) {
+        return Err(format!("'{symbol}' is already an operator"));
+    }
+
+    if symbol.contains("//") {
+        return Err(format!(
+            "'{symbol}' can't be an operator, since '//' starts a comment"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Options that change how source is split into tokens.
+///
+/// Embedders can give keywords extra spellings, so the language can read
+/// like a domain-specific language without changing the grammar:
+///
+/// ```
+/// use thrush::lexer::LexerConfig;
+///
+/// let mut config = LexerConfig::new();
+///
+/// config.alias("rule", "fun").unwrap();
+/// config.alias("when", "match").unwrap();
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct LexerConfig {
+    /// Extra spellings of keywords.
+    aliases: HashMap<String, Keyword>,
+    /// The symbols of custom operators, longest first.
+    operators: Vec<String>,
+}
+
+impl LexerConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let `alias` be written in place of the keyword `keyword`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `keyword` is not a keyword, or if `alias` is
+    /// already a keyword or is not a valid identifier.
+    pub fn alias(&mut self, alias: &str, keyword: &str) -> Result<(), String> {
+        let target =
+            self::keyword(keyword).ok_or_else(|| format!("'{keyword}' is not a keyword"))?;
+
+        if self::keyword(alias).is_some() {
+            return Err(format!("'{alias}' is already a keyword"));
+        }
+
+        if !is_identifier(alias) {
+            return Err(format!("'{alias}' is not a valid identifier"));
+        }
+
+        self.aliases.insert(alias.into(), target);
+
+        Ok(())
+    }
+
+    /// Read `symbol` as a single [TokenKind::Operator].
+    ///
+    /// Symbols are matched longest first, so `**` is one token rather than
+    /// two `*`s.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `symbol` is a built-in token or isn't spelled
+    /// with operator characters.
+    pub fn operator(&mut self, symbol: &str) -> Result<(), String> {
+        check_operator(symbol)?;
+
+        if !self.operators.iter().any(|op| op == symbol) {
+            self.operators.push(symbol.into());
+            self.operators.sort_by_key(|op| Reverse(op.len()));
+        }
+
+        Ok(())
+    }
+}
+
 /// Generates a stream of [Token]s from input.
+///
+/// Iterating over a lexer produces its tokens lazily, ending with a single
+/// [TokenKind::Eof].
 pub struct Lexer<'a> {
     pub reader: StringReader<'a>,
+    /// Whether the end of the input has been reached.
+    done: bool,
+    config: Option<&'a LexerConfig>,
+    /// Whether the next string is the text of a tagged template.
+    template: bool,
+    /// The unclosed braces inside each template interpolation being lexed,
+    /// innermost last.
+    interpolations: Vec<usize>,
+    /// How far into the source lines and columns have been counted, and
+    /// the position there.
+    counted: (usize, Span),
+    /// Whether comments are kept as tokens rather than skipped.
+    comments: bool,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(src: &'a str) -> Self {
         Self {
             reader: StringReader::new(src),
+            done: false,
+            config: None,
+            template: false,
+            interpolations: Vec::new(),
+            counted: (0, Span { line: 1, column: 1 }),
+            comments: false,
         }
     }
 
-    pub fn tokenize(src: &'a str) -> Vec<Token> {
-        let mut lexer = Lexer::new(src);
-        let mut tokens = Vec::new();
+    /// Keep comments as [TokenKind::Comment] tokens rather than skipping
+    /// them, for tools that need them, such as formatters.
+    ///
+    /// The parser sets the comments aside in [Ast::comments](crate::ast::Ast::comments).
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
 
-        loop {
-            if lexer.reader.peek().is_none() {
-                tokens.push(lexer.next_token());
-                break;
-            } else {
-                tokens.push(lexer.next_token());
-            }
+    /// Create a lexer that uses the options in `config`.
+    pub fn with_config(src: &'a str, config: &'a LexerConfig) -> Self {
+        Self {
+            config: Some(config),
+            ..Self::new(src)
         }
+    }
 
-        tokens
+    pub fn tokenize(src: &'a str) -> Vec<Token> {
+        Lexer::new(src).collect()
     }
 
     fn make_token(&mut self, kind: TokenKind) -> Token {
@@ -105,7 +234,10 @@ impl<'a> Lexer<'a> {
     }
 
     fn skip_whitespace(&mut self) {
-        while self.reader.peek().is_some() && is_whitespace(self.reader.peek().unwrap()) {
+        while self.reader.peek().is_some()
+            && self.reader.peek() != Some("\n")
+            && is_whitespace(self.reader.peek().unwrap())
+        {
             self.reader.advance();
         }
 
@@ -113,8 +245,17 @@ impl<'a> Lexer<'a> {
     }
 
     fn number(&mut self) -> Token {
-        while self.reader.peek().is_some() && is_numeric(self.reader.peek().unwrap()) {
+        self.digits();
+
+        let remaining = self.reader.remaining();
+
+        if remaining.starts_with('.') && remaining[1..].starts_with(|c: char| c.is_ascii_digit()) {
             self.reader.advance();
+            self.digits();
+
+            let num = &self.reader.next_token();
+
+            return Token::new(TokenKind::Literal(Lit::Float(num.parse::<f64>().unwrap())));
         }
 
         let num = &self.reader.next_token();
@@ -122,41 +263,279 @@ impl<'a> Lexer<'a> {
         Token::new(TokenKind::Literal(Lit::Integer(num.parse::<i64>().unwrap())))
     }
 
+    fn digits(&mut self) {
+        while self.reader.peek().is_some() && is_numeric(self.reader.peek().unwrap()) {
+            self.reader.advance();
+        }
+    }
+
+    fn string(&mut self) -> Token {
+        if mem::take(&mut self.template) {
+            return self.template_segment();
+        }
+
+        let (string, _) = self.string_body(false);
+
+        self.make_token(TokenKind::Literal(Lit::String(string)))
+    }
+
+    /// Read a string up to its closing `"`, or up to the next `${` when
+    /// reading a template. Returns the text and whether it stopped at `${`.
+    fn string_body(&mut self, template: bool) -> (String, bool) {
+        let mut string = String::new();
+
+        loop {
+            if template && self.reader.remaining().starts_with("${") {
+                self.reader.advance();
+                self.reader.advance();
+
+                return (string, true);
+            }
+
+            match self.reader.advance() {
+                Some("\"") | None => break,
+                Some("\\") => match self.reader.advance() {
+                    Some("n") => string.push('\n'),
+                    Some("t") => string.push('\t'),
+                    Some(c) => string.push_str(c),
+                    None => break,
+                },
+                Some(c) => string.push_str(c),
+            }
+        }
+
+        (string, false)
+    }
+
+    /// Read the text of a tagged template up to its end or its next
+    /// interpolation.
+    fn template_segment(&mut self) -> Token {
+        match self.string_body(true) {
+            (text, true) => {
+                self.interpolations.push(0);
+
+                self.make_token(TokenKind::TemplatePart(text))
+            }
+            (text, false) => self.make_token(TokenKind::TemplateEnd(text)),
+        }
+    }
+
     fn identifier(&mut self) -> Token {
         while self.reader.peek().is_some() && is_alphanumeric(self.reader.peek().unwrap()) {
             self.reader.advance();
         }
 
+        let tagged = self.reader.peek() == Some("\"");
         let identifier = self.reader.next_token();
 
-        match identifier {
-            "class" => Token::new(TokenKind::Keyword(Keyword::Class)),
-            "var" => Token::new(TokenKind::Keyword(Keyword::Var)),
-            "fun" => Token::new(TokenKind::Keyword(Keyword::Fun)),
-            "self" => Token::new(TokenKind::Keyword(Keyword::Slf)),
-            ident => Token::new(TokenKind::Ident(ident.into())),
+        let keyword = keyword(identifier).or_else(|| {
+            self.config
+                .and_then(|config| config.aliases.get(identifier).cloned())
+        });
+
+        match keyword {
+            Some(keyword) => Token::new(TokenKind::Keyword(keyword)),
+            None if tagged => {
+                self.template = true;
+
+                Token::new(TokenKind::Tag(identifier.into()))
+            }
+            None => Token::new(TokenKind::Ident(identifier.into())),
         }
     }
 
+    /// Read the custom operator at the start of the remaining input, if any.
+    fn operator(&mut self) -> Option<Token> {
+        let config = self.config?;
+        let remaining = self.reader.remaining();
+        let symbol = config
+            .operators
+            .iter()
+            .find(|symbol| remaining.starts_with(symbol.as_str()))?;
+
+        self.reader.current += symbol.len();
+
+        Some(self.make_token(TokenKind::Operator(symbol.as_str().into())))
+    }
+
     pub fn next_token(&mut self) -> Token {
+        loop {
+            if self.reader.peek().is_some_and(is_whitespace) {
+                self.skip_whitespace();
+            }
+
+            if self.comments || !self.reader.remaining().starts_with("//") {
+                break;
+            }
+
+            self.comment();
+            self.reader.previous = self.reader.current;
+        }
+
+        let span = self.span_at(self.reader.current);
+
+        self.token().at(span)
+    }
+
+    /// Get the position of `offset`, which is never before one asked for
+    /// already, so the source is only counted through once.
+    fn span_at(&mut self, offset: usize) -> Span {
+        let (counted, mut span) = self.counted;
+
+        for c in self.reader.src[counted..offset].chars() {
+            if c == '\n' {
+                span.line += 1;
+                span.column = 1;
+            } else {
+                span.column += 1;
+            }
+        }
+
+        self.counted = (offset, span);
+
+        span
+    }
+
+    /// Read a comment up to the end of its line, and return the text after
+    /// its `//`.
+    fn comment(&mut self) -> String {
+        self.reader.current += "//".len();
+
+        let remaining = self.reader.remaining();
+        let end = remaining.find('\n').unwrap_or(remaining.len());
+        let text = remaining[..end].trim_end_matches('\r').to_string();
+
+        self.reader.current += end;
+
+        text
+    }
+
+    fn token(&mut self) -> Token {
+        if self.reader.remaining().starts_with("//") {
+            let text = self.comment();
+
+            return self.make_token(TokenKind::Comment(text));
+        }
+
+        if let Some(token) = self.operator() {
+            return token;
+        }
+
+        let ellipsis = self.reader.remaining().starts_with("...");
+        let dot_dot = self.reader.remaining().starts_with("..");
+        let fat_arrow = self.reader.remaining().starts_with("=>");
+        let arrow = self.reader.remaining().starts_with("->");
+        let increment = self.reader.remaining().starts_with("++");
+        let decrement = self.reader.remaining().starts_with("--");
+        // Whether the symbol is followed by `=`, as in `<=` or `==`.
+        let equals = self.reader.remaining().as_bytes().get(1) == Some(&b'=');
+        let and = self.reader.remaining().starts_with("&&");
+        let or = self.reader.remaining().starts_with("||");
         let c = &self.reader.advance();
 
         match c {
+            Some("+") if increment => {
+                self.reader.advance();
+
+                self.make_token(TokenKind::Increment)
+            }
             Some("+") => self.make_token(TokenKind::Plus),
+            Some("-") if arrow => {
+                self.reader.advance();
+
+                self.make_token(TokenKind::Arrow)
+            }
+            Some("-") if decrement => {
+                self.reader.advance();
+
+                self.make_token(TokenKind::Decrement)
+            }
             Some("-") => self.make_token(TokenKind::Hypen),
             Some("*") => self.make_token(TokenKind::Star),
             Some("/") => self.make_token(TokenKind::BackSlash),
             Some("%") => self.make_token(TokenKind::Modulo),
+            Some("!") if equals => {
+                self.reader.advance();
+
+                self.make_token(TokenKind::NotEqual)
+            }
             Some("!") => self.make_token(TokenKind::Bang),
+            Some("<") if equals => {
+                self.reader.advance();
+
+                self.make_token(TokenKind::LessEqual)
+            }
+            Some("<") => self.make_token(TokenKind::Less),
+            Some(">") if equals => {
+                self.reader.advance();
+
+                self.make_token(TokenKind::GreaterEqual)
+            }
+            Some(">") => self.make_token(TokenKind::Greater),
+            Some("&") if and => {
+                self.reader.advance();
+
+                self.make_token(TokenKind::And)
+            }
+            Some("|") if or => {
+                self.reader.advance();
+
+                self.make_token(TokenKind::Or)
+            }
             Some("~") => self.make_token(TokenKind::Tilde),
+            Some("@") => self.make_token(TokenKind::At),
             Some("(") => self.make_token(TokenKind::LParen),
             Some(")") => self.make_token(TokenKind::RParen),
             Some("[") => self.make_token(TokenKind::LBracket),
             Some("]") => self.make_token(TokenKind::RBracket),
-            Some("{") => self.make_token(TokenKind::LBrace),
-            Some("}") => self.make_token(TokenKind::RBrace),
+            Some("{") => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+
+                self.make_token(TokenKind::LBrace)
+            }
+            Some("}") => match self.interpolations.last_mut() {
+                Some(0) => {
+                    self.interpolations.pop();
+
+                    self.template_segment()
+                }
+                Some(depth) => {
+                    *depth -= 1;
+
+                    self.make_token(TokenKind::RBrace)
+                }
+                None => self.make_token(TokenKind::RBrace),
+            },
+            Some(".") if ellipsis => {
+                self.reader.advance();
+                self.reader.advance();
+
+                self.make_token(TokenKind::Ellipsis)
+            }
+            Some(".") if dot_dot => {
+                self.reader.advance();
+
+                self.make_token(TokenKind::DotDot)
+            }
             Some(".") => self.make_token(TokenKind::Dot),
+            Some("=") if fat_arrow => {
+                self.reader.advance();
+
+                self.make_token(TokenKind::FatArrow)
+            }
+            Some("=") if equals => {
+                self.reader.advance();
+
+                self.make_token(TokenKind::Equal)
+            }
             Some("=") => self.make_token(TokenKind::Assign),
+            Some(",") => self.make_token(TokenKind::Comma),
+            Some(":") => self.make_token(TokenKind::Colon),
+            Some(";") => self.make_token(TokenKind::Semicolon),
+            Some("\n") => self.make_token(TokenKind::Newline),
+            Some("\"") => self.string(),
             Some(c) => {
                 if is_numeric(c) {
                     self.number()
@@ -165,9 +544,11 @@ impl<'a> Lexer<'a> {
                 } else if is_whitespace(c) {
                     self.skip_whitespace();
 
-                    self.next_token()
+                    self.token()
                 } else {
-                    todo!()
+                    let c = c.chars().next().expect("a character was read");
+
+                    self.make_token(TokenKind::Unknown(c))
                 }
             }
             None => Token::new(TokenKind::Eof),
@@ -175,12 +556,87 @@ impl<'a> Lexer<'a> {
     }
 }
 
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.done {
+            return None;
+        }
+
+        // Stop after the first end of file token, which whitespace or a
+        // comment at the end of the input can come before.
+        let token = self.next_token();
+
+        self.done = token.kind == TokenKind::Eof;
+
+        Some(token)
+    }
+}
+
+/// Whether `word` is spelled like an identifier.
+pub fn is_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Every keyword, as spelled in scripts.
+pub const KEYWORDS: &[&str] = &[
+    "class", "var", "fun", "self", "static", "return", "true", "false", "nil", "match", "switch",
+    "const", "import", "export", "try", "catch", "finally", "throw", "while", "break", "continue",
+    "typeof", "is", "for", "in", "yield", "async", "await", "let", "if", "else",
+];
+
+/// The keyword spelled `word`, if there is one.
+pub fn keyword(word: &str) -> Option<Keyword> {
+    let keyword = match word {
+        "class" => Keyword::Class,
+        "var" => Keyword::Var,
+        "fun" => Keyword::Fun,
+        "self" => Keyword::Slf,
+        "static" => Keyword::Static,
+        "return" => Keyword::Return,
+        "true" => Keyword::True,
+        "false" => Keyword::False,
+        "nil" => Keyword::Nil,
+        "match" => Keyword::Match,
+        "switch" => Keyword::Switch,
+        "const" => Keyword::Const,
+        "import" => Keyword::Import,
+        "export" => Keyword::Export,
+        "try" => Keyword::Try,
+        "catch" => Keyword::Catch,
+        "finally" => Keyword::Finally,
+        "throw" => Keyword::Throw,
+        "while" => Keyword::While,
+        "break" => Keyword::Break,
+        "continue" => Keyword::Continue,
+        "typeof" => Keyword::Typeof,
+        "is" => Keyword::Is,
+        "for" => Keyword::For,
+        "in" => Keyword::In,
+        "yield" => Keyword::Yield,
+        "async" => Keyword::Async,
+        "await" => Keyword::Await,
+        "let" => Keyword::Let,
+        "if" => Keyword::If,
+        "else" => Keyword::Else,
+        _ => return None,
+    };
+
+    Some(keyword)
+}
+
 fn is_alphabetic(c: &str) -> bool {
-    c.bytes().all(|c| c.is_ascii_alphabetic())
+    c.bytes().all(|c| c.is_ascii_alphabetic() || c == b'_')
 }
 
 fn is_alphanumeric(c: &str) -> bool {
-    c.bytes().all(|c| c.is_ascii_alphanumeric())
+    c.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_')
 }
 
 fn is_numeric(c: &str) -> bool {
@@ -193,30 +649,191 @@ fn is_whitespace(c: &str) -> bool {
 
 #[cfg(test)]
 mod test {
-    use crate::token::{Lit, Token, TokenKind};
+    use crate::{
+        token::{Keyword, Lit, Token, TokenKind},
+        value::Value,
+        Thrush,
+    };
 
-    use super::Lexer;
+    use super::{Lexer, LexerConfig};
 
     #[test]
     fn test_token() {
         let tokens = [
             ("1", Token::literal(Lit::Integer(1))),
             ("123", Token::literal(Lit::Integer(123))),
+            ("1.5", Token::literal(Lit::Float(1.5))),
+            ("\"a\\\"b\"", Token::literal(Lit::String("a\"b".into()))),
             ("+", Token::new(TokenKind::Plus)),
             ("-", Token::new(TokenKind::Hypen)),
+            ("++", Token::new(TokenKind::Increment)),
+            ("--", Token::new(TokenKind::Decrement)),
             ("/", Token::new(TokenKind::BackSlash)),
             ("*", Token::new(TokenKind::Star)),
             ("~", Token::new(TokenKind::Tilde)),
             ("!", Token::new(TokenKind::Bang)),
             ("(", Token::new(TokenKind::LParen)),
             (")", Token::new(TokenKind::RParen)),
+            (",", Token::new(TokenKind::Comma)),
+            (";", Token::new(TokenKind::Semicolon)),
+            ("...", Token::new(TokenKind::Ellipsis)),
+            ("..", Token::new(TokenKind::DotDot)),
+            ("=>", Token::new(TokenKind::FatArrow)),
+            ("->", Token::new(TokenKind::Arrow)),
+            ("@", Token::new(TokenKind::At)),
+            ("<", Token::new(TokenKind::Less)),
+            ("<=", Token::new(TokenKind::LessEqual)),
+            (">", Token::new(TokenKind::Greater)),
+            (">=", Token::new(TokenKind::GreaterEqual)),
+            ("==", Token::new(TokenKind::Equal)),
+            ("!=", Token::new(TokenKind::NotEqual)),
+            ("&&", Token::new(TokenKind::And)),
+            ("||", Token::new(TokenKind::Or)),
+            ("$", Token::new(TokenKind::Unknown('$'))),
+            ("é", Token::new(TokenKind::Unknown('é'))),
+            ("a_b", Token::new(TokenKind::Ident("a_b".into()))),
+            ("\n", Token::new(TokenKind::Newline)),
             (" ", Token::new(TokenKind::Eof)),
         ];
 
         for (string, token) in tokens {
             let lexer = &mut Lexer::new(string);
 
-            assert_eq!(lexer.next_token(), token);
+            assert_eq!(lexer.next_token().kind, token.kind);
         }
     }
+
+    #[test]
+    fn test_keyword_aliases() {
+        let mut config = LexerConfig::new();
+
+        config.alias("rule", "fun").unwrap();
+        config.alias("kind", "class").unwrap();
+
+        assert!(config.alias("when", "unless").is_err());
+        assert!(config.alias("var", "fun").is_err());
+        assert!(config.alias("2x", "fun").is_err());
+        assert_eq!(
+            Lexer::with_config("rule", &config).next_token().kind,
+            TokenKind::Keyword(Keyword::Fun)
+        );
+        assert_eq!(
+            Lexer::new("rule").next_token().kind,
+            TokenKind::Ident("rule".into())
+        );
+
+        let mut thrush = Thrush::new();
+
+        thrush.set_lexer_config(config);
+        thrush
+            .exec("kind Door {\n    var open\n}\nrule pair(x) {\n    return [x, x]\n}\nvar p = pair(1)")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("p").unwrap().to_string(),
+            "[1, 1]"
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        let mut config = LexerConfig::new();
+
+        config.operator("*").unwrap_err();
+        config.operator("a+").unwrap_err();
+        config.operator("<//").unwrap_err();
+        config.operator("**").unwrap();
+        config.operator("***").unwrap();
+
+        let kinds = Lexer::with_config("a *** b ** c * d", &config)
+            .map(|token| token.kind)
+            .collect::<Vec<_>>();
+
+        assert_eq!(kinds[1], TokenKind::Operator("***".into()));
+        assert_eq!(kinds[3], TokenKind::Operator("**".into()));
+        assert_eq!(kinds[5], TokenKind::Star);
+    }
+
+    #[test]
+    fn test_templates() {
+        let kinds = Lexer::new("sql\"a ${ {b: t\"${c}\"} } d\" e")
+            .map(|token| token.kind)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Tag("sql".into()),
+                TokenKind::TemplatePart("a ".into()),
+                TokenKind::LBrace,
+                TokenKind::Ident("b".into()),
+                TokenKind::Colon,
+                TokenKind::Tag("t".into()),
+                TokenKind::TemplatePart("".into()),
+                TokenKind::Ident("c".into()),
+                TokenKind::TemplateEnd("".into()),
+                TokenKind::RBrace,
+                TokenKind::TemplateEnd(" d".into()),
+                TokenKind::Ident("e".into()),
+                TokenKind::Eof,
+            ]
+        );
+        assert_eq!(
+            Lexer::new("sql \"${a}\"").nth(1).map(|token| token.kind),
+            Some(Token::literal(Lit::String("${a}".into())).kind)
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let spans = Lexer::new("var x\n  = \"a\nb\" y z")
+            .map(|token| (token.span.line, token.span.column))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            spans,
+            [
+                (1, 1),
+                (1, 5),
+                (1, 6),
+                (2, 3),
+                (2, 5),
+                (3, 4),
+                (3, 6),
+                (3, 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "x // note\n// own line\ny//";
+        let kinds = |lexer: Lexer| lexer.map(|token| token.kind).collect::<Vec<_>>();
+        let y = Lexer::new(source).nth(3).unwrap();
+
+        assert_eq!(
+            kinds(Lexer::new(source)),
+            [
+                TokenKind::Ident("x".into()),
+                TokenKind::Newline,
+                TokenKind::Newline,
+                TokenKind::Ident("y".into()),
+                TokenKind::Eof
+            ]
+        );
+        assert_eq!((y.span.line, y.span.column), (3, 1));
+        assert_eq!(
+            kinds(Lexer::new(source).with_comments()),
+            [
+                TokenKind::Ident("x".into()),
+                TokenKind::Comment(" note".into()),
+                TokenKind::Newline,
+                TokenKind::Comment(" own line".into()),
+                TokenKind::Newline,
+                TokenKind::Ident("y".into()),
+                TokenKind::Comment("".into()),
+                TokenKind::Eof
+            ]
+        );
+    }
 }
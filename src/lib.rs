@@ -1,43 +1,375 @@
 //! The Thrush scripting language, including the compiler and VM.
 
+pub mod assert;
 pub mod ast;
+pub mod audit;
 pub mod chunk;
+#[cfg(feature = "collation")]
+pub mod collation;
 pub mod compiler;
+#[cfg(feature = "csv")]
+pub mod csv;
+pub mod deep;
+pub mod diagnostic;
+pub mod doc;
+#[cfg(feature = "encoding")]
+pub mod encoding;
+pub mod exception;
+pub mod frame;
+pub mod inspect;
 pub mod instruction;
+pub mod iter;
 pub mod lexer;
+pub mod limits;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod opt;
+pub mod output;
+pub mod overrides;
 pub mod parser;
+pub mod path;
+pub mod print;
+pub mod remote;
+pub mod report;
+pub mod repr;
+pub mod rng;
 pub mod scope;
+pub mod serde_value;
+pub mod serve;
+pub mod shutdown;
+pub mod snapshot;
+pub mod sort;
+pub mod source;
+pub mod suite;
 pub mod token;
+pub mod tween;
+pub mod typecheck;
+#[cfg(feature = "uuid")]
+pub mod uuid;
 pub mod value;
 pub mod vm;
 pub mod hash;
+pub mod walk;
 
-use std::rc::Rc;
+use std::{cell::RefCell, collections::HashMap, fmt, mem, rc::Rc, time::Instant};
 
-use compiler::Compiler;
+use chunk::{Chunk, Exports, Module};
+use compiler::{Compiler, CompilerOptions, Symbols};
+use diagnostic::{Error, ErrorCode};
+use frame::Frame;
+use lexer::{Lexer, LexerConfig};
+use limits::HostLimits;
+use overrides::Overrides;
+use report::{Diagnostic, ExecReport, ExecStats};
 use scope::State;
+use snapshot::Snapshots;
+use tween::Tweens;
+use value::Value;
 use vm::Vm;
 
-#[derive(Debug, Default)]
+type LoadModule = dyn Fn(&str) -> Result<String, String>;
+
+/// Finds the source of a module from the name it is imported by.
+#[derive(Clone)]
+struct ModuleLoader(Rc<LoadModule>);
+
+impl fmt::Debug for ModuleLoader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ModuleLoader")
+    }
+}
+
+#[derive(Debug)]
 pub struct Thrush {
     vm: Vm,
+    tweens: Rc<RefCell<Tweens>>,
+    frame: Rc<RefCell<Frame>>,
+    max_depth: usize,
+    loader: Option<ModuleLoader>,
+    /// The modules currently being loaded, innermost last.
+    loading: Vec<String>,
+    lexer_config: LexerConfig,
+    compiler_options: CompilerOptions,
+    /// The suites and tests declared with `describe` and `it`.
+    tests: Rc<RefCell<suite::Declared>>,
+    /// Whether scripts are type checked before they are compiled.
+    typecheck: bool,
+    /// The warnings from compiling the last script and the modules it loaded.
+    warnings: Vec<Diagnostic>,
+    /// What the compiler knows about the declarations of the scripts run so
+    /// far.
+    symbols: Symbols,
+    /// What to run when the interpreter shuts down.
+    finalizers: Rc<RefCell<shutdown::Finalizers>>,
+    /// Whether [Thrush::shutdown] has already torn the interpreter down.
+    shut_down: bool,
+    #[cfg(feature = "metrics")]
+    metrics: metrics::Metrics,
 }
 
 impl Thrush {
     pub fn new() -> Self {
+        let mut vm = Vm::new();
+        let tweens = Rc::new(RefCell::new(Tweens::new()));
+        let frame = Rc::new(RefCell::new(Frame::default()));
+        let tests = Rc::new(RefCell::new(suite::Declared::default()));
+        let finalizers = Rc::new(RefCell::new(shutdown::Finalizers::default()));
+
+        tween::register(&mut vm.state, tweens.clone());
+        frame::register(&mut vm.state, frame.clone());
+
+        path::register(&mut vm.state);
+        repr::register(&mut vm.state);
+        doc::register(&mut vm.state);
+        inspect::register(&mut vm.state);
+        assert::register(&mut vm.state);
+        sort::register(&mut vm.state);
+        iter::register(&mut vm.state);
+        deep::register(&mut vm.state);
+        suite::register(&mut vm.state, Rc::clone(&tests));
+        shutdown::register(&mut vm.state, Rc::clone(&finalizers));
+        exception::register(&mut vm.state, Rc::clone(&vm.error_class));
+
+        #[cfg(feature = "collation")]
+        collation::register(&mut vm.state);
+
+        #[cfg(feature = "csv")]
+        csv::register(&mut vm.state);
+
+        #[cfg(feature = "encoding")]
+        encoding::register(&mut vm.state);
+
+        #[cfg(feature = "uuid")]
+        uuid::register(&mut vm.state, vm.rng.clone());
+
         Thrush {
-            vm: Vm::new(),
+            vm,
+            tweens,
+            frame,
+            max_depth: parser::DEFAULT_MAX_DEPTH,
+            loader: None,
+            loading: Vec::new(),
+            lexer_config: LexerConfig::default(),
+            compiler_options: CompilerOptions::default(),
+            tests,
+            typecheck: false,
+            warnings: Vec::new(),
+            symbols: Symbols::default(),
+            finalizers,
+            shut_down: false,
+            #[cfg(feature = "metrics")]
+            metrics: metrics::Metrics::default(),
         }
     }
 
+    /// Advance time-driven state, such as running tweens, by `dt` seconds.
+    ///
+    /// Hosts should call this once per frame. It also sets the values
+    /// returned by `frame.dt()` and `frame.number()`, and resumes the async
+    /// functions whose promises have settled.
+    pub fn update(&mut self, dt: f64) -> Result<(), String> {
+        self.frame.borrow_mut().advance(dt);
+        Tweens::update(&self.tweens, &mut self.vm, dt)?;
+        self.poll().map(|_| ())
+    }
+
+    /// Resume the async functions waiting on promises that have settled, and
+    /// return how many are still waiting, see [Vm::poll].
+    pub fn poll(&mut self) -> Result<usize, String> {
+        self.vm.poll().map_err(|err| err.0)
+    }
+
+    /// Seed the random number generator so builtins such as `uuid.v4` give the
+    /// same results on every run.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.vm.set_seed(seed);
+    }
+
+    /// Set how deeply a script's expressions and blocks can be nested.
+    ///
+    /// Deeper scripts fail to compile with an error instead of overflowing
+    /// the stack. The default is [parser::DEFAULT_MAX_DEPTH].
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Set how big the values the host gets from scripts can be, see
+    /// [limits]. The default is [HostLimits::default].
+    pub fn set_host_limits(&mut self, limits: HostLimits) {
+        self.vm.state.set_limits(limits);
+    }
+
+    /// Set the options used to tokenize scripts, such as keyword aliases.
+    ///
+    /// The options apply to scripts run afterwards, including the modules
+    /// they import.
+    pub fn set_lexer_config(&mut self, config: LexerConfig) {
+        self.lexer_config = config;
+    }
+
+    /// Set the options used to compile scripts, such as custom operators.
+    ///
+    /// Like [Thrush::set_lexer_config], the options apply to scripts run
+    /// afterwards, including the modules they import.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use thrush::{compiler::CompilerOptions, parser::Precedence, value::Value, Thrush};
+    ///
+    /// let mut options = CompilerOptions::new();
+    /// options
+    ///     .operator("<>", Precedence::Sum, |a, b| Ok(Value::list(vec![a, b])))
+    ///     .unwrap();
+    ///
+    /// let mut thrush = Thrush::new();
+    /// thrush.set_compiler_options(options);
+    ///
+    /// assert_eq!(thrush.exec("var pair = 1 <> 2"), Ok(()));
+    /// ```
+    pub fn set_compiler_options(&mut self, options: CompilerOptions) {
+        self.compiler_options = options;
+    }
+
+    /// The lexer options, with the symbols of custom operators added.
+    fn lexer_config(&self) -> LexerConfig {
+        let mut config = self.lexer_config.clone();
+
+        for (symbol, _) in self.compiler_options.operators() {
+            // The symbol was checked when the operator was registered.
+            let _ = config.operator(symbol);
+        }
+
+        config
+    }
+
+    /// Set the function that finds the source of imported modules.
+    ///
+    /// The loader is given the path from an `import` statement, such as
+    /// `"math/vec"`, and returns the module's source. Each module is loaded
+    /// and run once; later imports share it.
+    ///
+    /// # Examples 
+    ///
+    /// ```
+    /// use thrush::Thrush;
+    ///
+    /// let mut thrush = Thrush::new();
+    /// thrush.set_module_loader(|path| match path {
+    ///     "config" => Ok("export var lives = 3".into()),
+    ///     _ => Err("not found".into()),
+    /// });
+    ///
+    /// assert_eq!(thrush.exec("import \"config\"\nvar lives = config.lives"), Ok(()));
+    /// ```
+    pub fn set_module_loader<F>(&mut self, loader: F)
+    where
+        F: Fn(&str) -> Result<String, String> + 'static,
+    {
+        self.loader = Some(ModuleLoader(Rc::new(loader)));
+    }
+
+    /// Define the `assert_snapshot` global, which compares values with the
+    /// snapshot files described by `snapshots`.
+    pub fn enable_snapshots(&mut self, snapshots: Snapshots) {
+        snapshot::register(&mut self.vm.state, snapshots);
+    }
+
+    /// Pass everything scripts write to their [output] to `sink` instead of
+    /// stdout.
+    pub fn set_output<F: FnMut(&str) + 'static>(&mut self, sink: F) {
+        self.vm.state.output().set(sink);
+    }
+
+    /// Type check scripts and modules before compiling them from now on, so
+    /// the mistakes that can be found without running them are reported as
+    /// compile errors, see [typecheck].
+    pub fn enable_typecheck(&mut self) {
+        self.typecheck = true;
+    }
+
+    /// Record every native function and method that scripts call from now
+    /// on, see [audit].
+    pub fn enable_audit(&mut self) {
+        self.vm.enable_audit();
+    }
+
+    /// The native calls recorded since [Thrush::enable_audit] was called.
+    pub fn audit_log(&self) -> &[audit::AuditEntry] {
+        self.vm.audit_log()
+    }
+
+    /// The warnings from compiling the last script run, such as unused
+    /// variables, which don't stop it from running.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    /// Take the recorded native calls, leaving the log empty.
+    pub fn take_audit_log(&mut self) -> Vec<audit::AuditEntry> {
+        self.vm.take_audit_log()
+    }
+
+    /// Answer every call to a native function or method with `mock` instead
+    /// of running it, so scripts can be tested without touching the systems
+    /// the natives control.
+    ///
+    /// The mock is passed the name the [audit] log would record, such as
+    /// `"path.join"` or `"File.read"`, and the arguments.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use thrush::{value::Value, Thrush};
+    ///
+    /// let mut thrush = Thrush::new();
+    /// thrush.globals().add_function("delete_save", |_| panic!("deleted the save"));
+    /// thrush.enable_dry_run(|name, _| match name {
+    ///     "delete_save" => Ok(Value::Bool(true)),
+    ///     _ => Err(format!("no mock for '{name}'")),
+    /// });
+    ///
+    /// assert_eq!(thrush.exec("var deleted = delete_save()"), Ok(()));
+    /// assert_eq!(thrush.globals().get::<bool>("deleted"), Ok(true));
+    /// ```
+    pub fn enable_dry_run<F>(&mut self, mock: F)
+    where
+        F: Fn(&str, &[Value]) -> Result<Value, String> + 'static,
+    {
+        self.vm.enable_dry_run(mock);
+    }
+
+    /// Go back to calling the real native functions after a dry run.
+    pub fn disable_dry_run(&mut self) {
+        self.vm.disable_dry_run();
+    }
+
     /// Get a mutable reference to Thrush's global state.
     pub fn globals(&mut self) -> &mut State {
         &mut self.vm.state
     }
 
+    /// The globals and keywords starting with `prefix`, sorted, for editors
+    /// to offer as completions.
+    pub fn complete(&self, prefix: &str) -> Vec<String> {
+        let mut names = self
+            .vm
+            .state
+            .names()
+            .chain(lexer::KEYWORDS.iter().copied())
+            .filter(|name| name.starts_with(prefix))
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        names.sort();
+        names.dedup();
+
+        names
+    }
+
     /// Run a Thrush script.
     ///
-    /// # Examples 
+    /// # Examples
     ///
     /// ```
     /// use thrush::Thrush;
@@ -49,26 +381,378 @@ impl Thrush {
     /// # Errors
     ///
     /// This function will return an error if there are any lexical or semanitic errors in the scipt.
-    pub fn exec(&mut self, script: &str) -> Result<(), String> {
+    pub fn exec(&mut self, script: &str) -> Result<(), Error> {
         self._exec(script)
     } 
 
-    fn _exec(&mut self, script: &str) -> Result<(), String> {
-        let tokens = lexer::Lexer::tokenize(script);
-        let ast = parser::Parser::parse_ast(tokens)?; 
-        let mut compiler = Compiler::new(&mut self.vm.state);
+    fn _exec(&mut self, script: &str) -> Result<(), Error> {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
 
-        let chunk = compiler.run(ast)?;
+        let result = self.run_script(script, None).map(|_| ());
 
-        self.vm.execute(Rc::new(chunk)).unwrap();
+        #[cfg(feature = "metrics")]
+        self.metrics.record(&result, start.elapsed());
+
+        result
+    }
+
+    /// Run the tests declared with `describe` and `it` by the scripts run so
+    /// far, see [suite].
+    pub fn run_tests(&mut self) -> suite::TestReport {
+        suite::run(&mut self.vm, &self.tests)
+    }
+
+    /// Run a script and collect its value, what it printed, its errors and
+    /// how long it took into one [ExecReport], see [report].
+    pub fn exec_report(&mut self, script: &str) -> ExecReport {
+        let mut stats = ExecStats::default();
+        let output = self.vm.state.output();
+        let (result, stdout) = output.capture(|| -> Result<Value, Error> {
+            let start = Instant::now();
+            let chunk = self.compile_script(script, true);
+
+            stats.compile_time = start.elapsed();
+
+            let chunk = chunk?;
+            let start = Instant::now();
+
+            stats.instructions = chunk.instructions.len();
+
+            let result = self.vm.execute(Rc::new(chunk)).and_then(|()| {
+                self.vm
+                    .pop_result()
+                    .map_err(|vm::VmError(err)| Error::new(err))
+            });
+
+            stats.run_time = start.elapsed();
+            self.vm.reset();
+
+            result
+        });
+
+        #[cfg(feature = "metrics")]
+        self.metrics.record(
+            &result.as_ref().map(|_| ()).map_err(Clone::clone),
+            stats.compile_time + stats.run_time,
+        );
+
+        let mut diagnostics = self.warnings.clone();
+
+        match result {
+            Ok(value) => ExecReport {
+                value: Some(value),
+                stdout,
+                diagnostics,
+                stats,
+            },
+            Err(err) => {
+                diagnostics.push(Diagnostic::error(err));
+
+                ExecReport {
+                    value: None,
+                    stdout,
+                    diagnostics,
+                    stats,
+                }
+            }
+        }
+    }
+
+    /// Run a script with some globals and modules replaced, then put them
+    /// back, see [overrides].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an overriding module or the
+    /// script fails. The overrides are undone either way.
+    pub fn exec_with_overrides(
+        &mut self,
+        script: &str,
+        overrides: &Overrides,
+    ) -> Result<(), Error> {
+        let mut globals = Vec::new();
+        let mut modules = Vec::new();
+
+        for (name, value) in &overrides.globals {
+            globals.push((name.as_str(), self.vm.state.remove(name)));
+            self.vm.state.add(name, value.clone());
+        }
+
+        let mut result = Ok(());
+
+        for (path, source) in &overrides.modules {
+            let module = self.vm.state.remove_module(path);
+
+            modules.push((path.as_str(), module, self.vm.state.remove_namespace(path)));
+            result = self.load_source(path, source);
+
+            if result.is_err() {
+                break;
+            }
+        }
+
+        if result.is_ok() {
+            result = self._exec(script);
+        }
+
+        for (path, module, namespace) in modules.into_iter().rev() {
+            self.vm.state.remove_module(path);
+            self.vm.state.remove_namespace(path);
+
+            for (name, value) in namespace {
+                self.vm.state.add(&name, value);
+            }
+
+            if let Some(module) = module {
+                self.vm.state.restore_module(module);
+            }
+        }
+
+        for (name, value) in globals.into_iter().rev() {
+            match value {
+                Some(value) => self.vm.state.add(name, value),
+                None => {
+                    self.vm.state.remove(name);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// The counters describing the scripts run so far, see [metrics].
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &metrics::Metrics {
+        &self.metrics
+    }
+
+    /// Get a mutable reference to the metrics, to set a recorder.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_mut(&mut self) -> &mut metrics::Metrics {
+        &mut self.metrics
+    }
+
+    /// Compile and run a script, as the module `module` if given, and return
+    /// the names it exports.
+    fn run_script(&mut self, script: &str, module: Option<&str>) -> Result<Exports, Error> {
+        let (chunk, exports) = match module {
+            Some(module) => self.compile_module(script, module)?,
+            None => (self.compile_script(script, false)?, HashMap::new()),
+        };
+
+        let result = self.vm.execute(Rc::new(chunk));
 
         self.vm.reset();
 
+        result?;
+
+        Ok(exports)
+    }
+
+    /// Compile a script a statement at a time as it is parsed, so the AST of
+    /// the whole script is never held in memory at once.
+    ///
+    /// If `value` is true, the chunk leaves the value of the script on the
+    /// stack, see [Compiler::finish_with_value].
+    fn compile_script(&mut self, script: &str, value: bool) -> Result<Chunk, Error> {
+        let config = self.lexer_config();
+
+        self.warnings.clear();
+
+        for path in parser::scan_imports(Lexer::with_config(script, &config)) {
+            self.load_module(&path)?;
+        }
+
+        let mut parser = parser::Parser::new(Lexer::with_config(script, &config))
+            .with_max_depth(self.max_depth)
+            .with_options(&self.compiler_options);
+        let mut compiler = Compiler::new(&mut self.vm.state)
+            .with_max_depth(self.max_depth)
+            .with_options(self.compiler_options.clone())
+            .with_symbols(self.symbols.clone());
+        let mut checker = self.typecheck.then(typecheck::Checker::new);
+
+        while let Some(ast) = parser.next_statement()? {
+            if let Some(checker) = &mut checker {
+                checker.check(&ast)?;
+            }
+
+            compiler.compile(ast)?;
+        }
+
+        compiler.check_globals()?;
+        self.warnings.extend(compiler.diagnostics());
+        self.symbols = compiler.symbols();
+
+        Ok(if value {
+            compiler.finish_with_value()
+        } else {
+            compiler.finish()
+        })
+    }
+
+    /// Compile the module `module` and return the names it exports.
+    ///
+    /// Modules are parsed in full before being compiled, since every name
+    /// they declare at the top level has to be known to namespace them.
+    fn compile_module(&mut self, script: &str, module: &str) -> Result<(Chunk, Exports), Error> {
+        let ast = parser::Parser::new(Lexer::with_config(script, &self.lexer_config()))
+            .with_max_depth(self.max_depth)
+            .with_options(&self.compiler_options)
+            .parse()?;
+
+        if self.typecheck {
+            typecheck::Checker::new().check(&ast)?;
+        }
+
+        for path in ast.imports().map(String::from).collect::<Vec<_>>() {
+            self.load_module(&path)?;
+        }
+
+        let mut compiler = Compiler::new(&mut self.vm.state)
+            .with_max_depth(self.max_depth)
+            .with_options(self.compiler_options.clone())
+            .with_module(module);
+        let chunk = compiler.run(ast)?;
+
+        self.warnings.extend(compiler.diagnostics());
+
+        Ok((chunk, compiler.exports()))
+    }
+
+    /// Load, run and cache the module `path`, unless it is already loaded.
+    fn load_module(&mut self, path: &str) -> Result<(), Error> {
+        if self.vm.state.module(path).is_some() {
+            return Ok(());
+        }
+
+        if self.loading.iter().any(|loading| loading == path) {
+            return Err(ErrorCode::Import
+                .error(format!(
+                    "module \"{path}\" imports itself: {} -> {path}",
+                    self.loading.join(" -> ")
+                ))
+                .into());
+        }
+
+        let loader = self.loader.clone().ok_or_else(|| {
+            ErrorCode::Import.error(format!(
+                "cannot import \"{path}\" because no module loader is set"
+            ))
+        })?;
+        let source = (loader.0)(path).map_err(|err| {
+            ErrorCode::Import.error(format!("cannot load module \"{path}\": {err}"))
+        })?;
+
+        self.load_source(path, &source)
+    }
+
+    /// Run and cache `source` as the module `path`.
+    fn load_source(&mut self, path: &str, source: &str) -> Result<(), Error> {
+        self.loading.push(path.into());
+        let result = self.run_script(source, Some(path));
+        self.loading.pop();
+
+        let exports = result.map_err(|mut err| {
+            err.message = format!("{} (in module \"{path}\")", err.message);
+            err
+        })?;
+
+        self.vm.state.add_module(Module {
+            name: path.into(),
+            exports,
+        });
+
         Ok(())
     }
 
+    /// Convert `value` to plain data the host can save, see [serde_value].
+    ///
+    /// Both the value and the plain data have to be within the
+    /// [HostLimits], since `__serialize` methods can make the data bigger.
+    pub fn to_plain(&mut self, value: &Value) -> Result<Value, String> {
+        let limits = self.vm.state.limits();
+
+        limits.check(value)?;
+
+        let result = serde_value::to_plain(&mut self.vm, value);
+
+        self.vm.reset();
+
+        let plain = result?;
+
+        limits.check(&plain)?;
+
+        Ok(plain)
+    }
+
+    /// Convert plain data made by [Thrush::to_plain] back into values.
+    pub fn from_plain(&mut self, value: &Value) -> Result<Value, String> {
+        let result = serde_value::from_plain(&mut self.vm, value);
+
+        self.vm.reset();
+
+        result
+    }
+
+    /// Run `finalizer` when the interpreter shuts down, before the
+    /// finalizers registered earlier, see [shutdown].
+    pub fn add_finalizer<F>(&mut self, finalizer: F)
+    where
+        F: FnOnce(&mut Vm) -> Result<(), String> + 'static,
+    {
+        self.finalizers.borrow_mut().add_host(finalizer);
+    }
+
+    /// Tear the interpreter down, running its finalizers and releasing the
+    /// values it holds, see [shutdown].
+    ///
+    /// Dropping the interpreter does the same but ignores errors. Here the
+    /// errors reported by finalizers are returned, one per line, once every
+    /// finalizer has run.
+    pub fn shutdown(mut self) -> Result<(), String> {
+        let errors = self.teardown();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    fn teardown(&mut self) -> Vec<String> {
+        if mem::replace(&mut self.shut_down, true) {
+            return Vec::new();
+        }
+
+        let errors = shutdown::finalize(&mut self.vm, &self.finalizers);
+
+        self.vm
+            .cancel_tasks(Value::String("the interpreter shut down".into()));
+        self.vm.reset();
+        *self.tweens.borrow_mut() = Tweens::new();
+        *self.tests.borrow_mut() = suite::Declared::default();
+
+        shutdown::break_cycles(self.vm.state.clear());
+
+        errors
+    }
+
     /// Get a mutable reference to the Thrush's vm.
     pub fn vm_mut(&mut self) -> &mut Vm {
         &mut self.vm
     }
 }
+
+impl Default for Thrush {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Thrush {
+    fn drop(&mut self) {
+        self.teardown();
+    }
+}
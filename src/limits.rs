@@ -0,0 +1,198 @@
+//! Limits on the size of values handed from scripts to the host.
+//!
+//! A script can build a value far bigger than the host expects, such as a
+//! list nested a million deep, and pass it on to systems that can't cope
+//! with it. Values are checked against the [HostLimits] set with
+//! [Thrush::set_host_limits](crate::Thrush::set_host_limits) when the host
+//! gets a global or converts a value with
+//! [Thrush::to_plain](crate::Thrush::to_plain), and a value over a limit is
+//! an error instead.
+
+use std::{collections::HashSet, rc::Rc};
+
+use crate::{diagnostic::ErrorCode, value::Value};
+
+/// How big a value can be when it crosses to the host.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct HostLimits {
+    /// How many lists, maps and instances deep a value can be nested.
+    pub max_depth: usize,
+    /// How many items a value can hold in total, counting every list item,
+    /// map entry and instance field across the containers inside it.
+    pub max_elements: usize,
+    /// How many bytes long a string, map key or byte string can be.
+    pub max_string_len: usize,
+}
+
+impl Default for HostLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 128,
+            max_elements: 1_000_000,
+            max_string_len: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl HostLimits {
+    /// Limits that every value is within.
+    pub fn unlimited() -> Self {
+        Self {
+            max_depth: usize::MAX,
+            max_elements: usize::MAX,
+            max_string_len: usize::MAX,
+        }
+    }
+
+    /// Check that `value` is within the limits.
+    ///
+    /// A container that contains itself is only counted once along each
+    /// path, but one that appears in several places is counted in each, since
+    /// a host that copies the value out will copy it that many times.
+    pub fn check(&self, value: &Value) -> Result<(), String> {
+        Checker {
+            limits: self,
+            elements: 0,
+            path: HashSet::new(),
+        }
+        .check(value, 0)
+    }
+}
+
+struct Checker<'a> {
+    limits: &'a HostLimits,
+    /// The number of items counted so far.
+    elements: usize,
+    /// The addresses of the containers being checked.
+    path: HashSet<usize>,
+}
+
+impl Checker<'_> {
+    fn check(&mut self, value: &Value, depth: usize) -> Result<(), String> {
+        let address = match value {
+            Value::String(string) => return self.string(string.len()),
+            Value::Bytes(bytes) => return self.string(bytes.len()),
+            Value::List(list) => list.as_ptr() as usize,
+            Value::Map(map) => map.as_ptr() as usize,
+            Value::Instance(instance) => Rc::as_ptr(instance) as usize,
+            _ => return Ok(()),
+        };
+
+        if depth >= self.limits.max_depth {
+            return Err(ErrorCode::ValueTooLarge.error(format!(
+                "value is nested more than {} deep",
+                self.limits.max_depth
+            )));
+        }
+
+        if !self.path.insert(address) {
+            return Ok(());
+        }
+
+        let result = match value {
+            Value::List(list) => list
+                .borrow()
+                .iter()
+                .try_for_each(|item| self.item(item, depth)),
+            Value::Map(map) => map.borrow().iter().try_for_each(|(key, value)| {
+                self.string(key.len())?;
+                self.item(value, depth)
+            }),
+            Value::Instance(instance) => instance
+                .fields()
+                .iter()
+                .try_for_each(|field| self.item(field, depth)),
+            _ => unreachable!(),
+        };
+
+        self.path.remove(&address);
+
+        result
+    }
+
+    /// Count and check an item of a container at `depth`.
+    fn item(&mut self, value: &Value, depth: usize) -> Result<(), String> {
+        self.elements += 1;
+
+        if self.elements > self.limits.max_elements {
+            return Err(ErrorCode::ValueTooLarge.error(format!(
+                "value has more than {} elements",
+                self.limits.max_elements
+            )));
+        }
+
+        self.check(value, depth + 1)
+    }
+
+    fn string(&self, len: usize) -> Result<(), String> {
+        if len > self.limits.max_string_len {
+            return Err(ErrorCode::ValueTooLarge.error(format!(
+                "string of {len} bytes is longer than the limit of {}",
+                self.limits.max_string_len
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HostLimits;
+    use crate::{value::Value, Thrush};
+
+    #[test]
+    fn test_host_limits() {
+        let mut thrush = Thrush::new();
+
+        thrush.set_host_limits(HostLimits {
+            max_depth: 3,
+            max_elements: 10,
+            max_string_len: 5,
+        });
+        thrush
+            .exec("var ok = [[[1]], {a: \"abcde\"}]\nvar deep = [[[[1]]]]\nvar long = [\"abcdef\"]\nvar key = {abcdef: 1}\nvar many = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]\nvar row = [1, 2, 3, 4, 5]\nvar shared = [row, row]")
+            .unwrap();
+
+        assert!(thrush.globals().get::<Value>("ok").is_ok());
+        assert_eq!(
+            thrush.globals().get::<Value>("deep"),
+            Err("[E0207] value is nested more than 3 deep".into())
+        );
+        assert!(thrush
+            .globals()
+            .get::<Vec<String>>("long")
+            .unwrap_err()
+            .contains("string of 6 bytes is longer than the limit of 5"));
+        assert!(thrush.globals().get::<Value>("key").is_err());
+        assert_eq!(
+            thrush.globals().get::<Value>("many"),
+            Err("[E0207] value has more than 10 elements".into())
+        );
+        assert!(thrush.globals().get::<Value>("shared").is_err());
+
+        let many = thrush.globals().remove("many").unwrap();
+
+        assert!(thrush.to_plain(&many).is_err());
+
+        thrush.set_host_limits(HostLimits::unlimited());
+        assert!(thrush.globals().get::<Value>("deep").is_ok());
+        assert!(thrush.to_plain(&many).is_ok());
+    }
+
+    #[test]
+    fn test_host_limits_cycle() {
+        let mut thrush = Thrush::new();
+        let cycle = Value::list(vec![Value::Integer(1)]);
+        let Value::List(items) = &cycle else {
+            unreachable!()
+        };
+
+        items.borrow_mut().push(cycle.clone());
+        thrush.globals().add("cycle", cycle.clone());
+
+        assert!(thrush.globals().get::<Value>("cycle").is_ok());
+
+        items.borrow_mut().clear();
+    }
+}
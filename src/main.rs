@@ -1,8 +1,33 @@
 use std::{rc::Rc};
 
-use thrush::{value::{Class, Value}, Thrush};
+use thrush::{diagnostic, serve::Server, value::{Class, Value}, Thrush};
 
 fn main() -> Result<(), String> {
+    let args = std::env::args().collect::<Vec<_>>();
+
+    if let [_, command, code] = args.as_slice() {
+        if command == "explain" {
+            return match diagnostic::explain(code) {
+                Some(explanation) => {
+                    print!("{explanation}");
+
+                    Ok(())
+                }
+                None => Err(format!("'{code}' is not a thrush error code")),
+            };
+        }
+    }
+
+    if let [_, command, path] = args.as_slice() {
+        if command == "test" {
+            return test(path);
+        }
+    }
+
+    if args.get(1).map(String::as_str) == Some("serve") {
+        return serve(&args[2..]);
+    }
+
     let mut thrush = Thrush::new();
 
     let scope = thrush.globals();
@@ -39,3 +64,43 @@ fn main() -> Result<(), String> {
 
     Ok(())
 }
+
+/// Run `thrush test <file>`, printing the results of the tests the file
+/// declares with `describe` and `it`.
+fn test(path: &str) -> Result<(), String> {
+    let script =
+        std::fs::read_to_string(path).map_err(|err| format!("cannot read '{path}': {err}"))?;
+    let mut thrush = Thrush::new();
+
+    thrush.exec(&script).map_err(|err| err.render(&script))?;
+
+    let report = thrush.run_tests();
+
+    print!("{report}");
+
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(format!("{} tests failed", report.failed()))
+    }
+}
+
+/// Run `thrush serve [--port N]`, answering requests over TCP when given a
+/// port and over stdin and stdout otherwise.
+fn serve(args: &[String]) -> Result<(), String> {
+    let mut server = Server::new(Thrush::new);
+
+    let result = match args {
+        [] => server.serve(std::io::stdin().lock(), std::io::stdout()),
+        [flag, port] if flag == "--port" => {
+            let port = port
+                .parse::<u16>()
+                .map_err(|_| format!("'{port}' is not a port number"))?;
+
+            server.listen(port)
+        }
+        _ => return Err("usage: thrush serve [--port N]".into()),
+    };
+
+    result.map_err(|err| err.to_string())
+}
@@ -0,0 +1,193 @@
+//! Counters for monitoring an embedded VM like any other subsystem.
+//!
+//! [Metrics] counts the scripts run through [Thrush::exec](crate::Thrush::exec)
+//! and how they failed, and renders them in the Prometheus text format:
+//!
+//! ```text
+//! thrush_executions_total 12
+//! thrush_errors_total{code="E0204"} 1
+//! thrush_execution_seconds_total 0.0042
+//! ```
+//!
+//! Hosts that already use a metrics library can forward each update to it
+//! with a [Recorder] instead of scraping the text. Thrush doesn't depend on
+//! the `metrics` facade itself; a host that does only needs a short adapter:
+//!
+//! ```ignore
+//! struct Facade;
+//!
+//! impl thrush::metrics::Recorder for Facade {
+//!     fn increment(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+//!         let labels: Vec<_> = labels
+//!             .iter()
+//!             .map(|&(key, value)| metrics::Label::new(key.to_string(), value.to_string()))
+//!             .collect();
+//!
+//!         metrics::counter!(name.to_string(), labels).increment(value);
+//!     }
+//!
+//!     fn record_duration(&self, name: &str, duration: std::time::Duration) {
+//!         metrics::counter!(name.to_string()).absolute(duration.as_micros() as u64);
+//!     }
+//! }
+//! ```
+//!
+//! Only executions, errors and execution time are counted. The VM has no
+//! fuel limit to exhaust, and values are reference counted rather than
+//! collected, so there are no fuel or GC pause counters to export.
+
+use std::{collections::BTreeMap, fmt, fmt::Write, rc::Rc, time::Duration};
+
+use crate::diagnostic::{Error, ErrorCode};
+
+/// The counter of scripts run.
+pub const EXECUTIONS: &str = "thrush_executions_total";
+/// The counter of scripts that failed, labeled by error code.
+pub const ERRORS: &str = "thrush_errors_total";
+/// The total time spent running scripts.
+pub const EXECUTION_SECONDS: &str = "thrush_execution_seconds_total";
+
+/// Receives metric updates as they happen, to pass them on to the host's
+/// metrics library.
+pub trait Recorder {
+    /// Add `value` to the counter `name` with `labels`.
+    fn increment(&self, name: &str, labels: &[(&str, &str)], value: u64);
+
+    /// Record that an execution took `duration`.
+    fn record_duration(&self, _name: &str, _duration: Duration) {}
+}
+
+/// Counters describing the scripts a VM has run.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    /// How many scripts have been run.
+    pub executions: u64,
+    /// How many scripts failed, by error code.
+    pub errors: BTreeMap<&'static str, u64>,
+    /// The time spent running scripts.
+    pub execution_time: Duration,
+    recorder: Option<Rc<dyn Recorder>>,
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics")
+            .field("executions", &self.executions)
+            .field("errors", &self.errors)
+            .field("execution_time", &self.execution_time)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Metrics {
+    /// Forward every update to `recorder` as well as counting it.
+    pub fn set_recorder<R: Recorder + 'static>(&mut self, recorder: R) {
+        self.recorder = Some(Rc::new(recorder));
+    }
+
+    /// Count an execution that took `duration` and ended with `result`.
+    pub(crate) fn record(&mut self, result: &Result<(), Error>, duration: Duration) {
+        self.executions += 1;
+        self.execution_time += duration;
+
+        if let Some(recorder) = &self.recorder {
+            recorder.increment(EXECUTIONS, &[], 1);
+            recorder.record_duration(EXECUTION_SECONDS, duration);
+        }
+
+        if let Err(err) = result {
+            let code = err.code.map_or("unknown", ErrorCode::code);
+
+            *self.errors.entry(code).or_default() += 1;
+
+            if let Some(recorder) = &self.recorder {
+                recorder.increment(ERRORS, &[("code", code)], 1);
+            }
+        }
+    }
+
+    /// The number of scripts that failed with any error.
+    pub fn total_errors(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    /// Render the counters in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        header(&mut out, EXECUTIONS, "Scripts run.");
+        let _ = writeln!(out, "{EXECUTIONS} {}", self.executions);
+
+        header(&mut out, ERRORS, "Scripts that failed, by error code.");
+
+        for (code, count) in &self.errors {
+            let _ = writeln!(out, "{ERRORS}{{code=\"{code}\"}} {count}");
+        }
+
+        header(&mut out, EXECUTION_SECONDS, "Time spent running scripts.");
+        let _ = writeln!(
+            out,
+            "{EXECUTION_SECONDS} {}",
+            self.execution_time.as_secs_f64()
+        );
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::Thrush;
+
+    use super::Recorder;
+
+    #[derive(Default)]
+    struct Log(Rc<RefCell<Vec<String>>>);
+
+    impl Recorder for Log {
+        fn increment(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+            self.0
+                .borrow_mut()
+                .push(format!("{name}{labels:?} {value}"));
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut thrush = Thrush::new();
+        let log = Log::default();
+        let updates = Rc::clone(&log.0);
+
+        thrush.metrics_mut().set_recorder(log);
+        thrush.exec("var x = 1").unwrap();
+        thrush.exec("var y = x + \"a\"").unwrap_err();
+        thrush.exec("var z = w").unwrap_err();
+
+        let metrics = thrush.metrics();
+
+        assert_eq!(metrics.executions, 3);
+        assert_eq!(metrics.total_errors(), 2);
+
+        let text = metrics.to_prometheus();
+
+        assert!(text.contains("\nthrush_executions_total 3\n"));
+        assert!(text.contains("\nthrush_errors_total{code=\"E0001\"} 1\n"));
+        assert!(text.contains("\nthrush_errors_total{code=\"E0204\"} 1\n"));
+        assert!(text.contains("# TYPE thrush_execution_seconds_total counter\n"));
+        assert_eq!(
+            updates.borrow()[..3],
+            [
+                "thrush_executions_total[] 1",
+                "thrush_executions_total[] 1",
+                "thrush_errors_total[(\"code\", \"E0204\")] 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,466 @@
+//! Optimizations on a script before and after it is compiled.
+//!
+//! [fold] replaces arithmetic, comparisons, string concatenation and `!` on literals
+//! with the literal they evaluate to, so `60 * 60 * 24` compiles to a
+//! single push of `86400`. An operation that fails, such as `1 / 0`, is
+//! left to raise its error when the script runs.
+//!
+//! [optimize] then improves a compiled [Chunk]: [eliminate_dead_code]
+//! removes the instructions that can never run, and [peephole] rewrites
+//! short sequences of the rest into fewer instructions.
+
+use crate::{
+    ast::{Ast, BinOp, Expr, ExprId, Lit},
+    chunk::Chunk,
+    instruction::{InstanceValue, Instruction},
+    value::Value,
+    vm,
+};
+
+/// Fold the operations on literals in `ast` into literals.
+///
+/// ```
+/// use thrush::{ast::{Expr, Lit}, lexer::Lexer, opt, parser::Parser};
+///
+/// let (mut ast, expr) = Parser::parse_expression(Lexer::tokenize("60 * 60 * 24")).unwrap();
+///
+/// opt::fold(&mut ast);
+///
+/// assert_eq!(ast[expr], Expr::Literal(Lit::Integer(86400)));
+/// ```
+pub fn fold(ast: &mut Ast) {
+    // An expression is added to the AST after its operands, so they are
+    // already folded by the time it is reached.
+    for id in (0..ast.exprs.len() as u32).map(ExprId) {
+        if let Some(lit) = fold_expr(ast, id) {
+            ast[id] = Expr::Literal(lit);
+        }
+    }
+}
+
+/// The literal `expr` evaluates to, if its operands are literals.
+fn fold_expr(ast: &Ast, expr: ExprId) -> Option<Lit> {
+    let value = match &ast[expr] {
+        Expr::BinExpr { op, left, right } => {
+            let inst = binary_instruction(op)?;
+            let a = literal(ast, *left)?;
+            let b = literal(ast, *right)?;
+
+            // A repeated string could be much bigger than the source that
+            // makes it, so it is built when the script runs.
+            if inst == Instruction::Mul
+                && (matches!(a, Value::String(_)) || matches!(b, Value::String(_)))
+            {
+                return None;
+            }
+
+            vm::binary(inst, a, b).ok()?
+        }
+        Expr::UnaryExpr { op, value } => {
+            let inst = match op {
+                BinOp::Sub => Instruction::Neg,
+                BinOp::Bang => Instruction::Not,
+                _ => return None,
+            };
+
+            vm::unary(inst, literal(ast, *value)?).ok()?
+        }
+        _ => return None,
+    };
+
+    value_lit(value)
+}
+
+/// The value of `expr`, if it is a literal.
+fn literal(ast: &Ast, expr: ExprId) -> Option<Value> {
+    match &ast[expr] {
+        Expr::Literal(lit) => lit_value(lit),
+        _ => None,
+    }
+}
+
+/// Run the optimizations on a compiled chunk.
+pub fn optimize(chunk: &mut Chunk) {
+    eliminate_dead_code(chunk);
+    peephole(chunk);
+}
+
+/// The instruction that applies the binary operator `op`, unless it is
+/// compiled to something else, such as the jumps of `&&` and `||`.
+pub(crate) fn binary_instruction(op: &BinOp) -> Option<Instruction> {
+    Some(match op {
+        BinOp::Add => Instruction::Add,
+        BinOp::Sub => Instruction::Sub,
+        BinOp::Mul => Instruction::Mul,
+        BinOp::Div => Instruction::Div,
+        BinOp::Rem => Instruction::Rem,
+        BinOp::Less => Instruction::Less,
+        BinOp::LessEqual => Instruction::LessEqual,
+        BinOp::Greater => Instruction::Greater,
+        BinOp::GreaterEqual => Instruction::GreaterEqual,
+        BinOp::Equal => Instruction::Equal,
+        BinOp::NotEqual => Instruction::NotEqual,
+        BinOp::And | BinOp::Or | BinOp::Bang | BinOp::Custom(_) => return None,
+    })
+}
+
+/// Remove the instructions in `chunk` that can never run, such as those
+/// after a `return`, `throw` or `break`, and the branches a condition known
+/// at compile time never takes. The jumps and spans of the instructions
+/// that are left are updated to match.
+pub fn eliminate_dead_code(chunk: &mut Chunk) {
+    let len = chunk.instructions.len();
+    let targeted = targeted(chunk);
+
+    // A literal tested by the branch after it decides which way the branch
+    // goes, unless another jump reaches the branch with some other value.
+    let mut removed = vec![false; len];
+
+    for ip in 1..len {
+        let Instruction::JumpIfFalse { offset } = chunk.instructions[ip] else {
+            continue;
+        };
+        let Some(truthy) = truthiness(&chunk.instructions[ip - 1]) else {
+            continue;
+        };
+
+        if targeted[ip] {
+            continue;
+        }
+
+        removed[ip - 1] = true;
+
+        if truthy {
+            removed[ip] = true;
+        } else {
+            chunk.instructions[ip] = Instruction::Jump { offset };
+        }
+    }
+
+    let mut reachable = vec![false; len];
+    let mut work = vec![0];
+
+    while let Some(ip) = work.pop() {
+        if ip >= len || reachable[ip] {
+            continue;
+        }
+
+        reachable[ip] = true;
+
+        if removed[ip] || falls_through(&chunk.instructions[ip]) {
+            work.push(ip + 1);
+        }
+
+        if !removed[ip] {
+            work.extend(jumps(chunk, ip));
+        }
+    }
+
+    let keep = (0..len)
+        .map(|ip| reachable[ip] && !removed[ip])
+        .collect::<Vec<_>>();
+
+    compact(chunk, &keep);
+}
+
+/// Rewrite short sequences of instructions in `chunk` into fewer that do
+/// the same, unless a jump lands inside them:
+///
+/// - a value that is pushed and popped straight away isn't pushed at all
+/// - `Not` followed by `JumpIfFalse` becomes `JumpIfTrue`
+/// - two `GetLocal`s followed by `Add` become `AddLocals`
+pub fn peephole(chunk: &mut Chunk) {
+    let len = chunk.instructions.len();
+    let targeted = targeted(chunk);
+    let mut keep = vec![true; len];
+    let mut ip = 0;
+
+    while ip < len {
+        match chunk.instructions[ip..] {
+            [Instruction::Push { .. }
+            | Instruction::LoadNil
+            | Instruction::Constant { .. }
+            | Instruction::GetLocal { .. }
+            | Instruction::Dup { .. }, Instruction::Pop, ..]
+                if !targeted[ip + 1] =>
+            {
+                keep[ip] = false;
+                keep[ip + 1] = false;
+                ip += 2;
+            }
+            [Instruction::Not, Instruction::JumpIfFalse { offset }, ..] if !targeted[ip + 1] => {
+                keep[ip] = false;
+                chunk.instructions[ip + 1] = Instruction::JumpIfTrue { offset };
+                ip += 2;
+            }
+            [Instruction::GetLocal { slot: a }, Instruction::GetLocal { slot: b }, Instruction::Add, ..]
+                if !targeted[ip + 1] && !targeted[ip + 2] =>
+            {
+                keep[ip] = false;
+                keep[ip + 1] = false;
+                chunk.instructions[ip + 2] = Instruction::AddLocals { a, b };
+                ip += 3;
+            }
+            _ => ip += 1,
+        }
+    }
+
+    compact(chunk, &keep);
+}
+
+/// Which instructions in `chunk`, or the end of it, a jump lands on.
+fn targeted(chunk: &Chunk) -> Vec<bool> {
+    let mut targeted = vec![false; chunk.instructions.len() + 1];
+
+    for ip in 0..chunk.instructions.len() {
+        for target in jumps(chunk, ip) {
+            targeted[target] = true;
+        }
+    }
+
+    targeted
+}
+
+/// Remove the instructions in `chunk` that aren't kept, and move the jumps
+/// and spans of the rest to match. A jump to a removed instruction goes to
+/// the next one kept instead.
+fn compact(chunk: &mut Chunk, keep: &[bool]) {
+    let len = chunk.instructions.len();
+
+    if keep.iter().all(|&keep| keep) {
+        return;
+    }
+
+    // Where each instruction ends up, or the one after it if it is removed.
+    let mut moved = Vec::with_capacity(len + 1);
+    let mut kept = 0;
+
+    for &keep in keep {
+        moved.push(kept);
+        kept += usize::from(keep);
+    }
+
+    moved.push(kept);
+
+    for ip in (0..len).filter(|&ip| keep[ip]) {
+        let from = moved[ip] + 1;
+
+        match &mut chunk.instructions[ip] {
+            Instruction::Jump { offset }
+            | Instruction::JumpIfFalse { offset }
+            | Instruction::JumpIfTrue { offset }
+            | Instruction::JumpIfFalseOrPop { offset }
+            | Instruction::JumpIfTrueOrPop { offset }
+            | Instruction::Try { offset }
+            | Instruction::ForNext { offset, .. } => *offset = moved[ip + 1 + *offset] - from,
+            Instruction::Loop { offset } => *offset = from - moved[ip + 1 - *offset],
+            Instruction::JumpTable { index } => {
+                let table = &mut chunk.tables[*index];
+
+                for offset in table.targets.iter_mut().chain([&mut table.default]) {
+                    *offset = moved[ip + 1 + *offset] - from;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut flags = keep.iter();
+    chunk.instructions.retain(|_| *flags.next().unwrap());
+
+    let mut flags = keep.iter();
+    chunk.spans.retain(|_| *flags.next().unwrap());
+}
+
+/// The instructions the instruction at `ip` can jump to.
+fn jumps(chunk: &Chunk, ip: usize) -> Vec<usize> {
+    match &chunk.instructions[ip] {
+        Instruction::Jump { offset }
+        | Instruction::JumpIfFalse { offset }
+        | Instruction::JumpIfTrue { offset }
+        | Instruction::JumpIfFalseOrPop { offset }
+        | Instruction::JumpIfTrueOrPop { offset }
+        | Instruction::Try { offset }
+        | Instruction::ForNext { offset, .. } => vec![ip + 1 + offset],
+        Instruction::Loop { offset } => vec![ip + 1 - offset],
+        Instruction::JumpTable { index } => {
+            let table = &chunk.tables[*index];
+
+            table
+                .targets
+                .iter()
+                .chain([&table.default])
+                .map(|offset| ip + 1 + offset)
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `inst` can go on to the instruction after it.
+fn falls_through(inst: &Instruction) -> bool {
+    !matches!(
+        inst,
+        Instruction::Jump { .. }
+            | Instruction::Loop { .. }
+            | Instruction::JumpTable { .. }
+            | Instruction::Return
+            | Instruction::Throw
+            | Instruction::Halt
+    )
+}
+
+/// Whether the value `inst` pushes is truthy, if it is a literal.
+fn truthiness(inst: &Instruction) -> Option<bool> {
+    match inst {
+        Instruction::Push {
+            value: InstanceValue::Bool(v),
+        } => Some(*v),
+        Instruction::Push { .. } => Some(true),
+        Instruction::LoadNil => Some(false),
+        _ => None,
+    }
+}
+
+/// The value of a literal, if it has one at runtime.
+pub(crate) fn lit_value(lit: &Lit) -> Option<Value> {
+    match lit {
+        Lit::Integer(v) => Some(Value::Integer(*v)),
+        Lit::Float(v) => Some(Value::Float(*v)),
+        Lit::Bool(v) => Some(Value::Bool(*v)),
+        Lit::String(v) => Some(Value::String(v.clone())),
+        Lit::Nil => Some(Value::Nil),
+        Lit::Char(_) => None,
+    }
+}
+
+/// The literal for `value`, if it can be written as one.
+pub(crate) fn value_lit(value: Value) -> Option<Lit> {
+    match value {
+        Value::Integer(v) => Some(Lit::Integer(v)),
+        Value::Float(v) => Some(Lit::Float(v)),
+        Value::Bool(v) => Some(Lit::Bool(v)),
+        Value::String(v) => Some(Lit::String(v)),
+        Value::Nil => Some(Lit::Nil),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ast::{Expr, Lit},
+        compiler::Compiler,
+        instruction::Instruction,
+        lexer::Lexer,
+        parser::Parser,
+        scope::State,
+        value::Value,
+        Thrush,
+    };
+
+    fn fold(source: &str) -> Expr {
+        let (mut ast, expr) = Parser::parse_expression(Lexer::tokenize(source)).unwrap();
+
+        super::fold(&mut ast);
+
+        ast[expr].clone()
+    }
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(fold("60 * 60 * 24"), Expr::Literal(Lit::Integer(86400)));
+        assert_eq!(fold("1.5 + 2 * -1"), Expr::Literal(Lit::Float(-0.5)));
+        assert_eq!(
+            fold("\"day\" + \"light\""),
+            Expr::Literal(Lit::String("daylight".into()))
+        );
+        assert_eq!(fold("!!false"), Expr::Literal(Lit::Bool(false)));
+        assert_eq!(fold("2 * 3 >= 6"), Expr::Literal(Lit::Bool(true)));
+        assert_eq!(fold("1 != 1.0"), Expr::Literal(Lit::Bool(false)));
+        assert!(matches!(fold("1 < \"a\""), Expr::BinExpr { .. }));
+        assert!(matches!(fold("true || x"), Expr::BinExpr { .. }));
+        assert!(matches!(fold("1 / 0"), Expr::BinExpr { .. }));
+        assert!(matches!(fold("\"ab\" * 3"), Expr::BinExpr { .. }));
+        assert!(matches!(fold("x * (2 + 3)"), Expr::BinExpr { .. }));
+    }
+
+    #[test]
+    fn test_eliminate_dead_code() {
+        let ast = Parser::new(Lexer::tokenize("if false {\n    a()\n} else {\n    b()\n}\nwhile true {\n    break\n    c()\n}\nfun f() {\n    return 1\n    d()\n}"))
+            .parse()
+            .unwrap();
+        let mut state = State::new();
+
+        for name in ["a", "b", "c", "d"] {
+            state.add(name, Value::Nil);
+        }
+
+        let chunk = Compiler::new(&mut state).run(ast).unwrap();
+        let mut gets = |name| Instruction::GetGlobal {
+            slot: state.slot(name),
+        };
+
+        assert!(!chunk.instructions.contains(&gets("a")));
+        assert!(chunk.instructions.contains(&gets("b")));
+        assert!(!chunk.instructions.contains(&gets("c")));
+        assert!(!chunk
+            .instructions
+            .iter()
+            .any(|inst| matches!(inst, Instruction::JumpIfFalse { .. })));
+        assert_eq!(chunk.spans.len(), chunk.instructions.len());
+
+        let Some(Value::ScriptFun(f)) = chunk.constants.last() else {
+            panic!("expected the function f");
+        };
+
+        assert_eq!(
+            f.chunk.instructions,
+            [Instruction::integer(1), Instruction::Return]
+        );
+
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("fun f(n) {\n    var log = []\n    for i in 0..n {\n        switch i {\n            0 => log = [...log, \"zero\"]\n            1 => {\n                continue\n                log = [...log, \"one\"]\n            }\n            2 => log = [...log, \"two\"]\n            _ => {\n                break\n            }\n        }\n        if false {\n            log = [...log, \"never\"]\n        }\n    }\n    try {\n        throw \"thrown\"\n        log = [...log, \"after\"]\n    } catch (e) {\n        log = [...log, e]\n    }\n    return log\n    log = []\n}\nvar log = f(5)")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("log").unwrap().to_string(),
+            "[\"zero\", \"two\", \"thrown\"]"
+        );
+    }
+
+    #[test]
+    fn test_peephole() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("fun f(flag, a, b) {\n    \"unused\"\n    if !flag {\n        return a + b\n    }\n    return b\n}\nvar sum = f(nil, 2, 3)\nvar other = f(true, 2, 3)")
+            .unwrap();
+
+        let Ok(Value::ScriptFun(f)) = thrush.globals().get::<Value>("f") else {
+            panic!("expected the function f");
+        };
+
+        assert!(!f.chunk.instructions.contains(&Instruction::Pop));
+        assert!(!f.chunk.instructions.contains(&Instruction::Not));
+        assert!(f
+            .chunk
+            .instructions
+            .iter()
+            .any(|inst| matches!(inst, Instruction::JumpIfTrue { .. })));
+        assert!(f
+            .chunk
+            .instructions
+            .contains(&Instruction::AddLocals { a: 2, b: 3 }));
+        assert_eq!(thrush.globals().get::<i64>("sum"), Ok(5));
+        assert_eq!(thrush.globals().get::<i64>("other"), Ok(3));
+        assert_eq!(
+            thrush
+                .exec("f(nil, \"a\", 1)")
+                .map_err(|err| err.to_string()),
+            Err("[E0204] line 4:20: unsupported operand types for +: string and int".into())
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Where scripts write their output.
+//!
+//! Natives that print should write to the [Output] of the VM's state rather
+//! than to stdout, so hosts can redirect it with
+//! [Thrush::set_output](crate::Thrush::set_output), e.g. into a console
+//! widget, and [Thrush::exec_report](crate::Thrush::exec_report) can capture
+//! what a single run printed.
+
+use std::{cell::RefCell, fmt, mem, rc::Rc};
+
+/// Where written text ends up.
+enum Sink {
+    Stdout,
+    Capture(String),
+    Custom(Box<dyn FnMut(&str)>),
+}
+
+/// A shared handle to the output sink of a VM. Clones write to the same
+/// sink.
+#[derive(Clone)]
+pub struct Output(Rc<RefCell<Sink>>);
+
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Output")
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self(Rc::new(RefCell::new(Sink::Stdout)))
+    }
+}
+
+impl Output {
+    /// Write `text` to the sink.
+    pub fn write(&self, text: &str) {
+        match &mut *self.0.borrow_mut() {
+            Sink::Stdout => print!("{text}"),
+            Sink::Capture(captured) => captured.push_str(text),
+            Sink::Custom(sink) => sink(text),
+        }
+    }
+
+    /// Pass everything written from now on to `sink`.
+    pub fn set<F: FnMut(&str) + 'static>(&self, sink: F) {
+        *self.0.borrow_mut() = Sink::Custom(Box::new(sink));
+    }
+
+    /// Run `f` and return what was written while it ran, instead of passing
+    /// it to the sink.
+    pub(crate) fn capture<T>(&self, f: impl FnOnce() -> T) -> (T, String) {
+        let sink = mem::replace(&mut *self.0.borrow_mut(), Sink::Capture(String::new()));
+        let result = f();
+        let mut current = self.0.borrow_mut();
+
+        match mem::replace(&mut *current, sink) {
+            Sink::Capture(captured) => (result, captured),
+            // The sink was set while `f` ran, so keep the new one.
+            sink => {
+                *current = sink;
+                (result, String::new())
+            }
+        }
+    }
+}
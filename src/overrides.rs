@@ -0,0 +1,114 @@
+//! Swapping out globals and modules for a single run of a script.
+//!
+//! Tests of scripts that use the network or the filesystem can replace the
+//! natives and modules that reach them with fixtures, without changing how
+//! the VM is set up for everything else:
+//!
+//! ```
+//! use thrush::{overrides::Overrides, Thrush};
+//!
+//! let mut thrush = Thrush::new();
+//! let overrides = Overrides::new()
+//!     .global("fetch", "<html></html>")
+//!     .module("config", "export var retries = 0");
+//!
+//! thrush.set_module_loader(|_| Err("no network in tests".into()));
+//! thrush
+//!     .exec_with_overrides("import \"config\"\nvar page = [fetch, config.retries]", &overrides)
+//!     .unwrap();
+//! ```
+//!
+//! Once the script has run, every overridden global goes back to its old
+//! value and every overridden module is unloaded, restoring the module that
+//! was loaded before, if any.
+
+use crate::value::{ToValue, Value};
+
+/// The globals and modules to replace while a script runs, for
+/// [Thrush::exec_with_overrides](crate::Thrush::exec_with_overrides).
+#[derive(Debug, Default, Clone)]
+pub struct Overrides {
+    pub(crate) globals: Vec<(String, Value)>,
+    pub(crate) modules: Vec<(String, String)>,
+}
+
+impl Overrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind the global `name` to `value`, such as a fixture in place of a
+    /// native function or class.
+    pub fn global<T: ToValue>(mut self, name: &str, value: T) -> Self {
+        self.globals.push((name.into(), value.to_value()));
+        self
+    }
+
+    /// Load the module `path` from `source` instead of asking the module
+    /// loader for it.
+    pub fn module(mut self, path: &str, source: &str) -> Self {
+        self.modules.push((path.into(), source.into()));
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{value::Value, Thrush};
+
+    use super::Overrides;
+
+    #[test]
+    fn test_overrides() {
+        let mut thrush = Thrush::new();
+
+        thrush.set_module_loader(|path| match path {
+            "http" => Ok("export fun get(url) {\n    return \"live\"\n}".into()),
+            _ => Err("not found".into()),
+        });
+        thrush
+            .globals()
+            .add_function("now", |_| Ok(Value::Integer(1000)));
+        thrush
+            .exec("import \"http\"\nvar before = [http.get(1), now()]")
+            .unwrap();
+
+        let overrides = Overrides::new()
+            .global("now", Value::Integer(5))
+            .global("extra", Value::Bool(true))
+            .module("http", "export fun get(url) {\n    return \"fixture\"\n}");
+
+        thrush
+            .exec_with_overrides(
+                "import \"http\"\nvar during = [http.get(1), now, extra]",
+                &overrides,
+            )
+            .unwrap();
+        thrush
+            .exec("import \"http\"\nvar after = [http.get(1), now()]")
+            .unwrap();
+
+        let global = |thrush: &mut Thrush, name| thrush.globals().get::<Value>(name).unwrap();
+
+        assert_eq!(
+            global(&mut thrush, "before").to_string(),
+            "[\"live\", 1000]"
+        );
+        assert_eq!(
+            global(&mut thrush, "during").to_string(),
+            "[\"fixture\", 5, true]"
+        );
+        assert_eq!(global(&mut thrush, "after").to_string(), "[\"live\", 1000]");
+        assert!(!thrush.globals().contains("extra"));
+
+        // Overrides are undone when the script fails, too.
+        let err = thrush
+            .exec_with_overrides("now = nil\nmissing", &Overrides::new().global("now", 1))
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("undefined variable 'missing'"));
+        thrush.exec("var later = now()").unwrap();
+        assert_eq!(thrush.globals().get::<i64>("later"), Ok(1000));
+    }
+}
@@ -1,18 +1,39 @@
-use std::mem;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    mem,
+};
 
 use crate::{
-    ast::{Ast, BinOp, Expr, Lit, Stmt},
-    token::{self, Keyword, Token, TokenKind},
+    ast::{
+        Ast, BinOp, Catch, Comment, Destructure, Expr, ExprId, FunDecl, FunKind, HostStmt, Lit,
+        MatchArm, Pattern, StaticField, Stmt, SwitchCase,
+    },
+    compiler::CompilerOptions,
+    diagnostic::{Error, ErrorCode},
+    token::{self, Keyword, Span, Token, TokenKind},
 };
 
-/// Defines the precedence of different operators and expressions.
+/// Defines the precedence of different operators and expressions, from
+/// loosest to tightest.
+///
+/// Some levels don't have any built-in operators yet, but custom operators
+/// can be given them.
 #[repr(u8)]
 #[derive(Debug, PartialEq, PartialOrd, Copy, Clone)]
 pub enum Precedence {
     None = 0,
-    Sum,  // +, -
-    Term, // *, /, %
-    Call,
+    Assignment, // =
+    Or,
+    And,
+    Equality,
+    Comparison, // is
+    Range,      // ..
+    Sum,        // +, -
+    Term,       // *, /, %
+    Unary,      // -, +, !, typeof, await
+    Call,       // (), ., ++, --
+    Primary,
     End,
 }
 
@@ -22,28 +43,145 @@ impl Precedence {
     }
 }
 
+/// How deeply expressions and blocks can be nested by default.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// The name given to anonymous functions, such as in error messages.
+pub const ANONYMOUS: &str = "anonymous";
+
 /// Parser for the Thrush langauge.
-pub struct Parser {
-    pub tokens: Vec<Token>,
+///
+/// Tokens are pulled from the token stream as they are needed, so parsing
+/// can start before the whole script has been tokenized.
+pub struct Parser<'a> {
+    tokens: Box<dyn Iterator<Item = Token> + 'a>,
     pub current: Token,
-    pub pos: usize,
+    /// The token after the current one.
+    next: Token,
+    /// How deeply expressions and blocks can be nested before parsing fails,
+    /// rather than overflowing the stack.
+    pub max_depth: usize,
+    depth: usize,
+    /// The AST being built.
+    ast: Ast,
+    /// The precedence of custom operators, by symbol.
+    operators: HashMap<String, Precedence>,
+    /// The keywords of statements claimed by the host.
+    host_keywords: HashSet<String>,
+    /// How many brackets, braces and parentheses are open in the tokens
+    /// consumed so far, to find where statements end when recovering from
+    /// an error.
+    open: usize,
+    /// Where the innermost expression, block or statement being parsed
+    /// starts, which the expressions added for it are given.
+    start: Span,
+    /// The line of the last token taken from the token stream, to tell
+    /// comments after code from comments on lines of their own.
+    line: usize,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self {
-            tokens,
+impl<'a> Parser<'a> {
+    pub fn new<I>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = Token>,
+        I::IntoIter: 'a,
+    {
+        let mut parser = Self {
+            tokens: Box::new(tokens.into_iter()),
             current: Token::new(TokenKind::Eof),
-            pos: 0,
+            next: Token::new(TokenKind::Eof),
+            max_depth: DEFAULT_MAX_DEPTH,
+            depth: 0,
+            ast: Ast::default(),
+            operators: HashMap::new(),
+            host_keywords: HashSet::new(),
+            open: 0,
+            start: Span::default(),
+            line: 0,
+        };
+
+        parser.current = parser.pull();
+        parser.next = parser.pull();
+        parser
+    }
+
+    /// Set how deeply expressions and blocks can be nested.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Parse the custom operators and host statements registered in `options`.
+    pub fn with_options(mut self, options: &CompilerOptions) -> Self {
+        self.operators = options
+            .operators()
+            .map(|(symbol, operator)| (symbol.into(), operator.precedence))
+            .collect();
+        self.host_keywords = options.host_keywords().map(String::from).collect();
+        self
+    }
+
+    /// Run `f` one level of nesting deeper, failing if that exceeds the limit.
+    ///
+    /// The expressions added while it runs start at the current token,
+    /// unless they are nested further.
+    fn nested<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth >= self.max_depth {
+            return Err(self.error(nesting_error(self.max_depth)));
         }
+
+        let start = mem::replace(&mut self.start, self.current.span);
+
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        self.start = start;
+
+        result
+    }
+
+    /// Add `expr` to the AST, starting where the innermost expression being
+    /// parsed does.
+    fn add(&mut self, expr: Expr) -> ExprId {
+        self.ast.add_at(expr, self.start)
     }
 
     // TODO: add error handling.
     /// Consume the current token, and get the next one from the token stream.
     pub fn consume(&mut self) {
-        if self.pos <= self.tokens.len() {
-            self.pos += 1;
-            self.current = self.tokens[self.pos].clone();
+        match self.current.kind {
+            TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => self.open += 1,
+            TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => {
+                self.open = self.open.saturating_sub(1)
+            }
+            _ => {}
+        }
+
+        let next = self.pull();
+
+        self.current = mem::replace(&mut self.next, next);
+    }
+
+    /// Take the next token from the token stream, setting aside the
+    /// comments before it in the AST.
+    fn pull(&mut self) -> Token {
+        loop {
+            let token = self.tokens.next().unwrap_or(Token::new(TokenKind::Eof));
+
+            let TokenKind::Comment(text) = token.kind else {
+                self.line = token.span.line;
+
+                return token;
+            };
+
+            self.ast.comments.push(Comment {
+                text,
+                span: token.span,
+                trailing: token.span.line == self.line,
+            });
         }
     }
 
@@ -52,250 +190,2885 @@ impl Parser {
         match &self.current.kind {
             TokenKind::Plus | TokenKind::Hypen => Precedence::Sum,
             TokenKind::Star | TokenKind::BackSlash | TokenKind::Modulo => Precedence::Term,
-            TokenKind::Dot | TokenKind::LParen => Precedence::Call,
-            TokenKind::Eof | TokenKind::RParen => Precedence::End,
-            kind => todo!("No rule implemented for {kind:?}"),
+            TokenKind::Or => Precedence::Or,
+            TokenKind::And => Precedence::And,
+            TokenKind::Equal | TokenKind::NotEqual => Precedence::Equality,
+            TokenKind::Less
+            | TokenKind::LessEqual
+            | TokenKind::Greater
+            | TokenKind::GreaterEqual
+            | TokenKind::Keyword(Keyword::Is) => Precedence::Comparison,
+            TokenKind::DotDot => Precedence::Range,
+            TokenKind::Dot | TokenKind::LParen | TokenKind::Increment | TokenKind::Decrement => {
+                Precedence::Call
+            }
+            TokenKind::Operator(symbol) => self
+                .operators
+                .get(&**symbol)
+                .copied()
+                .unwrap_or(Precedence::End),
+            // Assignments are parsed by `expression`, since only some
+            // expressions can be assigned to.
+            //
+            // Anything else can't continue an expression, so it ends it and
+            // is left for whatever comes after to deal with.
+            _ => Precedence::End,
         }
     }
 
     /// Parse a statement.
-    fn statement(&mut self) -> Result<Stmt, String> {
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        self.start = self.current.span;
+
         match &self.current.kind {
             TokenKind::Keyword(keyword) => match keyword {
                 Keyword::Class => self.class(),
+                Keyword::Fun | Keyword::Async => self.function(),
                 Keyword::Var => self.var_decl(),
-                _ => todo!(),
+                Keyword::Const => self.const_decl(),
+                Keyword::Let => self.let_decl(),
+                Keyword::Return => self.return_stmt(),
+                Keyword::Match => self.match_stmt(),
+                Keyword::Switch => self.switch_stmt(),
+                Keyword::Import => self.import(),
+                Keyword::Export => self.export(),
+                Keyword::Throw => self.throw(),
+                Keyword::Try => self.try_stmt(),
+                Keyword::While => self.while_stmt(None),
+                Keyword::For => self.for_stmt(None),
+                Keyword::Yield => self.yield_stmt(),
+                Keyword::Break => self.jump(Keyword::Break),
+                Keyword::Continue => self.jump(Keyword::Continue),
+                _ => self.expr(),
             },
+            TokenKind::At => self.decorated_function(),
+            TokenKind::Ident(name) if self.host_keywords.contains(&**name) => self.host_stmt(),
+            TokenKind::Ident(name) if self.is_trait() => self.trait_decl(),
+            TokenKind::Ident(_) if *self.peek() == TokenKind::Colon => self.labeled(),
             _ => self.expr(),
         }
     }
 
     /// Parse a class declaration.
-    fn class(&mut self) -> Result<Stmt, String> {
+    fn class(&mut self) -> Result<Stmt, ParseError> {
+        let span = self.current.span;
+
         self.consume();
 
         let name = self.identifier()?;
+        let mut traits = Vec::new();
+        let mut mixins = Vec::new();
+        let mut fields = Vec::new();
+        let mut statics = Vec::new();
+        let mut methods = Vec::new();
+        let mut version = None;
 
-        let class = Stmt::Class { name };
+        // The traits after `:` and the mixins after `with` can come in
+        // either order.
+        loop {
+            let names = match &self.current.kind {
+                TokenKind::Colon if traits.is_empty() => &mut traits,
+                TokenKind::Ident(ident) if &**ident == "with" && mixins.is_empty() => &mut mixins,
+                _ => break,
+            };
 
-        // {
-        self.consume();
-        // }
-        self.consume();
+            self.consume();
+            names.push(self.identifier()?);
 
-        Ok(class)
+            while self.current.kind == TokenKind::Comma {
+                self.consume();
+                names.push(self.identifier()?);
+            }
+        }
+
+        self.expect(TokenKind::LBrace, "after class name")?;
+        self.skip_newlines();
+
+        let doc = self.docstring();
+
+        while self.current.kind != TokenKind::RBrace && self.current.kind != TokenKind::Eof {
+            if self.current.kind == TokenKind::Keyword(Keyword::Var) {
+                self.consume();
+                fields.push(self.identifier()?);
+            } else if self.current.kind == TokenKind::Keyword(Keyword::Static)
+                && matches!(
+                    self.peek(),
+                    TokenKind::Keyword(Keyword::Var | Keyword::Const)
+                )
+            {
+                statics.push(self.static_field()?);
+            } else if self.is_version() {
+                if version.is_some() {
+                    return Err(self.error(format!(
+                        "class '{name}' declares its version more than once"
+                    )));
+                }
+
+                version = Some(self.version()?);
+            } else {
+                let decorators = self.decorators()?;
+
+                methods.push(FunDecl {
+                    decorators,
+                    ..self.method()?
+                });
+            }
+
+            self.skip_newlines();
+        }
+
+        self.expect(TokenKind::RBrace, "after class body")?;
+
+        Ok(Stmt::Class {
+            name,
+            span,
+            traits,
+            mixins,
+            fields,
+            statics,
+            methods,
+            version,
+            doc,
+        })
     }
 
-    fn var_decl(&mut self) -> Result<Stmt, String> {
-        // var ...
+    /// Parse a `static var` or `static const` declaration in a class body.
+    fn static_field(&mut self) -> Result<StaticField, ParseError> {
+        self.consume();
+
+        let constant = self.current.kind == TokenKind::Keyword(Keyword::Const);
+
         self.consume();
-        // var id ...
-        let id = self.identifier()?;
 
+        let name = self.identifier()?;
         let init = if let TokenKind::Assign = self.current.kind {
-            // var id = ...
             self.consume();
-            // var id = expr ...
             self.expression(Precedence::None)?
+        } else if constant {
+            return Err(self.error(format!("static constant '{name}' must be initialized")));
         } else {
-            Expr::Literal(Lit::Nil)
+            self.add(Expr::Literal(Lit::Nil))
         };
 
-        match &self.current.kind {
-            TokenKind::Newline => {
-                self.consume();
-            }
-            TokenKind::Eof | TokenKind::RBrace => {},
-            _ => panic!("Unexpected token"),
+        Ok(StaticField {
+            name,
+            init,
+            constant,
+        })
+    }
+
+    /// Whether the current token starts a trait declaration, which like
+    /// `version` is only a keyword when followed by a name.
+    fn is_trait(&self) -> bool {
+        matches!(&self.current.kind, TokenKind::Ident(ident) if &**ident == "trait")
+            && matches!(self.peek(), TokenKind::Ident(_))
+    }
+
+    /// Parse a trait declaration, whose body lists the methods it requires
+    /// without their bodies.
+    fn trait_decl(&mut self) -> Result<Stmt, ParseError> {
+        self.consume();
+
+        let name = self.identifier()?;
+        let mut methods = Vec::new();
+
+        self.expect(TokenKind::LBrace, "after trait name")?;
+        self.skip_newlines();
+
+        while self.current.kind != TokenKind::RBrace && self.current.kind != TokenKind::Eof {
+            self.expect(TokenKind::Keyword(Keyword::Fun), "in trait body")?;
+
+            let method = self.identifier()?;
+            let (params, ..) = self.params()?;
+
+            methods.push((method, params.len()));
+            self.skip_newlines();
         }
 
-        Ok(Stmt::VarDecl { id, init })
+        self.expect(TokenKind::RBrace, "after trait body")?;
+
+        Ok(Stmt::Trait { name, methods })
     }
 
-    /// Parse a expression and a newline.
-    pub fn expr(&mut self) -> Result<Stmt, String> {
-        let expr = self.expression(Precedence::None)?;
+    /// Parse an object literal, whose body declares fields and methods like
+    /// a class body, except that fields can have initializers.
+    fn object(&mut self) -> Result<ExprId, ParseError> {
+        self.consume();
+        self.expect(TokenKind::LBrace, "after 'object'")?;
+        self.skip_newlines();
 
-        match &self.current.kind {
-            TokenKind::Newline => {
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+
+        while self.current.kind != TokenKind::RBrace && self.current.kind != TokenKind::Eof {
+            if self.current.kind == TokenKind::Keyword(Keyword::Var) {
                 self.consume();
 
-                Ok(Stmt::Expr(expr))
+                let name = self.identifier()?;
+                let init = if let TokenKind::Assign = self.current.kind {
+                    self.consume();
+                    self.expression(Precedence::None)?
+                } else {
+                    self.add(Expr::Literal(Lit::Nil))
+                };
+
+                fields.push((name, init));
+            } else {
+                let decorators = self.decorators()?;
+                let method = self.method()?;
+
+                if method.kind == FunKind::Static {
+                    return Err(self.error(format!(
+                        "an object cannot have a static method '{}'",
+                        method.name
+                    )));
+                }
+
+                methods.push(FunDecl {
+                    decorators,
+                    ..method
+                });
             }
-            TokenKind::Eof | TokenKind::RBrace => Ok(Stmt::Expr(expr)),
-            _ => panic!("Unexpected token"),
+
+            self.skip_newlines();
         }
+
+        self.expect(TokenKind::RBrace, "after object body")?;
+
+        Ok(self.add(Expr::Object { fields, methods }))
     }
 
-    /// Parse an expression.
-    pub fn expression(&mut self, prec: Precedence) -> Result<Expr, String> {
-        let mut left = self.literal()?;
+    /// Consume the string literal at the start of a class body, if there is
+    /// one, and return it.
+    fn docstring(&mut self) -> Option<String> {
+        let TokenKind::Literal(token::Lit::String(doc)) = self.current.kind.clone() else {
+            return None;
+        };
 
-        while self.prec() >= prec && self.prec() != Precedence::End {
-            left = self.infix_expr(left)?;
+        self.consume();
+        self.skip_newlines();
+
+        Some(doc)
+    }
+
+    /// Whether the current token starts a class version, which like `get`
+    /// and `set` is only a keyword when followed by an integer.
+    fn is_version(&self) -> bool {
+        matches!(&self.current.kind, TokenKind::Ident(ident) if &**ident == "version")
+            && matches!(self.peek(), TokenKind::Literal(token::Lit::Integer(_)))
+    }
+
+    /// Parse a class version, such as `version 2`.
+    fn version(&mut self) -> Result<u32, ParseError> {
+        self.consume();
+
+        let TokenKind::Literal(token::Lit::Integer(version)) = self.current.kind else {
+            return Err(self.expected(&["a version number"], ""));
+        };
+
+        self.consume();
+
+        u32::try_from(version).map_err(|_| self.error(format!("invalid class version {version}")))
+    }
+
+    /// Parse a function declaration.
+    fn function(&mut self) -> Result<Stmt, ParseError> {
+        let is_async = self.asyncness();
+
+        self.expect(TokenKind::Keyword(Keyword::Fun), "after \'async\'")?;
+
+        let name = self.identifier()?;
+        let (params, param_types, variadic, unpack) = self.params()?;
+        let returns = self.return_type()?;
+        let (doc, body) = self.function_body()?;
+
+        Ok(Stmt::Fun(FunDecl {
+            name,
+            params,
+            param_types,
+            variadic,
+            unpack,
+            returns,
+            body,
+            kind: FunKind::Function,
+            is_async,
+            doc,
+            decorators: Vec::new(),
+        }))
+    }
+
+    /// Parse an anonymous function, which can't use the locals of the
+    /// function it is in.
+    fn anonymous_function(&mut self) -> Result<ExprId, ParseError> {
+        self.consume();
+
+        let (params, param_types, variadic, unpack) = self.params()?;
+        let returns = self.return_type()?;
+        let (doc, body) = self.function_body()?;
+
+        Ok(self.add(Expr::Function(Box::new(FunDecl {
+            name: ANONYMOUS.into(),
+            params,
+            param_types,
+            variadic,
+            unpack,
+            returns,
+            body,
+            kind: FunKind::Function,
+            is_async: false,
+            doc,
+            decorators: Vec::new(),
+        }))))
+    }
+
+    /// Parse the body of a function, taking a string literal at its start as
+    /// the function's docstring.
+    fn function_body(&mut self) -> Result<(Option<String>, Vec<Stmt>), ParseError> {
+        let mut body = self.block()?;
+
+        let doc = match body.first() {
+            Some(Stmt::Expr(expr)) => match &self.ast[*expr] {
+                Expr::Literal(Lit::String(doc)) => Some(doc.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if doc.is_some() {
+            body.remove(0);
         }
 
-        Ok(left)
+        Ok((doc, body))
     }
 
-    /// Parse a binary expression.
-    pub fn infix_expr(&mut self, mut left: Expr) -> Result<Expr, String> {
-        match &self.current.kind {
-            TokenKind::Plus => {
-                self.consume();
+    /// Parse a function declaration after its decorators.
+    fn decorated_function(&mut self) -> Result<Stmt, ParseError> {
+        let decorators = self.decorators()?;
 
-                left =
-                    Expr::binary_expr(BinOp::Add, left, self.expression(Precedence::Sum.left())?);
-            }
-            TokenKind::Hypen => {
-                self.consume();
+        if !matches!(
+            self.current.kind,
+            TokenKind::Keyword(Keyword::Fun | Keyword::Async)
+        ) {
+            return Err(self.expected(&["a function declaration after its decorators"], ""));
+        }
 
-                left =
-                    Expr::binary_expr(BinOp::Sub, left, self.expression(Precedence::Sum.left())?);
-            }
-            TokenKind::Star => {
-                self.consume();
+        let mut stmt = self.function()?;
 
-                left =
-                    Expr::binary_expr(BinOp::Mul, left, self.expression(Precedence::Term.left())?);
-            }
-            TokenKind::BackSlash => {
-                self.consume();
+        if let Stmt::Fun(decl) = &mut stmt {
+            decl.decorators = decorators;
+        }
 
-                left =
-                    Expr::binary_expr(BinOp::Div, left, self.expression(Precedence::Term.left())?);
-            }
-            TokenKind::Modulo => {
-                self.consume();
+        Ok(stmt)
+    }
 
-                left =
-                    Expr::binary_expr(BinOp::Rem, left, self.expression(Precedence::Term.left())?);
-            }
-            TokenKind::LParen => {
-                self.consume();
+    /// Parse the decorators before a declaration, such as `@memoize`, each
+    /// followed by a newline or the declaration.
+    fn decorators(&mut self) -> Result<Vec<ExprId>, ParseError> {
+        let mut decorators = Vec::new();
 
-                left = Expr::Call {
-                    callee: Box::new(left),
-                    args: Vec::new(),
-                };
+        while self.current.kind == TokenKind::At {
+            self.consume();
+            decorators.push(self.expression(Precedence::None)?);
+            self.skip_newlines();
+        }
 
-                self.consume();
-            }
-            TokenKind::Dot => {
-                self.consume();
+        Ok(decorators)
+    }
 
-                left = Expr::Dot {
-                    object: Box::new(left),
-                    property: Box::new(self.literal()?),
-                };
+    /// Consume `async` if it is the current token, and return whether it was.
+    fn asyncness(&mut self) -> bool {
+        let is_async = self.current.kind == TokenKind::Keyword(Keyword::Async);
+
+        if is_async {
+            self.consume();
+        }
+
+        is_async
+    }
+
+    /// Parse a method declaration inside a class body.
+    fn method(&mut self) -> Result<FunDecl, ParseError> {
+        // `get` and `set` are only keywords when followed by a name.
+        let kind = match (&self.current.kind, self.peek()) {
+            (TokenKind::Keyword(Keyword::Static), _) => FunKind::Static,
+            (TokenKind::Ident(ident), TokenKind::Ident(_)) if &**ident == "get" => FunKind::Getter,
+            (TokenKind::Ident(ident), TokenKind::Ident(_)) if &**ident == "set" => FunKind::Setter,
+            _ => FunKind::Method,
+        };
+
+        if kind != FunKind::Method {
+            self.consume();
+        }
+
+        let is_async = self.asyncness();
+
+        if matches!(kind, FunKind::Method | FunKind::Static) {
+            self.expect(TokenKind::Keyword(Keyword::Fun), "before a method")?;
+        }
+
+        let name = self.identifier()?;
+        let (params, param_types, variadic, unpack) = self.params()?;
+        let returns = self.return_type()?;
+
+        match (kind, params.len()) {
+            (FunKind::Getter, 1..) => {
+                return Err(
+                    self.error(ErrorCode::InvalidAccessor.error("a getter cannot take parameters"))
+                )
+            }
+            (FunKind::Setter, len) if len != 1 || variadic => {
+                return Err(self.error(
+                    ErrorCode::InvalidAccessor.error("a setter must take exactly one parameter"),
+                ))
             }
             _ => {}
         }
 
-        Ok(left)
+        let (doc, body) = self.function_body()?;
+
+        Ok(FunDecl {
+            name,
+            params,
+            param_types,
+            variadic,
+            unpack,
+            returns,
+            body,
+            kind,
+            is_async,
+            doc,
+            decorators: Vec::new(),
+        })
     }
 
-    /// Parse a literal.
-    pub fn literal(&mut self) -> Result<Expr, String> {
-        match &self.current.kind.clone() {
-            TokenKind::Literal(literal) => match literal {
-                token::Lit::Integer(int) => {
-                    self.consume();
-                    Ok(Expr::Literal(Lit::Integer(*int)))
-                }
-                token::Lit::String(_) => todo!(),
-                token::Lit::Float(_) => todo!(),
-            },
-            TokenKind::Hypen => {
-                self.consume();
+    /// Parse a parenthesized list of parameter names and their type
+    /// annotations, and whether the last one is a `...rest` parameter.
+    fn params(&mut self) -> Result<ParamList, ParseError> {
+        let mut params = Vec::new();
+        let mut types = Vec::new();
+        let mut variadic = false;
+        let mut unpack = Vec::new();
 
-                Ok(Expr::UnaryExpr {
-                    value: Box::new(self.expression(Precedence::End)?),
-                    op: BinOp::Sub,
-                })
-            }
-            TokenKind::Plus => {
-                self.consume();
+        self.expect(TokenKind::LParen, "before parameters")?;
 
-                Ok(Expr::UnaryExpr {
-                    value: Box::new(self.expression(Precedence::End)?),
-                    op: BinOp::Add,
-                })
-            }
-            TokenKind::Bang => {
+        while self.current.kind != TokenKind::RParen {
+            if self.current.kind == TokenKind::Ellipsis {
                 self.consume();
+                variadic = true;
+            }
 
-                Ok(Expr::UnaryExpr {
-                    value: Box::new(self.expression(Precedence::End)?),
-                    op: BinOp::Bang,
-                })
+            match self.current.kind {
+                TokenKind::LParen | TokenKind::LBrace if !variadic => {
+                    let (name, pattern) = self.destructure()?;
+
+                    unpack.push((params.len(), pattern));
+                    params.push(name);
+                }
+                _ => params.push(self.identifier()?),
             }
-            TokenKind::LParen => {
-                self.consume();
-                let node = self.expression(Precedence::None.left())?;
-                self.consume();
 
-                Ok(node)
+            types.push(self.annotation()?);
+
+            if variadic || self.current.kind != TokenKind::Comma {
+                break;
             }
-            TokenKind::Ident(_) => Ok(Expr::Identifier(self.identifier()?)),
-            _ => Err("unexpected token".into()),
+
+            self.consume();
         }
+
+        self.expect(TokenKind::RParen, "after parameters")?;
+
+        Ok((params, types, variadic, unpack))
     }
 
-    /// Constructs an [Ast] from a stream of tokens.
-    pub fn parse(&mut self) -> Result<Ast, String> {
-        self.current = self.tokens[self.pos].clone();
+    /// Parse a parameter that unpacks its argument, `(x, y)` or
+    /// `{host, port}`, and the name it is given in the parameter list.
+    fn destructure(&mut self) -> Result<(String, Destructure), ParseError> {
+        let items = self.current.kind == TokenKind::LParen;
+        let mut names = Vec::new();
 
-        let mut nodes = Vec::new();
-        while self.current.kind != TokenKind::Eof {
-            nodes.push(self.statement()?);
+        self.consume();
+        names.push(self.identifier()?);
+
+        while self.current.kind == TokenKind::Comma {
+            self.consume();
+            names.push(self.identifier()?);
         }
 
-        Ok(Ast { nodes })
+        let list = names.join(", ");
+
+        if items {
+            self.expect(TokenKind::RParen, "after unpacked items")?;
+
+            Ok((format!("({list})"), Destructure::Items(names)))
+        } else {
+            self.expect(TokenKind::RBrace, "after unpacked keys")?;
+
+            Ok((format!("{{{list}}}"), Destructure::Keys(names)))
+        }
     }
 
-    pub fn parse_ast(tokens: Vec<Token>) -> Result<Ast, String> {
-        let mut parser = Parser::new(tokens);
+    /// Parse the type annotation after a name, such as `: Int`, if there is one.
+    fn annotation(&mut self) -> Result<Option<String>, ParseError> {
+        if self.current.kind != TokenKind::Colon {
+            return Ok(None);
+        }
 
-        parser.parse()
+        self.consume();
+        self.type_name().map(Some)
     }
 
-    fn identifier(&mut self) -> Result<String, String> {
-        if let TokenKind::Ident(name) = &self.current.kind {
-            let name = name.to_string();
-            self.consume();
+    /// Parse the return type after a function's parameters, such as `-> Int`,
+    /// if there is one.
+    fn return_type(&mut self) -> Result<Option<String>, ParseError> {
+        if self.current.kind != TokenKind::Arrow {
+            return Ok(None);
+        }
 
-            Ok(name)
-        } else {
-            Err("expected a identifier".into())
+        self.consume();
+        self.type_name().map(Some)
+    }
+
+    fn type_name(&mut self) -> Result<String, ParseError> {
+        self.identifier()
+            .map_err(|_| self.expected(&["a type name"], ""))
+    }
+
+    /// Parse a block of statements surrounded by braces.
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.nested(Parser::block_body)
+    }
+
+    fn block_body(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.expect(TokenKind::LBrace, "before block")?;
+        self.skip_newlines();
+        self.block_rest()
+    }
+
+    /// Parse the statements of a block after its `{`, and the closing `}`.
+    fn block_rest(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut body = Vec::new();
+
+        while self.current.kind != TokenKind::RBrace && self.current.kind != TokenKind::Eof {
+            body.push(self.statement()?);
+            self.skip_newlines();
         }
+
+        self.expect(TokenKind::RBrace, "after block")?;
+
+        Ok(body)
     }
-}
 
-#[cfg(test)]
-pub mod test {
-    use crate::{
-        ast::{BinOp, Expr, Lit, Stmt},
-        lexer::Lexer,
-    };
+    fn return_stmt(&mut self) -> Result<Stmt, ParseError> {
+        let stmt = self.return_value()?;
 
-    use super::Parser;
+        self.end_of_statement()?;
 
-    #[test]
-    fn test_parser() {
-        let mut parser = Parser::new(Lexer::tokenize("4 + 2 * 5"));
+        Ok(stmt)
+    }
 
-        assert_eq!(
-            parser.parse().unwrap().nodes[0],
-            Stmt::Expr(Expr::BinExpr {
-                left: Box::new(Expr::Literal(Lit::Integer(4))),
-                right: Box::new(Expr::BinExpr {
-                    left: Box::new(Expr::Literal(Lit::Integer(2))),
-                    right: Box::new(Expr::Literal(Lit::Integer(5))),
-                    op: BinOp::Mul,
-                }),
-                op: BinOp::Add,
-            })
-        );
+    /// Parse `return` and its optional value.
+    fn return_value(&mut self) -> Result<Stmt, ParseError> {
+        // return ...
+        self.consume();
+
+        Ok(Stmt::Return(self.optional_value()?))
+    }
+
+    /// Parse the value after `return` or `yield`, which is nil if left out.
+    fn optional_value(&mut self) -> Result<ExprId, ParseError> {
+        match &self.current.kind {
+            TokenKind::Newline | TokenKind::Eof | TokenKind::RBrace | TokenKind::Comma => {
+                Ok(self.add(Expr::Literal(Lit::Nil)))
+            }
+            _ => self.expression(Precedence::None),
+        }
+    }
+
+    /// Parse a loop preceded by `label:`.
+    fn labeled(&mut self) -> Result<Stmt, ParseError> {
+        let label = self.identifier()?;
+
+        // label: ...
+        self.consume();
+
+        match self.current.kind {
+            TokenKind::Keyword(Keyword::While) => self.while_stmt(Some(label)),
+            TokenKind::Keyword(Keyword::For) => self.for_stmt(Some(label)),
+            _ => Err(self.expected(&["a loop"], &format!("after label '{label}'"))),
+        }
+    }
+
+    /// Parse a statement claimed by the host.
+    fn host_stmt(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.identifier()?;
+        let mut args = Vec::new();
+
+        while !matches!(
+            self.current.kind,
+            TokenKind::LBrace | TokenKind::Newline | TokenKind::RBrace | TokenKind::Eof
+        ) {
+            args.push(self.expression(Precedence::None)?);
+
+            if self.current.kind != TokenKind::Comma {
+                break;
+            }
+
+            self.consume();
+        }
+
+        let body = match self.current.kind {
+            TokenKind::LBrace => Some(self.block()?),
+            _ => {
+                self.end_of_statement()?;
+                None
+            }
+        };
+
+        Ok(Stmt::Host(HostStmt {
+            keyword,
+            args,
+            body,
+        }))
+    }
+
+    fn while_stmt(&mut self, label: Option<String>) -> Result<Stmt, ParseError> {
+        // while ...
+        self.consume();
+
+        let cond = self.expression(Precedence::None)?;
+        let body = self.block()?;
+
+        Ok(Stmt::While { label, cond, body })
+    }
+
+    fn for_stmt(&mut self, label: Option<String>) -> Result<Stmt, ParseError> {
+        // for ...
+        self.consume();
+
+        let var = self.identifier()?;
+
+        self.expect(TokenKind::Keyword(Keyword::In), "after loop variable")?;
+
+        let iterable = self.expression(Precedence::None)?;
+        let body = self.block()?;
+
+        Ok(Stmt::For {
+            label,
+            var,
+            iterable,
+            body,
+        })
+    }
+
+    fn yield_stmt(&mut self) -> Result<Stmt, ParseError> {
+        // yield ...
+        self.consume();
+
+        let value = self.optional_value()?;
+
+        self.end_of_statement()?;
+
+        Ok(Stmt::Yield(value))
+    }
+
+    /// Parse `break` or `continue` and an optional label.
+    fn jump(&mut self, keyword: Keyword) -> Result<Stmt, ParseError> {
+        // break ...
+        self.consume();
+
+        let label = match self.current.kind {
+            TokenKind::Ident(_) => Some(self.identifier()?),
+            _ => None,
+        };
+
+        self.end_of_statement()?;
+
+        match keyword {
+            Keyword::Break => Ok(Stmt::Break(label)),
+            _ => Ok(Stmt::Continue(label)),
+        }
+    }
+
+    fn throw(&mut self) -> Result<Stmt, ParseError> {
+        // throw ...
+        self.consume();
+
+        let value = self.expression(Precedence::None)?;
+
+        self.end_of_statement()?;
+
+        Ok(Stmt::Throw(value))
+    }
+
+    /// Parse `try { } catch (e) { } finally { }`, which needs a catch
+    /// clause, a finally block, or both.
+    fn try_stmt(&mut self) -> Result<Stmt, ParseError> {
+        // try ...
+        self.consume();
+
+        let body = self.block()?;
+        self.skip_newlines();
+
+        let catch = if self.current.kind == TokenKind::Keyword(Keyword::Catch) {
+            self.consume();
+            self.expect(TokenKind::LParen, "after 'catch'")?;
+
+            let name = self.identifier()?;
+            let class = if self.current.kind == TokenKind::Colon {
+                self.consume();
+                Some(self.identifier()?)
+            } else {
+                None
+            };
+
+            self.expect(TokenKind::RParen, "after catch variable")?;
+
+            let body = self.block()?;
+            self.skip_newlines();
+
+            Some(Catch { name, class, body })
+        } else {
+            None
+        };
+
+        let finally = if self.current.kind == TokenKind::Keyword(Keyword::Finally) {
+            self.consume();
+
+            Some(self.block()?)
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            return Err(self.expected(&["'catch'", "'finally'"], "after try block"));
+        }
+
+        Ok(Stmt::Try {
+            body,
+            catch,
+            finally,
+        })
+    }
+
+    fn match_stmt(&mut self) -> Result<Stmt, ParseError> {
+        // match ...
+        self.consume();
+
+        let value = self.expression(Precedence::None)?;
+        let mut arms = Vec::new();
+
+        self.expect(TokenKind::LBrace, "after match value")?;
+        self.skip_newlines();
+
+        while self.current.kind != TokenKind::RBrace && self.current.kind != TokenKind::Eof {
+            let pattern = self.pattern()?;
+
+            self.expect(TokenKind::FatArrow, "after pattern")?;
+
+            let body = self.arm_body()?;
+
+            arms.push(MatchArm { pattern, body });
+
+            if self.current.kind == TokenKind::Comma {
+                self.consume();
+            }
+
+            self.skip_newlines();
+        }
+
+        self.expect(TokenKind::RBrace, "after match arms")?;
+
+        Ok(Stmt::Match { value, arms })
+    }
+
+    fn switch_stmt(&mut self) -> Result<Stmt, ParseError> {
+        // switch ...
+        self.consume();
+
+        let value = self.expression(Precedence::None)?;
+        let mut cases = Vec::new();
+        let mut default = None;
+
+        self.expect(TokenKind::LBrace, "after switch value")?;
+        self.skip_newlines();
+
+        while self.current.kind != TokenKind::RBrace && self.current.kind != TokenKind::Eof {
+            let values = match &self.current.kind {
+                TokenKind::Ident(name) if &**name == "_" => {
+                    if default.is_some() {
+                        return Err(self.error("a switch can only have one default case"));
+                    }
+
+                    self.consume();
+                    None
+                }
+                _ => Some(self.switch_values()?),
+            };
+
+            self.expect(TokenKind::FatArrow, "after switch case")?;
+
+            let body = self.arm_body()?;
+
+            match values {
+                Some(values) => cases.push(SwitchCase { values, body }),
+                None => default = Some(body),
+            }
+
+            if self.current.kind == TokenKind::Comma {
+                self.consume();
+            }
+
+            self.skip_newlines();
+        }
+
+        self.expect(TokenKind::RBrace, "after switch cases")?;
+
+        Ok(Stmt::Switch {
+            value,
+            cases,
+            default,
+        })
+    }
+
+    /// Parse the comma separated constants of a switch case.
+    fn switch_values(&mut self) -> Result<Vec<Lit>, ParseError> {
+        let mut values = Vec::new();
+
+        loop {
+            match self.pattern()? {
+                Pattern::Literal(lit @ (Lit::Integer(_) | Lit::String(_))) => values.push(lit),
+                _ => return Err(self.error("switch cases must be integer or string constants")),
+            }
+
+            if self.current.kind != TokenKind::Comma {
+                return Ok(values);
+            }
+
+            self.consume();
+        }
+    }
+
+    /// Parse the body of a match arm or switch case.
+    fn arm_body(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        match self.current.kind {
+            TokenKind::LBrace => self.block(),
+            TokenKind::Keyword(Keyword::Return) => Ok(vec![self.return_value()?]),
+            _ => Ok(vec![Stmt::Expr(self.expression(Precedence::None)?)]),
+        }
+    }
+
+    /// Parse a pattern in a match arm.
+    fn pattern(&mut self) -> Result<Pattern, ParseError> {
+        let negative = self.current.kind == TokenKind::Hypen;
+
+        if negative {
+            self.consume();
+        }
+
+        let pattern = match self.current.kind.clone() {
+            TokenKind::Ident(name) if &*name == "_" => {
+                self.consume();
+                Pattern::Wildcard
+            }
+            TokenKind::Ident(_) if *self.peek() == TokenKind::LParen => {
+                let name = self.identifier()?;
+                let mut fields = Vec::new();
+
+                self.consume();
+
+                while self.current.kind != TokenKind::RParen {
+                    fields.push(self.nested(Parser::pattern)?);
+
+                    if self.current.kind == TokenKind::Comma {
+                        self.consume();
+                    } else {
+                        break;
+                    }
+                }
+
+                self.expect(TokenKind::RParen, "after field patterns")?;
+
+                Pattern::Class { name, fields }
+            }
+            TokenKind::Ident(_) => Pattern::Binding(self.identifier()?),
+            TokenKind::Literal(_)
+            | TokenKind::Keyword(Keyword::True | Keyword::False | Keyword::Nil) => {
+                self.literal()?;
+
+                self.ast.spans.pop();
+
+                let Some(Expr::Literal(literal)) = self.ast.exprs.pop() else {
+                    unreachable!()
+                };
+
+                Pattern::Literal(literal)
+            }
+            _ => return Err(self.expected(&["a pattern"], "")),
+        };
+
+        match (negative, pattern) {
+            (false, pattern) => Ok(pattern),
+            (true, Pattern::Literal(Lit::Integer(v))) => Ok(Pattern::Literal(Lit::Integer(-v))),
+            (true, Pattern::Literal(Lit::Float(v))) => Ok(Pattern::Literal(Lit::Float(-v))),
+            (true, _) => Err(self.error("only numbers can be negated in patterns")),
+        }
+    }
+
+    /// Parse `import "path"`, optionally followed by `as name`.
+    fn import(&mut self) -> Result<Stmt, ParseError> {
+        // import ...
+        self.consume();
+
+        let TokenKind::Literal(token::Lit::String(path)) = self.current.kind.clone() else {
+            return Err(self.expected(&["a module path after 'import'"], ""));
+        };
+
+        self.consume();
+
+        let name = match &self.current.kind {
+            TokenKind::Ident(ident) if &**ident == "as" => {
+                self.consume();
+                self.identifier()?
+            }
+            _ => module_name(&path).ok_or_else(|| {
+                self.error(format!(
+                    "cannot name module \"{path}\", use `import \"{path}\" as name`"
+                ))
+            })?,
+        };
+
+        self.end_of_statement()?;
+
+        Ok(Stmt::Import { path, name })
+    }
+
+    fn export(&mut self) -> Result<Stmt, ParseError> {
+        // export ...
+        self.consume();
+
+        let stmt = match &self.current.kind {
+            TokenKind::Keyword(Keyword::Class) => self.class()?,
+            TokenKind::Ident(_) if self.is_trait() => self.trait_decl()?,
+            TokenKind::Keyword(Keyword::Fun | Keyword::Async) => self.function()?,
+            TokenKind::At => self.decorated_function()?,
+            TokenKind::Keyword(Keyword::Var) => self.var_decl()?,
+            TokenKind::Keyword(Keyword::Const) => self.const_decl()?,
+            TokenKind::Keyword(Keyword::Let) => self.let_decl()?,
+            _ => return Err(self.error("only declarations can be exported")),
+        };
+
+        Ok(Stmt::Export(Box::new(stmt)))
+    }
+
+    fn var_decl(&mut self) -> Result<Stmt, ParseError> {
+        // var ...
+        self.consume();
+        // var id ...
+        let id = self.identifier()?;
+        let ty = self.annotation()?;
+
+        let init = if let TokenKind::Assign = self.current.kind {
+            // var id = ...
+            self.consume();
+            // var id = expr ...
+            self.expression(Precedence::None)?
+        } else {
+            self.add(Expr::Literal(Lit::Nil))
+        };
+
+        self.end_of_statement()?;
+
+        Ok(Stmt::VarDecl { id, ty, init })
+    }
+
+    fn const_decl(&mut self) -> Result<Stmt, ParseError> {
+        // const ...
+        self.consume();
+        // const id ...
+        let id = self.identifier()?;
+        let ty = self.annotation()?;
+
+        // const id = expr ...
+        self.expect(TokenKind::Assign, "after constant name")?;
+        let init = self.expression(Precedence::None)?;
+
+        self.end_of_statement()?;
+
+        Ok(Stmt::ConstDecl { id, ty, init })
+    }
+
+    fn let_decl(&mut self) -> Result<Stmt, ParseError> {
+        // let ...
+        self.consume();
+        // let id ...
+        let id = self.identifier()?;
+        let ty = self.annotation()?;
+
+        // let id = expr ...
+        self.expect(TokenKind::Assign, "after variable name")?;
+        let init = self.expression(Precedence::None)?;
+
+        self.end_of_statement()?;
+
+        Ok(Stmt::LetDecl { id, ty, init })
+    }
+
+    /// Parse a expression and a newline.
+    pub fn expr(&mut self) -> Result<Stmt, ParseError> {
+        let expr = self.expression(Precedence::None)?;
+
+        if self.current.kind == TokenKind::Comma {
+            return self.multiple_assignment(expr);
+        }
+
+        self.end_of_statement()?;
+
+        Ok(Stmt::Expr(expr))
+    }
+
+    /// Parse the rest of an assignment to several targets, starting with
+    /// `first`.
+    fn multiple_assignment(&mut self, first: ExprId) -> Result<Stmt, ParseError> {
+        let mut targets = vec![first];
+
+        while self.current.kind == TokenKind::Comma {
+            self.consume();
+            targets.push(self.expression(Precedence::None.left())?);
+        }
+
+        for target in &targets {
+            if !matches!(self.ast[*target], Expr::Identifier(_) | Expr::Dot { .. }) {
+                return Err(
+                    self.error(ErrorCode::InvalidAssignment.error("invalid assignment target"))
+                );
+            }
+        }
+
+        self.expect(TokenKind::Assign, "after assignment targets")?;
+
+        let mut values = vec![self.expression(Precedence::None.left())?];
+
+        while self.current.kind == TokenKind::Comma {
+            self.consume();
+            values.push(self.expression(Precedence::None.left())?);
+        }
+
+        if values.len() != 1 && values.len() != targets.len() {
+            return Err(self.error(ErrorCode::InvalidAssignment.error(format!(
+                "cannot assign {} values to {} targets",
+                values.len(),
+                targets.len()
+            ))));
+        }
+
+        self.end_of_statement()?;
+
+        Ok(Stmt::Assign { targets, values })
+    }
+
+    /// Consume the newline terminating a statement, if there is one.
+    fn end_of_statement(&mut self) -> Result<(), ParseError> {
+        match &self.current.kind {
+            TokenKind::Newline => {
+                self.consume();
+
+                Ok(())
+            }
+            TokenKind::Semicolon => {
+                self.consume();
+                self.skip_newlines();
+
+                Ok(())
+            }
+            TokenKind::Eof | TokenKind::RBrace => Ok(()),
+            _ => Err(self.expected(&["the end of the statement"], "")),
+        }
+    }
+
+    /// Parse an expression.
+    pub fn expression(&mut self, prec: Precedence) -> Result<ExprId, ParseError> {
+        self.nested(|parser| parser.expression_body(prec))
+    }
+
+    fn expression_body(&mut self, prec: Precedence) -> Result<ExprId, ParseError> {
+        let mut left = self.literal()?;
+
+        while self.prec() >= prec && self.prec() != Precedence::End {
+            left = self.infix_expr(left)?;
+        }
+
+        if prec == Precedence::None && self.current.kind == TokenKind::Assign {
+            left = self.assignment(left)?;
+        }
+
+        Ok(left)
+    }
+
+    /// Parse the right hand side of an assignment to `target`.
+    fn assignment(&mut self, target: ExprId) -> Result<ExprId, ParseError> {
+        match self.ast[target] {
+            Expr::Identifier(_) | Expr::Dot { .. } => {
+                self.consume();
+
+                let value = self.expression(Precedence::None)?;
+
+                Ok(self.add(Expr::Assign { target, value }))
+            }
+            _ => Err(self.error(ErrorCode::InvalidAssignment.error("invalid assignment target"))),
+        }
+    }
+
+    /// Make an increment or decrement of `target`, which has to be a variable
+    /// or a property.
+    fn increment(
+        &mut self,
+        target: ExprId,
+        op: BinOp,
+        postfix: bool,
+    ) -> Result<ExprId, ParseError> {
+        if !matches!(self.ast[target], Expr::Identifier(_) | Expr::Dot { .. }) {
+            return Err(self.error(
+                ErrorCode::InvalidAssignment.error("invalid increment or decrement target"),
+            ));
+        }
+
+        Ok(self.add(Expr::Increment {
+            target,
+            op,
+            postfix,
+        }))
+    }
+
+    /// Parse a binary expression.
+    pub fn infix_expr(&mut self, mut left: ExprId) -> Result<ExprId, ParseError> {
+        match &self.current.kind {
+            TokenKind::Increment => {
+                self.consume();
+
+                left = self.increment(left, BinOp::Add, true)?;
+            }
+            TokenKind::Decrement => {
+                self.consume();
+
+                left = self.increment(left, BinOp::Sub, true)?;
+            }
+            TokenKind::Plus => {
+                self.consume();
+
+                let right = self.expression(Precedence::Sum.left())?;
+                left = self.binary_expr(BinOp::Add, left, right);
+            }
+            TokenKind::Hypen => {
+                self.consume();
+
+                let right = self.expression(Precedence::Sum.left())?;
+                left = self.binary_expr(BinOp::Sub, left, right);
+            }
+            TokenKind::Star => {
+                self.consume();
+
+                let right = self.expression(Precedence::Term.left())?;
+                left = self.binary_expr(BinOp::Mul, left, right);
+            }
+            TokenKind::BackSlash => {
+                self.consume();
+
+                let right = self.expression(Precedence::Term.left())?;
+                left = self.binary_expr(BinOp::Div, left, right);
+            }
+            TokenKind::Modulo => {
+                self.consume();
+
+                let right = self.expression(Precedence::Term.left())?;
+                left = self.binary_expr(BinOp::Rem, left, right);
+            }
+            TokenKind::Or
+            | TokenKind::And
+            | TokenKind::Equal
+            | TokenKind::NotEqual
+            | TokenKind::Less
+            | TokenKind::LessEqual
+            | TokenKind::Greater
+            | TokenKind::GreaterEqual => {
+                let op = match self.current.kind {
+                    TokenKind::Or => BinOp::Or,
+                    TokenKind::And => BinOp::And,
+                    TokenKind::Equal => BinOp::Equal,
+                    TokenKind::NotEqual => BinOp::NotEqual,
+                    TokenKind::Less => BinOp::Less,
+                    TokenKind::LessEqual => BinOp::LessEqual,
+                    TokenKind::Greater => BinOp::Greater,
+                    _ => BinOp::GreaterEqual,
+                };
+                let prec = self.prec();
+                self.consume();
+
+                let right = self.expression(prec.left())?;
+                left = self.binary_expr(op, left, right);
+            }
+            TokenKind::Keyword(Keyword::Is) => {
+                self.consume();
+
+                let class = self.expression(Precedence::Comparison.left())?;
+                left = self.add(Expr::Is { value: left, class });
+            }
+            TokenKind::DotDot => {
+                self.consume();
+
+                let end = self.expression(Precedence::Range.left())?;
+                let step = match &self.current.kind {
+                    TokenKind::Ident(name) if &**name == "step" => {
+                        self.consume();
+
+                        Some(self.expression(Precedence::Range.left())?)
+                    }
+                    _ => None,
+                };
+
+                left = self.add(Expr::Range {
+                    start: left,
+                    end,
+                    step,
+                });
+            }
+            TokenKind::Operator(symbol) => {
+                let op = BinOp::Custom(symbol.to_string());
+                let prec = self.prec();
+                self.consume();
+
+                let right = self.expression(prec.left())?;
+                left = self.binary_expr(op, left, right);
+            }
+            TokenKind::LParen => {
+                self.consume();
+
+                let mut args = Vec::new();
+                let mut named = Vec::new();
+
+                self.skip_newlines();
+
+                while self.current.kind != TokenKind::RParen {
+                    match (&self.current.kind, self.peek()) {
+                        (TokenKind::Ident(_), TokenKind::Colon) => {
+                            let name = self.identifier()?;
+                            self.consume();
+
+                            named.push((name, self.expression(Precedence::None)?));
+                        }
+                        _ if !named.is_empty() => {
+                            return Err(
+                                self.error("positional arguments must come before named arguments")
+                            )
+                        }
+                        _ => args.push(self.spread_or_expression()?),
+                    }
+
+                    self.skip_newlines();
+
+                    if self.current.kind == TokenKind::Comma {
+                        self.consume();
+                        self.skip_newlines();
+                    } else {
+                        break;
+                    }
+                }
+
+                self.expect(TokenKind::RParen, "after arguments")?;
+
+                left = self.add(Expr::Call {
+                    callee: left,
+                    args,
+                    named,
+                });
+            }
+            TokenKind::Dot => {
+                self.consume();
+
+                let TokenKind::Ident(name) = &self.current.kind else {
+                    return Err(self.expected(&["a property name after '.'"], ""));
+                };
+                let property = self.add(Expr::Identifier(name.to_string()));
+
+                self.consume();
+
+                left = self.add(Expr::Dot {
+                    object: left,
+                    property,
+                });
+            }
+            _ => {}
+        }
+
+        Ok(left)
+    }
+
+    /// Parse a tagged template, such as `sql"WHERE id = ${id}"`.
+    fn template(&mut self) -> Result<ExprId, ParseError> {
+        let TokenKind::Tag(tag) = &self.current.kind else {
+            return Err(self.expected(&["a template tag"], ""));
+        };
+        let tag = tag.to_string();
+        let mut parts = Vec::new();
+        let mut values = Vec::new();
+
+        self.consume();
+
+        loop {
+            match &self.current.kind {
+                TokenKind::TemplatePart(text) => {
+                    parts.push(text.clone());
+                    self.consume();
+
+                    values.push(self.expression(Precedence::None)?);
+                }
+                TokenKind::TemplateEnd(text) => {
+                    parts.push(text.clone());
+                    self.consume();
+
+                    break;
+                }
+                _ => {
+                    return Err(self.expected(
+                        &["'}'"],
+                        &format!("after interpolation in '{tag}' template"),
+                    ))
+                }
+            }
+        }
+
+        Ok(self.add(Expr::Template { tag, parts, values }))
+    }
+
+    fn binary_expr(&mut self, op: BinOp, left: ExprId, right: ExprId) -> ExprId {
+        self.add(Expr::BinExpr { left, right, op })
+    }
+
+    /// Parse a literal.
+    pub fn literal(&mut self) -> Result<ExprId, ParseError> {
+        match &self.current.kind.clone() {
+            TokenKind::Literal(literal) => match literal {
+                token::Lit::Integer(int) => {
+                    self.consume();
+                    Ok(self.add(Expr::Literal(Lit::Integer(*int))))
+                }
+                token::Lit::String(string) => {
+                    self.consume();
+                    Ok(self.add(Expr::Literal(Lit::String(string.clone()))))
+                }
+                token::Lit::Float(float) => {
+                    self.consume();
+                    Ok(self.add(Expr::Literal(Lit::Float(*float))))
+                }
+            },
+            TokenKind::Hypen => {
+                self.consume();
+
+                let value = self.expression(Precedence::Unary)?;
+
+                Ok(self.add(Expr::UnaryExpr {
+                    value,
+                    op: BinOp::Sub,
+                }))
+            }
+            TokenKind::Plus => {
+                self.consume();
+
+                let value = self.expression(Precedence::Unary)?;
+
+                Ok(self.add(Expr::UnaryExpr {
+                    value,
+                    op: BinOp::Add,
+                }))
+            }
+            TokenKind::Bang => {
+                self.consume();
+
+                let value = self.expression(Precedence::Unary)?;
+
+                Ok(self.add(Expr::UnaryExpr {
+                    value,
+                    op: BinOp::Bang,
+                }))
+            }
+            TokenKind::Increment | TokenKind::Decrement => {
+                let op = match self.current.kind {
+                    TokenKind::Increment => BinOp::Add,
+                    _ => BinOp::Sub,
+                };
+
+                self.consume();
+
+                let target = self.expression(Precedence::Call)?;
+
+                self.increment(target, op, false)
+            }
+            TokenKind::LParen => {
+                self.consume();
+                let node = self.expression(Precedence::None.left())?;
+                self.consume();
+
+                Ok(node)
+            }
+            // Like `version`, `object` is only a keyword before a body.
+            TokenKind::Ident(ident)
+                if &**ident == "object" && *self.peek() == TokenKind::LBrace =>
+            {
+                self.object()
+            }
+            TokenKind::Ident(_) => {
+                let name = self.identifier()?;
+
+                Ok(self.add(Expr::Identifier(name)))
+            }
+            TokenKind::Tag(_) => self.template(),
+            TokenKind::Keyword(Keyword::Slf) => {
+                self.consume();
+
+                Ok(self.add(Expr::Slf))
+            }
+            TokenKind::Keyword(Keyword::Typeof) => {
+                self.consume();
+
+                let value = self.expression(Precedence::Unary)?;
+
+                Ok(self.add(Expr::TypeOf(value)))
+            }
+            TokenKind::Keyword(Keyword::Await) => {
+                self.consume();
+
+                let value = self.expression(Precedence::Unary)?;
+
+                Ok(self.add(Expr::Await(value)))
+            }
+            TokenKind::Keyword(Keyword::Fun) => self.anonymous_function(),
+            TokenKind::Keyword(Keyword::If) => self.if_expr(),
+            TokenKind::Keyword(Keyword::True) => {
+                self.consume();
+
+                Ok(self.add(Expr::Literal(Lit::Bool(true))))
+            }
+            TokenKind::Keyword(Keyword::False) => {
+                self.consume();
+
+                Ok(self.add(Expr::Literal(Lit::Bool(false))))
+            }
+            TokenKind::Keyword(Keyword::Nil) => {
+                self.consume();
+
+                Ok(self.add(Expr::Literal(Lit::Nil)))
+            }
+            TokenKind::LBracket => self.list(),
+            TokenKind::LBrace => self.brace(),
+            _ => Err(self.expected(&["an expression"], "")),
+        }
+    }
+
+    /// Parse `if cond { ... } else { ... }`, where the `else` is optional
+    /// and can be followed by another `if` instead of a block.
+    fn if_expr(&mut self) -> Result<ExprId, ParseError> {
+        self.consume();
+
+        let cond = self.expression(Precedence::None)?;
+        let then = self.block()?;
+        let otherwise = match self.current.kind {
+            TokenKind::Keyword(Keyword::Else) => {
+                self.consume();
+
+                match self.current.kind {
+                    TokenKind::Keyword(Keyword::If) => {
+                        Some(vec![Stmt::Expr(self.nested(Parser::if_expr)?)])
+                    }
+                    _ => Some(self.block()?),
+                }
+            }
+            _ => None,
+        };
+
+        Ok(self.add(Expr::If {
+            cond,
+            then,
+            otherwise,
+        }))
+    }
+
+    /// Parse a map literal or a block, which are told apart by what follows
+    /// the `{`: a map is empty or starts with a key and a colon or a spread.
+    fn brace(&mut self) -> Result<ExprId, ParseError> {
+        self.consume();
+        self.skip_newlines();
+
+        let is_map = matches!(
+            (&self.current.kind, self.peek()),
+            (TokenKind::RBrace | TokenKind::Ellipsis, _)
+                | (TokenKind::Literal(token::Lit::String(_)), _)
+                | (TokenKind::Ident(_), TokenKind::Colon)
+        );
+
+        if is_map {
+            return self.map();
+        }
+
+        let body = self.nested(Parser::block_rest)?;
+
+        Ok(self.add(Expr::Block(body)))
+    }
+
+    /// Parse a list literal.
+    fn list(&mut self) -> Result<ExprId, ParseError> {
+        let mut items = Vec::new();
+
+        self.consume();
+        self.skip_newlines();
+
+        while self.current.kind != TokenKind::RBracket {
+            items.push(self.spread_or_expression()?);
+            self.skip_newlines();
+
+            if self.current.kind == TokenKind::Comma {
+                self.consume();
+                self.skip_newlines();
+            } else {
+                break;
+            }
+        }
+
+        self.expect(TokenKind::RBracket, "after list items")?;
+
+        Ok(self.add(Expr::List(items)))
+    }
+
+    /// Parse an argument or item, which is spread if it starts with `...`.
+    fn spread_or_expression(&mut self) -> Result<ExprId, ParseError> {
+        if self.current.kind != TokenKind::Ellipsis {
+            return self.expression(Precedence::None);
+        }
+
+        self.consume();
+
+        let value = self.expression(Precedence::None)?;
+
+        Ok(self.add(Expr::Spread(value)))
+    }
+
+    /// Parse the entries of a map literal after its `{`.
+    fn map(&mut self) -> Result<ExprId, ParseError> {
+        let mut entries = Vec::new();
+
+        while self.current.kind != TokenKind::RBrace {
+            let key = match &self.current.kind {
+                TokenKind::Ellipsis => String::new(),
+                TokenKind::Literal(token::Lit::String(key)) => {
+                    let key = key.clone();
+                    self.consume();
+                    key
+                }
+                _ => self.identifier()?,
+            };
+
+            if self.current.kind == TokenKind::Ellipsis {
+                entries.push((key, self.spread_or_expression()?));
+            } else {
+                self.expect(TokenKind::Colon, "after map key")?;
+                entries.push((key, self.expression(Precedence::None)?));
+            }
+            self.skip_newlines();
+
+            if self.current.kind == TokenKind::Comma {
+                self.consume();
+                self.skip_newlines();
+            } else {
+                break;
+            }
+        }
+
+        self.expect(TokenKind::RBrace, "after map entries")?;
+
+        Ok(self.add(Expr::Map(entries)))
+    }
+
+    /// Constructs an [Ast] from a stream of tokens.
+    ///
+    /// If the script has errors, the parser skips to the next statement
+    /// after each one so that all of them are reported.
+    pub fn parse(&mut self) -> Result<Ast, ParseErrors> {
+        loop {
+            match self.top_level_statement() {
+                Ok(Some(node)) => self.ast.nodes.push(node),
+                Ok(None) => break,
+                Err(err) => return Err(self.recover(err)),
+            }
+        }
+
+        Ok(mem::take(&mut self.ast))
+    }
+
+    /// Parse the next top-level statement into an [Ast] of its own, or
+    /// return `None` at the end of the script.
+    ///
+    /// Only the tokens of the statement are read, so a script can be
+    /// compiled a statement at a time without holding all of it in memory,
+    /// unless it has an error, when the rest of the script is read to
+    /// report any others along with it.
+    pub fn next_statement(&mut self) -> Result<Option<Ast>, ParseErrors> {
+        match self.top_level_statement() {
+            Ok(Some(node)) => {
+                let mut ast = mem::take(&mut self.ast);
+
+                ast.nodes.push(node);
+
+                Ok(Some(ast))
+            }
+            Ok(None) => Ok(None),
+            Err(err) => Err(self.recover(err)),
+        }
+    }
+
+    /// Gather `first` and the errors in the statements after it, skipping
+    /// to the end of the statement each error is in.
+    fn recover(&mut self, first: ParseError) -> ParseErrors {
+        let mut errors = vec![first];
+
+        self.synchronize();
+
+        loop {
+            match self.top_level_statement() {
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(err) => {
+                    errors.push(err);
+                    self.synchronize();
+                }
+            }
+        }
+
+        self.ast = Ast::default();
+
+        ParseErrors(errors)
+    }
+
+    /// Skip past the newline or `;` that ends the statement with an error,
+    /// ignoring those inside brackets, braces and parentheses.
+    fn synchronize(&mut self) {
+        while self.current.kind != TokenKind::Eof {
+            let end = self.open == 0
+                && matches!(self.current.kind, TokenKind::Newline | TokenKind::Semicolon);
+
+            self.consume();
+
+            if end {
+                break;
+            }
+        }
+    }
+
+    fn top_level_statement(&mut self) -> Result<Option<Stmt>, ParseError> {
+        self.skip_newlines();
+
+        if self.current.kind == TokenKind::Eof {
+            return Ok(None);
+        }
+
+        let node = self.statement()?;
+
+        self.skip_newlines();
+
+        Ok(Some(node))
+    }
+
+    pub fn parse_ast(tokens: Vec<Token>) -> Result<Ast, ParseErrors> {
+        let mut parser = Parser::new(tokens);
+
+        parser.parse()
+    }
+
+    /// Parse `tokens` as a single expression, such as `player.health + 10`,
+    /// rather than as statements.
+    ///
+    /// The expression is returned as its id in an [Ast] holding it and its
+    /// sub-expressions, which has no statements.
+    pub fn parse_expression(tokens: Vec<Token>) -> Result<(Ast, ExprId), ParseErrors> {
+        let mut parser = Parser::new(tokens);
+
+        parser.skip_newlines();
+
+        let expr = parser
+            .expression(Precedence::None)
+            .and_then(|expr| {
+                parser.skip_newlines();
+
+                match parser.current.kind {
+                    TokenKind::Eof => Ok(expr),
+                    _ => Err(parser.expected(&["the end of the expression"], "")),
+                }
+            })
+            .map_err(|err| ParseErrors(vec![err]))?;
+
+        Ok((mem::take(&mut parser.ast), expr))
+    }
+
+    /// Consume the current token if it matches `kind`, otherwise return an
+    /// error saying where it was expected, such as "after arguments".
+    fn expect(&mut self, kind: TokenKind, context: &str) -> Result<(), ParseError> {
+        if self.current.kind == kind {
+            self.consume();
+
+            Ok(())
+        } else {
+            Err(self.expected(&[&kind.to_string()], context))
+        }
+    }
+
+    /// An error at the current token, which isn't any of `expected`.
+    fn expected(&self, expected: &[&str], context: &str) -> ParseError {
+        ParseError {
+            code: ErrorCode::Syntax,
+            expected: expected.iter().map(|e| e.to_string()).collect(),
+            found: self.current.kind.to_string(),
+            span: self.current.span,
+            message: context.into(),
+        }
+    }
+
+    /// An error at the current token, with a syntax error code unless
+    /// `message` already starts with one.
+    fn error(&self, message: impl Display) -> ParseError {
+        let message = message.to_string();
+        let code = ErrorCode::of_message(&message).unwrap_or(ErrorCode::Syntax);
+        let message = match message.split_once("] ") {
+            Some((_, rest)) if message.starts_with('[') => rest.to_string(),
+            _ => message,
+        };
+
+        ParseError {
+            code,
+            expected: Vec::new(),
+            found: self.current.kind.to_string(),
+            span: self.current.span,
+            message,
+        }
+    }
+
+    /// Get the kind of the token after the current one.
+    fn peek(&self) -> &TokenKind {
+        &self.next.kind
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.current.kind == TokenKind::Newline {
+            self.consume();
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, ParseError> {
+        if let TokenKind::Ident(name) = &self.current.kind {
+            let name = name.to_string();
+            self.consume();
+
+            Ok(name)
+        } else {
+            Err(self.expected(&["an identifier"], ""))
+        }
+    }
+}
+
+/// A function's parameter names, their type annotations, whether the last
+/// one is a rest parameter, and the parameters that unpack their argument.
+type ParamList = (
+    Vec<String>,
+    Vec<Option<String>>,
+    bool,
+    Vec<(usize, Destructure)>,
+);
+
+/// The name a module is bound to when imported without `as`: the last
+/// segment of its path, without an extension.
+pub(crate) fn module_name(path: &str) -> Option<String> {
+    let name = path.rsplit('/').next()?;
+    let name = name.split('.').next()?;
+
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+
+    valid.then(|| name.to_string())
+}
+
+/// The paths of the modules imported by a script, found from its tokens
+/// without parsing it.
+pub fn scan_imports(tokens: impl IntoIterator<Item = Token>) -> Vec<String> {
+    let mut tokens = tokens.into_iter();
+    let mut paths = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        if token.kind != TokenKind::Keyword(Keyword::Import) {
+            continue;
+        }
+
+        if let Some(TokenKind::Literal(token::Lit::String(path))) = tokens.next().map(|t| t.kind) {
+            paths.push(path);
+        }
+    }
+
+    paths
+}
+
+/// An error found while parsing a script.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub code: ErrorCode,
+    /// What could have come instead of the token found, such as `')'` or
+    /// `an expression`. Empty if the error isn't about a missing token.
+    pub expected: Vec<String>,
+    /// The token the error was found at, such as `'var'`.
+    pub found: String,
+    /// Where the token found starts.
+    pub span: Span,
+    /// Where the expected tokens should have been, such as "after
+    /// arguments", or the whole error if nothing in particular was expected.
+    pub message: String,
+}
+
+impl ParseError {
+    /// What went wrong, without the code or position.
+    pub fn detail(&self) -> String {
+        if self.expected.is_empty() {
+            return self.message.clone();
+        }
+
+        let mut detail = format!("expected {}", self.expected.join(" or "));
+
+        if !self.message.is_empty() {
+            detail.push(' ');
+            detail.push_str(&self.message);
+        }
+
+        format!("{detail} but found {}", self.found)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.code.code(), self.span, self.detail())
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error {
+            code: Some(err.code),
+            span: Some(err.span),
+            message: err.detail(),
+        }
+    }
+}
+
+/// All the errors found while parsing a script, in order.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseErrors(pub Vec<ParseError>);
+
+impl fmt::Display for ParseErrors {
+    /// Write each error on a line of its own.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, err) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{err}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<ParseErrors> for String {
+    fn from(errors: ParseErrors) -> Self {
+        errors.to_string()
+    }
+}
+
+impl From<ParseErrors> for Error {
+    /// The first error, with the others on lines of their own after its
+    /// message.
+    fn from(ParseErrors(errors): ParseErrors) -> Self {
+        let mut errors = errors.into_iter();
+        let mut error = errors.next().map_or_else(|| Error::new(""), Error::from);
+
+        for err in errors {
+            error.message.push_str(&format!("\n{err}"));
+        }
+
+        error
+    }
+}
+
+/// The error returned when a program is nested more than `max_depth` levels deep.
+pub fn nesting_error(max_depth: usize) -> String {
+    ErrorCode::NestingLimit.error(format!(
+        "program too deeply nested (more than {max_depth} levels)"
+    ))
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        ast::{
+            Ast, BinOp, Destructure, Expr, ExprId, FunDecl, FunKind, HostStmt, Lit, Pattern, Stmt,
+        },
+        compiler::CompilerOptions,
+        diagnostic::ErrorCode,
+        lexer::Lexer,
+        token::Span,
+    };
+
+    use super::{ParseErrors, Parser, Precedence};
+
+    #[test]
+    fn test_parser() {
+        let mut parser = Parser::new(Lexer::tokenize("4 + 2 * 5"));
+
+        let mut ast = Ast::default();
+        let left = ast.add(Expr::Literal(Lit::Integer(4)));
+        let two = ast.add(Expr::Literal(Lit::Integer(2)));
+        let five = ast.add(Expr::Literal(Lit::Integer(5)));
+        let right = ast.add(Expr::BinExpr {
+            left: two,
+            right: five,
+            op: BinOp::Mul,
+        });
+        let sum = ast.add(Expr::BinExpr {
+            left,
+            right,
+            op: BinOp::Add,
+        });
+
+        ast.nodes.push(Stmt::Expr(sum));
+
+        assert_eq!(parser.parse().unwrap(), ast);
+    }
+
+    #[test]
+    fn test_static_method() {
+        let mut parser = Parser::new(Lexer::tokenize(
+            "class Math {\n    static fun id(x) {\n        return x\n    }\n}",
+        ));
+
+        assert_eq!(
+            parser.parse().unwrap().nodes[0],
+            Stmt::Class {
+                name: "Math".into(),
+                span: Span { line: 1, column: 1 },
+                traits: Vec::new(),
+                mixins: Vec::new(),
+                statics: Vec::new(),
+                fields: Vec::new(),
+                methods: vec![FunDecl {
+                    name: "id".into(),
+                    params: vec!["x".into()],
+                    param_types: vec![None],
+                    variadic: false,
+                    unpack: Vec::new(),
+                    returns: None,
+                    body: vec![Stmt::Return(ExprId(0))],
+                    kind: FunKind::Static,
+                    is_async: false,
+                    doc: None,
+                    decorators: Vec::new(),
+                }],
+                version: None,
+                doc: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_annotations() {
+        let ast = Parser::parse_ast(Lexer::tokenize(
+            "var x: Int = 5\nlet y: Point = p\nfun add(a: Int, b, ...rest: List) -> Int {}\nclass P {\n    fun m() -> Nil {}\n}",
+        ))
+        .unwrap();
+
+        assert!(matches!(&ast.nodes[0], Stmt::VarDecl { ty: Some(ty), .. } if ty == "Int"));
+        assert!(matches!(&ast.nodes[1], Stmt::LetDecl { ty: Some(ty), .. } if ty == "Point"));
+
+        let Stmt::Fun(add) = &ast.nodes[2] else {
+            panic!("expected a function");
+        };
+
+        assert_eq!(
+            add.param_types,
+            [Some("Int".into()), None, Some("List".into())]
+        );
+        assert_eq!(add.returns.as_deref(), Some("Int"));
+
+        let Stmt::Class { methods, .. } = &ast.nodes[3] else {
+            panic!("expected a class");
+        };
+
+        assert_eq!(methods[0].returns.as_deref(), Some("Nil"));
+        assert_eq!(
+            Parser::parse_ast(Lexer::tokenize("var x: = 1"))
+                .unwrap_err()
+                .to_string(),
+            "[E0401] line 1:8: expected a type name but found '='"
+        );
+    }
+
+    #[test]
+    fn test_async() {
+        let ast = Parser::parse_ast(Lexer::tokenize(
+            "async fun load(name) {\n    return await read(name).text\n}\nclass Store {\n    static async fun open() {}\n    async fun get() {}\n}",
+        ))
+        .unwrap();
+
+        let Stmt::Fun(load) = &ast.nodes[0] else {
+            panic!("expected a function");
+        };
+
+        assert!(load.is_async);
+
+        let Stmt::Return(value) = load.body[0] else {
+            panic!("expected a return");
+        };
+        let Expr::Await(operand) = ast[value] else {
+            panic!("expected an await");
+        };
+
+        assert!(matches!(ast[operand], Expr::Dot { .. }));
+
+        let Stmt::Class { methods, .. } = &ast.nodes[1] else {
+            panic!("expected a class");
+        };
+        let methods = methods
+            .iter()
+            .map(|method| (method.kind, method.is_async))
+            .collect::<Vec<_>>();
+
+        assert_eq!(methods, [(FunKind::Static, true), (FunKind::Method, true)]);
+        assert!(Parser::parse_ast(Lexer::tokenize("async var x = 1")).is_err());
+    }
+
+    #[test]
+    fn test_decorators() {
+        let ast = Parser::parse_ast(Lexer::tokenize(
+            "@memoize fun f(x) {}\n@route(\"/\")\n@log\nfun g() {}\nclass C {\n    @cached\n    get size() {}\n}",
+        ))
+        .unwrap();
+
+        let Stmt::Fun(f) = &ast.nodes[0] else {
+            panic!("expected a function");
+        };
+
+        assert_eq!(ast[f.decorators[0]], Expr::Identifier("memoize".into()));
+        assert!(matches!(&ast.nodes[1], Stmt::Fun(g) if g.decorators.len() == 2));
+
+        let Stmt::Class { methods, .. } = &ast.nodes[2] else {
+            panic!("expected a class");
+        };
+
+        assert_eq!(methods[0].kind, FunKind::Getter);
+        assert_eq!(methods[0].decorators.len(), 1);
+        assert!(Parser::parse_ast(Lexer::tokenize("@log\nvar x = 1")).is_err());
+    }
+
+    #[test]
+    fn test_increment() {
+        let parse = |source| Parser::parse_ast(Lexer::tokenize(source));
+
+        assert_ne!(parse("i++"), parse("++i"));
+        assert_ne!(parse("i++"), parse("i = i + 1"));
+
+        let ast = parse("a.b--").unwrap();
+        let Stmt::Expr(increment) = ast.nodes[0] else {
+            panic!("expected an expression");
+        };
+        let Expr::Increment {
+            target,
+            op,
+            postfix,
+        } = &ast[increment]
+        else {
+            panic!("expected an increment");
+        };
+
+        assert_eq!(*op, BinOp::Sub);
+        assert!(*postfix);
+        assert!(matches!(ast[*target], Expr::Dot { .. }));
+        assert!(parse("f()++").is_err());
+        assert!(parse("++1").is_err());
+    }
+
+    #[test]
+    fn test_trait() {
+        let parse = |source| Parser::parse_ast(Lexer::tokenize(source));
+        let ast = parse("trait Shape {\n    fun area()\n    fun scale(by, around)\n}\nclass Square : Shape, Named {}\nvar trait = 1").unwrap();
+
+        assert_eq!(
+            ast.nodes[0],
+            Stmt::Trait {
+                name: "Shape".into(),
+                methods: vec![("area".into(), 0), ("scale".into(), 2)],
+            }
+        );
+        assert!(matches!(
+            &ast.nodes[1],
+            Stmt::Class { traits, .. } if *traits == ["Shape", "Named"]
+        ));
+        assert!(matches!(ast.nodes[2], Stmt::VarDecl { .. }));
+        assert!(parse("trait Shape { var x }").is_err());
+        assert!(parse("class Square : {}").is_err());
+    }
+
+    #[test]
+    fn test_mixin() {
+        let parse = |source| Parser::parse_ast(Lexer::tokenize(source));
+        let ast = parse(
+            "class A with Logger, Counter : Shape {}\nclass B : Shape with Logger {}\nvar with = 1",
+        )
+        .unwrap();
+
+        for node in &ast.nodes[..2] {
+            assert!(matches!(
+                node,
+                Stmt::Class { traits, mixins, .. } if *traits == ["Shape"] && !mixins.is_empty()
+            ));
+        }
+        assert!(matches!(
+            &ast.nodes[0],
+            Stmt::Class { mixins, .. } if *mixins == ["Logger", "Counter"]
+        ));
+        assert!(matches!(ast.nodes[2], Stmt::VarDecl { .. }));
+        assert!(parse("class A with {}").is_err());
+        assert!(parse("class A with B with C {}").is_err());
+    }
+
+    #[test]
+    fn test_static_field() {
+        let parse = |source| Parser::parse_ast(Lexer::tokenize(source));
+        let ast = parse("class Counter {\n    static var count = 0\n    static var last\n    static const MAX = 10\n    static fun reset() {}\n}").unwrap();
+
+        let Stmt::Class {
+            statics, methods, ..
+        } = &ast.nodes[0]
+        else {
+            panic!("expected a class");
+        };
+        let names = statics
+            .iter()
+            .map(|field| (field.name.as_str(), field.constant))
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, [("count", false), ("last", false), ("MAX", true)]);
+        assert_eq!(ast[statics[1].init], Expr::Literal(Lit::Nil));
+        assert_eq!(methods[0].kind, FunKind::Static);
+        assert!(parse("class A { static const MAX }").is_err());
+    }
+
+    #[test]
+    fn test_if() {
+        let parse = |source| Parser::parse_ast(Lexer::tokenize(source));
+        let ast = parse("var x = if a { 1 } else if b { 2 } else { 3 }\nif a { f() }\nvar y = { f(); g() }\nvar m = {}\nvar n = {a: 1}").unwrap();
+
+        let Stmt::VarDecl { init, .. } = &ast.nodes[0] else {
+            panic!("expected a variable declaration");
+        };
+        let Expr::If {
+            then,
+            otherwise: Some(otherwise),
+            ..
+        } = &ast[*init]
+        else {
+            panic!("expected an if with an else branch");
+        };
+
+        assert_eq!(then.len(), 1);
+        assert!(matches!(
+            otherwise.as_slice(),
+            [Stmt::Expr(id)] if matches!(&ast[*id], Expr::If { otherwise: Some(_), .. })
+        ));
+        assert!(matches!(
+            &ast.nodes[1],
+            Stmt::Expr(id) if matches!(&ast[*id], Expr::If { otherwise: None, .. })
+        ));
+
+        let inits = ast.nodes[2..]
+            .iter()
+            .map(|node| match node {
+                Stmt::VarDecl { init, .. } => &ast[*init],
+                _ => panic!("expected a variable declaration"),
+            })
+            .collect::<Vec<_>>();
+
+        assert!(matches!(inits[0], Expr::Block(body) if body.len() == 2));
+        assert!(matches!(inits[1], Expr::Map(entries) if entries.is_empty()));
+        assert!(matches!(inits[2], Expr::Map(entries) if entries.len() == 1));
+        assert!(parse("if a { 1 } else 2").is_err());
+    }
+
+    #[test]
+    fn test_destructure_params() {
+        let parse = |source| Parser::parse_ast(Lexer::tokenize(source));
+        let ast = parse("fun f(a, (x, y), {host, port}: Map, ...rest) {}").unwrap();
+
+        let Stmt::Fun(decl) = &ast.nodes[0] else {
+            panic!("expected a function");
+        };
+
+        assert_eq!(decl.params, ["a", "(x, y)", "{host, port}", "rest"]);
+        assert_eq!(decl.param_types[2].as_deref(), Some("Map"));
+        assert_eq!(
+            decl.unpack,
+            [
+                (1, Destructure::Items(vec!["x".into(), "y".into()])),
+                (2, Destructure::Keys(vec!["host".into(), "port".into()])),
+            ]
+        );
+        assert!(parse("fun f(()) {}").is_err());
+        assert!(parse("fun f({host) {}").is_err());
+        assert!(parse("fun f(...(x, y)) {}").is_err());
+    }
+
+    #[test]
+    fn test_object() {
+        let parse = |source| Parser::parse_ast(Lexer::tokenize(source));
+        let ast = parse("var o = object {\n    var x = 1\n    var y\n    fun speak() { return self.x }\n    get double() { return self.x * 2 }\n}").unwrap();
+
+        let Stmt::VarDecl { init, .. } = &ast.nodes[0] else {
+            panic!("expected a variable declaration");
+        };
+        let Expr::Object { fields, methods } = &ast[*init] else {
+            panic!("expected an object");
+        };
+
+        assert_eq!(fields.len(), 2);
+        assert_eq!(ast[fields[0].1], Expr::Literal(Lit::Integer(1)));
+        assert_eq!(ast[fields[1].1], Expr::Literal(Lit::Nil));
+        assert_eq!(methods[1].kind, FunKind::Getter);
+        assert_eq!(parse("var object = 1\nobject").unwrap().nodes.len(), 2);
+        assert!(parse("object { static fun make() {} }").is_err());
+        assert!(parse("object { var x = 1").is_err());
+    }
+
+    #[test]
+    fn test_multiple_assignment() {
+        let parse = |source| Parser::parse_ast(Lexer::tokenize(source));
+        let ast = parse("a, b.c = b, a").unwrap();
+
+        let Stmt::Assign { targets, values } = &ast.nodes[0] else {
+            panic!("expected an assignment");
+        };
+
+        assert_eq!(targets.len(), 2);
+        assert_eq!(ast[values[0]], Expr::Identifier("b".into()));
+        assert!(matches!(ast[targets[1]], Expr::Dot { .. }));
+        assert!(matches!(
+            &parse("x, y = point()").unwrap().nodes[0],
+            Stmt::Assign { values, .. } if values.len() == 1
+        ));
+        assert!(parse("a, 1 = 2, 3").is_err());
+        assert!(parse("a, b = 1, 2, 3").is_err());
+        assert!(parse("a, b").is_err());
+    }
+
+    #[test]
+    fn test_spread() {
+        let ast =
+            Parser::parse_ast(Lexer::tokenize("f(1, ...xs)\n[...a, 2]\n{...m, k: 3}")).unwrap();
+
+        let Stmt::Expr(call) = ast.nodes[0] else {
+            panic!("expected an expression");
+        };
+        let Expr::Call { args, .. } = &ast[call] else {
+            panic!("expected a call");
+        };
+
+        assert!(
+            matches!(ast[args[1]], Expr::Spread(xs) if ast[xs] == Expr::Identifier("xs".into()))
+        );
+
+        let Stmt::Expr(map) = ast.nodes[2] else {
+            panic!("expected an expression");
+        };
+        let Expr::Map(entries) = &ast[map] else {
+            panic!("expected a map");
+        };
+
+        assert_eq!(entries[0].0, "");
+        assert!(matches!(ast[entries[0].1], Expr::Spread(_)));
+        assert_eq!(entries[1].0, "k");
+    }
+
+    #[test]
+    fn test_accessors() {
+        let mut parser = Parser::new(Lexer::tokenize(
+            "class Box {\n    get size() {}\n    set size(v) {}\n    fun get() {}\n}",
+        ));
+
+        let Stmt::Class { methods, .. } = &parser.parse().unwrap().nodes[0] else {
+            panic!("expected a class");
+        };
+        let kinds = methods.iter().map(|m| m.kind).collect::<Vec<_>>();
+
+        assert_eq!(kinds, [FunKind::Getter, FunKind::Setter, FunKind::Method]);
+        assert!(Parser::new(Lexer::tokenize("class A {\n    set x() {}\n}"))
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn test_class_version() {
+        let mut parser = Parser::new(Lexer::tokenize(
+            "class Save {\n    version 3\n    fun version() {}\n}",
+        ));
+
+        let Stmt::Class {
+            version, methods, ..
+        } = &parser.parse().unwrap().nodes[0]
+        else {
+            panic!("expected a class");
+        };
+
+        assert_eq!(*version, Some(3));
+        assert_eq!(methods.len(), 1);
+        assert!(Parser::new(Lexer::tokenize(
+            "class A {\n    version 1\n    version 2\n}"
+        ))
+        .parse()
+        .is_err());
+    }
+
+    #[test]
+    fn test_variadic() {
+        let mut parser = Parser::new(Lexer::tokenize("fun log(fmt, ...args) {}"));
+
+        let Stmt::Fun(decl) = &parser.parse().unwrap().nodes[0] else {
+            panic!("expected a function");
+        };
+
+        assert_eq!(decl.params, ["fmt", "args"]);
+        assert!(decl.variadic);
+        assert!(Parser::new(Lexer::tokenize("fun f(...a, b) {}"))
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn test_named_args() {
+        let mut parser = Parser::new(Lexer::tokenize("draw(1, y: 2)"));
+
+        let ast = parser.parse().unwrap();
+        let Stmt::Expr(call) = ast.nodes[0] else {
+            panic!("expected an expression");
+        };
+        let Expr::Call {
+            callee,
+            args,
+            named,
+        } = &ast[call]
+        else {
+            panic!("expected a call");
+        };
+
+        assert_eq!(ast[*callee], Expr::Identifier("draw".into()));
+        assert_eq!(ast[args[0]], Expr::Literal(Lit::Integer(1)));
+        assert_eq!(named[0].0, "y");
+        assert_eq!(ast[named[0].1], Expr::Literal(Lit::Integer(2)));
+        assert!(Parser::new(Lexer::tokenize("draw(y: 2, 1)"))
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn test_multiline_args() {
+        let ast = Parser::parse_ast(Lexer::tokenize("draw(\n    1,\n    y: 2,\n)\nf()")).unwrap();
+
+        let Stmt::Expr(call) = ast.nodes[0] else {
+            panic!("expected an expression");
+        };
+        let Expr::Call { args, named, .. } = &ast[call] else {
+            panic!("expected a call");
+        };
+
+        assert_eq!((args.len(), named.len()), (1, 1));
+        assert_eq!(ast.nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_match() {
+        let mut parser = Parser::new(Lexer::tokenize(
+            "match p {\n    -1 => a\n    Point(0, y) => { b }\n    _ => c\n}",
+        ));
+
+        let Stmt::Match { arms, .. } = &parser.parse().unwrap().nodes[0] else {
+            panic!("expected a match");
+        };
+        let patterns = arms.iter().map(|arm| &arm.pattern).collect::<Vec<_>>();
+
+        assert_eq!(
+            patterns,
+            [
+                &Pattern::Literal(Lit::Integer(-1)),
+                &Pattern::Class {
+                    name: "Point".into(),
+                    fields: vec![
+                        Pattern::Literal(Lit::Integer(0)),
+                        Pattern::Binding("y".into())
+                    ],
+                },
+                &Pattern::Wildcard,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_switch() {
+        let mut parser = Parser::new(Lexer::tokenize(
+            "switch cmd {\n    1, -2 => a\n    \"go\" => { b }\n    _ => c\n}",
+        ));
+
+        let Stmt::Switch { cases, default, .. } = &parser.parse().unwrap().nodes[0] else {
+            panic!("expected a switch");
+        };
+        let values = cases.iter().map(|case| &case.values).collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            [
+                &vec![Lit::Integer(1), Lit::Integer(-2)],
+                &vec![Lit::String("go".into())],
+            ]
+        );
+        assert!(default.is_some());
+        assert!(Parser::new(Lexer::tokenize("switch x {\n    y => 1\n}"))
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let ParseErrors(errors) =
+            Parser::parse_ast(Lexer::tokenize("var a = 1\nprint(a,\n  var")).unwrap_err();
+        let err = &errors[0];
+
+        assert_eq!(err.code, ErrorCode::Syntax);
+        assert_eq!(err.expected, ["an expression"]);
+        assert_eq!(err.found, "'var'");
+        assert_eq!((err.span.line, err.span.column), (3, 3));
+        assert_eq!(
+            Parser::parse_ast(Lexer::tokenize("f(1 var"))
+                .unwrap_err()
+                .to_string(),
+            "[E0401] line 1:5: expected ')' after arguments but found 'var'"
+        );
+        assert_eq!(
+            Parser::parse_ast(Lexer::tokenize("1 = 2"))
+                .unwrap_err()
+                .to_string(),
+            "[E0002] line 1:3: invalid assignment target"
+        );
+
+        // Characters that start no token are reported where they are.
+        assert_eq!(
+            Parser::parse_ast(Lexer::tokenize("a $ b"))
+                .unwrap_err()
+                .to_string(),
+            "[E0401] line 1:3: expected the end of the statement but found '$'"
+        );
+        assert_eq!(
+            Parser::parse_ast(Lexer::tokenize("var x = #"))
+                .unwrap_err()
+                .to_string(),
+            "[E0401] line 1:9: expected an expression but found '#'"
+        );
+        assert!(crate::Thrush::new().exec("a $ b").is_err());
+    }
+
+    #[test]
+    fn test_recovery() {
+        let ParseErrors(errors) = Parser::parse_ast(Lexer::tokenize(
+            "var = 1\nfun f() {\n    print(1 2)\n    return 3\n}\nvar ok = [1,\n    2]\nvar a = ); var b = )",
+        ))
+        .unwrap_err();
+        let lines = errors.iter().map(|err| err.span.line).collect::<Vec<_>>();
+
+        assert_eq!(lines, [1, 3, 8, 8]);
+        assert!(errors[1]
+            .to_string()
+            .contains("expected ')' after arguments but found '2'"));
+        assert_eq!(
+            Parser::parse_ast(Lexer::tokenize("1 +\n2 *"))
+                .unwrap_err()
+                .to_string(),
+            "[E0401] line 1:4: expected an expression but found a newline\n[E0401] line 2:4: expected an expression but found the end of the script"
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+
+        assert!(Parser::new(Lexer::tokenize(&nested(10)))
+            .with_max_depth(11)
+            .parse()
+            .is_ok());
+        assert!(Parser::new(Lexer::tokenize(&nested(10)))
+            .with_max_depth(10)
+            .parse()
+            .unwrap_err()
+            .to_string()
+            .starts_with("[E0403] line 1:11: program too deeply nested"));
+        assert!(Parser::new(Lexer::tokenize(&nested(100_000)))
+            .parse()
+            .is_err());
+
+        let pattern = format!(
+            "match x {{ {}_{} => 1 }}",
+            "P(".repeat(100_000),
+            ")".repeat(100_000)
+        );
+
+        assert!(Parser::new(Lexer::tokenize(&pattern))
+            .parse()
+            .unwrap_err()
+            .to_string()
+            .contains("program too deeply nested"));
+    }
+
+    #[test]
+    fn test_import_export() {
+        let mut parser = Parser::new(Lexer::tokenize(
+            "import \"lib/vec2.thr\"\nimport \"lib/vec2\" as v\nexport var x = 1",
+        ));
+
+        let ast = parser.parse().unwrap();
+
+        assert_eq!(
+            ast.nodes[0],
+            Stmt::Import {
+                path: "lib/vec2.thr".into(),
+                name: "vec2".into()
+            }
+        );
+        assert_eq!(
+            ast.imports().collect::<Vec<_>>(),
+            ["lib/vec2.thr", "lib/vec2"]
+        );
+        assert_eq!(ast.nodes[2].declared_name(), Some("x"));
+        assert!(Parser::new(Lexer::tokenize("import \"my-lib\""))
+            .parse()
+            .is_err());
+        assert!(Parser::new(Lexer::tokenize("export 1")).parse().is_err());
+    }
+
+    #[test]
+    fn test_expr_ids() {
+        let ast = Parser::parse_ast(Lexer::tokenize("var a = [x, y.z]\ndraw(a)")).unwrap();
+        let copy = ast.clone();
+
+        assert_eq!(copy, ast);
+        assert_eq!(ast.iter_exprs().count(), 8);
+        assert!(ast.iter_exprs().all(|(id, expr)| &copy[id] == expr));
+
+        // Sub-expressions are added before the expressions containing them.
+        for (id, expr) in ast.iter_exprs() {
+            if let Expr::List(items) = expr {
+                assert!(items.iter().all(|item| *item < id));
+            }
+        }
+    }
+
+    #[test]
+    fn test_try() {
+        let ast = Parser::parse_ast(Lexer::tokenize(
+            "try {\n    throw 1\n} catch (e) {\n    e\n}\ntry {} finally {}",
+        ))
+        .unwrap();
+
+        let Stmt::Try {
+            body,
+            catch: Some(catch),
+            finally: None,
+        } = &ast.nodes[0]
+        else {
+            panic!("expected a try statement with a catch clause");
+        };
+
+        assert!(matches!(body[..], [Stmt::Throw(_)]));
+        assert_eq!(catch.name, "e");
+        assert!(matches!(
+            &ast.nodes[1],
+            Stmt::Try {
+                catch: None,
+                finally: Some(_),
+                ..
+            }
+        ));
+        assert!(Parser::parse_ast(Lexer::tokenize("try {}")).is_err());
+        assert!(Parser::parse_ast(Lexer::tokenize("try {} catch {}")).is_err());
+    }
+
+    #[test]
+    fn test_next_statement() {
+        let mut parser = Parser::new(Lexer::new("var a = [1, 2]\n\nvar b = a\n"));
+
+        let first = parser.next_statement().unwrap().unwrap();
+        let second = parser.next_statement().unwrap().unwrap();
+
+        assert_eq!(first.nodes.len(), 1);
+        assert_eq!(first.exprs.len(), 3);
+        assert_eq!(
+            second.nodes,
+            [Stmt::VarDecl {
+                id: "b".into(),
+                ty: None,
+                init: ExprId(0)
+            }]
+        );
+        assert_eq!(second.exprs, [Expr::Identifier("a".into())]);
+        assert_eq!(parser.next_statement(), Ok(None));
+        assert_eq!(
+            super::scan_imports(Lexer::new("import \"a/b\"\nvar x = 1\nimport \"c\" as d")),
+            ["a/b", "c"]
+        );
+    }
+
+    #[test]
+    fn test_labeled_loop() {
+        let ast = Parser::parse_ast(Lexer::tokenize(
+            "outer: while x {\n    break outer\n    continue\n}",
+        ))
+        .unwrap();
+
+        let Stmt::While { label, body, .. } = &ast.nodes[0] else {
+            panic!("expected a loop");
+        };
+
+        assert_eq!(label.as_deref(), Some("outer"));
+        assert_eq!(
+            body,
+            &[Stmt::Break(Some("outer".into())), Stmt::Continue(None)]
+        );
+        assert!(Parser::parse_ast(Lexer::tokenize("outer: x")).is_err());
+    }
+
+    #[test]
+    fn test_host_statement() {
+        let mut options = CompilerOptions::new();
+
+        options.statement("quest", |_, _| Ok(())).unwrap();
+
+        let parse = |source: &str| {
+            Parser::new(Lexer::tokenize(source))
+                .with_options(&options)
+                .parse()
+        };
+        let ast = parse("quest \"Find the sword\", 100 {\n    x\n}\nquest\n").unwrap();
+
+        let Stmt::Host(stmt) = &ast.nodes[0] else {
+            panic!("expected a host statement");
+        };
+
+        assert_eq!(stmt.keyword, "quest");
+        assert_eq!(ast[stmt.args[1]], Expr::Literal(Lit::Integer(100)));
+        assert!(matches!(stmt.body.as_deref(), Some([Stmt::Expr(_)])));
+        assert_eq!(
+            ast.nodes[1],
+            Stmt::Host(HostStmt {
+                keyword: "quest".into(),
+                args: Vec::new(),
+                body: None,
+            })
+        );
+        assert!(parse("quest 1 {\n    x").is_err());
+    }
+
+    #[test]
+    fn test_for() {
+        let ast =
+            Parser::parse_ast(Lexer::tokenize("each: for x in items {\n    yield x\n}")).unwrap();
+
+        let Stmt::For {
+            label,
+            var,
+            iterable,
+            body,
+        } = &ast.nodes[0]
+        else {
+            panic!("expected a for loop");
+        };
+
+        assert_eq!(label.as_deref(), Some("each"));
+        assert_eq!(var, "x");
+        assert_eq!(ast[*iterable], Expr::Identifier("items".into()));
+        assert!(matches!(body[..], [Stmt::Yield(_)]));
+        assert!(Parser::parse_ast(Lexer::tokenize("for x items {}")).is_err());
+    }
+
+    #[test]
+    fn test_dot() {
+        let ast = Parser::parse_ast(Lexer::tokenize("dog.owner.name()")).unwrap();
+
+        let Stmt::Expr(expr) = ast.nodes[0] else {
+            panic!("expected an expression");
+        };
+        let Expr::Call { callee, .. } = ast[expr] else {
+            panic!("expected a call");
+        };
+        let Expr::Dot { object, property } = ast[callee] else {
+            panic!("expected a dot expression");
+        };
+
+        assert_eq!(ast[property], Expr::Identifier("name".into()));
+        assert!(matches!(ast[object], Expr::Dot { .. }));
+        assert!(Parser::parse_ast(Lexer::tokenize("dog.1")).is_err());
+    }
+
+    #[test]
+    fn test_typeof_is() {
+        let ast = Parser::parse_ast(Lexer::tokenize("typeof a.b is C")).unwrap();
+
+        let Stmt::Expr(expr) = ast.nodes[0] else {
+            panic!("expected an expression");
+        };
+        let Expr::Is { value, class } = ast[expr] else {
+            panic!("expected an is expression");
+        };
+        let Expr::TypeOf(operand) = ast[value] else {
+            panic!("expected a typeof expression");
+        };
+
+        assert!(matches!(ast[operand], Expr::Dot { .. }));
+        assert_eq!(ast[class], Expr::Identifier("C".into()));
+    }
+
+    #[test]
+    fn test_precedence() {
+        let ast = Parser::parse_ast(Lexer::tokenize("-a.b * c")).unwrap();
+
+        let Stmt::Expr(expr) = ast.nodes[0] else {
+            panic!("expected an expression");
+        };
+        let Expr::BinExpr {
+            left,
+            op: BinOp::Mul,
+            ..
+        } = ast[expr]
+        else {
+            panic!("expected a multiplication");
+        };
+        let Expr::UnaryExpr { value, .. } = ast[left] else {
+            panic!("expected a unary expression");
+        };
+
+        assert!(matches!(ast[value], Expr::Dot { .. }));
+
+        // Each operator binds more tightly than the one before it.
+        let ast = Parser::parse_ast(Lexer::tokenize("a || b && c == d < e + f")).unwrap();
+        let Stmt::Expr(mut expr) = ast.nodes[0] else {
+            panic!("expected an expression");
+        };
+
+        for op in [BinOp::Or, BinOp::And, BinOp::Equal, BinOp::Less, BinOp::Add] {
+            let Expr::BinExpr {
+                op: found, right, ..
+            } = &ast[expr]
+            else {
+                panic!("expected a binary expression");
+            };
+
+            assert_eq!(*found, op);
+            expr = *right;
+        }
+
+        assert!(Precedence::Assignment < Precedence::Or);
+        assert!(Precedence::Term < Precedence::Unary);
+        assert!(Precedence::Call < Precedence::Primary);
+        // Tokens without an infix rule end the expression instead of
+        // panicking.
+        assert!(Parser::parse_ast(Lexer::tokenize("1 ~ 2")).is_err());
+        assert!(Parser::parse_ast(Lexer::tokenize("a b")).is_err());
+    }
+
+    #[test]
+    fn test_range() {
+        let ast = Parser::parse_ast(Lexer::tokenize("0..n + 1 step 2 * 3")).unwrap();
+
+        let Stmt::Expr(expr) = ast.nodes[0] else {
+            panic!("expected an expression");
+        };
+        let Expr::Range {
+            start,
+            end,
+            step: Some(step),
+        } = ast[expr]
+        else {
+            panic!("expected a range with a step");
+        };
+
+        assert_eq!(ast[start], Expr::Literal(Lit::Integer(0)));
+        assert!(matches!(ast[end], Expr::BinExpr { .. }));
+        assert!(matches!(ast[step], Expr::BinExpr { .. }));
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        let (ast, expr) =
+            Parser::parse_expression(Lexer::tokenize("\nplayer.health + 10\n")).unwrap();
+
+        let Expr::BinExpr { left, right, .. } = ast[expr] else {
+            panic!("expected a binary expression");
+        };
+
+        assert!(ast.nodes.is_empty());
+        assert!(matches!(ast[left], Expr::Dot { .. }));
+        assert_eq!(ast[right], Expr::Literal(Lit::Integer(10)));
+        assert_eq!(
+            Parser::parse_expression(Lexer::tokenize("1 + 2 3"))
+                .unwrap_err()
+                .to_string(),
+            "[E0401] line 1:7: expected the end of the expression but found '3'"
+        );
+        assert!(Parser::parse_expression(Lexer::tokenize("var x = 1")).is_err());
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "// Greet someone.\nfun greet(name) {\n    print(name) // says hi\n}";
+        let ast = Parser::new(Lexer::new(source).with_comments())
+            .parse()
+            .unwrap();
+
+        let comments = ast
+            .comments
+            .iter()
+            .map(|comment| (&*comment.text, comment.span.line, comment.trailing))
+            .collect::<Vec<_>>();
+
+        assert_eq!(ast, Parser::parse_ast(Lexer::tokenize(source)).unwrap());
+        assert_eq!(
+            comments,
+            [(" Greet someone.", 1, false), (" says hi", 3, true)]
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let ast = Parser::parse_ast(Lexer::tokenize("var x = 1\nprint(x,\n    x * 2)")).unwrap();
+
+        let Stmt::Expr(call) = ast.nodes[1] else {
+            panic!("expected an expression");
+        };
+        let Expr::Call { ref args, .. } = ast[call] else {
+            panic!("expected a call");
+        };
+        let at = |id| {
+            let span = ast.span(id);
+            (span.line, span.column)
+        };
+
+        assert_eq!(at(call), (2, 1));
+        assert_eq!(at(args[0]), (2, 7));
+        assert_eq!(at(args[1]), (3, 5));
     }
 }
@@ -0,0 +1,153 @@
+//! String-only path manipulation, exposed as the `path` global.
+//!
+//! None of these functions touch the filesystem, so they are safe to expose
+//! to sandboxed scripts. Paths use `/` as the separator.
+
+use crate::{
+    scope::State,
+    value::{Class, Value},
+};
+
+/// Join path segments with `/`. An absolute segment discards everything before it.
+pub fn join<S: AsRef<str>>(parts: &[S]) -> String {
+    let mut path = String::new();
+
+    for part in parts {
+        let part = part.as_ref();
+
+        if part.starts_with('/') {
+            path.clear();
+        } else if !path.is_empty() && !path.ends_with('/') && !part.is_empty() {
+            path.push('/');
+        }
+
+        path.push_str(part);
+    }
+
+    path
+}
+
+/// The last segment of a path, ignoring trailing separators.
+pub fn basename(path: &str) -> &str {
+    let path = path.trim_end_matches('/');
+
+    path.rsplit('/').next().unwrap_or("")
+}
+
+/// The extension of the last segment, including the leading `.`.
+///
+/// Returns an empty string when there is no extension, including for
+/// dotfiles such as `.gitignore`.
+pub fn ext(path: &str) -> &str {
+    let name = basename(path);
+
+    match name.rfind('.') {
+        Some(0) | None => "",
+        Some(index) => &name[index..],
+    }
+}
+
+/// Collapse repeated separators and resolve `.` and `..` segments.
+pub fn normalize(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let mut segments: Vec<&str> = Vec::new();
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => match segments.last() {
+                Some(&last) if last != ".." => {
+                    segments.pop();
+                }
+                // `..` can't go above the root.
+                _ if absolute => {}
+                _ => segments.push(".."),
+            },
+            segment => segments.push(segment),
+        }
+    }
+
+    let joined = segments.join("/");
+
+    match (absolute, joined.is_empty()) {
+        (true, _) => format!("/{joined}"),
+        (false, true) => ".".into(),
+        (false, false) => joined,
+    }
+}
+
+fn string_args(name: &str, args: &[Value]) -> Result<Vec<String>, String> {
+    args.iter()
+        .map(|arg| match arg {
+            Value::String(s) => Ok(s.clone()),
+            value => Err(format!("path.{name} expects strings, found '{value}'")),
+        })
+        .collect()
+}
+
+fn string_arg(name: &str, args: &[Value]) -> Result<String, String> {
+    match string_args(name, args)?.as_slice() {
+        [path] => Ok(path.clone()),
+        _ => Err(format!("path.{name} expects a single path")),
+    }
+}
+
+/// Register the `path` global.
+pub fn register(state: &mut State) {
+    let class = Class::new("path");
+
+    class.add_static_method("join", |args| {
+        Ok(Value::String(join(&string_args("join", &args)?)))
+    });
+
+    class.add_static_method("basename", |args| {
+        Ok(Value::String(
+            basename(&string_arg("basename", &args)?).into(),
+        ))
+    });
+
+    class.add_static_method("ext", |args| {
+        Ok(Value::String(ext(&string_arg("ext", &args)?).into()))
+    });
+
+    class.add_static_method("normalize", |args| {
+        Ok(Value::String(normalize(&string_arg("normalize", &args)?)))
+    });
+
+    state.add("path", Value::Class(class));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Thrush;
+
+    use super::{basename, ext, join, normalize};
+
+    #[test]
+    fn test_path() {
+        assert_eq!(
+            join(&["assets", "sprites/", "hero.png"]),
+            "assets/sprites/hero.png"
+        );
+        assert_eq!(join(&["assets", "/tmp", "x"]), "/tmp/x");
+        assert_eq!(basename("/a/b/"), "b");
+        assert_eq!(ext("archive.tar.gz"), ".gz");
+        assert_eq!(ext(".gitignore"), "");
+        assert_eq!(normalize("a//./b/../c/"), "a/c");
+        assert_eq!(normalize("/../a/.."), "/");
+        assert_eq!(normalize("../x/../.."), "../..");
+        assert_eq!(normalize(""), ".");
+    }
+
+    #[test]
+    fn test_path_script() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("var p = path.normalize(path.join(\"a\", \"../b\", \"c.txt\"))")
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<String>("p"), Ok("b/c.txt".into()));
+        assert!(thrush.exec("path.ext(1)").is_err());
+    }
+}
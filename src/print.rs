@@ -0,0 +1,47 @@
+//! The `print` and `println` globals, which every [State] has.
+//!
+//! Both write their arguments separated by spaces to the state's
+//! [Output](crate::output::Output), so hosts decide where they end up.
+
+use crate::{scope::State, value::Value};
+
+/// Join `args` with spaces, as `print` writes them.
+fn join(args: &[Value]) -> String {
+    args.iter()
+        .map(Value::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Register the `print` and `println` globals.
+pub fn register(state: &mut State) {
+    let output = state.output();
+
+    state.add_function("print", move |args| {
+        output.write(&join(&args));
+        Ok(Value::Nil)
+    });
+
+    let output = state.output();
+
+    state.add_function("println", move |args| {
+        output.write(&format!("{}\n", join(&args)));
+        Ok(Value::Nil)
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{scope::State, Thrush};
+
+    #[test]
+    fn test_print() {
+        let mut thrush = Thrush::new();
+        let report = thrush.exec_report(
+            "print(\"a\", 1)\nprint(\"b\")\nprintln()\nprintln(\"hello\", [1, \"x\"], nil)",
+        );
+
+        assert_eq!(report.stdout, "a 1b\nhello [1, \"x\"] nil\n");
+        assert!(State::new().contains("println"));
+    }
+}
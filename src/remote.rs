@@ -0,0 +1,265 @@
+//! Attaching a developer console to a VM embedded in a host, such as a
+//! shipped game.
+//!
+//! A host opens a port only it knows about and wraps the listener in a
+//! [Console], which answers the [serve](crate::serve) protocol against the
+//! live VM, so the team can inspect globals and run code while the game is
+//! running. `reset` isn't offered, since the VM belongs to the host.
+//!
+//! ```no_run
+//! use std::net::TcpListener;
+//!
+//! use thrush::{remote::Console, Thrush};
+//!
+//! let mut thrush = Thrush::new();
+//! let mut console = Console::new(TcpListener::bind("127.0.0.1:7777").unwrap()).unwrap();
+//!
+//! loop {
+//!     // Answer the requests consoles have sent, then carry on with the frame.
+//!     console.serve(&mut thrush).unwrap();
+//!     thrush.update(1.0 / 60.0).unwrap();
+//! }
+//! ```
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{serve, Thrush};
+
+/// A console port, with the consoles connected to it.
+#[derive(Debug)]
+pub struct Console {
+    listener: TcpListener,
+    clients: Vec<Client>,
+}
+
+/// A connected console, with what it sent that isn't a whole request yet
+/// and the responses it hasn't taken yet.
+#[derive(Debug)]
+struct Client {
+    stream: TcpStream,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    closed: bool,
+}
+
+impl Console {
+    /// Open a console on `listener`, which is made non-blocking.
+    pub fn new(listener: TcpListener) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    /// The number of consoles connected.
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Whether no console is connected.
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// Accept the consoles waiting to connect and answer the whole requests
+    /// every console has sent so far, against `thrush`.
+    ///
+    /// This never waits for a console, so a host can call it once per frame
+    /// while the game runs. Consoles that disconnect are dropped once they
+    /// have been answered.
+    pub fn serve(&mut self, thrush: &mut Thrush) -> io::Result<()> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(true)?;
+                    self.clients.push(Client {
+                        stream,
+                        input: Vec::new(),
+                        output: Vec::new(),
+                        closed: false,
+                    });
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        for client in &mut self.clients {
+            client.read();
+
+            for request in client.requests() {
+                let response = serve::handle(&request, |method, params| {
+                    serve::call(thrush, method, params)
+                });
+
+                if let Some(response) = response {
+                    client.output.extend_from_slice(response.as_bytes());
+                    client.output.push(b'\n');
+                }
+            }
+
+            client.write();
+        }
+
+        self.clients
+            .retain(|client| !client.closed || !client.output.is_empty());
+
+        Ok(())
+    }
+}
+
+impl Client {
+    /// Read what the console has sent without waiting for more.
+    fn read(&mut self) {
+        let mut buffer = [0; 4096];
+
+        while !self.closed {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.closed = true,
+                Ok(len) => self.input.extend_from_slice(&buffer[..len]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                // A console failing shouldn't stop the others.
+                Err(_) => self.closed = true,
+            }
+        }
+    }
+
+    /// Take the whole lines out of the input, and what is left of it once
+    /// the console has disconnected.
+    fn requests(&mut self) -> Vec<String> {
+        let mut requests = Vec::new();
+
+        while let Some(end) = self.input.iter().position(|&byte| byte == b'\n') {
+            let line = self.input.drain(..=end).collect::<Vec<_>>();
+
+            requests.push(String::from_utf8_lossy(&line).into_owned());
+        }
+
+        if self.closed && !self.input.is_empty() {
+            requests.push(String::from_utf8_lossy(&self.input).into_owned());
+            self.input.clear();
+        }
+
+        requests.retain(|request| !request.trim().is_empty());
+        requests
+    }
+
+    /// Send as many of the responses as the console will take now.
+    fn write(&mut self) {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => break,
+                Ok(len) => {
+                    self.output.drain(..len);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => {
+                    self.output.clear();
+                    self.closed = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+        time::Duration,
+    };
+
+    use crate::Thrush;
+
+    use super::Console;
+
+    #[test]
+    fn test_serve() {
+        let mut thrush = Thrush::new();
+        let mut console = Console::new(TcpListener::bind("127.0.0.1:0").unwrap()).unwrap();
+        let address = console.listener.local_addr().unwrap();
+        let _idle = TcpStream::connect(address).unwrap();
+        let mut client = TcpStream::connect(address).unwrap();
+
+        thrush.globals().add("lives", 3);
+
+        // An idle console doesn't hold up the host.
+        console.serve(&mut thrush).unwrap();
+
+        client
+            .write_all(
+                concat!(
+                    r#"{"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"code": "lives = lives - 1"}}"#,
+                    "\n",
+                    r#"{"jsonrpc": "2.0", "id": 2, "method": "reset"}"#,
+                    "\n",
+                    r#"{"jsonrpc": "2.0", "id": 3, "method": "eval""#,
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+
+        for _ in 0..100 {
+            console.serve(&mut thrush).unwrap();
+
+            if thrush.globals().get::<i64>("lives") == Ok(2) {
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        let mut lines = [String::new(), String::new()];
+
+        for line in &mut lines {
+            reader.read_line(line).unwrap();
+        }
+
+        assert_eq!(
+            lines,
+            [
+                concat!(r#"{"jsonrpc":"2.0","id":1,"result":2}"#, "\n"),
+                concat!(
+                    r#"{"jsonrpc":"2.0","id":2,"error":{"code":-32601,"message":"no method named 'reset'"}}"#,
+                    "\n"
+                ),
+            ]
+        );
+        assert_eq!(thrush.globals().get::<i64>("lives"), Ok(2));
+        assert_eq!(console.len(), 2);
+
+        // The rest of a request is answered once it arrives.
+        client
+            .write_all(b", \"params\": {\"code\": \"lives\"}}\n")
+            .unwrap();
+
+        let mut line = String::new();
+
+        client
+            .set_read_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+
+        for _ in 0..100 {
+            console.serve(&mut thrush).unwrap();
+
+            if reader.read_line(&mut line).is_ok() {
+                break;
+            }
+        }
+
+        assert_eq!(
+            line,
+            concat!(r#"{"jsonrpc":"2.0","id":3,"result":2}"#, "\n")
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Everything about a single run of a script, in one value.
+//!
+//! [Thrush::exec_report](crate::Thrush::exec_report) runs a script like
+//! [Thrush::exec](crate::Thrush::exec), but captures what it printed and
+//! returns it with the value of the script and any errors, for hosts such as
+//! web playgrounds or grading systems that show the result of each run.
+
+use std::time::Duration;
+
+use crate::{
+    diagnostic::{self, Error, ErrorCode},
+    token::Span,
+    value::Value,
+};
+
+/// The result of running a script with
+/// [Thrush::exec_report](crate::Thrush::exec_report).
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExecReport {
+    /// The value of the script's last statement if it is an expression, or
+    /// nil. `None` if the script failed.
+    pub value: Option<Value>,
+    /// Everything the script wrote to its [Output](crate::output::Output).
+    pub stdout: String,
+    /// The warnings compiling the script reported, then the errors it failed
+    /// with.
+    pub diagnostics: Vec<Diagnostic>,
+    pub stats: ExecStats,
+}
+
+impl ExecReport {
+    /// Whether the script ran without errors.
+    pub fn is_ok(&self) -> bool {
+        self.value.is_some()
+    }
+}
+
+/// An error or warning reported by a run.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The code of the diagnostic, unless it came from outside the language.
+    pub code: Option<ErrorCode>,
+    /// The full message, including the code.
+    pub message: String,
+    /// Where in the script it was reported, if that is known.
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub(crate) fn error(err: Error) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: err.code,
+            message: err.to_string(),
+            span: err.span,
+        }
+    }
+
+    pub(crate) fn warning(err: Error) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(err)
+        }
+    }
+
+    /// The message with the line of `source` it was reported at, see
+    /// [diagnostic::render].
+    pub fn render(&self, source: &str) -> String {
+        diagnostic::render(&self.message, self.span, source)
+    }
+}
+
+/// Whether a diagnostic stopped the script.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    /// Something that is likely a mistake, which the script still ran with.
+    Warning,
+}
+
+/// How much work a run took.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct ExecStats {
+    /// The time spent parsing and compiling the script.
+    pub compile_time: Duration,
+    /// The time spent running the compiled script.
+    pub run_time: Duration,
+    /// The number of instructions the script compiled to.
+    pub instructions: usize,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{diagnostic::ErrorCode, value::Value, Thrush};
+
+    use super::Severity;
+
+    #[test]
+    fn test_exec_report() {
+        let mut thrush = Thrush::new();
+        let output = thrush.globals().output();
+
+        thrush.globals().add_function("say", move |args| {
+            output.write(&format!("{}\n", args[0]));
+            Ok(Value::Nil)
+        });
+
+        let report = thrush.exec_report("say(\"hi\")\nvar x = 2\nsay(x)\nx * 21");
+
+        assert_eq!(report.value, Some(Value::Integer(42)));
+        assert_eq!(report.stdout, "hi\n2\n");
+        assert!(report.diagnostics.is_empty());
+        assert!(report.stats.instructions > 0);
+
+        let report = thrush.exec_report("var y = x");
+
+        assert_eq!(report.value, Some(Value::Nil));
+
+        let report = thrush.exec_report("say(\"before\")\nvar z = x + \"a\"");
+
+        assert!(!report.is_ok());
+        assert_eq!(report.stdout, "before\n");
+        assert_eq!(report.diagnostics[0].code, Some(ErrorCode::TypeMismatch));
+        assert_eq!(report.diagnostics[0].span.map(|span| span.line), Some(2));
+        assert!(report.diagnostics[0]
+            .render("say(\"before\")\nvar z = x + \"a\"")
+            .ends_with("2 | var z = x + \"a\"\n  |             ^"));
+
+        let report = thrush.exec_report("var w = (");
+
+        assert_eq!(report.stats.instructions, 0);
+        assert_eq!(report.diagnostics.len(), 1);
+
+        let report = thrush.exec_report("fun f() {\n    var unused = 1\n}\nf()");
+
+        assert!(report.is_ok());
+        assert_eq!(report.diagnostics[0].severity, Severity::Warning);
+        assert_eq!(report.diagnostics[0].code, Some(ErrorCode::UnusedVariable));
+    }
+}
@@ -0,0 +1,207 @@
+//! Printing values with limits on their size.
+//!
+//! Lists and maps can be huge or contain themselves, so printing them is
+//! capped by a [PrintLimits]. Anything past a limit is elided with `...`, and
+//! a container that is already being printed is shown as `[...]` or `{...}`.
+
+use std::{collections::HashSet, fmt};
+
+use crate::{scope::State, value::Value};
+
+/// How much of a value is printed before the rest is elided.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PrintLimits {
+    /// How many containers deep to print.
+    pub max_depth: usize,
+    /// How many elements to print in total, across every nested container.
+    pub max_items: usize,
+}
+
+impl Default for PrintLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 16,
+            max_items: 1000,
+        }
+    }
+}
+
+/// A value formatted within some [PrintLimits].
+///
+/// Unlike the [Display](fmt::Display) impl of [Value], strings are quoted
+/// even at the top level, so the output is unambiguous.
+pub struct Repr<'a> {
+    value: &'a Value,
+    limits: PrintLimits,
+}
+
+impl<'a> Repr<'a> {
+    pub fn new(value: &'a Value, limits: PrintLimits) -> Self {
+        Self { value, limits }
+    }
+}
+
+impl fmt::Display for Repr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Printer::new(self.limits).value(f, self.value, 0)
+    }
+}
+
+/// Format a list or map for [Value]'s [Display](fmt::Display) impl.
+pub(crate) fn fmt_container(value: &Value, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    Printer::new(PrintLimits::default()).container(f, value, 0)
+}
+
+struct Printer {
+    limits: PrintLimits,
+    /// The addresses of the containers currently being printed.
+    seen: HashSet<usize>,
+    /// The number of elements printed so far.
+    items: usize,
+}
+
+impl Printer {
+    fn new(limits: PrintLimits) -> Self {
+        Self {
+            limits,
+            seen: HashSet::new(),
+            items: 0,
+        }
+    }
+
+    fn value(&mut self, f: &mut fmt::Formatter<'_>, value: &Value, depth: usize) -> fmt::Result {
+        match value {
+            Value::String(v) => f.write_fmt(format_args!("{v:?}")),
+            Value::List(_) | Value::Map(_) => self.container(f, value, depth),
+            value => fmt::Display::fmt(value, f),
+        }
+    }
+
+    fn container(
+        &mut self,
+        f: &mut fmt::Formatter<'_>,
+        value: &Value,
+        depth: usize,
+    ) -> fmt::Result {
+        let (address, open, close) = match value {
+            Value::List(list) => (list.as_ptr() as *const () as usize, "[", "]"),
+            Value::Map(map) => (map.as_ptr() as *const () as usize, "{", "}"),
+            value => return self.value(f, value, depth),
+        };
+
+        if depth >= self.limits.max_depth || !self.seen.insert(address) {
+            return f.write_fmt(format_args!("{open}...{close}"));
+        }
+
+        f.write_str(open)?;
+
+        let result = match value {
+            Value::List(list) => self.items(f, list.borrow().iter().map(|v| (None, v)), depth),
+            Value::Map(map) => {
+                let map = map.borrow();
+
+                self.items(f, map.iter().map(|(k, v)| (Some(k), v)), depth)
+            }
+            _ => unreachable!(),
+        };
+
+        self.seen.remove(&address);
+        result?;
+
+        f.write_str(close)
+    }
+
+    fn items<'v, I>(&mut self, f: &mut fmt::Formatter<'_>, items: I, depth: usize) -> fmt::Result
+    where
+        I: Iterator<Item = (Option<&'v str>, &'v Value)>,
+    {
+        for (i, (key, value)) in items.enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+
+            if self.items >= self.limits.max_items {
+                return f.write_str("...");
+            }
+
+            self.items += 1;
+
+            if let Some(key) = key {
+                f.write_fmt(format_args!("{key}: "))?;
+            }
+
+            self.value(f, value, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Register the `repr` global.
+pub fn register(state: &mut State) {
+    state.add_function("repr", |args| match args.as_slice() {
+        [value] => Ok(Value::String(
+            Repr::new(value, PrintLimits::default()).to_string(),
+        )),
+        _ => Err("repr expects a single value".into()),
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        value::{Map, Value},
+        Thrush,
+    };
+
+    use super::{PrintLimits, Repr};
+
+    #[test]
+    fn test_limits() {
+        let limits = PrintLimits {
+            max_depth: 2,
+            max_items: 3,
+        };
+        let nested = Value::list(vec![Value::list(vec![Value::list(vec![])])]);
+        let long = Value::list((0..5).map(Value::Integer).collect());
+
+        assert_eq!(Repr::new(&nested, limits).to_string(), "[[[...]]]");
+        assert_eq!(Repr::new(&long, limits).to_string(), "[0, 1, 2, ...]");
+        assert_eq!(
+            Repr::new(&Value::String("a".into()), limits).to_string(),
+            "\"a\""
+        );
+    }
+
+    #[test]
+    fn test_cycles() {
+        let list = Value::list(vec![Value::Integer(1)]);
+        let map = Value::map(Map::new());
+
+        if let (Value::List(inner), Value::Map(entries)) = (&list, &map) {
+            inner.borrow_mut().push(list.clone());
+            inner.borrow_mut().push(map.clone());
+            entries.borrow_mut().insert("list", list.clone());
+        }
+
+        assert_eq!(list.to_string(), "[1, [...], {list: [...]}]");
+        assert_eq!(map.to_string(), "{list: [1, [...], {...}]}");
+
+        // Break the cycle so the test doesn't leak.
+        if let Value::List(inner) = &list {
+            inner.borrow_mut().clear();
+        }
+    }
+
+    #[test]
+    fn test_repr_script() {
+        let mut thrush = Thrush::new();
+
+        thrush.exec("var r = repr([\"a\", {b: 1}])").unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<String>("r"),
+            Ok("[\"a\", {b: 1}]".into())
+        );
+    }
+}
@@ -0,0 +1,84 @@
+//! The VM's random number generator.
+//!
+//! Every builtin that needs randomness draws from the VM's [Rng], so hosts
+//! can make scripts reproducible by seeding it with
+//! [Thrush::set_seed](crate::Thrush::set_seed).
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A small, non-cryptographic xorshift64* generator.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator that always yields the same sequence for `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        // Run the seed through splitmix64 so that small seeds, including 0,
+        // still give a well-mixed, non-zero state.
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+
+        Self {
+            state: if z == 0 { 0x9e3779b97f4a7c15 } else { z },
+        }
+    }
+
+    /// Create a generator seeded from the clock and the process's hash keys.
+    pub fn from_entropy() -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos());
+
+        hasher.write_u128(nanos);
+
+        Self::with_seed(hasher.finish())
+    }
+
+    /// The next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// Fill `bytes` with random data.
+    pub fn fill(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let random = self.next_u64().to_le_bytes();
+
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::from_entropy()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Rng;
+
+    #[test]
+    fn test_seeded_rng() {
+        let mut a = Rng::with_seed(0);
+        let mut b = Rng::with_seed(0);
+        let values = (0..4).map(|_| a.next_u64()).collect::<Vec<_>>();
+
+        assert_eq!(values, (0..4).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(values[0], values[1]);
+        assert_ne!(Rng::with_seed(1).next_u64(), values[0]);
+    }
+}
@@ -1,35 +1,239 @@
-use std::{collections::HashMap};
+use std::collections::{HashMap, HashSet};
 
-use crate::value::{Class, FromValue, ToValue, Value};
+use std::rc::Rc;
+
+use crate::{
+    chunk::Module,
+    limits::HostLimits,
+    output::Output,
+    print,
+    value::{Class, FromValue, Function, ToValue, Value},
+    vm::{Vm, VmError},
+};
 
 /// Struct for tracking global state.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct State {
-    globals: HashMap<String, Value>,
+    /// The slot of each global, which compiled code refers to it by.
+    slots: HashMap<String, usize>,
+    /// The name of the global in each slot.
+    names: Vec<String>,
+    /// The value of the global in each slot, or `None` if it isn't defined.
+    values: Vec<Option<Value>>,
+    /// The names of globals declared with `const`.
+    constants: HashSet<String>,
+    /// The names of globals declared with `let`.
+    immutables: HashSet<String>,
+    /// Modules that have already been loaded, by name.
+    modules: HashMap<String, Rc<Module>>,
+    /// Where natives write the script's output.
+    output: Output,
+    /// How big the values the host gets with [State::get] can be.
+    limits: HostLimits,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl State {
+    /// Create a state with only the `print` and `println` globals.
     pub fn new() -> Self {
-        State {
-            globals: HashMap::new(),
-        }
+        let mut state = State {
+            slots: HashMap::new(),
+            names: Vec::new(),
+            values: Vec::new(),
+            constants: HashSet::new(),
+            immutables: HashSet::new(),
+            modules: HashMap::new(),
+            output: Output::default(),
+            limits: HostLimits::default(),
+        };
+
+        print::register(&mut state);
+        state
+    }
+
+    /// A handle to the sink natives should write output to, see [Output].
+    pub fn output(&self) -> Output {
+        self.output.clone()
     }
 
     pub fn add<T: ToValue>(&mut self, name: &str, value: T) {
-        self.globals.insert(name.into(), value.to_value());
+        let slot = self.slot(name);
+
+        self.values[slot] = Some(value.to_value());
     }
 
     pub fn add_class<S: Into<String> + Copy>(&mut self, name: S) {
-        self.globals
-            .insert(name.into(), Value::Class(Class::new(name.into())));
+        let name = name.into();
+
+        self.add(&name, Value::Class(Class::new(name.as_str())));
+    }
+
+    /// The slot of the global `name`, adding an undefined one if it has none
+    /// yet. A global keeps its slot when it is removed, so code compiled to
+    /// refer to it still finds it if it is defined again.
+    pub fn slot(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.slots.get(name) {
+            return slot;
+        }
+
+        self.slots.insert(name.into(), self.names.len());
+        self.names.push(name.into());
+        self.values.push(None);
+
+        self.names.len() - 1
+    }
+
+    /// The name of the global in `slot`.
+    pub(crate) fn slot_name(&self, slot: usize) -> &str {
+        &self.names[slot]
+    }
+
+    /// The value of the global in `slot`, if it is defined.
+    pub(crate) fn get_slot(&self, slot: usize) -> Option<&Value> {
+        self.values[slot].as_ref()
+    }
+
+    /// Define the global in `slot`.
+    pub(crate) fn set_slot(&mut self, slot: usize, value: Value) {
+        self.values[slot] = Some(value);
+    }
+
+    /// Define a global native function that scripts can call.
+    pub fn add_function<F>(&mut self, name: &str, fun: F)
+    where
+        F: Fn(Vec<Value>) -> Result<Value, String> + 'static,
+    {
+        self.add(name, Function::new(name, fun));
+    }
+
+    /// Define a global native function that can call back into the script,
+    /// see [Function::reentrant].
+    pub fn add_reentrant_function<F>(&mut self, name: &str, fun: F)
+    where
+        F: Fn(&mut Vm, Vec<Value>) -> Result<Value, VmError> + 'static,
+    {
+        self.add(name, Function::reentrant(name, fun));
+    }
+
+    /// Mark a global as a constant, so scripts can't reassign it.
+    pub fn add_constant(&mut self, name: &str) {
+        self.constants.insert(name.into());
+    }
+
+    /// Check whether a global was declared as a constant.
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.constants.contains(name)
+    }
+
+    /// Mark a global as declared with `let`, so scripts can't reassign it.
+    pub fn add_immutable(&mut self, name: &str) {
+        self.immutables.insert(name.into());
+    }
+
+    /// Check whether a global was declared with `let`.
+    pub fn is_immutable(&self, name: &str) -> bool {
+        self.immutables.contains(name)
+    }
+
+    /// Remove a global, returning its value if it existed.
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        let slot = *self.slots.get(name)?;
+
+        self.values[slot].take()
+    }
+
+    /// Remove every global and loaded module, returning the globals' values.
+    pub fn clear(&mut self) -> Vec<Value> {
+        self.constants.clear();
+        self.immutables.clear();
+        self.modules.clear();
+        self.values.iter_mut().filter_map(Option::take).collect()
+    }
+
+    /// Remove every global namespaced by the module `module`, returning
+    /// them with their values.
+    pub fn remove_namespace(&mut self, module: &str) -> Vec<(String, Value)> {
+        let prefix = Module::global(module, "");
+        let names = self
+            .names()
+            .filter(|name| name.starts_with(&prefix))
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let value = self.remove(&name)?;
+
+                Some((name, value))
+            })
+            .collect()
+    }
+
+    /// Cache a loaded module, so later imports of it share it.
+    pub fn add_module(&mut self, module: Module) {
+        self.modules
+            .insert(module.name.to_string(), Rc::new(module));
+    }
+
+    /// Cache a module that was removed with [State::remove_module].
+    pub fn restore_module(&mut self, module: Rc<Module>) {
+        self.modules.insert(module.name.to_string(), module);
+    }
+
+    /// Forget a loaded module, so the next import loads it again.
+    pub fn remove_module(&mut self, name: &str) -> Option<Rc<Module>> {
+        self.modules.remove(name)
+    }
+
+    /// Get a module that has already been loaded.
+    pub fn module(&self, name: &str) -> Option<Rc<Module>> {
+        self.modules.get(name).cloned()
+    }
+
+    /// Check whether a global with the given name exists.
+    pub fn contains(&self, name: &str) -> bool {
+        self.slots
+            .get(name)
+            .is_some_and(|&slot| self.values[slot].is_some())
     }
 
+    /// Iterate over the names of every global, in the order they were first
+    /// defined.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names
+            .iter()
+            .zip(&self.values)
+            .filter(|(_, value)| value.is_some())
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Get a global, failing if its value is over the [HostLimits] or can't
+    /// be converted to `T`.
     pub fn get<T: FromValue>(&self, name: &str) -> Result<T, String> {
         let value = self
-            .globals
+            .slots
             .get(name)
+            .and_then(|&slot| self.values[slot].as_ref())
             .expect("cannot find name in this scope.");
 
+        self.limits.check(value)?;
+
         T::from_value(value)
     }
+
+    /// The limits on the size of values the host gets.
+    pub fn limits(&self) -> HostLimits {
+        self.limits
+    }
+
+    /// Set the limits on the size of values the host gets.
+    pub fn set_limits(&mut self, limits: HostLimits) {
+        self.limits = limits;
+    }
 }
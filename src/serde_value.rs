@@ -0,0 +1,473 @@
+//! Converting values to plain data and back, so hosts can save scripted
+//! objects, such as in a save game.
+//!
+//! Plain data is made of nil, booleans, numbers, strings, bytes, lists and
+//! maps, which can be stored in any format. Instances become maps naming
+//! their class in a `__class` key, alongside their fields:
+//!
+//! ```text
+//! class Door {
+//!     var open
+//! }
+//!
+//! Door()  =>  {__class: "Door", open: nil}
+//! ```
+//!
+//! A class can choose what it saves with a `__serialize` method returning a
+//! map, and restore itself with a `__deserialize` method, which is called on
+//! a new instance with that map.
+//!
+//! A class that declares a version with `version 2` in its body stores it in
+//! a `__version` key. When older data is restored, the class's
+//! `__migrate(old_version, data)` method is called first and returns the
+//! upgraded map.
+
+use std::{collections::HashSet, rc::Rc};
+
+use crate::{
+    diagnostic::ErrorCode,
+    value::{Class, Instance, Map, Method, Value},
+    vm::{Vm, VmError},
+};
+
+/// The key naming the class of a serialized instance.
+pub const CLASS_KEY: &str = "__class";
+
+/// The key holding the class version of a serialized instance, left out for
+/// classes without one.
+pub const VERSION_KEY: &str = "__version";
+
+/// Convert `value` to plain data, calling `__serialize` on instances that
+/// define it.
+pub fn to_plain(vm: &mut Vm, value: &Value) -> Result<Value, String> {
+    Converter::new(vm).plain(value)
+}
+
+/// Convert plain data made by [to_plain] back into values, creating
+/// instances of the global classes they name.
+pub fn from_plain(vm: &mut Vm, value: &Value) -> Result<Value, String> {
+    Converter::new(vm).restore(value)
+}
+
+struct Converter<'a> {
+    vm: &'a mut Vm,
+    /// The addresses of the containers being converted, to detect cycles.
+    seen: HashSet<usize>,
+}
+
+impl<'a> Converter<'a> {
+    fn new(vm: &'a mut Vm) -> Self {
+        Self {
+            vm,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Run `f` on the container at `address`, failing if it contains itself.
+    fn enter<T>(
+        &mut self,
+        address: usize,
+        f: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        if !self.seen.insert(address) {
+            return Err(ErrorCode::TypeMismatch.error("can't convert a value that contains itself"));
+        }
+
+        let result = f(self);
+        self.seen.remove(&address);
+
+        result
+    }
+
+    fn plain(&mut self, value: &Value) -> Result<Value, String> {
+        match value {
+            Value::Nil
+            | Value::Bool(_)
+            | Value::Integer(_)
+            | Value::Float(_)
+            | Value::String(_)
+            | Value::Bytes(_) => Ok(value.clone()),
+            Value::List(list) => self.enter(list.as_ptr() as usize, |this| {
+                let items = list
+                    .borrow()
+                    .iter()
+                    .map(|item| this.plain(item))
+                    .collect::<Result<_, _>>()?;
+
+                Ok(Value::list(items))
+            }),
+            Value::Map(map) => self.enter(map.as_ptr() as usize, |this| {
+                let map = map.borrow();
+
+                if let Some(key) = reserved_key(map.iter().map(|(key, _)| key)) {
+                    return Err(ErrorCode::TypeMismatch
+                        .error(format!("can't convert a map with the reserved key '{key}'")));
+                }
+
+                this.plain_map(map.iter()).map(Value::map)
+            }),
+            Value::Instance(instance) => {
+                let instance = Rc::clone(instance);
+
+                self.enter(Rc::as_ptr(&instance) as usize, |this| {
+                    this.plain_instance(instance)
+                })
+            }
+            value => Err(ErrorCode::TypeMismatch.error(format!(
+                "can't convert {} '{value}' to plain data",
+                value.type_name()
+            ))),
+        }
+    }
+
+    fn plain_map<'v>(
+        &mut self,
+        entries: impl Iterator<Item = (&'v str, &'v Value)>,
+    ) -> Result<Map, String> {
+        entries
+            .map(|(key, value)| Ok((key, self.plain(value)?)))
+            .collect()
+    }
+
+    fn plain_instance(&mut self, instance: Rc<Instance>) -> Result<Value, String> {
+        let class = Rc::clone(&instance.class);
+        let data = match class.get_method("__serialize") {
+            Some(method) => match self.invoke(instance, method, Vec::new())? {
+                Value::Map(map) => map.borrow().clone(),
+                value => {
+                    return Err(ErrorCode::TypeMismatch.error(format!(
+                        "'{}.__serialize' returned '{value}' rather than a map",
+                        class.name
+                    )))
+                }
+            },
+            None => class
+                .fields
+                .borrow()
+                .iter()
+                .zip(instance.fields().iter())
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        };
+
+        let mut plain = Map::new();
+
+        plain.insert(CLASS_KEY, Value::String(class.name.to_string()));
+
+        if class.version.get() > 0 {
+            plain.insert(VERSION_KEY, Value::Integer(class.version.get().into()));
+        }
+
+        if let Some(key) = reserved_key(data.iter().map(|(key, _)| key)) {
+            return Err(ErrorCode::TypeMismatch.error(format!(
+                "'{}' can't serialize the reserved key '{key}'",
+                class.name
+            )));
+        }
+
+        for (key, value) in self.plain_map(data.iter())?.iter() {
+            plain.insert(key, value.clone());
+        }
+
+        Ok(Value::map(plain))
+    }
+
+    fn restore(&mut self, value: &Value) -> Result<Value, String> {
+        match value {
+            Value::Nil
+            | Value::Bool(_)
+            | Value::Integer(_)
+            | Value::Float(_)
+            | Value::String(_)
+            | Value::Bytes(_) => Ok(value.clone()),
+            Value::List(list) => self.enter(list.as_ptr() as usize, |this| {
+                let items = list
+                    .borrow()
+                    .iter()
+                    .map(|item| this.restore(item))
+                    .collect::<Result<_, _>>()?;
+
+                Ok(Value::list(items))
+            }),
+            Value::Map(map) => self.enter(map.as_ptr() as usize, |this| {
+                let map = map.borrow();
+                let class = map.get(CLASS_KEY).cloned();
+                let data = map
+                    .iter()
+                    .filter(|(key, _)| *key != CLASS_KEY)
+                    .map(|(key, value)| Ok((key, this.restore(value)?)))
+                    .collect::<Result<Map, String>>()?;
+
+                match class {
+                    Some(Value::String(name)) => this.restore_instance(&name, data),
+                    Some(name) => Err(ErrorCode::TypeMismatch
+                        .error(format!("expected a class name, found '{name}'"))),
+                    None => Ok(Value::map(data)),
+                }
+            }),
+            value => Err(ErrorCode::TypeMismatch
+                .error(format!("{} '{value}' is not plain data", value.type_name()))),
+        }
+    }
+
+    fn restore_instance(&mut self, name: &str, data: Map) -> Result<Value, String> {
+        let class = match self.vm.state.contains(name) {
+            true => self.vm.state.get::<Rc<Class>>(name).ok(),
+            false => None,
+        }
+        .ok_or_else(|| {
+            ErrorCode::UndefinedVariable.error(format!("no class named '{name}' to deserialize"))
+        })?;
+        let instance = Class::instance(Rc::clone(&class));
+        let data = self.migrate(&class, &instance, data)?;
+
+        match class.get_method("__deserialize") {
+            Some(method) => {
+                self.invoke(Rc::clone(&instance), method, vec![Value::map(data)])?;
+            }
+            None => {
+                for (key, value) in data.iter() {
+                    instance
+                        .set_field(key, value.clone())
+                        .map_err(|err| ErrorCode::UndefinedProperty.error(err))?;
+                }
+            }
+        }
+
+        Ok(Value::Instance(instance))
+    }
+
+    /// Upgrade `data` saved by an older version of `class` by calling its
+    /// `__migrate` method on `instance`.
+    fn migrate(
+        &mut self,
+        class: &Class,
+        instance: &Rc<Instance>,
+        mut data: Map,
+    ) -> Result<Map, String> {
+        let current = i64::from(class.version.get());
+        let version = match data.remove(VERSION_KEY) {
+            Some(Value::Integer(version)) if version >= 0 => version,
+            Some(version) => {
+                return Err(ErrorCode::TypeMismatch
+                    .error(format!("expected a class version, found '{version}'")))
+            }
+            None => 0,
+        };
+
+        if version > current {
+            return Err(ErrorCode::TypeMismatch.error(format!(
+                "can't deserialize version {version} of '{}', which is at version {current}",
+                class.name
+            )));
+        }
+
+        let method = match class.get_method("__migrate") {
+            Some(method) if version < current => method,
+            _ => return Ok(data),
+        };
+
+        match self.invoke(
+            Rc::clone(instance),
+            method,
+            vec![Value::Integer(version), Value::map(data)],
+        )? {
+            Value::Map(map) => Ok(map.borrow().clone()),
+            value => Err(ErrorCode::TypeMismatch.error(format!(
+                "'{}.__migrate' returned '{value}' rather than a map",
+                class.name
+            ))),
+        }
+    }
+
+    fn invoke(
+        &mut self,
+        instance: Rc<Instance>,
+        method: Method,
+        args: Vec<Value>,
+    ) -> Result<Value, String> {
+        self.vm
+            .invoke(instance, method, args)
+            .map_err(|VmError(err)| err)
+    }
+}
+
+/// Find a key that plain data reserves for instances.
+fn reserved_key<'k>(mut keys: impl Iterator<Item = &'k str>) -> Option<&'k str> {
+    keys.find(|key| *key == CLASS_KEY || *key == VERSION_KEY)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{value::Value, Thrush};
+
+    #[test]
+    fn test_round_trip() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "class Door {
+    var open
+    var key
+}
+class Key {
+    var id
+    fun __serialize() {
+        return {code: self.id}
+    }
+    fun __deserialize(data) {
+        self.id = [data, \"restored\"]
+    }
+}
+var key = Key()
+key.id = 7
+var door = Door()
+door.open = true
+door.key = key
+var save = [door, {count: 2}]",
+            )
+            .unwrap();
+
+        let save = thrush.globals().get::<Value>("save").unwrap();
+        let plain = thrush.to_plain(&save).unwrap();
+
+        assert_eq!(
+            plain.to_string(),
+            "[{__class: \"Door\", open: true, key: {__class: \"Key\", code: 7}}, {count: 2}]"
+        );
+
+        let restored = thrush.from_plain(&plain).unwrap();
+        let Value::List(restored) = restored else {
+            panic!("expected a list");
+        };
+        let Value::Instance(door) = restored.borrow()[0].clone() else {
+            panic!("expected a door");
+        };
+        let Some(Value::Instance(key)) = door.get_field("key") else {
+            panic!("expected a key");
+        };
+
+        assert_eq!(door.get_field("open"), Some(Value::Bool(true)));
+        assert_eq!(
+            key.get_field("id").unwrap().to_string(),
+            "[{code: 7}, \"restored\"]"
+        );
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "class Player {
+    var name
+    var level
+}
+var player = Player()
+player.name = \"Ada\"
+player.level = 3",
+            )
+            .unwrap();
+
+        let player = thrush.globals().get::<Value>("player").unwrap();
+        let old = thrush.to_plain(&player).unwrap();
+
+        assert_eq!(
+            old.to_string(),
+            "{__class: \"Player\", name: \"Ada\", level: 3}"
+        );
+
+        thrush
+            .exec(
+                "class Player {
+    version 2
+    var name
+    var rank
+    fun __migrate(version, data) {
+        return {name: data, rank: version}
+    }
+}",
+            )
+            .unwrap();
+
+        let Value::Instance(player) = thrush.from_plain(&old).unwrap() else {
+            panic!("expected a player");
+        };
+
+        assert_eq!(
+            player.get_field("name").unwrap().to_string(),
+            "{name: \"Ada\", level: 3}"
+        );
+        assert_eq!(player.get_field("rank"), Some(Value::Integer(0)));
+
+        let new = thrush.to_plain(&Value::Instance(player)).unwrap();
+
+        assert!(new
+            .to_string()
+            .starts_with("{__class: \"Player\", __version: 2,"));
+
+        thrush.exec("class Player {}").unwrap();
+
+        assert!(thrush
+            .from_plain(&new)
+            .unwrap_err()
+            .contains("can't deserialize version 2 of 'Player'"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut thrush = Thrush::new();
+
+        thrush.exec("class Door {}").unwrap();
+
+        let class = thrush.globals().get::<Value>("Door").unwrap();
+
+        assert!(thrush
+            .to_plain(&class)
+            .unwrap_err()
+            .contains("can't convert class"));
+
+        let cycle = Value::list(Vec::new());
+
+        if let Value::List(items) = &cycle {
+            items.borrow_mut().push(cycle.clone());
+        }
+
+        assert!(thrush
+            .to_plain(&cycle)
+            .unwrap_err()
+            .contains("contains itself"));
+
+        // Break the cycle so the test doesn't leak.
+        if let Value::List(items) = &cycle {
+            items.borrow_mut().clear();
+        }
+
+        let plain = Value::map(
+            [("__class", Value::String("Window".into()))]
+                .into_iter()
+                .collect(),
+        );
+
+        assert!(thrush
+            .from_plain(&plain)
+            .unwrap_err()
+            .contains("no class named 'Window'"));
+
+        let plain = Value::map(
+            [
+                ("__class", Value::String("Door".into())),
+                ("open", Value::Bool(true)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        assert!(thrush
+            .from_plain(&plain)
+            .unwrap_err()
+            .contains("'Door' has no field named 'open'"));
+    }
+}
@@ -0,0 +1,670 @@
+//! A JSON-RPC protocol for driving a running VM from external tools, such as
+//! editors, test runners or game consoles.
+//!
+//! Each request is a [JSON-RPC 2.0](https://www.jsonrpc.org/specification)
+//! object on its own line, answered by a response on its own line. Requests
+//! without an `id` are notifications and get no response.
+//!
+//! ```text
+//! --> {"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"code": "var x = 1"}}
+//! <-- {"jsonrpc":"2.0","id":1,"result":null}
+//! --> {"jsonrpc": "2.0", "id": 2, "method": "eval", "params": {"code": "x + 1"}}
+//! <-- {"jsonrpc":"2.0","id":2,"result":2}
+//! --> {"jsonrpc": "2.0", "id": 3, "method": "complete", "params": {"prefix": "x"}}
+//! <-- {"jsonrpc":"2.0","id":3,"result":["x"]}
+//! ```
+//!
+//! The methods are:
+//!
+//! - `eval {code}` runs a script, answering with the value of its last
+//!   statement if that is an expression, or null.
+//! - `complete {prefix}` lists the globals and keywords starting with `prefix`.
+//! - `inspect {name}` returns the [inspect](crate::inspect) dump of a global.
+//! - `reset` replaces the VM with a fresh one.
+
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::TcpListener,
+    str::Chars,
+};
+
+use crate::{
+    inspect::inspect,
+    repr::PrintLimits,
+    value::{Map, Value},
+    Thrush,
+};
+
+/// The request isn't valid JSON.
+pub const PARSE_ERROR: i64 = -32700;
+/// The request isn't a JSON-RPC request object.
+pub const INVALID_REQUEST: i64 = -32600;
+/// There is no method with the requested name.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// The params are missing or have the wrong type.
+pub const INVALID_PARAMS: i64 = -32602;
+/// The script failed, with its error as the message.
+pub const SCRIPT_ERROR: i64 = -32000;
+
+/// How deeply arrays and objects can be nested in a request.
+const MAX_JSON_DEPTH: usize = 256;
+
+/// An error response.
+pub(crate) struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new<S: Into<String>>(code: i64, message: S) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Answers protocol requests with a VM.
+pub struct Server {
+    thrush: Thrush,
+    init: Box<dyn Fn() -> Thrush>,
+}
+
+impl Server {
+    /// Serve a VM made by `init`, which is called again on `reset` so hosts
+    /// can register their globals.
+    pub fn new<F>(init: F) -> Self
+    where
+        F: Fn() -> Thrush + 'static,
+    {
+        Self {
+            thrush: init(),
+            init: Box::new(init),
+        }
+    }
+
+    /// Get a mutable reference to the VM being served.
+    pub fn thrush(&mut self) -> &mut Thrush {
+        &mut self.thrush
+    }
+
+    /// Answer requests read line by line from `reader` until it ends.
+    pub fn serve<R: BufRead, W: Write>(&mut self, reader: R, writer: W) -> io::Result<()> {
+        serve_lines(reader, writer, |request| self.handle(request))
+    }
+
+    /// Answer requests from TCP clients on `port` of the local machine, one
+    /// connection at a time.
+    pub fn listen(&mut self, port: u16) -> io::Result<()> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+
+        accept(&listener, |request| self.handle(request))
+    }
+
+    /// Answer a single request, returning the response unless the request
+    /// is a notification.
+    pub fn handle(&mut self, request: &str) -> Option<String> {
+        handle(request, |method, params| match method {
+            "reset" => {
+                self.thrush = (self.init)();
+
+                Ok(Value::Nil)
+            }
+            method => call(&mut self.thrush, method, params),
+        })
+    }
+}
+
+/// Answer requests read line by line from `reader` with `handle`.
+pub(crate) fn serve_lines<R: BufRead, W: Write>(
+    reader: R,
+    mut writer: W,
+    mut handle: impl FnMut(&str) -> Option<String>,
+) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(response) = handle(&line) {
+            writeln!(writer, "{response}")?;
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Answer the connections to `listener` one at a time, returning once none
+/// are waiting if the listener is non-blocking.
+pub(crate) fn accept(
+    listener: &TcpListener,
+    mut handle: impl FnMut(&str) -> Option<String>,
+) -> io::Result<()> {
+    loop {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        stream.set_nonblocking(false)?;
+
+        // A client disconnecting halfway through shouldn't stop the others.
+        let _ = serve_lines(BufReader::new(stream.try_clone()?), &stream, &mut handle);
+    }
+}
+
+/// Parse a request and answer it with `call`, which is given the method name
+/// and params.
+pub(crate) fn handle(
+    request: &str,
+    call: impl FnOnce(&str, &Map) -> Result<Value, RpcError>,
+) -> Option<String> {
+    let request = match parse(request) {
+        Ok(Value::Map(request)) => request.borrow().clone(),
+        Ok(_) => {
+            return Some(response(
+                &Value::Nil,
+                Err(RpcError::new(INVALID_REQUEST, "expected a request object")),
+            ))
+        }
+        Err(err) => return Some(response(&Value::Nil, Err(RpcError::new(PARSE_ERROR, err)))),
+    };
+
+    let id = request.get("id").cloned();
+    let result = match request.get("method") {
+        Some(Value::String(method)) => {
+            let params = match request.get("params") {
+                Some(Value::Map(params)) => params.borrow().clone(),
+                _ => Map::new(),
+            };
+
+            call(method, &params)
+        }
+        _ => Err(RpcError::new(INVALID_REQUEST, "expected a method name")),
+    };
+
+    id.map(|id| response(&id, result))
+}
+
+/// Call one of the methods that use an existing VM, which are all of them
+/// but `reset`.
+pub(crate) fn call(thrush: &mut Thrush, method: &str, params: &Map) -> Result<Value, RpcError> {
+    match method {
+        "eval" => {
+            let code = string_param(params, "code")?;
+            let report = thrush.exec_report(code);
+
+            // What the script printed still goes where the host sends output.
+            thrush.globals().output().write(&report.stdout);
+
+            report.value.ok_or_else(|| {
+                let message = report
+                    .diagnostics
+                    .last()
+                    .map(|diagnostic| diagnostic.message.clone())
+                    .unwrap_or_default();
+
+                RpcError::new(SCRIPT_ERROR, message)
+            })
+        }
+        "complete" => {
+            let prefix = string_param(params, "prefix")?;
+            let names = thrush.complete(prefix);
+
+            Ok(Value::list(names.into_iter().map(Value::String).collect()))
+        }
+        "inspect" => {
+            let name = string_param(params, "name")?;
+            let globals = thrush.globals();
+
+            if !globals.contains(name) {
+                return Err(RpcError::new(
+                    SCRIPT_ERROR,
+                    format!("no global named '{name}'"),
+                ));
+            }
+
+            let value = globals
+                .get::<Value>(name)
+                .map_err(|err| RpcError::new(SCRIPT_ERROR, err))?;
+
+            Ok(Value::String(inspect(&value, PrintLimits::default())))
+        }
+        method => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("no method named '{method}'"),
+        )),
+    }
+}
+
+fn string_param<'a>(params: &'a Map, name: &str) -> Result<&'a str, RpcError> {
+    match params.get(name) {
+        Some(Value::String(value)) => Ok(value),
+        _ => Err(RpcError::new(
+            INVALID_PARAMS,
+            format!("expected a string '{name}' param"),
+        )),
+    }
+}
+
+fn response(id: &Value, result: Result<Value, RpcError>) -> String {
+    let mut out = format!("{{\"jsonrpc\":\"2.0\",\"id\":{}", stringify(id));
+
+    match result {
+        Ok(value) => {
+            let _ = write!(out, ",\"result\":{}}}", stringify(&value));
+        }
+        Err(RpcError { code, message }) => {
+            let _ = write!(
+                out,
+                ",\"error\":{{\"code\":{code},\"message\":{}}}}}",
+                stringify(&Value::String(message))
+            );
+        }
+    }
+
+    out
+}
+
+/// Write `value` as JSON. Values without a JSON form, such as classes, are
+/// written as the string they print as.
+fn stringify(value: &Value) -> String {
+    let mut out = String::new();
+
+    write_json(&mut out, value);
+
+    out
+}
+
+fn write_json(out: &mut String, value: &Value) {
+    match value {
+        Value::Nil => out.push_str("null"),
+        Value::Bool(v) => {
+            let _ = write!(out, "{v}");
+        }
+        Value::Integer(v) => {
+            let _ = write!(out, "{v}");
+        }
+        Value::Float(v) if v.is_finite() => {
+            let _ = write!(out, "{v:?}");
+        }
+        Value::Float(_) => out.push_str("null"),
+        Value::String(v) => write_string(out, v),
+        Value::List(list) => {
+            out.push('[');
+
+            for (i, item) in list.borrow().iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+
+                write_json(out, item);
+            }
+
+            out.push(']');
+        }
+        Value::Map(map) => {
+            out.push('{');
+
+            for (i, (key, value)) in map.borrow().iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+
+                write_string(out, key);
+                out.push(':');
+                write_json(out, value);
+            }
+
+            out.push('}');
+        }
+        value => write_string(out, &value.to_string()),
+    }
+}
+
+fn write_string(out: &mut String, text: &str) {
+    out.push('"');
+
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+/// Parse JSON into values: objects become maps, and numbers become integers
+/// unless they have a fraction or exponent. Arrays and objects nested more
+/// than [MAX_JSON_DEPTH] deep are an error.
+fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = JsonParser {
+        chars: text.chars(),
+        depth: 0,
+    };
+    let value = parser.value()?;
+
+    parser.skip_whitespace();
+
+    match parser.chars.next() {
+        Some(c) => Err(format!("unexpected '{c}' after JSON value")),
+        None => Ok(value),
+    }
+}
+
+struct JsonParser<'a> {
+    chars: Chars<'a>,
+    /// How many arrays and objects the parser is inside.
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.clone().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .peek()
+            .is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        {
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{expected}', found '{c}'")),
+            None => Err(format!("expected '{expected}', found the end of input")),
+        }
+    }
+
+    fn word(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        if self.chars.as_str().starts_with(word) {
+            self.chars = self.chars.as_str()[word.len()..].chars();
+
+            Ok(value)
+        } else {
+            Err("invalid JSON value".into())
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('{' | '[') if self.depth >= MAX_JSON_DEPTH => Err(format!(
+                "arrays and objects can't be nested more than {MAX_JSON_DEPTH} deep"
+            )),
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => self.string().map(Value::String),
+            Some('t') => self.word("true", Value::Bool(true)),
+            Some('f') => self.word("false", Value::Bool(false)),
+            Some('n') => self.word("null", Value::Nil),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(format!("unexpected '{c}' in JSON")),
+            None => Err("expected a JSON value".into()),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        self.depth += 1;
+
+        let value = parse(self);
+
+        self.depth -= 1;
+
+        value
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        let mut map = Map::new();
+
+        self.expect('{')?;
+        self.skip_whitespace();
+
+        if self.peek() == Some('}') {
+            self.chars.next();
+
+            return Ok(Value::map(map));
+        }
+
+        loop {
+            self.skip_whitespace();
+
+            if self.peek() != Some('"') {
+                return Err("expected a string key".into());
+            }
+
+            let key = self.string()?;
+
+            self.expect(':')?;
+            map.insert(key, self.value()?);
+            self.skip_whitespace();
+
+            match self.chars.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::map(map)),
+                _ => return Err("expected ',' or '}' in object".into()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        let mut items = Vec::new();
+
+        self.expect('[')?;
+        self.skip_whitespace();
+
+        if self.peek() == Some(']') {
+            self.chars.next();
+
+            return Ok(Value::list(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+
+            match self.chars.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::list(items)),
+                _ => return Err("expected ',' or ']' in array".into()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let mut string = String::new();
+
+        self.chars.next();
+
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.chars.next() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('/') => string.push('/'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('u') => string.push(self.unicode_escape()?),
+                    _ => return Err("invalid escape in string".into()),
+                },
+                Some(c) => string.push(c),
+                None => return Err("unterminated string".into()),
+            }
+        }
+    }
+
+    /// Parse the digits of a `\u` escape, joining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex()?;
+
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.chars.as_str().starts_with("\\u") {
+                return Err("unpaired surrogate in string".into());
+            }
+
+            self.chars.next();
+            self.chars.next();
+
+            let low = self.hex()?;
+
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err("unpaired surrogate in string".into());
+            }
+
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+
+        char::from_u32(code).ok_or_else(|| "invalid unicode escape".into())
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits = self.chars.as_str().get(..4).unwrap_or("");
+        let code = u32::from_str_radix(digits, 16).map_err(|_| "invalid unicode escape")?;
+
+        self.chars = self.chars.as_str()[4..].chars();
+
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let rest = self.chars.as_str();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')))
+            .unwrap_or(rest.len());
+        let number = &rest[..len];
+
+        self.chars = rest[len..].chars();
+
+        let value = if number.contains(['.', 'e', 'E']) {
+            number.parse().ok().map(Value::Float)
+        } else {
+            number.parse().ok().map(Value::Integer)
+        };
+
+        value.ok_or_else(|| format!("invalid number '{number}'"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{value::Value, Thrush};
+
+    use super::{parse, stringify, Server};
+
+    #[test]
+    fn test_json() {
+        let value = parse(r#" {"a": [1, -2.5e1, true, null], "bé": "😀\n"} "#).unwrap();
+
+        assert_eq!(value.to_string(), "{a: [1, -25, true, nil], bé: \"😀\\n\"}");
+        assert_eq!(
+            stringify(&value),
+            "{\"a\":[1,-25.0,true,null],\"bé\":\"😀\\n\"}"
+        );
+        assert!(parse("[1,]").is_err());
+        assert!(parse("{} x").is_err());
+        assert!(parse(&format!("{}{}", "[".repeat(256), "]".repeat(256))).is_ok());
+        assert!(parse(&format!("{}{}", "[".repeat(257), "]".repeat(257)))
+            .unwrap_err()
+            .contains("nested more than 256 deep"));
+        assert_eq!(stringify(&Value::Float(f64::NAN)), "null");
+    }
+
+    #[test]
+    fn test_server() {
+        let mut server = Server::new(|| {
+            let mut thrush = Thrush::new();
+            thrush.globals().add("host", true);
+            thrush
+        });
+
+        let input = concat!(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"code": "var score = [1, 2]"}}"#,
+            "\n\n",
+            r#"{"jsonrpc": "2.0", "method": "eval", "params": {"code": "var note = 3"}}"#,
+            "\n",
+            r#"{"jsonrpc": "2.0", "id": "c", "method": "complete", "params": {"prefix": "sc"}}"#,
+            "\n",
+            r#"{"jsonrpc": "2.0", "id": "v", "method": "eval", "params": {"code": "note + 1"}}"#,
+            "\n",
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "inspect", "params": {"name": "score"}}"#,
+            "\n",
+        );
+        let mut output = Vec::new();
+
+        server.serve(input.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                r#"{"jsonrpc":"2.0","id":1,"result":null}"#,
+                "\n",
+                r#"{"jsonrpc":"2.0","id":"c","result":["score"]}"#,
+                "\n",
+                r#"{"jsonrpc":"2.0","id":"v","result":4}"#,
+                "\n",
+                r#"{"jsonrpc":"2.0","id":2,"result":"[\n  1,\n  2,\n]"}"#,
+                "\n",
+            )
+        );
+        assert!(server.thrush().globals().contains("note"));
+
+        let reset = server
+            .handle(r#"{"jsonrpc": "2.0", "id": 3, "method": "reset"}"#)
+            .unwrap();
+
+        assert_eq!(reset, r#"{"jsonrpc":"2.0","id":3,"result":null}"#);
+        assert!(!server.thrush().globals().contains("score"));
+        assert!(server.thrush().globals().contains("host"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut server = Server::new(Thrush::new);
+        let mut error = |request: &str| server.handle(request).unwrap();
+
+        assert!(error("{").contains("\"code\":-32700"));
+        assert!(error(&"[".repeat(100_000)).contains("\"code\":-32700"));
+        assert!(error("[1]").contains("\"code\":-32600"));
+        assert!(error(r#"{"id": 1, "method": "fly"}"#)
+            .contains(r#""error":{"code":-32601,"message":"no method named 'fly'"}"#));
+        assert!(error(r#"{"id": 1, "method": "eval"}"#).contains("\"code\":-32602"));
+        assert!(
+            error(r#"{"id": 1, "method": "inspect", "params": {"name": "ghost"}}"#)
+                .contains("no global named 'ghost'")
+        );
+        assert!(
+            error(r#"{"id": 1, "method": "eval", "params": {"code": "var = 1"}}"#)
+                .contains("\"code\":-32000")
+        );
+        assert!(
+            error(r#"{"id": 1, "method": "eval", "params": {"code": "a $ b"}}"#)
+                .contains("but found '$'")
+        );
+    }
+}
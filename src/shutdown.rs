@@ -0,0 +1,403 @@
+//! Tearing down an interpreter, with [Thrush::shutdown](crate::Thrush::shutdown)
+//! or when it is dropped.
+//!
+//! Shutting down happens in this order:
+//!
+//! 1. Finalizers run, newest first. Scripts register them with
+//!    `on_shutdown(fun)` and hosts with
+//!    [Thrush::add_finalizer](crate::Thrush::add_finalizer). A finalizer
+//!    that fails doesn't stop the others.
+//! 2. Async functions still waiting on a promise are cancelled, rejecting
+//!    their promises.
+//! 3. Every global is removed, and the lists, maps, instances, classes,
+//!    closures and generators that are part of a cycle are emptied, so
+//!    reference counting can free them along with the values they hold.
+//!
+//! A value the host still holds keeps working afterwards, except that if it
+//! was part of a cycle it is now empty.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
+    rc::Rc,
+    vec,
+};
+
+use crate::{
+    scope::State,
+    value::{GeneratorState, Map, Upvalue, Value},
+    vm::Vm,
+};
+
+type HostFinalizer = dyn FnOnce(&mut Vm) -> Result<(), String>;
+
+/// Something to run when the interpreter shuts down.
+enum Finalizer {
+    /// A function registered by a script with `on_shutdown`.
+    Script(Value),
+    /// A closure registered by the host.
+    Host(Box<HostFinalizer>),
+}
+
+/// The finalizers registered so far, oldest first.
+#[derive(Default)]
+pub(crate) struct Finalizers(Vec<Finalizer>);
+
+impl fmt::Debug for Finalizers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Finalizers({})", self.0.len())
+    }
+}
+
+impl Finalizers {
+    pub(crate) fn add_host<F>(&mut self, finalizer: F)
+    where
+        F: FnOnce(&mut Vm) -> Result<(), String> + 'static,
+    {
+        self.0.push(Finalizer::Host(Box::new(finalizer)));
+    }
+}
+
+/// Register the `on_shutdown` global, which adds to `finalizers`.
+pub(crate) fn register(state: &mut State, finalizers: Rc<RefCell<Finalizers>>) {
+    state.add_function("on_shutdown", move |args| match args.as_slice() {
+        [fun] => {
+            finalizers
+                .borrow_mut()
+                .0
+                .push(Finalizer::Script(fun.clone()));
+            Ok(Value::Nil)
+        }
+        _ => Err("on_shutdown expects a function".into()),
+    });
+}
+
+/// Run the finalizers newest first, including any they register, and
+/// return the errors they report.
+pub(crate) fn finalize(vm: &mut Vm, finalizers: &RefCell<Finalizers>) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    loop {
+        // Pop one at a time without holding the borrow, so a finalizer can
+        // register another.
+        let Some(finalizer) = finalizers.borrow_mut().0.pop() else {
+            break;
+        };
+        let result = match finalizer {
+            Finalizer::Script(fun) => vm.call(fun, Vec::new()).map(|_| ()).map_err(|err| err.0),
+            Finalizer::Host(finalizer) => finalizer(vm),
+        };
+
+        if let Err(err) = result {
+            errors.push(err);
+        }
+    }
+
+    errors
+}
+
+/// Empty the containers that are part of a cycle among the values reachable
+/// from `roots`, then release the roots.
+pub(crate) fn break_cycles(roots: Vec<Value>) {
+    let mut finder = Cycles::default();
+
+    for root in &roots {
+        finder.visit(root);
+    }
+
+    for value in finder.cyclic.into_values() {
+        match value {
+            Value::List(list) => list.borrow_mut().clear(),
+            Value::Map(map) => *map.borrow_mut() = Map::new(),
+            Value::Instance(instance) => instance.fields_mut().fill(Value::Nil),
+            Value::Class(class) => class.statics.borrow_mut().clear(),
+            Value::ScriptFun(fun) => {
+                for upvalue in &fun.upvalues {
+                    let mut upvalue = upvalue.borrow_mut();
+
+                    if let Upvalue::Closed(_) = *upvalue {
+                        *upvalue = Upvalue::Closed(Value::Nil);
+                    }
+                }
+            }
+            Value::Generator(generator) => *generator.state.borrow_mut() = GeneratorState::Done,
+            _ => {}
+        }
+    }
+
+    release(roots);
+}
+
+/// Drop `values`, emptying the containers nothing else holds before
+/// dropping them, so freeing deeply nested values can't overflow the stack.
+fn release(mut values: Vec<Value>) {
+    while let Some(value) = values.pop() {
+        match &value {
+            Value::List(list) if Rc::strong_count(list) == 1 => {
+                values.append(&mut list.borrow_mut());
+            }
+            Value::Map(map) if Rc::strong_count(map) == 1 => {
+                let map = std::mem::replace(&mut *map.borrow_mut(), Map::new());
+
+                values.extend(map.iter().map(|(_, value)| value.clone()));
+            }
+            Value::Instance(instance) if Rc::strong_count(instance) == 1 => {
+                values.append(&mut instance.fields_mut());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Finds the containers on cycles with a depth first search, where a cycle
+/// is found whenever a container leads back to one still being searched.
+#[derive(Default)]
+struct Cycles {
+    /// The containers being searched, outermost first.
+    path: Vec<Value>,
+    /// The index in `path` of each container being searched, by address.
+    on_path: HashMap<usize, usize>,
+    /// The containers whose search has finished.
+    done: HashSet<usize>,
+    /// The containers found to be on a cycle, by address.
+    cyclic: HashMap<usize, Value>,
+}
+
+impl Cycles {
+    /// Search the containers reachable from `root`.
+    ///
+    /// The items left to search in each container on the path are kept on
+    /// a stack rather than by recursion, so deeply nested values can't
+    /// overflow it.
+    fn visit(&mut self, root: &Value) {
+        let mut items = Vec::new();
+
+        self.enter(root, &mut items);
+
+        while let Some(remaining) = items.last_mut() {
+            match remaining.next() {
+                Some(item) => self.enter(&item, &mut items),
+                None => {
+                    items.pop();
+
+                    if let Some(address) = self.path.pop().as_ref().and_then(address_of) {
+                        self.on_path.remove(&address);
+                        self.done.insert(address);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Start searching `value`, pushing its items onto `items`, unless it
+    /// isn't a container or has been searched already.
+    fn enter(&mut self, value: &Value, items: &mut Vec<vec::IntoIter<Value>>) {
+        let value = match value {
+            // A bound method leads on to its receiver.
+            Value::Method(method) => Value::Instance(Rc::clone(&method.receiver)),
+            value => value.clone(),
+        };
+        let Some(address) = address_of(&value) else {
+            return;
+        };
+
+        if let Some(&start) = self.on_path.get(&address) {
+            for value in &self.path[start..] {
+                if let Some(address) = address_of(value) {
+                    self.cyclic.insert(address, value.clone());
+                }
+            }
+
+            return;
+        }
+
+        if self.done.contains(&address) {
+            return;
+        }
+
+        self.on_path.insert(address, self.path.len());
+        items.push(contents(&value).into_iter());
+        self.path.push(value);
+    }
+}
+
+/// The address of a container, which identifies it while it is searched.
+fn address_of(value: &Value) -> Option<usize> {
+    Some(match value {
+        Value::List(list) => list.as_ptr() as usize,
+        Value::Map(map) => map.as_ptr() as usize,
+        Value::Instance(instance) => Rc::as_ptr(instance) as usize,
+        Value::Class(class) => Rc::as_ptr(class) as usize,
+        Value::ScriptFun(fun) => Rc::as_ptr(fun) as usize,
+        Value::Generator(generator) => Rc::as_ptr(generator) as usize,
+        _ => return None,
+    })
+}
+
+/// The values a container holds, copied out so they can be searched while
+/// scripts aren't running.
+fn contents(value: &Value) -> Vec<Value> {
+    match value {
+        Value::List(list) => list.borrow().clone(),
+        Value::Map(map) => map.borrow().iter().map(|(_, v)| v.clone()).collect(),
+        Value::Instance(instance) => {
+            let mut items = instance.fields().clone();
+
+            items.push(Value::Class(Rc::clone(&instance.class)));
+            items
+        }
+        Value::Class(class) => class.statics.borrow().values().cloned().collect(),
+        Value::ScriptFun(fun) => closed(&fun.upvalues),
+        Value::Generator(generator) => {
+            let mut items = vec![Value::ScriptFun(Rc::clone(&generator.fun))];
+
+            if let GeneratorState::Suspended {
+                slots, upvalues, ..
+            } = &*generator.state.borrow()
+            {
+                items.extend(slots.iter().cloned());
+                items.extend(closed(upvalues.iter().map(|(_, upvalue)| upvalue)));
+            }
+
+            items
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The values of the upvalues whose variables have gone out of scope.
+fn closed<'a>(upvalues: impl IntoIterator<Item = &'a Rc<RefCell<Upvalue>>>) -> Vec<Value> {
+    upvalues
+        .into_iter()
+        .filter_map(|upvalue| match &*upvalue.borrow() {
+            Upvalue::Closed(value) => Some(value.clone()),
+            Upvalue::Open(_) => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        value::{Map, Promise, PromiseState, Upvalue, Value},
+        Thrush,
+    };
+
+    #[test]
+    fn test_shutdown() {
+        let mut thrush = Thrush::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let host_log = Rc::clone(&log);
+
+        thrush.add_finalizer(move |_| {
+            host_log.borrow_mut().push("host".to_string());
+            Ok(())
+        });
+        thrush.globals().add_function("record", {
+            let log = Rc::clone(&log);
+
+            move |args| {
+                log.borrow_mut().push(args[0].to_string());
+                Ok(Value::Nil)
+            }
+        });
+        thrush
+            .exec("on_shutdown(fun() { record(\"first\") })\non_shutdown(fun() { throw \"failed\" })\non_shutdown(fun() {\n    record(\"last\")\n    on_shutdown(fun() { record(\"nested\") })\n})")
+            .unwrap();
+
+        assert_eq!(thrush.shutdown(), Err("[E0302] failed".into()));
+        assert_eq!(*log.borrow(), ["last", "nested", "first", "host"]);
+    }
+
+    #[test]
+    fn test_shutdown_releases() {
+        let mut thrush = Thrush::new();
+        let waiting = Promise::new();
+
+        thrush
+            .globals()
+            .add("waiting", Value::Promise(Rc::clone(&waiting)));
+        thrush
+            .exec("class Node {\n    var next\n}\nvar a = Node()\nvar b = Node()\na.next = b\nb.next = a\nvar tree = {leaf: [1, 2]}\nasync fun wait() {\n    return await waiting\n}\nvar task = wait()")
+            .unwrap();
+
+        let a = thrush.globals().get::<Value>("a").unwrap();
+        let leaf = Value::list(vec![Value::Integer(1), Value::Integer(2)]);
+        let Ok(Value::Map(tree)) = thrush.globals().get::<Value>("tree") else {
+            panic!("expected a map");
+        };
+        let Ok(Value::Promise(task)) = thrush.globals().get::<Value>("task") else {
+            panic!("expected a promise");
+        };
+        let Value::Instance(node) = &a else {
+            panic!("expected an instance");
+        };
+        let looped = Value::map(Map::new());
+        let Value::Map(entries) = &looped else {
+            unreachable!()
+        };
+
+        entries.borrow_mut().insert("me", looped.clone());
+        thrush.globals().add("looped", looped.clone());
+
+        assert_eq!(thrush.shutdown(), Ok(()));
+        assert!(matches!(task.state(), PromiseState::Rejected(_)));
+        assert_eq!(node.get_field("next"), Some(Value::Nil));
+        assert!(entries.borrow().is_empty());
+        assert_eq!(tree.borrow().get("leaf"), Some(&leaf));
+        assert_eq!(Rc::strong_count(&waiting), 1);
+    }
+
+    #[test]
+    fn test_shutdown_releases_closures() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                r#"fun make() {
+    var me = nil
+    me = fun() { return me }
+    return me
+}
+var looped = make()
+var deep = []
+for i in 0..20000 {
+    deep = [deep]
+}"#,
+            )
+            .unwrap();
+
+        let Ok(Value::ScriptFun(looped)) = thrush.globals().get::<Value>("looped") else {
+            panic!("expected a function");
+        };
+
+        assert_eq!(
+            *looped.upvalues[0].borrow(),
+            Upvalue::Closed(Value::ScriptFun(Rc::clone(&looped)))
+        );
+        assert_eq!(thrush.shutdown(), Ok(()));
+        assert_eq!(*looped.upvalues[0].borrow(), Upvalue::Closed(Value::Nil));
+        assert_eq!(Rc::strong_count(&looped), 1);
+    }
+
+    #[test]
+    fn test_drop_finalizes() {
+        let ran = Rc::new(RefCell::new(false));
+        let mut thrush = Thrush::new();
+
+        thrush.add_finalizer({
+            let ran = Rc::clone(&ran);
+
+            move |_| {
+                *ran.borrow_mut() = true;
+                Err("ignored when dropped".into())
+            }
+        });
+        drop(thrush);
+
+        assert!(*ran.borrow());
+    }
+}
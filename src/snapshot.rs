@@ -0,0 +1,108 @@
+//! Snapshot testing for scripts, exposed as the `assert_snapshot` global.
+//!
+//! `assert_snapshot(name, value)` compares the [inspect](crate::inspect)
+//! dump of `value` with the file `<name>.snap` in the snapshot directory,
+//! which is `tests/snapshots/` by default. A missing snapshot is written
+//! instead, and every snapshot is rewritten when update mode is on, either
+//! through [Snapshots::update] or by setting `THRUSH_UPDATE_SNAPSHOTS=1`.
+//!
+//! Snapshots touch the filesystem, so the global is only defined once a host
+//! calls [Thrush::enable_snapshots](crate::Thrush::enable_snapshots).
+
+use std::{env, fs, path::PathBuf};
+
+use crate::{inspect::inspect, repr::PrintLimits, scope::State, value::Value};
+
+/// Where snapshots are stored, and whether they are being rewritten.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Snapshots {
+    pub dir: PathBuf,
+    pub update: bool,
+}
+
+impl Default for Snapshots {
+    fn default() -> Self {
+        Self::new("tests/snapshots")
+    }
+}
+
+impl Snapshots {
+    /// Store snapshots in `dir`, in update mode if `THRUSH_UPDATE_SNAPSHOTS` is set.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            update: env::var("THRUSH_UPDATE_SNAPSHOTS").is_ok_and(|v| v != "0"),
+        }
+    }
+
+    /// Set whether every snapshot is rewritten rather than compared.
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Compare `value` with the snapshot called `name`, writing it if it is
+    /// missing or in update mode.
+    pub fn assert(&self, name: &str, value: &Value) -> Result<(), String> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.');
+
+        if name.is_empty() || name.starts_with('.') || !name.chars().all(valid) {
+            return Err(format!("invalid snapshot name '{name}'"));
+        }
+
+        let path = self.dir.join(format!("{name}.snap"));
+        let actual = inspect(value, PrintLimits::default()) + "\n";
+
+        match fs::read_to_string(&path) {
+            Ok(expected) if !self.update => {
+                if expected == actual {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "snapshot '{name}' does not match\n--- expected\n{expected}+++ actual\n{actual}"
+                    ))
+                }
+            }
+            _ => fs::create_dir_all(&self.dir)
+                .and_then(|_| fs::write(&path, actual))
+                .map_err(|err| format!("cannot write snapshot '{}': {err}", path.display())),
+        }
+    }
+}
+
+/// Register the `assert_snapshot` global, storing snapshots as `snapshots` says.
+pub fn register(state: &mut State, snapshots: Snapshots) {
+    state.add_function("assert_snapshot", move |args| match args.as_slice() {
+        [Value::String(name), value] => snapshots.assert(name, value).map(|_| Value::Nil),
+        _ => Err("assert_snapshot expects a name and a value".into()),
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process};
+
+    use crate::Thrush;
+
+    use super::Snapshots;
+
+    #[test]
+    fn test_snapshot() {
+        let dir = env::temp_dir().join(format!("thrush-snapshots-{}", process::id()));
+        let mut thrush = Thrush::new();
+
+        thrush.enable_snapshots(Snapshots::new(&dir).update(false));
+
+        thrush.exec("assert_snapshot(\"list\", [1, 2])").unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("list.snap")).unwrap(),
+            "[\n  1,\n  2,\n]\n"
+        );
+
+        thrush.exec("assert_snapshot(\"list\", [1, 2])").unwrap();
+        assert!(thrush.exec("assert_snapshot(\"list\", [2])").is_err());
+        assert!(thrush.exec("assert_snapshot(\"../x\", 1)").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
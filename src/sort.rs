@@ -0,0 +1,256 @@
+//! Sorting and searching lists, exposed as the `sort`, `sorted_by_key`,
+//! `binary_search`, `min_by` and `max_by` globals.
+//!
+//! ```text
+//! sort(scores, fun(a, b) { return b - a })
+//! var names = sorted_by_key(people, fun(person) { return person.name })
+//! var index = binary_search(scores, 42)
+//! var oldest = max_by(people, fun(a, b) { return a.age - b.age })
+//! ```
+//!
+//! A comparator is called with two values and returns a number that is
+//! negative if the first goes before the second, positive if it goes after
+//! and zero if they are equal. Without one, numbers, strings and bools are
+//! compared by their natural order. Sorts are stable, so equal values keep
+//! their order, and an error thrown by a comparator or key function stops
+//! the sort and is raised from the call that started it.
+
+use std::cmp::Ordering;
+
+use crate::{
+    diagnostic::ErrorCode,
+    scope::State,
+    value::Value,
+    vm::{Vm, VmError},
+};
+
+/// Register the sorting and searching globals.
+pub fn register(state: &mut State) {
+    state.add_reentrant_function("sort", |vm, args| match args.as_slice() {
+        [list @ Value::List(items), rest @ ..] if rest.len() < 2 => {
+            // Copy the items out, so a comparator can look at the list while
+            // it is being sorted.
+            let copied = items.borrow().clone();
+            let sorted = merge_sort(copied, &mut |a, b| compare(vm, "sort", rest.first(), a, b))?;
+
+            *items.borrow_mut() = sorted;
+            Ok(list.clone())
+        }
+        _ => Err(error("sort expects a list and an optional comparator")),
+    });
+
+    state.add_reentrant_function("sorted_by_key", |vm, args| match args.as_slice() {
+        [Value::List(items), key] => {
+            let items = items.borrow().clone();
+            let keyed = items
+                .into_iter()
+                .map(|item| Ok((vm.call_from_native(key.clone(), vec![item.clone()])?, item)))
+                .collect::<Result<Vec<_>, VmError>>()?;
+            let sorted = merge_sort(keyed, &mut |(a, _), (b, _)| natural("sorted_by_key", a, b))?;
+
+            Ok(Value::list(
+                sorted.into_iter().map(|(_, item)| item).collect(),
+            ))
+        }
+        _ => Err(error("sorted_by_key expects a list and a key function")),
+    });
+
+    state.add_reentrant_function("binary_search", |vm, args| match args.as_slice() {
+        [Value::List(items), value, rest @ ..] if rest.len() < 2 => {
+            let items = items.borrow().clone();
+            let (mut low, mut high) = (0, items.len());
+
+            while low < high {
+                let middle = low + (high - low) / 2;
+
+                match compare(vm, "binary_search", rest.first(), &items[middle], value)? {
+                    Ordering::Less => low = middle + 1,
+                    Ordering::Greater => high = middle,
+                    Ordering::Equal => return Ok(Value::Integer(middle as i64)),
+                }
+            }
+
+            Ok(Value::Nil)
+        }
+        _ => Err(error(
+            "binary_search expects a sorted list, a value and an optional comparator",
+        )),
+    });
+
+    state.add_reentrant_function("min_by", |vm, args| {
+        extreme(vm, "min_by", &args, Ordering::Less)
+    });
+
+    state.add_reentrant_function("max_by", |vm, args| {
+        extreme(vm, "max_by", &args, Ordering::Greater)
+    });
+}
+
+fn error(message: &str) -> VmError {
+    VmError::new(ErrorCode::Native, message)
+}
+
+/// Find the first item of a list that no other item compares `wanted` to.
+fn extreme(vm: &mut Vm, name: &str, args: &[Value], wanted: Ordering) -> Result<Value, VmError> {
+    let (items, comparator) = match args {
+        [Value::List(items), comparator] => (items.borrow().clone(), comparator),
+        _ => return Err(error(&format!("{name} expects a list and a comparator"))),
+    };
+    let mut items = items.into_iter();
+    let mut best = match items.next() {
+        Some(item) => item,
+        None => return Ok(Value::Nil),
+    };
+
+    for item in items {
+        if compare(vm, name, Some(comparator), &item, &best)? == wanted {
+            best = item;
+        }
+    }
+
+    Ok(best)
+}
+
+/// Compare two values with `comparator`, or by their natural order if there
+/// is none.
+fn compare(
+    vm: &mut Vm,
+    name: &str,
+    comparator: Option<&Value>,
+    a: &Value,
+    b: &Value,
+) -> Result<Ordering, VmError> {
+    let comparator = match comparator {
+        Some(comparator) => comparator.clone(),
+        None => return natural(name, a, b),
+    };
+
+    match vm.call_from_native(comparator, vec![a.clone(), b.clone()])? {
+        Value::Integer(order) => Ok(order.cmp(&0)),
+        Value::Float(order) if !order.is_nan() => Ok(order.total_cmp(&0.0)),
+        value => Err(error(&format!(
+            "the comparator passed to {name} must return a number, found '{value}'"
+        ))),
+    }
+}
+
+/// Compare two numbers, strings or bools.
+fn natural(name: &str, a: &Value, b: &Value) -> Result<Ordering, VmError> {
+    let order = match (a, b) {
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+        (Value::Integer(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+        (Value::Float(a), Value::Integer(b)) => a.partial_cmp(&(*b as f64)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+
+    order.ok_or_else(|| error(&format!("{name} cannot compare '{a}' and '{b}'")))
+}
+
+/// Sort `items` with a comparator that can fail, keeping equal items in
+/// their original order.
+fn merge_sort<T, F>(mut items: Vec<T>, compare: &mut F) -> Result<Vec<T>, VmError>
+where
+    F: FnMut(&T, &T) -> Result<Ordering, VmError>,
+{
+    if items.len() < 2 {
+        return Ok(items);
+    }
+
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, compare)?;
+    let right = merge_sort(right, compare)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Only take from the right when it is strictly smaller, so ties keep
+        // the left item first.
+        let next = if compare(b, a)? == Ordering::Less {
+            right.next()
+        } else {
+            left.next()
+        };
+
+        merged.extend(next);
+    }
+
+    merged.extend(left);
+    merged.extend(right);
+
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{value::Value, Thrush};
+
+    #[test]
+    fn test_sort() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .globals()
+            .add_function("first", |args| match args.as_slice() {
+                [Value::List(items)] => Ok(items.borrow()[0].clone()),
+                _ => Err("first expects a list".into()),
+            });
+
+        thrush
+            .exec("var xs = [3, 1, 2]\nsort(xs, fun(a, b) { return b - a })\nvar asc = sort([2.5, 1, 3])\nvar pairs = sorted_by_key([[2, \"a\"], [1, \"b\"], [2, \"c\"], [1, \"d\"]], first)\nvar stable = sort([\"b\", \"a\", \"c\"], fun(a, b) { return 0 })\nvar found = binary_search([1, 3, 5, 7], 5)\nvar missing = binary_search([1, 3, 5, 7], 4)\nvar low = min_by([4, 2, 8, 2], fun(a, b) { return a - b })\nvar high = max_by([4, 2, 8, 2], fun(a, b) { return a - b })\nvar empty = min_by([], fun(a, b) { return a - b })")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("xs"), "[3, 2, 1]");
+        assert_eq!(global("asc"), "[1, 2.5, 3]");
+        assert_eq!(
+            global("pairs"),
+            "[[1, \"b\"], [1, \"d\"], [2, \"a\"], [2, \"c\"]]"
+        );
+        assert_eq!(global("stable"), "[\"b\", \"a\", \"c\"]");
+        assert_eq!(global("found"), "2");
+        assert_eq!(global("missing"), "nil");
+        assert_eq!(global("low"), "2");
+        assert_eq!(global("high"), "8");
+        assert_eq!(global("empty"), "nil");
+    }
+
+    #[test]
+    fn test_sort_errors() {
+        let mut thrush = Thrush::new();
+
+        assert_eq!(
+            thrush
+                .exec("sort([2, 1], fun(a, b) { throw \"bad comparator\" })")
+                .unwrap_err()
+                .to_string(),
+            "[E0302] line 1:32: bad comparator"
+        );
+        assert!(thrush
+            .exec("sort([2, 1], fun(a, b) { return \"less\" })")
+            .unwrap_err()
+            .to_string()
+            .contains("must return a number, found 'less'"));
+        assert!(thrush
+            .exec("sort([1, \"a\"])")
+            .unwrap_err()
+            .to_string()
+            .contains("sort cannot compare"));
+        assert!(thrush.exec("sort(1)").is_err());
+
+        thrush
+            .exec("var caught = nil\ntry {\n    max_by([1, 2], fun(a, b) { throw \"oops\" })\n} catch (err) {\n    caught = err\n}")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("caught").unwrap(),
+            Value::String("oops".into())
+        );
+        assert_eq!(thrush.exec("var ok = sort([2, 1])"), Ok(()));
+    }
+}
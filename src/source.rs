@@ -0,0 +1,822 @@
+//! Printing an [Ast] back as Thrush source, with [Ast::to_source].
+//!
+//! ```
+//! use thrush::{lexer::Lexer, parser::Parser};
+//!
+//! let ast = Parser::parse_ast(Lexer::tokenize("fun area(w,h){return w*(h+1)}")).unwrap();
+//!
+//! assert_eq!(ast.to_source(), "fun area(w, h) {\n    return w * (h + 1)\n}\n");
+//! ```
+//!
+//! The source is canonical: indented with four spaces, one statement per
+//! line, and with parentheses only where precedence needs them. Parsing it
+//! gives back the same AST, except that the members of a class are printed
+//! in a fixed order. The operands of custom operators are always
+//! parenthesized, since their precedence isn't part of the AST.
+
+use crate::{
+    ast::{Ast, BinOp, Destructure, Expr, ExprId, FunDecl, FunKind, Lit, MatchArm, Pattern, Stmt},
+    lexer, parser,
+    parser::Precedence,
+};
+
+const INDENT: &str = "    ";
+
+impl Ast {
+    /// Print the AST as canonical Thrush source, which parses back to the
+    /// same AST.
+    pub fn to_source(&self) -> String {
+        let mut printer = Printer {
+            ast: self,
+            out: String::new(),
+            indent: 0,
+        };
+
+        for stmt in &self.nodes {
+            printer.stmt(stmt);
+            printer.out.push('\n');
+        }
+
+        printer.out
+    }
+}
+
+struct Printer<'a> {
+    ast: &'a Ast,
+    out: String,
+    /// How many blocks deep the line being printed is.
+    indent: usize,
+}
+
+impl Printer<'_> {
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    /// Start a new line at the current indentation.
+    fn newline(&mut self) {
+        self.out.push('\n');
+
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /// Print `items` separated by commas.
+    fn list<T>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, &T)) {
+        for (index, value) in items.iter().enumerate() {
+            if index > 0 {
+                self.write(", ");
+            }
+            item(self, value);
+        }
+    }
+
+    /// Print `{`, the lines printed by `body` indented, and `}`, or `{}` if
+    /// there are none.
+    fn braces(&mut self, lines: usize, body: impl FnOnce(&mut Self)) {
+        if lines == 0 {
+            return self.write("{}");
+        }
+
+        self.write("{");
+        self.indent += 1;
+        body(self);
+        self.indent -= 1;
+        self.newline();
+        self.write("}");
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.braces(stmts.len(), |printer| printer.stmts(stmts));
+    }
+
+    /// Print each of `stmts` on a new line.
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.newline();
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Fun(decl) => self.fun(decl),
+            Stmt::Class {
+                name,
+                traits,
+                mixins,
+                fields,
+                statics,
+                methods,
+                version,
+                doc,
+                ..
+            } => {
+                self.write(&format!("class {name} "));
+
+                if !traits.is_empty() {
+                    self.write(&format!(": {} ", traits.join(", ")));
+                }
+                if !mixins.is_empty() {
+                    self.write(&format!("with {} ", mixins.join(", ")));
+                }
+
+                let lines = doc.iter().count()
+                    + version.iter().count()
+                    + fields.len()
+                    + statics.len()
+                    + methods.len();
+
+                self.braces(lines, |printer| {
+                    if let Some(doc) = doc {
+                        printer.newline();
+                        printer.write(&quote(doc));
+                    }
+                    if let Some(version) = version {
+                        printer.newline();
+                        printer.write(&format!("version {version}"));
+                    }
+                    for field in fields {
+                        printer.newline();
+                        printer.write(&format!("var {field}"));
+                    }
+                    for field in statics {
+                        let keyword = if field.constant { "const" } else { "var" };
+
+                        printer.newline();
+                        printer.write(&format!("static {keyword} {} = ", field.name));
+                        printer.expr(field.init, Precedence::None);
+                    }
+                    for method in methods {
+                        printer.newline();
+                        printer.fun(method);
+                    }
+                });
+            }
+            Stmt::Trait { name, methods } => {
+                self.write(&format!("trait {name} "));
+                self.braces(methods.len(), |printer| {
+                    for (method, params) in methods {
+                        let params = (1..=*params)
+                            .map(|index| format!("arg{index}"))
+                            .collect::<Vec<_>>();
+
+                        printer.newline();
+                        printer.write(&format!("fun {method}({})", params.join(", ")));
+                    }
+                });
+            }
+            Stmt::VarDecl { id, ty, init } => {
+                self.declaration("var", id, ty.as_deref());
+
+                if self.ast[*init] != Expr::Literal(Lit::Nil) {
+                    self.write(" = ");
+                    self.expr(*init, Precedence::None);
+                }
+            }
+            Stmt::ConstDecl { id, ty, init } | Stmt::LetDecl { id, ty, init } => {
+                let keyword = match stmt {
+                    Stmt::ConstDecl { .. } => "const",
+                    _ => "let",
+                };
+
+                self.declaration(keyword, id, ty.as_deref());
+                self.write(" = ");
+                self.expr(*init, Precedence::None);
+            }
+            Stmt::Import { path, name } => {
+                self.write(&format!("import {}", quote(path)));
+
+                if parser::module_name(path).as_ref() != Some(name) {
+                    self.write(&format!(" as {name}"));
+                }
+            }
+            Stmt::Export(stmt) => {
+                self.write("export ");
+                self.stmt(stmt);
+            }
+            Stmt::Return(value) => self.keyword_value("return", *value),
+            Stmt::Yield(value) => self.keyword_value("yield", *value),
+            Stmt::Throw(value) => {
+                self.write("throw ");
+                self.expr(*value, Precedence::None);
+            }
+            Stmt::Match { value, arms } => {
+                self.write("match ");
+                self.expr(*value, Precedence::None);
+                self.write(" ");
+                self.braces(arms.len(), |printer| {
+                    for MatchArm { pattern, body } in arms {
+                        printer.newline();
+                        printer.pattern(pattern);
+                        printer.write(" => ");
+                        printer.arm_body(body);
+                    }
+                });
+            }
+            Stmt::Switch {
+                value,
+                cases,
+                default,
+            } => {
+                self.write("switch ");
+                self.expr(*value, Precedence::None);
+                self.write(" ");
+                self.braces(cases.len() + default.iter().count(), |printer| {
+                    for case in cases {
+                        printer.newline();
+                        printer.list(&case.values, |printer, value| {
+                            printer.write(&literal(value))
+                        });
+                        printer.write(" => ");
+                        printer.arm_body(&case.body);
+                    }
+                    if let Some(body) = default {
+                        printer.newline();
+                        printer.write("_ => ");
+                        printer.arm_body(body);
+                    }
+                });
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.write("try ");
+                self.block(body);
+
+                if let Some(catch) = catch {
+                    self.write(&format!(" catch ({}", catch.name));
+                    if let Some(class) = &catch.class {
+                        self.write(&format!(": {class}"));
+                    }
+                    self.write(") ");
+                    self.block(&catch.body);
+                }
+                if let Some(finally) = finally {
+                    self.write(" finally ");
+                    self.block(finally);
+                }
+            }
+            Stmt::While { label, cond, body } => {
+                self.label(label.as_deref());
+                self.write("while ");
+                self.expr(*cond, Precedence::None);
+                self.write(" ");
+                self.block(body);
+            }
+            Stmt::For {
+                label,
+                var,
+                iterable,
+                body,
+            } => {
+                self.label(label.as_deref());
+                self.write(&format!("for {var} in "));
+                self.expr(*iterable, Precedence::None);
+                self.write(" ");
+                self.block(body);
+            }
+            Stmt::Break(label) => self.jump("break", label.as_deref()),
+            Stmt::Continue(label) => self.jump("continue", label.as_deref()),
+            Stmt::Host(stmt) => {
+                self.write(&stmt.keyword);
+
+                if !stmt.args.is_empty() {
+                    self.write(" ");
+                    self.list(&stmt.args, |printer, arg| {
+                        printer.expr(*arg, Precedence::None)
+                    });
+                }
+                if let Some(body) = &stmt.body {
+                    self.write(" ");
+                    self.block(body);
+                }
+            }
+            Stmt::Assign { targets, values } => {
+                self.list(targets, |printer, target| {
+                    printer.expr(*target, Precedence::Or)
+                });
+                self.write(" = ");
+                self.list(values, |printer, value| {
+                    printer.expr(*value, Precedence::Or)
+                });
+            }
+            Stmt::Expr(expr) => self.expr(*expr, Precedence::None),
+        }
+    }
+
+    /// Print `keyword id`, and `: ty` if there is a type annotation.
+    fn declaration(&mut self, keyword: &str, id: &str, ty: Option<&str>) {
+        self.write(&format!("{keyword} {id}"));
+
+        if let Some(ty) = ty {
+            self.write(&format!(": {ty}"));
+        }
+    }
+
+    /// Print `return` or `yield`, leaving out a nil value.
+    fn keyword_value(&mut self, keyword: &str, value: ExprId) {
+        self.write(keyword);
+
+        if self.ast[value] != Expr::Literal(Lit::Nil) {
+            self.write(" ");
+            self.expr(value, Precedence::None);
+        }
+    }
+
+    fn label(&mut self, label: Option<&str>) {
+        if let Some(label) = label {
+            self.write(&format!("{label}: "));
+        }
+    }
+
+    fn jump(&mut self, keyword: &str, label: Option<&str>) {
+        self.write(keyword);
+
+        if let Some(label) = label {
+            self.write(&format!(" {label}"));
+        }
+    }
+
+    /// Print the body of a match arm or switch case, which is a block unless
+    /// it is a single expression or return.
+    fn arm_body(&mut self, body: &[Stmt]) {
+        match body {
+            // A body starting with `{` is read as a block.
+            [Stmt::Expr(expr)] if !self.starts_with_brace(*expr) => {
+                self.expr(*expr, Precedence::None)
+            }
+            [stmt @ Stmt::Return(_)] => self.stmt(stmt),
+            _ => self.block(body),
+        }
+    }
+
+    fn starts_with_brace(&self, mut id: ExprId) -> bool {
+        loop {
+            id = match &self.ast[id] {
+                Expr::Map(_) | Expr::Block(_) => return true,
+                Expr::BinExpr { left: id, .. }
+                | Expr::Call { callee: id, .. }
+                | Expr::Dot { object: id, .. }
+                | Expr::Assign { target: id, .. }
+                | Expr::Increment {
+                    target: id,
+                    postfix: true,
+                    ..
+                }
+                | Expr::Is { value: id, .. }
+                | Expr::Range { start: id, .. } => *id,
+                _ => return false,
+            };
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard => self.write("_"),
+            Pattern::Binding(name) => self.write(name),
+            Pattern::Literal(lit) => self.write(&literal(lit)),
+            Pattern::Class { name, fields } => {
+                self.write(&format!("{name}("));
+                self.list(fields, Printer::pattern);
+                self.write(")");
+            }
+        }
+    }
+
+    /// Print a function or method declaration, with its decorators on the
+    /// lines before it.
+    fn fun(&mut self, decl: &FunDecl) {
+        for decorator in &decl.decorators {
+            self.write("@");
+            self.expr(*decorator, Precedence::None);
+            self.newline();
+        }
+
+        let keyword = match decl.kind {
+            FunKind::Function | FunKind::Method => "",
+            FunKind::Static => "static ",
+            FunKind::Getter => "get ",
+            FunKind::Setter => "set ",
+        };
+
+        self.write(keyword);
+
+        if decl.is_async {
+            self.write("async ");
+        }
+        if !matches!(decl.kind, FunKind::Getter | FunKind::Setter) {
+            self.write("fun ");
+        }
+
+        self.write(&decl.name);
+        self.signature(decl);
+    }
+
+    /// Print the parameters, return type and body of a function.
+    fn signature(&mut self, decl: &FunDecl) {
+        self.write("(");
+
+        for (index, param) in decl.params.iter().enumerate() {
+            if index > 0 {
+                self.write(", ");
+            }
+            if decl.variadic && index + 1 == decl.params.len() {
+                self.write("...");
+            }
+
+            match decl.unpack.iter().find(|(at, _)| *at == index) {
+                Some((_, Destructure::Items(names))) => {
+                    self.write(&format!("({})", names.join(", ")))
+                }
+                Some((_, Destructure::Keys(names))) => {
+                    self.write(&format!("{{{}}}", names.join(", ")))
+                }
+                None => self.write(param),
+            }
+
+            if let Some(Some(ty)) = decl.param_types.get(index) {
+                self.write(&format!(": {ty}"));
+            }
+        }
+
+        self.write(")");
+
+        if let Some(returns) = &decl.returns {
+            self.write(&format!(" -> {returns}"));
+        }
+
+        self.write(" ");
+        self.braces(decl.doc.iter().count() + decl.body.len(), |printer| {
+            if let Some(doc) = &decl.doc {
+                printer.newline();
+                printer.write(&quote(doc));
+            }
+            printer.stmts(&decl.body);
+        });
+    }
+
+    /// Print the expression `id`, in parentheses if it binds more loosely
+    /// than `prec`.
+    ///
+    /// Operands are printed from a work list rather than by recursion, so a
+    /// long chain of operators can't overflow the stack.
+    fn expr(&mut self, id: ExprId, prec: Precedence) {
+        let mut work = vec![Piece::Expr(id, prec)];
+
+        while let Some(piece) = work.pop() {
+            match piece {
+                Piece::Text(text) => self.write(&text),
+                Piece::Compound(id) => self.compound(id),
+                Piece::Expr(id, prec) => {
+                    let parenthesize = precedence(&self.ast[id]) < prec;
+                    let mut pieces = Vec::new();
+
+                    if parenthesize {
+                        pieces.push(text("("));
+                    }
+
+                    self.pieces(id, &mut pieces);
+
+                    if parenthesize {
+                        pieces.push(text(")"));
+                    }
+
+                    work.extend(pieces.into_iter().rev());
+                }
+            }
+        }
+    }
+
+    /// Add the pieces the expression `id` is printed as to `out`.
+    fn pieces(&self, id: ExprId, out: &mut Vec<Piece>) {
+        let ast = self.ast;
+        // Print `items` separated by commas.
+        let list = |out: &mut Vec<Piece>, items: &[ExprId]| {
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(text(", "));
+                }
+                out.push(Piece::Expr(*item, Precedence::None));
+            }
+        };
+
+        match &ast[id] {
+            Expr::Identifier(name) => out.push(text(name)),
+            Expr::Slf => out.push(text("self")),
+            Expr::Literal(lit) => out.push(text(literal(lit))),
+            Expr::BinExpr { left, right, op } => {
+                let (left_prec, right_prec) = match (op, precedence(&ast[id])) {
+                    (BinOp::Custom(_), _) => (Precedence::Primary, Precedence::Primary),
+                    (_, prec) => (prec, prec.left()),
+                };
+
+                out.push(Piece::Expr(*left, left_prec));
+                out.push(text(format!(" {} ", op.into_string())));
+                out.push(Piece::Expr(*right, right_prec));
+            }
+            Expr::UnaryExpr { value, op } => {
+                out.push(text(op.into_string()));
+
+                // `- -x` would be read as `--x`.
+                match ast[*value] {
+                    Expr::UnaryExpr { .. } | Expr::Increment { postfix: false, .. } => {
+                        out.push(Piece::Expr(*value, Precedence::Call))
+                    }
+                    _ => out.push(Piece::Expr(*value, Precedence::Unary)),
+                }
+            }
+            Expr::TypeOf(value) => {
+                out.push(text("typeof "));
+                out.push(Piece::Expr(*value, Precedence::Unary));
+            }
+            Expr::Await(value) => {
+                out.push(text("await "));
+                out.push(Piece::Expr(*value, Precedence::Unary));
+            }
+            Expr::Call {
+                callee,
+                args,
+                named,
+            } => {
+                out.push(Piece::Expr(*callee, Precedence::Call));
+                out.push(text("("));
+                list(out, args);
+
+                for (index, (name, arg)) in named.iter().enumerate() {
+                    if index > 0 || !args.is_empty() {
+                        out.push(text(", "));
+                    }
+                    out.push(text(format!("{name}: ")));
+                    out.push(Piece::Expr(*arg, Precedence::None));
+                }
+
+                out.push(text(")"));
+            }
+            Expr::Dot { object, property } => {
+                out.push(Piece::Expr(*object, Precedence::Call));
+                out.push(text("."));
+                out.push(Piece::Expr(*property, Precedence::Primary));
+            }
+            Expr::List(items) => {
+                out.push(text("["));
+                list(out, items);
+                out.push(text("]"));
+            }
+            Expr::Map(entries) => {
+                out.push(text("{"));
+
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        out.push(text(", "));
+                    }
+                    // The entries of a spread map have an empty key.
+                    if !matches!(ast[*value], Expr::Spread(_)) {
+                        out.push(text(format!("{}: ", map_key(key))));
+                    }
+                    out.push(Piece::Expr(*value, Precedence::None));
+                }
+
+                out.push(text("}"));
+            }
+            Expr::Spread(value) => {
+                out.push(text("..."));
+                out.push(Piece::Expr(*value, Precedence::None));
+            }
+            Expr::Assign { target, value } => {
+                out.push(Piece::Expr(*target, Precedence::Call));
+                out.push(text(" = "));
+                out.push(Piece::Expr(*value, Precedence::None));
+            }
+            Expr::Increment {
+                target,
+                op,
+                postfix,
+            } => {
+                let symbol = match op {
+                    BinOp::Add => "++",
+                    _ => "--",
+                };
+
+                if *postfix {
+                    out.push(Piece::Expr(*target, Precedence::Call));
+                    out.push(text(symbol));
+                } else {
+                    out.push(text(symbol));
+                    out.push(Piece::Expr(*target, Precedence::Call));
+                }
+            }
+            Expr::Is { value, class } => {
+                out.push(Piece::Expr(*value, Precedence::Comparison));
+                out.push(text(" is "));
+                out.push(Piece::Expr(*class, Precedence::Comparison.left()));
+            }
+            Expr::Range { start, end, step } => {
+                out.push(Piece::Expr(*start, Precedence::Range));
+                out.push(text(".."));
+                out.push(Piece::Expr(*end, Precedence::Range.left()));
+
+                if let Some(step) = step {
+                    out.push(text(" step "));
+                    out.push(Piece::Expr(*step, Precedence::Range.left()));
+                }
+            }
+            Expr::Template { tag, parts, values } => {
+                out.push(text(format!("{tag}\"")));
+
+                for (index, part) in parts.iter().enumerate() {
+                    out.push(text(escape(part).replace("${", "\\${")));
+
+                    if let Some(value) = values.get(index) {
+                        out.push(text("${"));
+                        out.push(Piece::Expr(*value, Precedence::None));
+                        out.push(text("}"));
+                    }
+                }
+
+                out.push(text("\""));
+            }
+            Expr::Function(_) | Expr::If { .. } | Expr::Block(_) | Expr::Object { .. } => {
+                out.push(Piece::Compound(id))
+            }
+        }
+    }
+
+    /// Print an expression containing statements, which is only nested as
+    /// deep as the blocks in the script.
+    fn compound(&mut self, id: ExprId) {
+        let ast = self.ast;
+
+        match &ast[id] {
+            Expr::Function(decl) => {
+                self.write("fun");
+                self.signature(decl);
+            }
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.write("if ");
+                self.expr(*cond, Precedence::None);
+                self.write(" ");
+                self.block(then);
+
+                match otherwise.as_deref() {
+                    Some([Stmt::Expr(elif)]) if matches!(ast[*elif], Expr::If { .. }) => {
+                        self.write(" else ");
+                        self.expr(*elif, Precedence::None);
+                    }
+                    Some(otherwise) => {
+                        self.write(" else ");
+                        self.block(otherwise);
+                    }
+                    None => {}
+                }
+            }
+            Expr::Block(stmts) => self.block(stmts),
+            Expr::Object { fields, methods } => {
+                self.write("object ");
+                self.braces(fields.len() + methods.len(), |printer| {
+                    for (name, init) in fields {
+                        printer.newline();
+                        printer.write(&format!("var {name}"));
+
+                        if ast[*init] != Expr::Literal(Lit::Nil) {
+                            printer.write(" = ");
+                            printer.expr(*init, Precedence::None);
+                        }
+                    }
+                    for method in methods {
+                        printer.newline();
+                        printer.fun(method);
+                    }
+                });
+            }
+            _ => self.expr(id, Precedence::None),
+        }
+    }
+}
+
+/// A part of an expression still to be printed.
+enum Piece {
+    Text(String),
+    /// An operand, in parentheses if it binds more loosely than the
+    /// precedence.
+    Expr(ExprId, Precedence),
+    /// An expression containing statements.
+    Compound(ExprId),
+}
+
+fn text(text: impl Into<String>) -> Piece {
+    Piece::Text(text.into())
+}
+
+/// How tightly `expr` binds, where custom operators are taken to bind as
+/// loosely as any operator can.
+fn precedence(expr: &Expr) -> Precedence {
+    match expr {
+        Expr::Assign { .. } => Precedence::Assignment,
+        Expr::BinExpr { op, .. } => match op {
+            BinOp::Add | BinOp::Sub => Precedence::Sum,
+            BinOp::Mul | BinOp::Div | BinOp::Rem => Precedence::Term,
+            BinOp::Less | BinOp::LessEqual | BinOp::Greater | BinOp::GreaterEqual => {
+                Precedence::Comparison
+            }
+            BinOp::Equal | BinOp::NotEqual => Precedence::Equality,
+            BinOp::And => Precedence::And,
+            BinOp::Or => Precedence::Or,
+            BinOp::Bang | BinOp::Custom(_) => Precedence::Assignment,
+        },
+        Expr::Is { .. } => Precedence::Comparison,
+        Expr::Range { .. } => Precedence::Range,
+        Expr::UnaryExpr { .. }
+        | Expr::TypeOf(_)
+        | Expr::Await(_)
+        | Expr::Increment { postfix: false, .. } => Precedence::Unary,
+        Expr::Call { .. } | Expr::Dot { .. } | Expr::Increment { postfix: true, .. } => {
+            Precedence::Call
+        }
+        _ => Precedence::Primary,
+    }
+}
+
+fn literal(lit: &Lit) -> String {
+    match lit {
+        Lit::String(string) => quote(string),
+        // There are no character literals, so a character is written as a
+        // string.
+        Lit::Char(char) => quote(&char.to_string()),
+        Lit::Integer(int) => int.to_string(),
+        Lit::Float(float) => {
+            let float = float.to_string();
+
+            match float.contains('.') {
+                true => float,
+                false => format!("{float}.0"),
+            }
+        }
+        Lit::Bool(bool) => bool.to_string(),
+        Lit::Nil => "nil".into(),
+    }
+}
+
+/// A map key, which is written as a name if it can be.
+fn map_key(key: &str) -> String {
+    match lexer::is_identifier(key) && lexer::keyword(key).is_none() {
+        true => key.to_string(),
+        false => quote(key),
+    }
+}
+
+fn quote(string: &str) -> String {
+    format!("\"{}\"", escape(string))
+}
+
+/// Escape the characters of `string` that can't be written as they are
+/// between quotes.
+fn escape(string: &str) -> String {
+    string
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn parse(source: &str) -> crate::ast::Ast {
+        Parser::parse_ast(Lexer::tokenize(source)).unwrap()
+    }
+
+    #[test]
+    fn test_to_source() {
+        let source = "import \"math/vec\"\nimport \"util.th\" as helpers\n@memoize\nasync fun load(path: String, (x, y), {host}, ...rest) -> List {\n    \"Load the file at path.\"\n    var items = [1, 2.5, ...rest]\n    const total: Int = (x + y) * 2 - -x\n    let found = {name: \"a \\\"b\\\"\\n\", \"two words\": nil, ...items}\n    a, b = b, a\n    if x is Point {\n        return\n    } else if y {\n        yield f(x, named: 1).field\n    } else {\n        throw typeof await x\n    }\n}\nclass Point : Shape with Debug {\n    \"A point.\"\n    version 2\n    var x\n    static const ORIGIN = 0\n    get length() {}\n    static fun of(v) {\n        return self\n    }\n}\ntrait Shape {\n    fun area(arg1, arg2)\n}\nouter: for i in 0..n + 1 step 2 {\n    while true {\n        break outer\n    }\n}\nmatch p {\n    Point(0, _) => \"origin\"\n    -1 => return x\n    _ => {\n        {a: 1}\n    }\n}\nswitch n {\n    1, \"two\" => x = 1\n    _ => {}\n}\ntry {\n    lex()\n} catch (e: ParseError) {} finally {\n    var k = object {\n        var v = fun(a) {\n            return a % 2\n        }\n    }\n}\nexport var greeting = sql\"WHERE id = ${id} AND \\${literal}\"\n";
+
+        assert_eq!(parse(source).to_source(), source);
+        assert_eq!(parse(&parse(source).to_source()), parse(source));
+    }
+
+    #[test]
+    fn test_canonical_source() {
+        let source = "fun f( a,b ){ return (a*b)+(1) }\nvar x=nil\nx++\n-(--x)\nvar y = (a .. b)..c\nvar z = -(-(1))\nvar w = (a || (b && (c != (d >= e)))) && (f || g)";
+
+        assert_eq!(
+            parse(source).to_source(),
+            "fun f(a, b) {\n    return a * b + 1\n}\nvar x\nx++\n-(--x)\nvar y = a..b..c\nvar z = -(-1)\nvar w = (a || b && c != d >= e) && (f || g)\n"
+        );
+    }
+
+    #[test]
+    fn test_long_source() {
+        // A long chain of operators is printed without recursion.
+        let sum = vec!["x"; 20_000].join(" + ");
+
+        assert_eq!(parse(&sum).to_source(), sum + "\n");
+    }
+}
@@ -0,0 +1,246 @@
+//! A small test framework for scripts, built on the `describe` and `it`
+//! globals.
+//!
+//! ```text
+//! describe("math", fun() {
+//!     it("adds", fun() {
+//!         assert_snapshot("sum", add(1, 2))
+//!     })
+//! })
+//! ```
+//!
+//! `describe` and `it` only record the functions they are given. Once the
+//! script has run, [Thrush::run_tests](crate::Thrush::run_tests) calls each
+//! `describe` function to collect the tests and suites inside it, then each
+//! test, and returns a [TestReport] of which ones threw. `thrush test
+//! <file>` runs a file's tests and prints the report.
+
+use std::{cell::RefCell, fmt, mem, rc::Rc};
+
+use crate::{
+    scope::State,
+    value::Value,
+    vm::{Vm, VmError},
+};
+
+/// The suites and tests declared by the function running, in order.
+#[derive(Debug, Default)]
+pub(crate) struct Declared {
+    suites: Vec<(String, Value)>,
+    tests: Vec<(String, Value)>,
+}
+
+/// The result of a test declared with `it`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Test {
+    pub name: String,
+    /// The error the test threw, if it failed.
+    pub error: Option<String>,
+}
+
+/// The results of a suite declared with `describe`.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Suite {
+    pub name: String,
+    /// The error the suite's function threw while declaring its tests, if
+    /// any.
+    pub error: Option<String>,
+    pub tests: Vec<Test>,
+    pub suites: Vec<Suite>,
+}
+
+/// Count the tests that passed or, if `failed` is true, failed.
+fn count(tests: &[Test], suites: &[Suite], failed: bool) -> usize {
+    let own = tests
+        .iter()
+        .filter(|test| test.error.is_some() == failed)
+        .count();
+    let nested = suites
+        .iter()
+        .map(|suite| {
+            let error = usize::from(failed && suite.error.is_some());
+
+            error + count(&suite.tests, &suite.suites, failed)
+        })
+        .sum::<usize>();
+
+    own + nested
+}
+
+impl Suite {
+    fn write(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+
+        writeln!(f, "{indent}{}", self.name)?;
+
+        if let Some(error) = &self.error {
+            writeln!(f, "{indent}  error: {error}")?;
+        }
+
+        write_tests(f, &self.tests, depth + 1)?;
+
+        for suite in &self.suites {
+            suite.write(f, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The results of every test a script declared.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct TestReport {
+    /// The tests declared outside of any suite.
+    pub tests: Vec<Test>,
+    pub suites: Vec<Suite>,
+}
+
+impl TestReport {
+    /// The number of tests that passed.
+    pub fn passed(&self) -> usize {
+        count(&self.tests, &self.suites, false)
+    }
+
+    /// The number of tests that failed, counting a suite that threw while
+    /// declaring its tests as a failure.
+    pub fn failed(&self) -> usize {
+        count(&self.tests, &self.suites, true)
+    }
+
+    /// Whether every test passed.
+    pub fn is_ok(&self) -> bool {
+        self.failed() == 0
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_tests(f, &self.tests, 0)?;
+
+        for suite in &self.suites {
+            suite.write(f, 0)?;
+        }
+
+        writeln!(f, "\n{} passed, {} failed", self.passed(), self.failed())
+    }
+}
+
+fn write_tests(f: &mut fmt::Formatter<'_>, tests: &[Test], depth: usize) -> fmt::Result {
+    let indent = "  ".repeat(depth);
+
+    for test in tests {
+        match &test.error {
+            None => writeln!(f, "{indent}{} ... ok", test.name)?,
+            Some(error) => writeln!(f, "{indent}{} ... FAILED\n{indent}  {error}", test.name)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Register the `describe` and `it` globals, which add to `declared`.
+pub(crate) fn register(state: &mut State, declared: Rc<RefCell<Declared>>) {
+    let suites = Rc::clone(&declared);
+
+    state.add_function("describe", move |args| match args.as_slice() {
+        [Value::String(name), fun] => {
+            suites.borrow_mut().suites.push((name.clone(), fun.clone()));
+            Ok(Value::Nil)
+        }
+        _ => Err("describe expects a name and a function".into()),
+    });
+
+    state.add_function("it", move |args| match args.as_slice() {
+        [Value::String(name), fun] => {
+            declared
+                .borrow_mut()
+                .tests
+                .push((name.clone(), fun.clone()));
+            Ok(Value::Nil)
+        }
+        _ => Err("it expects a name and a function".into()),
+    });
+}
+
+/// Run the suites and tests declared so far, and the ones they declare.
+pub(crate) fn run(vm: &mut Vm, declared: &RefCell<Declared>) -> TestReport {
+    let Declared { suites, tests } = mem::take(&mut *declared.borrow_mut());
+
+    TestReport {
+        tests: run_tests(vm, declared, tests),
+        suites: suites
+            .into_iter()
+            .map(|(name, fun)| run_suite(vm, declared, name, fun))
+            .collect(),
+    }
+}
+
+fn run_suite(vm: &mut Vm, declared: &RefCell<Declared>, name: String, fun: Value) -> Suite {
+    let error = call(vm, declared, fun).err();
+    let Declared { suites, tests } = mem::take(&mut *declared.borrow_mut());
+
+    Suite {
+        name,
+        error,
+        tests: run_tests(vm, declared, tests),
+        suites: suites
+            .into_iter()
+            .map(|(name, fun)| run_suite(vm, declared, name, fun))
+            .collect(),
+    }
+}
+
+fn run_tests(vm: &mut Vm, declared: &RefCell<Declared>, tests: Vec<(String, Value)>) -> Vec<Test> {
+    tests
+        .into_iter()
+        .map(|(name, fun)| {
+            let error = call(vm, declared, fun).err();
+
+            // Suites and tests can only be declared inside `describe`.
+            *declared.borrow_mut() = Declared::default();
+
+            Test { name, error }
+        })
+        .collect()
+}
+
+fn call(vm: &mut Vm, declared: &RefCell<Declared>, fun: Value) -> Result<(), String> {
+    *declared.borrow_mut() = Declared::default();
+
+    vm.call(fun, Vec::new())
+        .map(|_| ())
+        .map_err(|VmError(err)| err)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Thrush;
+
+    #[test]
+    fn test_suite() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "fun add(a, b) { return a + b }\nit(\"runs\", fun() {})\ndescribe(\"math\", fun() {\n    it(\"adds\", fun() { add(1, 2) })\n    it(\"fails\", fun() { add(1, \"2\") })\n    describe(\"nested\", fun() {\n        it(\"throws\", fun() { throw \"oops\" })\n    })\n})\ndescribe(\"broken\", fun() { add() })",
+            )
+            .unwrap();
+
+        let report = thrush.run_tests();
+
+        assert_eq!((report.passed(), report.failed()), (2, 3));
+        assert_eq!(report.suites[0].tests[1].name, "fails");
+        assert!(report.suites[0].tests[1]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("unsupported operand types"));
+        assert_eq!(report.suites[0].suites[0].name, "nested");
+        assert!(report.suites[1].error.is_some());
+        assert!(report
+            .to_string()
+            .starts_with("runs ... ok\nmath\n  adds ... ok\n  fails ... FAILED\n"));
+        assert!(report.to_string().ends_with("\n2 passed, 3 failed\n"));
+        assert_eq!(thrush.run_tests().passed(), 0);
+    }
+}
@@ -1,3 +1,7 @@
+use std::fmt;
+
+use crate::lexer::{keyword, KEYWORDS};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Lit {
     Integer(i64),
@@ -11,6 +15,33 @@ pub enum Keyword {
     Fun,
     Var,
     Slf,
+    Static,
+    Return,
+    True,
+    False,
+    Nil,
+    Match,
+    Switch,
+    Const,
+    Import,
+    Export,
+    Try,
+    Catch,
+    Finally,
+    Throw,
+    While,
+    Break,
+    Continue,
+    Typeof,
+    Is,
+    For,
+    In,
+    Yield,
+    Async,
+    Await,
+    Let,
+    If,
+    Else,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -20,8 +51,12 @@ pub struct Symbol {}
 pub enum TokenKind {
     /// +
     Plus,
+    /// ++
+    Increment,
     /// -
     Hypen,
+    /// --
+    Decrement,
     /// *
     Star,
     /// /
@@ -30,14 +65,44 @@ pub enum TokenKind {
     Modulo,
     /// .
     Dot,
+    /// ..
+    DotDot,
+    /// ...
+    Ellipsis,
     /// ~
     Tilde,
     /// !
     Bang,
     /// ,
     Comma,
+    /// :
+    Colon,
+    /// ;
+    Semicolon,
     /// =
     Assign,
+    /// =>
+    FatArrow,
+    /// ->
+    Arrow,
+    /// @
+    At,
+    /// <
+    Less,
+    /// <=
+    LessEqual,
+    /// >
+    Greater,
+    /// >=
+    GreaterEqual,
+    /// ==
+    Equal,
+    /// !=
+    NotEqual,
+    /// &&
+    And,
+    /// ||
+    Or,
 
     /// (
     LParen,
@@ -55,6 +120,21 @@ pub enum TokenKind {
     Literal(Lit),
     Ident(Box<str>),
     Keyword(Keyword),
+    /// A custom infix operator registered by the embedder.
+    Operator(Box<str>),
+    /// The tag of a tagged template, written right before its opening `"`.
+    Tag(Box<str>),
+    /// A segment of a tagged template that is followed by an interpolation.
+    TemplatePart(String),
+    /// The last segment of a tagged template.
+    TemplateEnd(String),
+    /// A character that doesn't start any token, which the parser reports
+    /// as an error wherever it is found.
+    Unknown(char),
+    /// A `// ...` comment, with the text after the `//`, which is only
+    /// produced by a lexer made with
+    /// [Lexer::with_comments](crate::lexer::Lexer::with_comments).
+    Comment(String),
 
     /// /n
     Newline,
@@ -63,19 +143,101 @@ pub enum TokenKind {
     Eof,
 }
 
+impl fmt::Display for TokenKind {
+    /// Write the token as it is spelled in scripts, or describe it if it
+    /// isn't spelled out.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            TokenKind::Plus => "+",
+            TokenKind::Increment => "++",
+            TokenKind::Hypen => "-",
+            TokenKind::Decrement => "--",
+            TokenKind::Star => "*",
+            TokenKind::BackSlash => "/",
+            TokenKind::Modulo => "%",
+            TokenKind::Dot => ".",
+            TokenKind::DotDot => "..",
+            TokenKind::Ellipsis => "...",
+            TokenKind::Tilde => "~",
+            TokenKind::Bang => "!",
+            TokenKind::Comma => ",",
+            TokenKind::Colon => ":",
+            TokenKind::Semicolon => ";",
+            TokenKind::Assign => "=",
+            TokenKind::FatArrow => "=>",
+            TokenKind::Arrow => "->",
+            TokenKind::At => "@",
+            TokenKind::Less => "<",
+            TokenKind::LessEqual => "<=",
+            TokenKind::Greater => ">",
+            TokenKind::GreaterEqual => ">=",
+            TokenKind::Equal => "==",
+            TokenKind::NotEqual => "!=",
+            TokenKind::And => "&&",
+            TokenKind::Or => "||",
+            TokenKind::LParen => "(",
+            TokenKind::RParen => ")",
+            TokenKind::LBracket => "[",
+            TokenKind::RBracket => "]",
+            TokenKind::LBrace => "{",
+            TokenKind::RBrace => "}",
+            TokenKind::Literal(Lit::Integer(int)) => return write!(f, "'{int}'"),
+            TokenKind::Literal(Lit::Float(float)) => return write!(f, "'{float}'"),
+            TokenKind::Literal(Lit::String(string)) => return write!(f, "'\"{string}\"'"),
+            TokenKind::Ident(name) | TokenKind::Operator(name) => return write!(f, "'{name}'"),
+            TokenKind::Keyword(kw) => KEYWORDS
+                .iter()
+                .find(|word| keyword(word).as_ref() == Some(kw))
+                .expect("every keyword is spelled in KEYWORDS"),
+            TokenKind::Tag(tag) => return write!(f, "'{tag}\"'"),
+            TokenKind::Unknown(c) => return write!(f, "'{c}'"),
+            TokenKind::TemplatePart(_) | TokenKind::TemplateEnd(_) => {
+                return write!(f, "template text")
+            }
+            TokenKind::Comment(_) => return write!(f, "a comment"),
+            TokenKind::Newline => return write!(f, "a newline"),
+            TokenKind::Eof => return write!(f, "the end of the script"),
+        };
+
+        write!(f, "'{symbol}'")
+    }
+}
+
+/// Where a token starts in a script, counting lines and columns from 1.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}:{}", self.line, self.column)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub kind: TokenKind,
+    /// Where the token starts, which is left at its default for tokens
+    /// that weren't read from a script.
+    pub span: Span,
 }
 
 impl Token {
     pub fn new(kind: TokenKind) -> Self {
-        Self { kind }
+        Self {
+            kind,
+            span: Span::default(),
+        }
     }
 
     pub fn literal(lit: Lit) -> Self {
-        Self {
-            kind: TokenKind::Literal(lit),
-        }
+        Self::new(TokenKind::Literal(lit))
+    }
+
+    /// Give the token the position it was read from.
+    pub fn at(self, span: Span) -> Self {
+        Self { span, ..self }
     }
 }
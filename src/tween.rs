@@ -0,0 +1,305 @@
+//! Property tweening driven by [Thrush::update](crate::Thrush::update).
+//!
+//! Scripts start a tween with `tween.to(obj, "x", target, duration, easing)`,
+//! and each call to `update(dt)` moves the property towards its target. The
+//! property is read and written like `obj.x` in a script, so getters and
+//! setters run.
+
+use std::{cell::RefCell, f64::consts::PI, mem, rc::Rc};
+
+use crate::{
+    diagnostic::ErrorCode,
+    scope::State,
+    value::{Class, Instance, Value},
+    vm::{Vm, VmError},
+};
+
+/// An easing function mapping progress in `[0, 1]` to an eased progress.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+}
+
+impl Easing {
+    /// Look up an easing function by its script name, e.g. `"quad_in_out"`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let easing = match name {
+            "linear" => Easing::Linear,
+            "quad_in" => Easing::QuadIn,
+            "quad_out" => Easing::QuadOut,
+            "quad_in_out" => Easing::QuadInOut,
+            "cubic_in" => Easing::CubicIn,
+            "cubic_out" => Easing::CubicOut,
+            "cubic_in_out" => Easing::CubicInOut,
+            "sine_in" => Easing::SineIn,
+            "sine_out" => Easing::SineOut,
+            "sine_in_out" => Easing::SineInOut,
+            _ => return None,
+        };
+
+        Some(easing)
+    }
+
+    /// Apply the easing function to `t`, which is clamped to `[0, 1]`.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::SineIn => 1.0 - (t * PI / 2.0).cos(),
+            Easing::SineOut => (t * PI / 2.0).sin(),
+            Easing::SineInOut => -((t * PI).cos() - 1.0) / 2.0,
+        }
+    }
+}
+
+/// A single property animation.
+#[derive(Debug)]
+pub struct Tween {
+    pub target: Rc<Instance>,
+    pub property: Box<str>,
+    pub from: f64,
+    pub to: f64,
+    pub duration: f64,
+    pub elapsed: f64,
+    pub easing: Easing,
+}
+
+impl Tween {
+    /// Advance the tween by `dt` seconds and write the new value to its property.
+    ///
+    /// Returns `true` once the tween has reached its target.
+    fn step(&mut self, vm: &mut Vm, dt: f64) -> Result<bool, VmError> {
+        self.elapsed += dt;
+
+        let t = if self.duration > 0.0 {
+            self.elapsed / self.duration
+        } else {
+            1.0
+        };
+
+        let value = self.from + (self.to - self.from) * self.easing.apply(t);
+        vm.set_property(self.target.clone(), &self.property, Value::Float(value))?;
+
+        Ok(t >= 1.0)
+    }
+}
+
+/// The set of running tweens.
+#[derive(Debug, Default)]
+pub struct Tweens {
+    active: Vec<Tween>,
+}
+
+impl Tweens {
+    pub fn new() -> Self {
+        Self { active: Vec::new() }
+    }
+
+    /// Start a tween, replacing any tween already running on the same property.
+    pub fn add(&mut self, tween: Tween) {
+        self.active.retain(|other| {
+            !(Rc::ptr_eq(&other.target, &tween.target) && other.property == tween.property)
+        });
+
+        self.active.push(tween);
+    }
+
+    /// Advance every tween in `tweens` by `dt` seconds, dropping the ones
+    /// that finished.
+    ///
+    /// `tweens` isn't borrowed while the properties are set, so a setter can
+    /// start tweens of its own, which replace the running ones on the same
+    /// property.
+    pub fn update(tweens: &RefCell<Self>, vm: &mut Vm, dt: f64) -> Result<(), String> {
+        let mut running = mem::take(&mut tweens.borrow_mut().active);
+        let mut result = Ok(());
+
+        running.retain_mut(|tween| match tween.step(vm, dt) {
+            Ok(finished) => !finished,
+            Err(err) => {
+                result = Err(err.0);
+                false
+            }
+        });
+
+        let mut tweens = tweens.borrow_mut();
+        let started = mem::replace(&mut tweens.active, running);
+
+        for tween in started {
+            tweens.add(tween);
+        }
+
+        result
+    }
+
+    /// The number of running tweens.
+    pub fn len(&self) -> usize {
+        self.active.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+}
+
+/// Register the `tween` global, whose tweens are collected in `tweens`.
+pub fn register(state: &mut State, tweens: Rc<RefCell<Tweens>>) {
+    let class = Class::new("tween");
+
+    class.add_reentrant_static_method("to", move |vm, args| {
+        let tween = new_tween(vm, &args)?;
+
+        tweens.borrow_mut().add(tween);
+
+        Ok(Value::Nil)
+    });
+
+    state.add("tween", Value::Class(class));
+}
+
+fn new_tween(vm: &mut Vm, args: &[Value]) -> Result<Tween, VmError> {
+    let native = |err| VmError::new(ErrorCode::Native, err);
+    let (target, property, to, duration) = match args {
+        [Value::Instance(target), Value::String(property), to, duration, ..] => {
+            (target, property, to, duration)
+        }
+        _ => {
+            return Err(native(
+                "tween.to expects (object, property, target, duration)".into(),
+            ))
+        }
+    };
+
+    let easing = match args.get(4) {
+        Some(Value::String(name)) => {
+            Easing::from_name(name).ok_or_else(|| native(format!("unknown easing '{name}'")))?
+        }
+        Some(value) => return Err(native(format!("expected an easing name, found '{value}'"))),
+        None => Easing::Linear,
+    };
+
+    let from = vm.get_property(target.clone(), property)?;
+
+    Ok(Tween {
+        target: target.clone(),
+        property: property.as_str().into(),
+        from: number(&from).map_err(native)?,
+        to: number(to).map_err(native)?,
+        duration: number(duration).map_err(native)?,
+        elapsed: 0.0,
+        easing,
+    })
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Integer(v) => Ok(*v as f64),
+        Value::Float(v) => Ok(*v),
+        value => Err(format!("expected a number, found '{value}'")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{value::Value, Thrush};
+
+    use super::Easing;
+
+    #[test]
+    fn test_easing_endpoints() {
+        let easings = [
+            Easing::Linear,
+            Easing::QuadIn,
+            Easing::QuadOut,
+            Easing::QuadInOut,
+            Easing::CubicIn,
+            Easing::CubicOut,
+            Easing::CubicInOut,
+            Easing::SineIn,
+            Easing::SineOut,
+            Easing::SineInOut,
+        ];
+
+        for easing in easings {
+            assert!(easing.apply(0.0).abs() < 1e-9, "{easing:?}");
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-9, "{easing:?}");
+        }
+
+        assert_eq!(Easing::QuadIn.apply(0.5), 0.25);
+    }
+
+    #[test]
+    fn test_tween_to() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("class Sprite {\n    var x\n}\nvar s = Sprite()\ns.x = 0")
+            .unwrap();
+        thrush.exec("tween.to(s, \"x\", 10, 2.0)").unwrap();
+
+        thrush.update(1.0).unwrap();
+        assert_eq!(sprite_x(&mut thrush), Value::Float(5.0));
+
+        thrush.update(1.5).unwrap();
+        assert_eq!(sprite_x(&mut thrush), Value::Float(10.0));
+
+        assert!(thrush.exec("tween.to(s, \"x\", 1, 1, \"bounce\")").is_err());
+    }
+
+    #[test]
+    fn test_tween_accessor() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("class Sprite {\n    var x\n    var sets\n    get left() {\n        return self.x - 1\n    }\n    set left(v) {\n        self.x = v + 1\n        self.sets = self.sets + 1\n    }\n}\nvar s = Sprite()\ns.x = 1\ns.sets = 0")
+            .unwrap();
+        thrush.exec("tween.to(s, \"left\", 10, 2.0)").unwrap();
+
+        thrush.update(1.0).unwrap();
+        assert_eq!(sprite_x(&mut thrush), Value::Float(6.0));
+
+        thrush.update(1.0).unwrap();
+        assert_eq!(sprite_x(&mut thrush), Value::Float(11.0));
+        assert_eq!(
+            thrush
+                .exec("var sets = s.sets")
+                .map(|()| thrush.globals().get::<i64>("sets")),
+            Ok(Ok(2))
+        );
+    }
+
+    fn sprite_x(thrush: &mut Thrush) -> Value {
+        match thrush.globals().get::<Value>("s").unwrap() {
+            Value::Instance(s) => s.get_field("x").unwrap(),
+            value => panic!("expected an instance, found {value}"),
+        }
+    }
+}
@@ -0,0 +1,888 @@
+//! An optional static type check, run on the AST before it is compiled.
+//!
+//! Once [Thrush::enable_typecheck](crate::Thrush::enable_typecheck) is
+//! called, the checker infers the types of literals, of `let` and `const`
+//! bindings and of annotated variables and parameters, and reports the
+//! mistakes it can prove as compile errors, so nothing in the script runs:
+//!
+//! ```
+//! use thrush::Thrush;
+//!
+//! let mut thrush = Thrush::new();
+//! thrush.enable_typecheck();
+//!
+//! let err = thrush.exec("class Point {}\nvar ran = true\nvar p = \"a\" + Point");
+//!
+//! assert!(err.unwrap_err().message.contains("unsupported operand types for +: string and class"));
+//! assert!(!thrush.globals().contains("ran"));
+//! ```
+//!
+//! Anything whose type isn't known, such as a variable declared with `var`
+//! and no annotation, is assumed to be right, so code that runs without
+//! errors always passes the check. Errors report where the mistake is, and
+//! name the function it is in.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    ast::{Ast, BinOp, Expr, ExprId, FunDecl, FunKind, Lit, Pattern, Stmt},
+    diagnostic::{Error, ErrorCode},
+    token::Span,
+};
+
+/// The type of a value, as far as the checker can tell.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Type {
+    Int,
+    Float,
+    /// An int or a float.
+    Number,
+    Bool,
+    String,
+    Bytes,
+    List,
+    Map,
+    Function,
+    Class,
+    Generator,
+    Iterator,
+    Trait,
+    Promise,
+    Module,
+    Nil,
+    /// An instance of the named class.
+    Instance(String),
+    /// A value that could have any type.
+    Unknown,
+}
+
+impl Type {
+    /// The type an annotation such as `Int` or `Point` names.
+    pub fn from_annotation(annotation: &str) -> Self {
+        match annotation {
+            "Any" => Type::Unknown,
+            "Number" => Type::Number,
+            "Int" => Type::Int,
+            "Float" => Type::Float,
+            "Bool" => Type::Bool,
+            "String" => Type::String,
+            "Bytes" => Type::Bytes,
+            "List" => Type::List,
+            "Map" => Type::Map,
+            "Function" => Type::Function,
+            "Class" => Type::Class,
+            "Generator" => Type::Generator,
+            "Iterator" => Type::Iterator,
+            "Trait" => Type::Trait,
+            "Promise" => Type::Promise,
+            "Module" => Type::Module,
+            "Nil" => Type::Nil,
+            class => Type::Instance(class.to_string()),
+        }
+    }
+
+    /// Whether a value of this type could be stored somewhere annotated with
+    /// `annotation`.
+    fn fits(&self, annotation: &str) -> bool {
+        match (self, Type::from_annotation(annotation)) {
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (Type::Int | Type::Float, Type::Number) | (Type::Number, Type::Int | Type::Float) => {
+                true
+            }
+            (ty, expected) => *ty == expected,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::Number => "number",
+            Type::Bool => "bool",
+            Type::String => "string",
+            Type::Bytes => "bytes",
+            Type::List => "list",
+            Type::Map => "map",
+            Type::Function => "function",
+            Type::Class => "class",
+            Type::Generator => "generator",
+            Type::Iterator => "iterator",
+            Type::Trait => "trait",
+            Type::Promise => "promise",
+            Type::Module => "module",
+            Type::Nil => "nil",
+            Type::Instance(class) => class,
+            Type::Unknown => "any",
+        })
+    }
+}
+
+/// What the checker knows about a global.
+#[derive(Debug, Clone)]
+struct Global {
+    ty: Type,
+    /// The annotation every value assigned to the global has to fit.
+    annotation: Option<String>,
+}
+
+/// What the checker knows about a function, to check calls to it.
+#[derive(Debug, Clone)]
+struct Signature {
+    params: Vec<(String, Option<String>)>,
+    variadic: bool,
+    returns: Type,
+}
+
+/// Checks the statements of a script as they are parsed.
+///
+/// The checker remembers the globals declared by the statements it has
+/// checked, so statements should be passed to one checker in order.
+#[derive(Debug, Default)]
+pub struct Checker {
+    globals: HashMap<String, Global>,
+    functions: HashMap<String, Signature>,
+    classes: HashSet<String>,
+    traits: HashSet<String>,
+    /// The names bound by the enclosing functions, loops and catch clauses,
+    /// innermost last.
+    scopes: Vec<HashMap<String, Type>>,
+    /// The name of the function being checked and the annotation of its
+    /// return type.
+    function: Option<(String, Option<String>)>,
+    /// Whether the function being checked yields, so calls to it return a
+    /// generator.
+    yields: bool,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check the statements of `ast`, returning the first type error.
+    pub fn check(&mut self, ast: &Ast) -> Result<(), Error> {
+        for node in &ast.nodes {
+            self.declare(node);
+        }
+
+        self.block(ast, &ast.nodes)
+    }
+
+    /// Record the functions and classes a statement declares, so they can be
+    /// used before their declaration.
+    fn declare(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Fun(decl) if !decl.decorators.is_empty() => {
+                self.define(&decl.name, Type::Unknown, None);
+            }
+            Stmt::Fun(decl) => {
+                let signature = Signature {
+                    params: decl
+                        .params
+                        .iter()
+                        .cloned()
+                        .zip(decl.param_types.iter().cloned())
+                        .collect(),
+                    variadic: decl.variadic,
+                    returns: if decl.is_async {
+                        Type::Promise
+                    } else {
+                        decl.returns
+                            .as_deref()
+                            .map_or(Type::Unknown, Type::from_annotation)
+                    },
+                };
+
+                self.functions.insert(decl.name.clone(), signature);
+                self.globals.remove(&decl.name);
+            }
+            Stmt::Class { name, .. } => {
+                self.classes.insert(name.clone());
+                self.globals.remove(name);
+            }
+            Stmt::Trait { name, .. } => {
+                self.traits.insert(name.clone());
+                self.globals.remove(name);
+            }
+            Stmt::Export(stmt) => self.declare(stmt),
+            _ => (),
+        }
+    }
+
+    fn block(&mut self, ast: &Ast, stmts: &[Stmt]) -> Result<(), Error> {
+        stmts.iter().try_for_each(|stmt| self.stmt(ast, stmt))
+    }
+
+    /// Check `stmts` with `names` bound to values of unknown types.
+    fn scoped<'a>(
+        &mut self,
+        ast: &Ast,
+        names: impl IntoIterator<Item = &'a String>,
+        stmts: &[Stmt],
+    ) -> Result<(), Error> {
+        let scope = names
+            .into_iter()
+            .map(|name| (name.clone(), Type::Unknown))
+            .collect();
+
+        self.scopes.push(scope);
+        let result = self.block(ast, stmts);
+        self.scopes.pop();
+
+        result
+    }
+
+    fn stmt(&mut self, ast: &Ast, stmt: &Stmt) -> Result<(), Error> {
+        match stmt {
+            Stmt::Fun(decl) => {
+                self.declare(stmt);
+                self.decorators(ast, decl)?;
+                self.function(ast, decl, &decl.name)?;
+            }
+            Stmt::Trait { .. } => self.declare(stmt),
+            Stmt::Class { name, methods, .. } => {
+                self.declare(stmt);
+
+                for method in methods {
+                    self.decorators(ast, method)?;
+                    self.function(ast, method, &format!("{name}.{}", method.name))?;
+                }
+            }
+            Stmt::VarDecl { id, ty, init } => {
+                self.declaration(ast, id, ty, *init)?;
+
+                // Unannotated variables can be reassigned anything, even by
+                // functions that haven't been checked yet.
+                let known = ty.as_deref().map_or(Type::Unknown, Type::from_annotation);
+
+                self.define(id, known, ty.clone());
+            }
+            Stmt::ConstDecl { id, ty, init } | Stmt::LetDecl { id, ty, init } => {
+                let init = self.declaration(ast, id, ty, *init)?;
+                let ty = match (ty, init) {
+                    (Some(annotation), Type::Unknown) => Type::from_annotation(annotation),
+                    (_, init) => init,
+                };
+
+                self.define(id, ty, None);
+            }
+            Stmt::Import { name, .. } => self.define(name, Type::Module, None),
+            Stmt::Export(stmt) => self.stmt(ast, stmt)?,
+            Stmt::Return(value) => {
+                let ty = self.expr(ast, *value)?;
+
+                if let Some((name, Some(annotation))) = &self.function {
+                    if !self.yields {
+                        let what = format!("the return value of '{name}'");
+
+                        self.expect(&what, annotation, &ty, ast.span(*value))?;
+                    }
+                }
+            }
+            Stmt::Match { value, arms } => {
+                self.expr(ast, *value)?;
+
+                for arm in arms {
+                    let mut names = Vec::new();
+
+                    bindings(&arm.pattern, &mut names);
+                    self.scoped(ast, names, &arm.body)?;
+                }
+            }
+            Stmt::Switch {
+                value,
+                cases,
+                default,
+            } => {
+                self.expr(ast, *value)?;
+
+                for case in cases {
+                    self.block(ast, &case.body)?;
+                }
+
+                if let Some(default) = default {
+                    self.block(ast, default)?;
+                }
+            }
+            Stmt::Throw(value) | Stmt::Expr(value) => {
+                self.expr(ast, *value)?;
+            }
+            Stmt::Assign { targets, values } => {
+                let mut types = Vec::with_capacity(values.len());
+
+                for value in values {
+                    types.push(self.expr(ast, *value)?);
+                }
+
+                // The items a single value unpacks to have unknown types.
+                if types.len() != targets.len() {
+                    types = vec![Type::Unknown; targets.len()];
+                }
+
+                for (target, ty) in targets.iter().zip(&types) {
+                    match &ast[*target] {
+                        Expr::Identifier(name) => self.assign(name, ty, ast.span(*target))?,
+                        _ => {
+                            self.expr(ast, *target)?;
+                        }
+                    }
+                }
+            }
+            Stmt::Yield(value) => {
+                self.yields = true;
+                self.expr(ast, *value)?;
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.block(ast, body)?;
+
+                if let Some(catch) = catch {
+                    self.scoped(ast, [&catch.name], &catch.body)?;
+                }
+
+                if let Some(finally) = finally {
+                    self.block(ast, finally)?;
+                }
+            }
+            Stmt::While { cond, body, .. } => {
+                self.expr(ast, *cond)?;
+                self.block(ast, body)?;
+            }
+            Stmt::For {
+                var,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expr(ast, *iterable)?;
+                self.scoped(ast, [var], body)?;
+            }
+            Stmt::Host(stmt) => {
+                for arg in &stmt.args {
+                    self.expr(ast, *arg)?;
+                }
+
+                if let Some(body) = &stmt.body {
+                    self.block(ast, body)?;
+                }
+            }
+            Stmt::Break(_) | Stmt::Continue(_) => (),
+        }
+
+        Ok(())
+    }
+
+    /// Check the initializer of the variable `id` against its annotation,
+    /// returning the initializer's type.
+    fn declaration(
+        &mut self,
+        ast: &Ast,
+        id: &str,
+        ty: &Option<String>,
+        init: ExprId,
+    ) -> Result<Type, Error> {
+        let span = ast.span(init);
+        let init = self.expr(ast, init)?;
+
+        if let Some(annotation) = ty {
+            self.expect(&format!("'{id}'"), annotation, &init, span)?;
+        }
+
+        Ok(init)
+    }
+
+    /// Variables are globals even when they are declared in a function.
+    fn define(&mut self, name: &str, ty: Type, annotation: Option<String>) {
+        self.functions.remove(name);
+        self.classes.remove(name);
+        self.traits.remove(name);
+        self.globals
+            .insert(name.to_string(), Global { ty, annotation });
+    }
+
+    fn function(&mut self, ast: &Ast, decl: &FunDecl, name: &str) -> Result<(), Error> {
+        let scope = decl
+            .params
+            .iter()
+            .zip(&decl.param_types)
+            .map(|(param, ty)| {
+                let ty = ty.as_deref().map_or(Type::Unknown, Type::from_annotation);
+
+                (param.clone(), ty)
+            })
+            .chain(decl.unpack.iter().flat_map(|(_, pattern)| {
+                pattern
+                    .names()
+                    .iter()
+                    .map(|name| (name.clone(), Type::Unknown))
+            }))
+            .collect();
+
+        // The return value of an async function is checked when it resolves
+        // its promise, not by the caller.
+        let returns = decl.returns.clone().filter(|_| !decl.is_async);
+        let enclosing = self.function.replace((name.to_string(), returns));
+        let yielded = std::mem::take(&mut self.yields);
+
+        self.scopes.push(scope);
+        let result = self.block(ast, &decl.body);
+        self.scopes.pop();
+
+        if self.yields && decl.kind == FunKind::Function {
+            if let Some(signature) = self.functions.get_mut(&decl.name) {
+                signature.returns = Type::Generator;
+            }
+        }
+
+        self.function = enclosing;
+        self.yields = yielded;
+
+        result
+    }
+
+    fn decorators(&mut self, ast: &Ast, decl: &FunDecl) -> Result<(), Error> {
+        decl.decorators
+            .iter()
+            .try_for_each(|decorator| self.expr(ast, *decorator).map(|_| ()))
+    }
+
+    /// Check the expression `id`, returning its type.
+    ///
+    /// Operands are checked from a work list rather than by recursion, so a
+    /// long chain of operators can't overflow the stack.
+    fn expr(&mut self, ast: &Ast, id: ExprId) -> Result<Type, Error> {
+        let mut work = vec![Work::Expr(id)];
+        let mut types = Vec::new();
+
+        while let Some(next) = work.pop() {
+            match next {
+                Work::Expr(id) => {
+                    let operands = operands(ast, id);
+
+                    work.push(Work::Finish(id, operands.len()));
+                    work.extend(operands.into_iter().rev().map(Work::Expr));
+                }
+                Work::Finish(id, count) => {
+                    let operands = types.split_off(types.len() - count);
+
+                    types.push(self.finish(ast, id, operands)?);
+                }
+            }
+        }
+
+        Ok(types.pop().unwrap_or(Type::Unknown))
+    }
+
+    /// The type of the expression `id`, given the types of its
+    /// [operands].
+    fn finish(&mut self, ast: &Ast, id: ExprId, operands: Vec<Type>) -> Result<Type, Error> {
+        let span = ast.span(id);
+        let mut operands = operands.into_iter();
+        let mut operand = || operands.next().unwrap_or(Type::Unknown);
+
+        Ok(match &ast[id] {
+            Expr::Literal(lit) => match lit {
+                Lit::String(_) => Type::String,
+                Lit::Integer(_) => Type::Int,
+                Lit::Float(_) => Type::Float,
+                Lit::Bool(_) => Type::Bool,
+                Lit::Nil => Type::Nil,
+                Lit::Char(_) => Type::Unknown,
+            },
+            Expr::Identifier(name) => self.lookup(name),
+            Expr::Slf => Type::Unknown,
+            Expr::BinExpr { op, .. } => {
+                let left = operand();
+                let right = operand();
+
+                self.binary(op, left, right, span)?
+            }
+            Expr::UnaryExpr { op, .. } => {
+                use Type::{Float, Int, Number, Unknown};
+
+                match (op, operand()) {
+                    (BinOp::Bang, _) => Type::Bool,
+                    (_, ty @ (Int | Float | Number | Unknown)) => ty,
+                    (_, ty) => {
+                        return Err(self.error(
+                            format!(
+                                "unsupported operand type for unary {}: {ty}",
+                                op.into_string()
+                            ),
+                            span,
+                        ))
+                    }
+                }
+            }
+            Expr::Call {
+                callee,
+                args,
+                named,
+            } => self.call(ast, *callee, args, named, operands.collect())?,
+            Expr::Assign { target, .. } => {
+                let ty = operand();
+
+                if let Expr::Identifier(name) = &ast[*target] {
+                    self.assign(name, &ty, ast.span(*target))?;
+                }
+
+                ty
+            }
+            Expr::Increment { target, op, .. } => {
+                let ty = self.binary(op, operand(), Type::Int, span)?;
+
+                if let Expr::Identifier(name) = &ast[*target] {
+                    self.assign(name, &ty, span)?;
+                }
+
+                ty
+            }
+            Expr::List(_) => Type::List,
+            Expr::Map(_) => Type::Map,
+            Expr::TypeOf(_) => Type::String,
+            Expr::Is { .. } => Type::Bool,
+            Expr::Dot { .. }
+            | Expr::Spread(_)
+            | Expr::Await(_)
+            | Expr::Range { .. }
+            | Expr::Template { .. } => Type::Unknown,
+            Expr::Function(decl) => {
+                self.function(ast, decl, &decl.name)?;
+
+                Type::Function
+            }
+            Expr::If {
+                then, otherwise, ..
+            } => {
+                self.block(ast, then)?;
+                self.block(ast, otherwise.as_deref().unwrap_or_default())?;
+
+                Type::Unknown
+            }
+            Expr::Block(body) => {
+                self.block(ast, body)?;
+
+                Type::Unknown
+            }
+            Expr::Object { methods, .. } => {
+                for method in methods {
+                    self.decorators(ast, method)?;
+                    self.function(ast, method, &format!("object.{}", method.name))?;
+                }
+
+                Type::Unknown
+            }
+        })
+    }
+
+    fn lookup(&self, name: &str) -> Type {
+        if let Some(ty) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            ty.clone()
+        } else if let Some(global) = self.globals.get(name) {
+            global.ty.clone()
+        } else if self.functions.contains_key(name) {
+            Type::Function
+        } else if self.classes.contains(name) {
+            Type::Class
+        } else if self.traits.contains(name) {
+            Type::Trait
+        } else {
+            Type::Unknown
+        }
+    }
+
+    /// The names bound by an enclosing scope shadow the globals.
+    fn is_local(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains_key(name))
+    }
+
+    fn assign(&mut self, name: &str, ty: &Type, span: Span) -> Result<(), Error> {
+        if let Some(scope) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find(|scope| scope.contains_key(name))
+        {
+            scope.insert(name.to_string(), Type::Unknown);
+        } else if let Some(Global {
+            annotation: Some(annotation),
+            ..
+        }) = self.globals.get(name)
+        {
+            let annotation = annotation.clone();
+
+            self.expect(&format!("'{name}'"), &annotation, ty, span)?;
+        }
+
+        Ok(())
+    }
+
+    fn binary(&self, op: &BinOp, left: Type, right: Type, span: Span) -> Result<Type, Error> {
+        use Type::{Float, Int, Number, Unknown};
+
+        Ok(match (op, &left, &right) {
+            (BinOp::Equal | BinOp::NotEqual, _, _) => Type::Bool,
+            (BinOp::And | BinOp::Or, _, _) if left == right => left,
+            (BinOp::And | BinOp::Or | BinOp::Bang | BinOp::Custom(_), _, _)
+            | (_, Unknown, _)
+            | (_, _, Unknown) => Unknown,
+            (BinOp::Less | BinOp::LessEqual | BinOp::Greater | BinOp::GreaterEqual, _, _)
+                if matches!(
+                    (&left, &right),
+                    (Int | Float | Number, Int | Float | Number) | (Type::String, Type::String)
+                ) =>
+            {
+                Type::Bool
+            }
+            (_, Int, Int) => Int,
+            (_, Float, Int | Float | Number) | (_, Int | Number, Float) => Float,
+            (_, Int | Number, Int | Number) => Number,
+            (BinOp::Add, Type::String, Type::String) => Type::String,
+            (BinOp::Mul, Type::String, Int | Number) | (BinOp::Mul, Int | Number, Type::String) => {
+                Type::String
+            }
+            _ => {
+                return Err(self.error(
+                    format!(
+                        "unsupported operand types for {}: {left} and {right}",
+                        op.into_string()
+                    ),
+                    span,
+                ))
+            }
+        })
+    }
+
+    /// The type a call returns, given `types`, the types of its callee and
+    /// arguments.
+    fn call(
+        &self,
+        ast: &Ast,
+        callee: ExprId,
+        args: &[ExprId],
+        named: &[(String, ExprId)],
+        types: Vec<Type>,
+    ) -> Result<Type, Error> {
+        let (callee_ty, types) = types.split_first().unwrap_or((&Type::Unknown, &[]));
+        let (arg_types, named_types) = types.split_at(args.len().min(types.len()));
+
+        let name = match &ast[callee] {
+            Expr::Identifier(name) if !self.is_local(name) => name,
+            _ => return Ok(Type::Unknown),
+        };
+
+        if *callee_ty == Type::Class {
+            return Ok(Type::Instance(name.clone()));
+        }
+
+        let signature = match self.functions.get(name) {
+            Some(signature) if *callee_ty == Type::Function => signature.clone(),
+            _ => return Ok(Type::Unknown),
+        };
+
+        // Arguments from a spread onwards can't be matched to parameters.
+        let spread = args
+            .iter()
+            .position(|arg| matches!(ast[*arg], Expr::Spread(_)))
+            .unwrap_or(args.len());
+        let fixed = signature.params.len() - usize::from(signature.variadic);
+        let positional = signature.params[..fixed]
+            .iter()
+            .zip(args[..spread].iter().zip(arg_types));
+        let named = named.iter().zip(named_types).filter_map(|((arg, id), ty)| {
+            signature.params[..fixed]
+                .iter()
+                .find(|(param, _)| param == arg)
+                .map(|param| (param, (id, ty)))
+        });
+
+        for ((param, annotation), (arg, ty)) in positional.chain(named) {
+            if let Some(annotation) = annotation {
+                let what = format!("parameter '{param}' of '{name}'");
+
+                self.expect(&what, annotation, ty, ast.span(*arg))?;
+            }
+        }
+
+        Ok(signature.returns)
+    }
+
+    /// Check that a value of type `ty` fits `annotation`, where `what`
+    /// describes where the value is stored.
+    fn expect(&self, what: &str, annotation: &str, ty: &Type, span: Span) -> Result<(), Error> {
+        // Whether a class implements a trait is only known once it runs.
+        let implements = self.traits.contains(annotation) && matches!(ty, Type::Instance(_));
+
+        if implements || ty.fits(annotation) {
+            Ok(())
+        } else {
+            Err(self.error(
+                format!("expected {what} to be {annotation} but got {ty}"),
+                span,
+            ))
+        }
+    }
+
+    /// A type error at `span`, naming the function it is in.
+    fn error(&self, message: String, span: Span) -> Error {
+        let message = match &self.function {
+            Some((name, _)) => format!("{message} in '{name}'"),
+            None => message,
+        };
+
+        Error::new(ErrorCode::TypeMismatch.error(message)).at(span)
+    }
+}
+
+/// A step in checking an expression.
+enum Work {
+    /// Check an expression and its operands.
+    Expr(ExprId),
+    /// Replace the types of the last `count` operands checked with the type
+    /// of the expression they belong to.
+    Finish(ExprId, usize),
+}
+
+/// The subexpressions checked before the expression `id`, in the order
+/// they are evaluated.
+fn operands(ast: &Ast, id: ExprId) -> Vec<ExprId> {
+    match &ast[id] {
+        Expr::Literal(_) | Expr::Identifier(_) | Expr::Slf | Expr::Function(_) | Expr::Block(_) => {
+            Vec::new()
+        }
+        Expr::BinExpr { left, right, .. } => vec![*left, *right],
+        Expr::Is { value, class } => vec![*value, *class],
+        Expr::UnaryExpr { value, .. }
+        | Expr::Spread(value)
+        | Expr::TypeOf(value)
+        | Expr::Await(value)
+        | Expr::Dot { object: value, .. }
+        | Expr::Increment { target: value, .. }
+        | Expr::If { cond: value, .. } => vec![*value],
+        Expr::Call {
+            callee,
+            args,
+            named,
+        } => std::iter::once(*callee)
+            .chain(args.iter().copied())
+            .chain(named.iter().map(|(_, arg)| *arg))
+            .collect(),
+        Expr::List(items) | Expr::Template { values: items, .. } => items.clone(),
+        Expr::Map(entries) => entries.iter().map(|(_, value)| *value).collect(),
+        Expr::Object { fields, .. } => fields.iter().map(|(_, init)| *init).collect(),
+        // A variable being assigned isn't read.
+        Expr::Assign { target, value } => match &ast[*target] {
+            Expr::Identifier(_) => vec![*value],
+            _ => vec![*value, *target],
+        },
+        Expr::Range { start, end, step } => [Some(*start), Some(*end), *step]
+            .into_iter()
+            .flatten()
+            .collect(),
+    }
+}
+
+/// Collect the names a pattern binds.
+fn bindings<'a>(pattern: &'a Pattern, names: &mut Vec<&'a String>) {
+    match pattern {
+        Pattern::Binding(name) => names.push(name),
+        Pattern::Class { fields, .. } => {
+            for field in fields {
+                bindings(field, names);
+            }
+        }
+        Pattern::Wildcard | Pattern::Literal(_) => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Thrush;
+
+    fn check(script: &str) -> Result<(), String> {
+        let mut thrush = Thrush::new();
+
+        thrush.enable_typecheck();
+        thrush.exec(script).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_typecheck() {
+        assert_eq!(
+            check("class Point {}\nlet p = Point()\nvar s = \"a\" * 2 + p"),
+            Err("[E0204] line 3:9: unsupported operand types for +: string and Point".into())
+        );
+        assert_eq!(
+            check("let x = 1.5\nconst y = x * 2\nvar z: Int = y"),
+            Err("[E0204] line 3:14: expected 'z' to be Int but got float".into())
+        );
+        assert_eq!(
+            check("var n: Int = 1\nfun reset() { n = nil }"),
+            Err("[E0204] line 2:15: expected 'n' to be Int but got nil in 'reset'".into())
+        );
+        assert_eq!(
+            check("fun add(a: Int, b: Int) -> Int { return a + b }\nvar x = add(1, b: \"2\")"),
+            Err(
+                "[E0204] line 2:19: expected parameter 'b' of 'add' to be Int but got string"
+                    .into()
+            )
+        );
+        assert_eq!(
+            check("fun name(n: Number) -> String { return n * 2 }"),
+            Err(
+                "[E0204] line 1:40: expected the return value of 'name' to be String but got number in 'name'"
+                    .into()
+            )
+        );
+        assert_eq!(
+            check("fun f() -> Int { return 1 }\nvar s = f() + true"),
+            Err("[E0204] line 2:9: unsupported operand types for +: int and bool".into())
+        );
+        assert_eq!(
+            check("var b = !1 + 2"),
+            Err("[E0204] line 1:9: unsupported operand types for +: bool and int".into())
+        );
+        assert_eq!(
+            check("var s = -\"a\""),
+            Err("[E0204] line 1:9: unsupported operand type for unary -: string".into())
+        );
+        assert_eq!(
+            check("var b: Bool = 1 < 2.5 && \"a\" != 1\nvar c: Int = 1 < \"b\""),
+            Err("[E0204] line 2:14: unsupported operand types for <: int and string".into())
+        );
+        assert_eq!(
+            check("let b = 1 == 2\nvar c: Int = b"),
+            Err("[E0204] line 2:14: expected 'c' to be Int but got bool".into())
+        );
+
+        // A long chain of operators is checked without recursion.
+        let sum = vec!["x"; 2000].join(" + ");
+
+        assert_eq!(
+            check(&format!("let x = 1\nvar s: String = {sum}")),
+            Err("[E0204] line 2:17: expected 's' to be String but got int".into())
+        );
+    }
+
+    #[test]
+    fn test_typecheck_unknown() {
+        for script in [
+            "var x = 1\nx = \"a\"\nvar y = x + \"b\"",
+            "fun f(a) { return a + 1 }\nvar x = f(1.5) * 2",
+            "fun count() -> Int { yield 1 }\nvar g = count()",
+            "var s = \"a\"\nfun f(s: Int) { return s + 1 }",
+            "let x = 1\nfor x in [\"a\"] { var y = x + \"b\" }",
+            "trait Named { fun name() }\nclass Cat : Named { fun name() {} }\nlet n: Named = Cat()",
+        ] {
+            assert_eq!(check(script), Ok(()), "{script}");
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! UUID generation, exposed as the `uuid` global.
+//!
+//! UUIDs are drawn from the VM's [Rng], so a seeded VM produces the same
+//! sequence of UUIDs on every run.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    rng::Rng,
+    scope::State,
+    value::{Class, Value},
+};
+
+/// Generate a random (version 4) UUID in its hyphenated form.
+pub fn v4(rng: &mut Rng) -> String {
+    let mut bytes = [0u8; 16];
+
+    rng.fill(&mut bytes);
+
+    // Set the version and RFC 4122 variant bits.
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let mut out = String::with_capacity(36);
+
+    for (i, byte) in bytes.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            out.push('-');
+        }
+
+        out.push_str(&format!("{byte:02x}"));
+    }
+
+    out
+}
+
+/// Register the `uuid` global, which draws from `rng`.
+pub fn register(state: &mut State, rng: Rc<RefCell<Rng>>) {
+    let class = Class::new("uuid");
+
+    class.add_static_method("v4", move |args| {
+        if !args.is_empty() {
+            return Err("uuid.v4 takes no arguments".into());
+        }
+
+        Ok(Value::String(v4(&mut rng.borrow_mut())))
+    });
+
+    state.add("uuid", Value::Class(class));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{rng::Rng, Thrush};
+
+    use super::v4;
+
+    #[test]
+    fn test_v4_format() {
+        let uuid = v4(&mut Rng::with_seed(7));
+        let groups = uuid.split('-').map(str::len).collect::<Vec<_>>();
+
+        assert_eq!(groups, [8, 4, 4, 4, 12]);
+        assert_eq!(&uuid[14..15], "4");
+        assert!(matches!(&uuid[19..20], "8" | "9" | "a" | "b"));
+    }
+
+    #[test]
+    fn test_seeded_uuid() {
+        let run = || {
+            let mut thrush = Thrush::new();
+
+            thrush.set_seed(42);
+            thrush.exec("var a = uuid.v4()\nvar b = uuid.v4()").unwrap();
+
+            let a = thrush.globals().get::<String>("a").unwrap();
+            let b = thrush.globals().get::<String>("b").unwrap();
+
+            (a, b)
+        };
+
+        let (a, b) = run();
+
+        assert_ne!(a, b);
+        assert_eq!(run(), (a, b));
+    }
+}
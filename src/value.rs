@@ -1,19 +1,37 @@
 use core::fmt::{self, Debug};
 use std::{
-    cell::{Ref, RefCell, RefMut},
+    cell::{Cell, Ref, RefCell, RefMut},
     collections::HashMap,
     rc::Rc,
 };
 
-#[derive(Debug, PartialEq, Clone)]
+use crate::{
+    chunk::{Chunk, Module},
+    diagnostic::{did_you_mean, ErrorCode},
+    hash::{Hash, HashTable},
+    repr,
+    vm::{Vm, VmError},
+};
+
+#[derive(Debug, Clone)]
 pub enum Value {
     Bool(bool),
     Float(f64),
     Integer(i64),
     String(String),
+    Bytes(Rc<[u8]>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<Map>>),
     Instance(Rc<Instance>),
     Class(Rc<Class>),
     Method(Rc<BoundMethod>),
+    Function(Rc<Function>),
+    ScriptFun(Rc<ScriptFun>),
+    Generator(Rc<Generator>),
+    Iter(Rc<Iter>),
+    Trait(Rc<Trait>),
+    Promise(Rc<Promise>),
+    Module(Rc<Module>),
     Nil,
 }
 
@@ -24,45 +42,681 @@ impl fmt::Display for Value {
             Value::Float(v) => f.write_fmt(format_args!("{v}")),
             Value::Integer(v) => f.write_fmt(format_args!("{v}")),
             Value::String(v) => f.write_fmt(format_args!("{v}")),
+            Value::Bytes(v) => f.write_fmt(format_args!("<bytes {}>", v.len())),
+            Value::List(_) | Value::Map(_) => repr::fmt_container(self, f),
             Value::Instance(instance) => f.write_fmt(format_args!("{instance}")),
             Value::Class(class) => f.write_fmt(format_args!("<Class {}>", class.as_ref().name)),
             Value::Method(method) => f.write_fmt(format_args!(
                 "<method {}.{}>",
                 method.receiver.as_ref().class.as_ref().name,
-                method.function.name
+                method.function.name()
             )),
+            Value::Function(fun) => f.write_fmt(format_args!("<native fun {}>", fun.name)),
+            Value::ScriptFun(fun) => f.write_fmt(format_args!("<fun {}>", fun.name)),
+            Value::Generator(generator) => {
+                f.write_fmt(format_args!("<generator {}>", generator.fun.name))
+            }
+            Value::Iter(iter) => f.write_fmt(format_args!("<iterator {}>", iter.name)),
+            Value::Trait(t) => f.write_fmt(format_args!("<trait {}>", t.name)),
+            Value::Promise(_) => f.write_str("<promise>"),
+            Value::Module(module) => f.write_fmt(format_args!("<module {}>", module.name)),
             Value::Nil => f.write_str("nil"),
         }
     }
 }
 
+/// Values are equal when they have the same contents. Lists, maps and
+/// instances that contain themselves compare equal as long as no difference
+/// is found before a pair that is already being compared comes round again.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        equal(self, other, &mut Vec::new())
+    }
+}
+
+/// Compare `a` and `b`, where `seen` holds the addresses of the pairs of
+/// containers being compared further up.
+fn equal(a: &Value, b: &Value, seen: &mut Vec<(usize, usize)>) -> bool {
+    let pair = match (a, b) {
+        (Value::List(x), Value::List(y)) => (x.as_ptr() as usize, y.as_ptr() as usize),
+        (Value::Map(x), Value::Map(y)) => (x.as_ptr() as usize, y.as_ptr() as usize),
+        (Value::Instance(x), Value::Instance(y)) => {
+            (Rc::as_ptr(x) as usize, Rc::as_ptr(y) as usize)
+        }
+        (Value::Method(x), Value::Method(y)) => {
+            return x.function == y.function
+                && equal(
+                    &Value::Instance(x.receiver.clone()),
+                    &Value::Instance(y.receiver.clone()),
+                    seen,
+                )
+        }
+        (Value::Bool(x), Value::Bool(y)) => return x == y,
+        (Value::Float(x), Value::Float(y)) => return x == y,
+        (Value::Integer(x), Value::Integer(y)) => return x == y,
+        (Value::String(x), Value::String(y)) => return x == y,
+        (Value::Bytes(x), Value::Bytes(y)) => return x == y,
+        (Value::Class(x), Value::Class(y)) => return x == y,
+        (Value::Function(x), Value::Function(y)) => return x == y,
+        (Value::ScriptFun(x), Value::ScriptFun(y)) => return x == y,
+        (Value::Generator(x), Value::Generator(y)) => return x == y,
+        (Value::Iter(x), Value::Iter(y)) => return x == y,
+        (Value::Trait(x), Value::Trait(y)) => return x == y,
+        (Value::Promise(x), Value::Promise(y)) => return x == y,
+        (Value::Module(x), Value::Module(y)) => return x == y,
+        (Value::Nil, Value::Nil) => return true,
+        _ => return false,
+    };
+
+    if seen.contains(&pair) {
+        return true;
+    }
+
+    seen.push(pair);
+
+    let same = match (a, b) {
+        (Value::List(x), Value::List(y)) => {
+            let (x, y) = (x.borrow(), y.borrow());
+
+            x.len() == y.len() && x.iter().zip(y.iter()).all(|(x, y)| equal(x, y, seen))
+        }
+        (Value::Map(x), Value::Map(y)) => {
+            let (x, y) = (x.borrow(), y.borrow());
+
+            x.entries.len() == y.entries.len()
+                && x.entries
+                    .iter()
+                    .zip(&y.entries)
+                    .all(|((k, x), (l, y))| k == l && equal(x, y, seen))
+        }
+        (Value::Instance(x), Value::Instance(y)) => {
+            let (fields, other) = (x.fields(), y.fields());
+
+            x.class == y.class
+                && fields.len() == other.len()
+                && fields
+                    .iter()
+                    .zip(other.iter())
+                    .all(|(x, y)| equal(x, y, seen))
+        }
+        _ => unreachable!(),
+    };
+
+    seen.pop();
+
+    same
+}
+
+impl Value {
+    /// Create a list value.
+    pub fn list(items: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+
+    /// Whether the value counts as false in a condition, i.e. it is `false` or `nil`.
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, Value::Bool(false) | Value::Nil)
+    }
+
+    /// Create a map value.
+    pub fn map(map: Map) -> Value {
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+
+    /// Whether the value matches the type annotation `ty`, which is either
+    /// a builtin type such as `Int` or `List`, `Number` or `Any`, or the name
+    /// of a class.
+    pub fn has_type(&self, ty: &str) -> bool {
+        match ty {
+            "Any" => true,
+            "Number" => matches!(self, Value::Integer(_) | Value::Float(_)),
+            "Int" => matches!(self, Value::Integer(_)),
+            "Float" => matches!(self, Value::Float(_)),
+            "Bool" => matches!(self, Value::Bool(_)),
+            "String" => matches!(self, Value::String(_)),
+            "Bytes" => matches!(self, Value::Bytes(_)),
+            "List" => matches!(self, Value::List(_)),
+            "Map" => matches!(self, Value::Map(_)),
+            "Function" => matches!(
+                self,
+                Value::Function(_) | Value::ScriptFun(_) | Value::Method(_)
+            ),
+            "Class" => matches!(self, Value::Class(_)),
+            "Generator" => matches!(self, Value::Generator(_)),
+            "Iterator" => matches!(self, Value::Iter(_)),
+            "Trait" => matches!(self, Value::Trait(_)),
+            "Promise" => matches!(self, Value::Promise(_)),
+            "Module" => matches!(self, Value::Module(_)),
+            "Nil" => matches!(self, Value::Nil),
+            class => matches!(
+                self,
+                Value::Instance(instance)
+                    if instance.class.has_name(class) || instance.class.implements(class)
+            ),
+        }
+    }
+
+    /// The docstring of a class, script function or method, as given by
+    /// `doc`.
+    pub fn doc(&self) -> Option<String> {
+        let doc = match self {
+            Value::Class(class) => return class.doc.borrow().as_deref().map(String::from),
+            Value::ScriptFun(fun) => &fun.doc,
+            Value::Method(method) => match &method.function {
+                Method::Script(fun) => &fun.doc,
+                Method::Native(_) => return None,
+            },
+            _ => return None,
+        };
+
+        doc.as_deref().map(String::from)
+    }
+
+    /// The name of the value's type, as given by `typeof`. Instances are
+    /// named by their class.
+    pub fn type_name(&self) -> &str {
+        match self {
+            Value::Bool(_) => "bool",
+            Value::Float(_) => "float",
+            Value::Integer(_) => "int",
+            Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Instance(instance) => &instance.class.name,
+            Value::Class(_) => "class",
+            Value::Method(_) => "method",
+            Value::Function(_) | Value::ScriptFun(_) => "function",
+            Value::Generator(_) => "generator",
+            Value::Iter(_) => "iterator",
+            Value::Trait(_) => "trait",
+            Value::Promise(_) => "promise",
+            Value::Module(_) => "module",
+            Value::Nil => "nil",
+        }
+    }
+}
+
+/// A map from strings to values which remembers insertion order.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Map {
+    entries: Vec<(Box<str>, Value)>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Insert a value, replacing and returning any previous value for `key`.
+    pub fn insert<S: Into<Box<str>>>(&mut self, key: S, value: Value) -> Option<Value> {
+        let key = key.into();
+
+        match self.entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, old)) => Some(std::mem::replace(old, value)),
+            None => {
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries
+            .iter()
+            .find(|(k, _)| &**k == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let index = self.entries.iter().position(|(k, _)| &**k == key)?;
+
+        Some(self.entries.remove(index).1)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// The entry at `index` in insertion order.
+    pub fn entry(&self, index: usize) -> Option<(&str, &Value)> {
+        self.entries.get(index).map(|(k, v)| (&**k, v))
+    }
+
+    /// Iterate over the entries in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.entries.iter().map(|(k, v)| (&**k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(k, _)| &**k)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<K: Into<Box<str>>> FromIterator<(K, Value)> for Map {
+    fn from_iter<I: IntoIterator<Item = (K, Value)>>(iter: I) -> Self {
+        let mut map = Map::new();
+
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+
+        map
+    }
+}
+
 /// Representation of a Thrush class in rust.
 pub struct Class {
     pub name: Box<str>,
-    pub methods: RefCell<HashMap<Box<str>, Rc<InstanceFun>>>,
+    /// The names of the fields declared on the class, in slot order.
+    pub fields: RefCell<Vec<Box<str>>>,
+    pub methods: RefCell<HashMap<Box<str>, Method>>,
+    /// Methods run when a property is read.
+    pub getters: RefCell<HashMap<Box<str>, Method>>,
+    /// Methods run when a property is assigned, receiving the new value.
+    pub setters: RefCell<HashMap<Box<str>, Method>>,
+    /// Methods called on the class itself rather than on an instance, and
+    /// the values of its static fields.
+    pub statics: RefCell<HashMap<Box<str>, Value>>,
+    /// The static fields declared on the class, and whether each is a
+    /// constant.
+    pub static_fields: RefCell<HashMap<Box<str>, bool>>,
+    /// The version declared in the class body, or 0.
+    pub version: Cell<u32>,
+    /// The docstring at the start of the class body, if any.
+    pub doc: RefCell<Option<Box<str>>>,
+    /// The traits the class was checked to implement when it was declared.
+    pub traits: RefCell<Vec<Rc<Trait>>>,
+    /// The class this one extends, if any.
+    pub superclass: RefCell<Option<Rc<Class>>>,
+    /// The method each property called on an instance has turned out to
+    /// run, by the hash of its name. Changing `fields`, `methods` or
+    /// `getters` directly has to be followed by [Class::forget_calls].
+    calls: RefCell<HashTable<Option<Method>>>,
 }
 
 impl Class {
     pub fn new<S: Into<Box<str>>>(name: S) -> Rc<Self> {
         Rc::new(Self {
             name: name.into(),
+            fields: RefCell::new(Vec::new()),
             methods: RefCell::new(HashMap::new()),
+            getters: RefCell::new(HashMap::new()),
+            setters: RefCell::new(HashMap::new()),
+            statics: RefCell::new(HashMap::new()),
+            static_fields: RefCell::new(HashMap::new()),
+            version: Cell::new(0),
+            doc: RefCell::new(None),
+            traits: RefCell::new(Vec::new()),
+            superclass: RefCell::new(None),
+            calls: RefCell::new(HashTable::default()),
         })
     }
 
+    /// Declare a field on the class, returning its slot.
+    pub fn add_field<S: Into<Box<str>>>(&self, name: S) -> usize {
+        let name = name.into();
+        let mut fields = self.fields.borrow_mut();
+
+        self.forget_calls();
+
+        match fields.iter().position(|field| *field == name) {
+            Some(slot) => slot,
+            None => {
+                fields.push(name);
+                fields.len() - 1
+            }
+        }
+    }
+
+    /// Get the slot of a declared field.
+    pub fn field_slot(&self, name: &str) -> Option<usize> {
+        self.fields
+            .borrow()
+            .iter()
+            .position(|field| &**field == name)
+    }
+
     pub fn add_method<S: Into<Box<str>> + Copy>(
         &self,
         name: S,
         fun: fn(Rc<Instance>, Vec<Value>) -> Value,
     ) {
+        self.forget_calls();
+        self.methods.borrow_mut().insert(
+            name.into(),
+            Method::Native(Rc::new(InstanceFun::new(name.into(), fun))),
+        );
+    }
+
+    /// Add a getter, called with no arguments when the property `name` is read.
+    pub fn add_getter<S: Into<Box<str>> + Copy>(
+        &self,
+        name: S,
+        fun: fn(Rc<Instance>, Vec<Value>) -> Value,
+    ) {
+        self.forget_calls();
+        self.getters.borrow_mut().insert(
+            name.into(),
+            Method::Native(Rc::new(InstanceFun::new(name.into(), fun))),
+        );
+    }
+
+    /// Add a setter, called with the new value when the property `name` is assigned.
+    pub fn add_setter<S: Into<Box<str>> + Copy>(
+        &self,
+        name: S,
+        fun: fn(Rc<Instance>, Vec<Value>) -> Value,
+    ) {
+        self.setters.borrow_mut().insert(
+            name.into(),
+            Method::Native(Rc::new(InstanceFun::new(name.into(), fun))),
+        );
+    }
+
+    /// Add a method defined in a Thrush script.
+    pub fn add_script_method(&self, fun: Rc<ScriptFun>) {
+        self.forget_calls();
         self.methods
             .borrow_mut()
-            .insert(name.into(), Rc::new(InstanceFun::new(name.into(), fun)));
+            .insert(fun.name.clone(), Method::Script(fun));
+    }
+
+    /// Add a static method defined in a Thrush script.
+    pub fn add_script_static(&self, fun: Rc<ScriptFun>) {
+        self.statics
+            .borrow_mut()
+            .insert(fun.name.clone(), Value::ScriptFun(fun));
+    }
+
+    /// Add a static method implemented in rust.
+    pub fn add_static_method<S, F>(&self, name: S, fun: F)
+    where
+        S: Into<Box<str>> + Copy,
+        F: Fn(Vec<Value>) -> Result<Value, String> + 'static,
+    {
+        let name: Box<str> = name.into();
+        let qualified = format!("{}.{name}", self.name);
+
+        self.statics.borrow_mut().insert(
+            name,
+            Value::Function(Rc::new(Function::new(qualified, fun))),
+        );
+    }
+
+    /// Add a static method implemented in rust that can call back into the
+    /// script, see [Function::reentrant].
+    pub fn add_reentrant_static_method<S, F>(&self, name: S, fun: F)
+    where
+        S: Into<Box<str>> + Copy,
+        F: Fn(&mut Vm, Vec<Value>) -> Result<Value, VmError> + 'static,
+    {
+        let name: Box<str> = name.into();
+        let qualified = format!("{}.{name}", self.name);
+
+        self.statics.borrow_mut().insert(
+            name,
+            Value::Function(Rc::new(Function::reentrant(qualified, fun))),
+        );
+    }
+
+    /// Declare a static field set to `value`.
+    pub fn add_static_field<S: Into<Box<str>>>(&self, name: S, value: Value, constant: bool) {
+        let name = name.into();
+
+        self.static_fields
+            .borrow_mut()
+            .insert(name.clone(), constant);
+        self.statics.borrow_mut().insert(name, value);
+    }
+
+    /// Assign to a static field declared with `static var`.
+    pub fn set_static_field(&self, name: &str, value: Value) -> Result<(), String> {
+        match self.static_fields.borrow().get(name) {
+            Some(false) => {}
+            Some(true) => return Err(format!("cannot assign to constant '{}.{name}'", self.name)),
+            None => {
+                return Err(format!(
+                    "'{}' has no static field named '{name}'",
+                    self.name
+                ))
+            }
+        }
+
+        self.statics.borrow_mut().insert(name.into(), value);
+
+        Ok(())
+    }
+
+    /// Look up a method by name.
+    pub fn get_method(&self, name: &str) -> Option<Method> {
+        self.methods.borrow().get(name).cloned()
+    }
+
+    pub fn get_getter(&self, name: &str) -> Option<Method> {
+        self.getters.borrow().get(name).cloned()
+    }
+
+    pub fn get_setter(&self, name: &str) -> Option<Method> {
+        self.setters.borrow().get(name).cloned()
+    }
+
+    /// The method that calling the property `name`, whose hash is `hash`,
+    /// on an instance runs, or `None` if the property is a field, a getter
+    /// or missing. The answer is remembered until the class changes.
+    pub fn call_target(&self, hash: Hash, name: &str) -> Option<Method> {
+        if let Some(method) = self.calls.borrow().get(&hash) {
+            return method.clone();
+        }
+
+        let method = (self.field_slot(name).is_none() && self.get_getter(name).is_none())
+            .then(|| self.get_method(name))
+            .flatten();
+
+        self.calls.borrow_mut().insert(hash, method.clone());
+
+        method
+    }
+
+    /// Forget the methods found by [Class::call_target], after a field,
+    /// method or getter is added.
+    pub fn forget_calls(&self) {
+        self.calls.borrow_mut().clear();
+    }
+
+    /// Look up a static method by name.
+    pub fn get_static(&self, name: &str) -> Option<Value> {
+        self.statics.borrow().get(name).cloned()
+    }
+
+    /// The names of every field, method and getter that can be read from an instance.
+    pub fn property_names(&self) -> Vec<Box<str>> {
+        let mut names = self.fields.borrow().clone();
+
+        names.extend(self.methods.borrow().keys().cloned());
+        names.extend(self.getters.borrow().keys().cloned());
+
+        names
     }
 
     pub fn instance(self: Rc<Self>) -> Rc<Instance> {
         Instance::new(self)
     }
+
+    /// Check that the class has the methods `t` requires, and remember that
+    /// it implements it.
+    pub fn implement(&self, t: Rc<Trait>) -> Result<(), String> {
+        t.check(self)?;
+        self.traits.borrow_mut().push(t);
+
+        Ok(())
+    }
+
+    /// Make the class extend `base`, inheriting its fields, traits and the
+    /// methods, getters and setters it doesn't define itself.
+    pub fn extend(&self, base: Rc<Class>) -> Result<(), String> {
+        if let Some(other) = &*self.superclass.borrow() {
+            return Err(format!(
+                "class '{}' can only extend one class, but extends '{}' and '{}'",
+                self.name, other.name, base.name
+            ));
+        }
+
+        if !self.traits.borrow().is_empty() {
+            return Err(format!(
+                "class '{}' must list '{}' before the traits it implements",
+                self.name, base.name
+            ));
+        }
+
+        for field in base.fields.borrow().iter() {
+            self.add_field(field.clone());
+        }
+
+        self.forget_calls();
+
+        let tables = [
+            (&self.methods, &base.methods),
+            (&self.getters, &base.getters),
+            (&self.setters, &base.setters),
+        ];
+
+        for (table, inherited) in tables {
+            let mut table = table.borrow_mut();
+
+            for (name, method) in inherited.borrow().iter() {
+                table.entry(name.clone()).or_insert_with(|| method.clone());
+            }
+        }
+
+        self.traits
+            .borrow_mut()
+            .extend(base.traits.borrow().iter().cloned());
+        *self.superclass.borrow_mut() = Some(base);
+
+        Ok(())
+    }
+
+    /// Whether the class is `other` or extends it.
+    pub fn is_subclass_of(&self, other: &Class) -> bool {
+        std::ptr::eq(self, other)
+            || self
+                .superclass
+                .borrow()
+                .as_ref()
+                .is_some_and(|base| base.is_subclass_of(other))
+    }
+
+    /// Whether the class or a class it extends is named `name`.
+    pub fn has_name(&self, name: &str) -> bool {
+        &*self.name == name
+            || self
+                .superclass
+                .borrow()
+                .as_ref()
+                .is_some_and(|base| base.has_name(name))
+    }
+
+    /// Copy the fields, methods, getters and setters of `mixin` into the
+    /// class, failing if they have a method with the same name.
+    pub fn mix_in(&self, mixin: &Class) -> Result<(), String> {
+        for field in mixin.fields.borrow().iter() {
+            self.add_field(field.clone());
+        }
+
+        self.forget_calls();
+
+        let tables = [
+            (&self.methods, &mixin.methods),
+            (&self.getters, &mixin.getters),
+            (&self.setters, &mixin.setters),
+        ];
+
+        for (table, mixed) in tables {
+            let mut table = table.borrow_mut();
+
+            for (name, method) in mixed.borrow().iter() {
+                if table.insert(name.clone(), method.clone()).is_some() {
+                    return Err(format!(
+                        "'{name}' from mixin '{}' conflicts with the one in '{}'",
+                        mixin.name, self.name
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the class implements the trait named `name`.
+    pub fn implements(&self, name: &str) -> bool {
+        self.traits.borrow().iter().any(|t| &*t.name == name)
+    }
+
+    /// Whether the class implements `t` itself, rather than another trait
+    /// with the same name.
+    pub fn implements_trait(&self, t: &Rc<Trait>) -> bool {
+        self.traits
+            .borrow()
+            .iter()
+            .any(|other| Rc::ptr_eq(other, t))
+    }
+}
+
+/// A trait, naming the methods a class has to have to implement it.
+#[derive(Debug, PartialEq)]
+pub struct Trait {
+    pub name: Box<str>,
+    /// The names of the required methods, with how many parameters each
+    /// takes.
+    pub methods: Vec<(Box<str>, usize)>,
+}
+
+impl Trait {
+    pub fn new<S: Into<Box<str>>>(name: S, methods: Vec<(Box<str>, usize)>) -> Self {
+        Self {
+            name: name.into(),
+            methods,
+        }
+    }
+
+    /// Check that `class` has every method the trait requires. Script
+    /// methods must take the same number of parameters, or fewer if they
+    /// collect the rest.
+    pub fn check(&self, class: &Class) -> Result<(), String> {
+        for (name, arity) in &self.methods {
+            let fits = match class.get_method(name) {
+                Some(Method::Script(fun)) if fun.variadic => fun.arity <= *arity,
+                Some(Method::Script(fun)) => fun.arity == *arity,
+                Some(Method::Native(_)) => true,
+                None => {
+                    return Err(format!(
+                        "class '{}' is missing the method '{name}' required by trait '{}'",
+                        class.name, self.name
+                    ))
+                }
+            };
+
+            if !fits {
+                return Err(format!(
+                    "method '{}.{name}' must take {arity} parameters to implement trait '{}'",
+                    class.name, self.name
+                ));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Debug for Class {
@@ -77,26 +731,363 @@ impl PartialEq for Class {
     }
 }
 
+/// The declared parameters of a native function.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Params {
+    pub names: Vec<Box<str>>,
+    /// Whether the last parameter collects any extra arguments into a list.
+    pub variadic: bool,
+}
+
+impl Params {
+    /// The number of arguments that must be passed.
+    pub fn required(&self) -> usize {
+        self.names.len() - self.variadic as usize
+    }
+}
+
+type NativeFn = dyn Fn(Vec<Value>) -> Result<Value, String>;
+
+type ReentrantFn = dyn Fn(&mut Vm, Vec<Value>) -> Result<Value, VmError>;
+
+/// The rust implementation of a [Function].
+#[derive(Clone)]
+pub enum Native {
+    /// A function of its arguments alone.
+    Plain(Rc<NativeFn>),
+    /// A function that is given the VM, so it can call back into the script,
+    /// e.g. to run a comparator passed to it.
+    Reentrant(Rc<ReentrantFn>),
+}
+
+/// A function implemented in rust.
 pub struct Function {
     pub name: Box<str>,
-    pub inner: fn(Vec<Value>) -> Value,
+    pub inner: Native,
+    /// The function's parameters, if it declares them. Functions without
+    /// declared parameters receive their arguments unchecked.
+    pub params: Option<Params>,
 }
 
 impl Function {
-    pub fn new<T: Into<Box<str>>>(name: T, inner: fn(Vec<Value>) -> Value) -> Self {
+    pub fn new<T, F>(name: T, inner: F) -> Self
+    where
+        T: Into<Box<str>>,
+        F: Fn(Vec<Value>) -> Result<Value, String> + 'static,
+    {
+        Self {
+            name: name.into(),
+            inner: Native::Plain(Rc::new(inner)),
+            params: None,
+        }
+    }
+
+    /// Create a function that can call script functions through the VM with
+    /// [Vm::call_from_native]. Errors it returns, including those of the
+    /// functions it calls, are raised as they are.
+    pub fn reentrant<T, F>(name: T, inner: F) -> Self
+    where
+        T: Into<Box<str>>,
+        F: Fn(&mut Vm, Vec<Value>) -> Result<Value, VmError> + 'static,
+    {
         Self {
             name: name.into(),
-            inner,
+            inner: Native::Reentrant(Rc::new(inner)),
+            params: None,
         }
     }
+
+    /// Declare the function's parameters. A name starting with `...` must
+    /// come last and collects any extra arguments into a list, like a
+    /// script's rest parameter.
+    pub fn with_params(mut self, names: &[&str]) -> Self {
+        let variadic = names.last().is_some_and(|name| name.starts_with("..."));
+        let names = names
+            .iter()
+            .map(|name| name.trim_start_matches("...").into())
+            .collect();
+
+        self.params = Some(Params { names, variadic });
+        self
+    }
 }
 
 impl Callable for Function {
-    fn call(&self, args: Vec<Value>) -> Value {
-        (self.inner)(args)
+    fn call(&self, args: Vec<Value>) -> Result<Value, String> {
+        match &self.inner {
+            Native::Plain(inner) => inner(args),
+            Native::Reentrant(_) => Err(format!(
+                "native function '{}' can only be called by the vm",
+                self.name
+            )),
+        }
+    }
+}
+
+impl Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.inner, &other.inner) {
+            (Native::Plain(a), Native::Plain(b)) => Rc::ptr_eq(a, b),
+            (Native::Reentrant(a), Native::Reentrant(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// A function compiled from a Thrush script.
+#[derive(Debug, Clone)]
+pub struct ScriptFun {
+    pub name: Box<str>,
+    /// The number of arguments that must be passed.
+    pub arity: usize,
+    /// Whether a final rest parameter collects any extra arguments into a list.
+    pub variadic: bool,
+    /// The names of the parameters, including any rest parameter.
+    pub params: Vec<Box<str>>,
+    /// Whether the function contains `yield`, so calling it returns a
+    /// [Generator] rather than running it.
+    pub generator: bool,
+    /// Whether the function was declared `async`, so calling it starts a
+    /// task and returns a [Promise] of its result.
+    pub is_async: bool,
+    /// The docstring at the start of the function's body, if any.
+    pub doc: Option<Box<str>>,
+    pub chunk: Rc<Chunk>,
+    /// Where each closure made from the function gets its upvalues.
+    pub captures: Vec<Capture>,
+    /// The variables of enclosing functions a closure uses, in the order of
+    /// `captures`. Empty for the function closures are made from.
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl ScriptFun {
+    pub fn new<S: Into<Box<str>>>(name: S, arity: usize, chunk: Chunk) -> Self {
+        Self {
+            name: name.into(),
+            arity,
+            variadic: false,
+            params: Vec::new(),
+            generator: false,
+            is_async: false,
+            doc: None,
+            chunk: Rc::new(chunk),
+            captures: Vec::new(),
+            upvalues: Vec::new(),
+        }
+    }
+}
+
+impl PartialEq for ScriptFun {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.chunk, &other.chunk)
+            && self.upvalues.len() == other.upvalues.len()
+            && self
+                .upvalues
+                .iter()
+                .zip(&other.upvalues)
+                .all(|(a, b)| Rc::ptr_eq(a, b))
+    }
+}
+
+/// Where a closure gets one of its upvalues when it is made.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Capture {
+    /// Whether the upvalue is a local of the enclosing function, rather
+    /// than one of the enclosing closure's own upvalues.
+    pub local: bool,
+    /// The slot of the local, or the index of the upvalue.
+    pub index: usize,
+}
+
+/// A variable of an enclosing function that a closure uses, shared by every
+/// closure that captures it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Upvalue {
+    /// The variable is still in scope, in this stack slot.
+    Open(usize),
+    /// The variable went out of scope, so its value was moved here.
+    Closed(Value),
+}
+
+/// A call to a generator function, which runs until its next `yield` each
+/// time it is resumed.
+#[derive(Debug)]
+pub struct Generator {
+    pub fun: Rc<ScriptFun>,
+    pub(crate) state: RefCell<GeneratorState>,
+}
+
+impl Generator {
+    /// Create a generator that will run `fun` from the start, with the
+    /// receiver and arguments in `slots`.
+    pub fn new(fun: Rc<ScriptFun>, slots: Vec<Value>) -> Self {
+        Self {
+            fun,
+            state: RefCell::new(GeneratorState::Suspended {
+                ip: 0,
+                slots,
+                handlers: Vec::new(),
+                upvalues: Vec::new(),
+            }),
+        }
+    }
+
+    /// Whether the generator has returned, so resuming it produces nothing.
+    pub fn is_done(&self) -> bool {
+        matches!(*self.state.borrow(), GeneratorState::Done)
+    }
+}
+
+impl PartialEq for Generator {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+type NextFn = dyn FnMut(&mut Vm) -> Result<Option<Value>, VmError>;
+
+/// A lazy sequence made by a native function such as `range` or `zip`,
+/// which works out each item when a loop asks for it.
+pub struct Iter {
+    pub name: Box<str>,
+    next: RefCell<Box<NextFn>>,
+}
+
+impl Iter {
+    /// Create an iterator whose items are produced by calling `next` until it
+    /// returns `None`.
+    pub fn new<T, F>(name: T, next: F) -> Self
+    where
+        T: Into<Box<str>>,
+        F: FnMut(&mut Vm) -> Result<Option<Value>, VmError> + 'static,
+    {
+        Self {
+            name: name.into(),
+            next: RefCell::new(Box::new(next)),
+        }
+    }
+
+    /// Produce the next item, or `None` once the sequence has ended.
+    pub fn next(&self, vm: &mut Vm) -> Result<Option<Value>, VmError> {
+        let mut next = self.next.try_borrow_mut().map_err(|_| {
+            VmError::new(
+                ErrorCode::NotIterable,
+                format!("iterator '{}' is already running", self.name),
+            )
+        })?;
+
+        next(vm)
+    }
+}
+
+impl Debug for Iter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Iter").field("name", &self.name).finish()
+    }
+}
+
+impl PartialEq for Iter {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
     }
 }
 
+/// Where a [Generator] is in running its function.
+#[derive(Debug)]
+pub(crate) enum GeneratorState {
+    /// Waiting to run from `ip`, with the function's stack slots, its
+    /// active try blocks, whose stack heights are relative to the slots, and
+    /// the upvalues capturing its slots, which are reopened when it resumes.
+    Suspended {
+        ip: usize,
+        slots: Vec<Value>,
+        handlers: Vec<(usize, usize)>,
+        upvalues: Vec<(usize, Rc<RefCell<Upvalue>>)>,
+    },
+    /// Being run, so it can't be resumed again until it yields.
+    Running,
+    Done,
+}
+
+/// A value that will be available later, such as the result of an async
+/// function or of a native function doing IO.
+///
+/// `await` suspends the async function it is in until the promise settles.
+/// Hosts hand out pending promises from native functions, settle them once
+/// the work is done, and then call [Vm::poll](crate::vm::Vm::poll) to
+/// resume the functions waiting on them.
+#[derive(Debug, Default)]
+pub struct Promise {
+    state: RefCell<PromiseState>,
+}
+
+impl Promise {
+    /// Create a promise that hasn't settled yet.
+    pub fn new() -> Rc<Self> {
+        Rc::default()
+    }
+
+    /// Create a promise that has already resolved to `value`.
+    pub fn resolved(value: Value) -> Rc<Self> {
+        let promise = Self::new();
+
+        promise.resolve(value);
+        promise
+    }
+
+    /// Resolve the promise to `value`, unless it has already settled.
+    pub fn resolve(&self, value: Value) {
+        self.settle(PromiseState::Resolved(value));
+    }
+
+    /// Reject the promise with `error`, which is thrown from the `await`s
+    /// waiting on it, unless it has already settled.
+    pub fn reject(&self, error: Value) {
+        self.settle(PromiseState::Rejected(error));
+    }
+
+    fn settle(&self, state: PromiseState) {
+        let mut current = self.state.borrow_mut();
+
+        if matches!(*current, PromiseState::Pending) {
+            *current = state;
+        }
+    }
+
+    /// Whether the promise has settled, and how.
+    pub fn state(&self) -> PromiseState {
+        self.state.borrow().clone()
+    }
+
+    /// Whether the promise is still waiting to settle.
+    pub fn is_pending(&self) -> bool {
+        matches!(*self.state.borrow(), PromiseState::Pending)
+    }
+}
+
+impl PartialEq for Promise {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Where a [Promise] is in settling.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum PromiseState {
+    #[default]
+    Pending,
+    Resolved(Value),
+    Rejected(Value),
+}
+
 /// An instance of a [Class].
 #[derive(Debug, PartialEq)]
 pub struct Instance {
@@ -106,14 +1097,57 @@ pub struct Instance {
 
 impl Instance {
     pub fn new(class: Rc<Class>) -> Rc<Self> {
+        let fields = vec![Value::Nil; class.fields.borrow().len()];
+
         Rc::new(Self {
             class,
-            fields: RefCell::new(Vec::new()),
+            fields: RefCell::new(fields),
         })
     }
 
+    /// Whether the instance belongs to `class`.
+    ///
+    /// This is where `is` and class patterns decide membership, so
+    /// inheritance only has to extend it to walk the superclasses.
+    pub fn is_instance_of(&self, class: &Rc<Class>) -> bool {
+        self.class.is_subclass_of(class)
+    }
+
+    /// Get the value of a declared field.
+    pub fn get_field(&self, name: &str) -> Option<Value> {
+        let slot = self.class.field_slot(name)?;
+
+        self.fields().get(slot).cloned()
+    }
+
+    /// Set the value of a declared field.
+    pub fn set_field(&self, name: &str, value: Value) -> Result<(), String> {
+        let slot = self.class.field_slot(name).ok_or_else(|| {
+            let hint = did_you_mean(
+                name,
+                self.class
+                    .fields
+                    .borrow()
+                    .iter()
+                    .chain(self.class.setters.borrow().keys()),
+            );
+
+            format!("'{}' has no field named '{name}'{hint}", self.class.name)
+        })?;
+
+        let mut fields = self.fields_mut();
+
+        if fields.len() <= slot {
+            fields.resize(slot + 1, Value::Nil);
+        }
+
+        fields[slot] = value;
+
+        Ok(())
+    }
+
     /// Bind a method with the given name and call it immediately.
-    pub fn invoke<S: Into<Box<str>>>(receiver: Rc<Self>, name: S) -> Value {
+    pub fn invoke<S: Into<Box<str>>>(receiver: Rc<Self>, name: S) -> Result<Value, String> {
         let bound = Instance::bind(receiver, name);
 
         bound.call(vec![])
@@ -121,10 +1155,9 @@ impl Instance {
 
     /// Bind a method to an instance.
     pub fn bind<S: Into<Box<str>>>(receiver: Rc<Self>, name: S) -> BoundMethod {
-        let instance = receiver.as_ref().class.as_ref().methods.borrow();
-        let method = instance.get(&name.into()).unwrap();
+        let method = receiver.class.get_method(&name.into()).unwrap();
 
-        BoundMethod::new(receiver.clone(), method.clone())
+        BoundMethod::new(receiver, method)
     }
 
     /// Get a mutable reference to the instance's fields.
@@ -165,26 +1198,59 @@ impl PartialEq for InstanceFun {
     }
 }
 
+/// A method stored in a [Class]'s method table.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Method {
+    /// A method implemented in rust.
+    Native(Rc<InstanceFun>),
+    /// A method defined in a Thrush script.
+    Script(Rc<ScriptFun>),
+}
+
+impl Method {
+    pub fn name(&self) -> &str {
+        match self {
+            Method::Native(fun) => &fun.name,
+            Method::Script(fun) => &fun.name,
+        }
+    }
+}
+
+impl From<Rc<InstanceFun>> for Method {
+    fn from(fun: Rc<InstanceFun>) -> Self {
+        Method::Native(fun)
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct BoundMethod {
     pub receiver: Rc<Instance>,
-    pub function: Rc<InstanceFun>,
+    pub function: Method,
 }
 
-impl<'a, 'b> BoundMethod {
-    pub fn new(receiver: Rc<Instance>, function: Rc<InstanceFun>) -> Self {
-        Self { receiver, function }
+impl BoundMethod {
+    pub fn new<M: Into<Method>>(receiver: Rc<Instance>, function: M) -> Self {
+        Self {
+            receiver,
+            function: function.into(),
+        }
     }
 }
 
 impl Callable for BoundMethod {
-    fn call(&self, args: Vec<Value>) -> Value {
-        (self.function.fun)(self.receiver.clone(), args)
+    fn call(&self, args: Vec<Value>) -> Result<Value, String> {
+        match &self.function {
+            Method::Native(fun) => Ok((fun.fun)(self.receiver.clone(), args)),
+            Method::Script(fun) => Err(format!(
+                "script method '{}' can only be called by the vm",
+                fun.name
+            )),
+        }
     }
 }
 
 pub trait Callable {
-    fn call(&self, args: Vec<Value>) -> Value;
+    fn call(&self, args: Vec<Value>) -> Result<Value, String>;
 }
 
 pub trait FromValue: Sized {
@@ -239,6 +1305,30 @@ impl ToValue for Value {
     }
 }
 
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(self) -> Value {
+        Value::list(self.into_iter().map(ToValue::to_value).collect())
+    }
+}
+
+impl ToValue for Map {
+    fn to_value(self) -> Value {
+        Value::map(self)
+    }
+}
+
+impl ToValue for Function {
+    fn to_value(self) -> Value {
+        Value::Function(Rc::new(self))
+    }
+}
+
+impl ToValue for Rc<Function> {
+    fn to_value(self) -> Value {
+        Value::Function(self)
+    }
+}
+
 impl From<()> for Value {
     fn from(_: ()) -> Value {
         Value::Nil
@@ -261,10 +1351,11 @@ macro_rules! impl_from_value {
 impl_from_value!(f64, (Value::Float(v) => Ok(*v)));
 impl_from_value!(f32, (Value::Float(v) => Ok(*v as f32)));
 impl_from_value!(i32, (Value::Integer(v) => Ok(*v as i32)));
-impl_from_value!(i64, (Value::Integer(v) => Ok(*v as i64)));
+impl_from_value!(i64, (Value::Integer(v) => Ok(*v)));
 impl_from_value!(String, (Value::String(v) => Ok(v.to_string())));
 impl_from_value!(bool, (Value::Bool(v) => Ok(*v)));
 impl_from_value!(Rc<Class>, (Value::Class(v) => Ok(v.to_owned())));
+impl_from_value!(Rc<Function>, (Value::Function(v) => Ok(v.to_owned())));
 
 impl FromValue for Value {
     fn from_value(value: &Value) -> Result<Self, String> {
@@ -272,10 +1363,21 @@ impl FromValue for Value {
     }
 }
 
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::List(list) => list.borrow().iter().map(T::from_value).collect(),
+            _ => Err("cannot coerce type from value".into()),
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use std::{mem, rc::Rc};
 
+    use crate::hash::Hash;
+
     use super::{BoundMethod, Callable, Class, FromValue, Instance, InstanceFun, ToValue, Value};
 
     #[test]
@@ -295,13 +1397,13 @@ pub mod test {
             Value::Nil
         });
 
-        let mut method1 = BoundMethod::new(receiver.clone(), Rc::new(fun1));
-        let mut method2 = BoundMethod::new(receiver.clone(), Rc::new(fun2));
+        let method1 = BoundMethod::new(receiver.clone(), Rc::new(fun1));
+        let method2 = BoundMethod::new(receiver.clone(), Rc::new(fun2));
 
-        BoundMethod::call(&mut method1, vec![]);
+        BoundMethod::call(&method1, vec![]).unwrap();
 
         for _ in 0..10 {
-            BoundMethod::call(&mut method2, vec![]);
+            BoundMethod::call(&method2, vec![]).unwrap();
         }
 
         assert_eq!(&receiver.fields_mut()[0], &Value::Integer(20));
@@ -322,14 +1424,14 @@ pub mod test {
                 .to_value()
         });
 
-        let mut constructor = BoundMethod::new(receiver.clone(), Rc::new(constructor));
-        let mut method = BoundMethod::new(receiver.clone(), Rc::new(add));
+        let constructor = BoundMethod::new(receiver.clone(), Rc::new(constructor));
+        let method = BoundMethod::new(receiver.clone(), Rc::new(add));
 
-        BoundMethod::call(&mut constructor, vec![]);
+        BoundMethod::call(&constructor, vec![]).unwrap();
 
         assert_eq!(
-            BoundMethod::call(&mut method, vec![2_i32.to_value()]),
-            3_i32.to_value()
+            BoundMethod::call(&method, vec![2_i32.to_value()]),
+            Ok(3_i32.to_value())
         );
     }
 
@@ -340,7 +1442,7 @@ pub mod test {
         class.add_method("to_string", |_, _| Value::String("__io__".into()));
 
         class.add_method("print", |this, args| {
-            let name = Instance::invoke(this, "to_string");
+            let name = Instance::invoke(this, "to_string").unwrap();
 
             println!("{name}");
             println!("{}", args[0]);
@@ -350,15 +1452,55 @@ pub mod test {
 
         let receiver = class.instance();
 
-        let mut bound = Instance::bind(receiver, "print");
+        let bound = Instance::bind(receiver, "print");
 
-        BoundMethod::call(&mut bound, vec!["Hello, World!".to_value()]);
+        BoundMethod::call(&bound, vec!["Hello, World!".to_value()]).unwrap();
+    }
+
+    #[test]
+    fn test_call_target() {
+        let class = Class::new("Io");
+        let hash = Hash::of("read");
+
+        assert_eq!(class.call_target(hash, "read"), None);
+
+        class.add_method("read", |_, _| Value::Nil);
+
+        assert!(class.call_target(hash, "read").is_some());
+
+        class.add_field("read");
+
+        assert_eq!(class.call_target(hash, "read"), None);
     }
 
     #[test]
     #[allow(dead_code)]
     fn test_value_size() {
         //assert_eq!(16, mem::size_of::<Value>())
-        assert_eq!(32, mem::size_of::<Value>())
+        assert_eq!(24, mem::size_of::<Value>())
+    }
+
+    #[test]
+    fn test_equal_cycle() {
+        let cyclic = |first: i64| {
+            let list = Value::list(vec![Value::Integer(first)]);
+
+            if let Value::List(inner) = &list {
+                inner.borrow_mut().push(list.clone());
+            }
+
+            list
+        };
+        let (a, b, c) = (cyclic(1), cyclic(1), cyclic(2));
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(a, Value::list(vec![Value::Integer(1)]));
+
+        for list in [a, b, c] {
+            if let Value::List(inner) = &list {
+                inner.borrow_mut().clear();
+            }
+        }
     }
 }
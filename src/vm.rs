@@ -1,10 +1,26 @@
-use std::rc::Rc;
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    mem,
+    rc::Rc,
+};
 
 use crate::{
+    ast::FunKind,
+    audit::AuditEntry,
     chunk::Chunk,
+    diagnostic::{did_you_mean, Error, ErrorCode},
+    exception,
+    hash::Hash,
     instruction::{InstanceValue, Instruction},
+    iter,
+    rng::Rng,
     scope::State,
-    value::{BoundMethod, Callable, Class, Instance, Value},
+    token::Span,
+    value::{
+        BoundMethod, Callable, Class, Generator, GeneratorState, Instance, Map, Method, Native,
+        Promise, PromiseState, ScriptFun, Upvalue, Value,
+    },
 };
 
 /// The VM's stack.
@@ -22,7 +38,7 @@ impl Stack {
     pub fn pop(&mut self) -> Result<Value, VmError> {
         self.stack
             .pop()
-            .ok_or_else(|| VmError("stack should not be empty".into()))
+            .ok_or_else(|| VmError::new(ErrorCode::Internal, "stack should not be empty"))
     }
 
     /// Push a value onto the stack.
@@ -30,6 +46,49 @@ impl Stack {
         self.stack.push(value);
     }
 
+    /// Get a reference to the value `distance` slots down from the top of the stack.
+    pub fn peek(&self, distance: usize) -> Result<&Value, VmError> {
+        self.stack
+            .len()
+            .checked_sub(distance + 1)
+            .map(|index| &self.stack[index])
+            .ok_or_else(|| VmError::new(ErrorCode::Internal, "stack should not be empty"))
+    }
+
+    /// Get the value at an absolute position in the stack.
+    pub fn get(&self, index: usize) -> Result<&Value, VmError> {
+        self.stack.get(index).ok_or_else(|| {
+            VmError::new(
+                ErrorCode::Internal,
+                format!("stack slot {index} is out of bounds"),
+            )
+        })
+    }
+
+    /// Overwrite the value at an absolute position in the stack.
+    pub fn set(&mut self, index: usize, value: Value) {
+        self.stack[index] = value;
+    }
+
+    /// Remove every value above `len` and return them in order.
+    pub fn split_off(&mut self, len: usize) -> Vec<Value> {
+        self.stack.split_off(len)
+    }
+
+    /// Drop every value above `len`.
+    pub fn truncate(&mut self, len: usize) {
+        self.stack.truncate(len);
+    }
+
+    /// The number of values on the stack.
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
     /// Reset the stack.
     pub fn clear(&mut self) {
         self.stack.clear();
@@ -40,8 +99,79 @@ impl Stack {
 #[derive(Debug)]
 pub struct VmError(pub String);
 
+impl VmError {
+    /// Create an error with the code `code`.
+    pub fn new<M: Display>(code: ErrorCode, message: M) -> Self {
+        VmError(code.error(message))
+    }
+}
+
+/// The most calls that can be running at once, past which calling a
+/// function fails instead of using up memory.
+pub const MAX_FRAMES: usize = 10_000;
+
+/// The most runs of the dispatch loop that can be nested inside each other,
+/// by getters, setters, `__iter`, generators and native functions calling
+/// back into scripts, past which another fails instead of overflowing the
+/// native stack.
+pub const MAX_NESTED_RUNS: usize = 64;
+
+/// The longest string, in bytes, that repeating a string can build.
+pub const MAX_STRING_LEN: usize = 1 << 28;
+
+/// An active try block.
+#[derive(Debug)]
+struct Handler {
+    /// Where the error handler starts.
+    ip: usize,
+    /// The height of the stack when the try block started.
+    stack: usize,
+    /// The number of frames when the try block started.
+    frames: usize,
+}
+
+/// The state of a function that called another, put back when the call
+/// returns.
+#[derive(Debug)]
+struct Frame {
+    chunk: Rc<Chunk>,
+    ip: usize,
+    base: usize,
+    closure: Option<Rc<ScriptFun>>,
+    constructing: bool,
+}
+
+type MockFn = dyn Fn(&str, &[Value]) -> Result<Value, String>;
+
+/// Answers native calls in place of the real functions during a dry run.
+#[derive(Clone)]
+struct Mock(Rc<MockFn>);
+
+impl fmt::Debug for Mock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Mock")
+    }
+}
+
+/// A call to an async function, suspended until a promise settles.
+#[derive(Debug)]
+struct Task {
+    /// The function's suspended frame.
+    generator: Rc<Generator>,
+    /// The promise of the function's result.
+    promise: Rc<Promise>,
+    /// The promise the function is awaiting.
+    waiting: Rc<Promise>,
+}
+
+/// What a generator did when it was resumed.
+enum Resumed {
+    Yielded(Value),
+    Returned(Value),
+}
+
 /// The Thrush stack-based virtual machine.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Vm {
     /// The operand stack.
     stack: Stack,
@@ -51,6 +181,45 @@ pub struct Vm {
     chunk: Rc<Chunk>,
     /// index pointer
     ip: usize,
+    /// The stack slot of the current function's receiver.
+    base: usize,
+    /// The function being run, whose upvalues closures read and write.
+    closure: Option<Rc<ScriptFun>>,
+    /// Whether the function being run is the `__init` of an instance being
+    /// constructed, so it returns the instance rather than its own result.
+    constructing: bool,
+    /// The upvalues capturing locals that are still on the stack.
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    /// The random number generator shared by builtins.
+    pub rng: Rc<RefCell<Rng>>,
+    /// The try blocks that have been entered and not yet left, innermost last.
+    handlers: Vec<Handler>,
+    /// The value of the error being thrown by `throw`, if any.
+    thrown: Option<Value>,
+    /// The value produced by the `yield` that suspended the running generator.
+    yielded: Option<Value>,
+    /// The native calls made so far, if they are being audited.
+    audit: Option<Vec<AuditEntry>>,
+    /// The handler native calls go to instead in a dry run.
+    mock: Option<Mock>,
+    /// The async functions waiting for a promise to settle.
+    tasks: Vec<Task>,
+    /// The functions that called the running one, outermost first.
+    frames: Vec<Frame>,
+    /// How many runs of the dispatch loop are nested inside each other.
+    runs: usize,
+    /// Where the error being raised happened, in the innermost function it
+    /// happened in, until it is caught or reported.
+    error_span: Option<Span>,
+    /// The built-in `Error` class, whose instances get a stack trace when
+    /// they are thrown.
+    pub error_class: Rc<Class>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Vm {
@@ -60,19 +229,125 @@ impl Vm {
             stack: Stack::new(),
             chunk: Rc::new(Chunk::new()),
             ip: 0,
+            base: 0,
+            closure: None,
+            constructing: false,
+            open_upvalues: Vec::new(),
+            rng: Rc::new(RefCell::new(Rng::from_entropy())),
+            handlers: Vec::new(),
+            thrown: None,
+            yielded: None,
+            audit: None,
+            mock: None,
+            tasks: Vec::new(),
+            frames: Vec::new(),
+            runs: 0,
+            error_span: None,
+            error_class: exception::error_class(),
+        }
+    }
+
+    /// Reseed the random number generator, making builtins that use it deterministic.
+    pub fn set_seed(&mut self, seed: u64) {
+        *self.rng.borrow_mut() = Rng::with_seed(seed);
+    }
+
+    /// Start recording every native call in the audit log.
+    pub fn enable_audit(&mut self) {
+        self.audit.get_or_insert_with(Vec::new);
+    }
+
+    /// The native calls recorded since auditing was enabled.
+    pub fn audit_log(&self) -> &[AuditEntry] {
+        self.audit.as_deref().unwrap_or_default()
+    }
+
+    /// Take the recorded native calls, leaving the log empty.
+    pub fn take_audit_log(&mut self) -> Vec<AuditEntry> {
+        self.audit.as_mut().map(mem::take).unwrap_or_default()
+    }
+
+    /// Start a dry run, in which every call to a native function or method
+    /// goes to `mock` instead, with the name the audit log would record.
+    pub fn enable_dry_run<F>(&mut self, mock: F)
+    where
+        F: Fn(&str, &[Value]) -> Result<Value, String> + 'static,
+    {
+        self.mock = Some(Mock(Rc::new(mock)));
+    }
+
+    /// Go back to calling the real native functions.
+    pub fn disable_dry_run(&mut self) {
+        self.mock = None;
+    }
+
+    /// Call the native `name` with `call`, recording the call if auditing is
+    /// enabled and passing it to the mock instead during a dry run.
+    fn call_native<N, F>(&mut self, name: N, args: Vec<Value>, call: F) -> Result<Value, VmError>
+    where
+        N: FnOnce() -> String,
+        F: FnOnce(&mut Self, Vec<Value>) -> Result<Value, VmError>,
+    {
+        if self.audit.is_none() && self.mock.is_none() {
+            return call(self, args);
+        }
+
+        let name = name();
+
+        if let Some(log) = &mut self.audit {
+            log.push(AuditEntry::new(name.clone(), &args));
         }
+
+        match self.mock.clone() {
+            Some(mock) => (mock.0)(&name, &args).map_err(native_error),
+            None => call(self, args),
+        }
+    }
+
+    /// Pop the value a script left on the stack, see
+    /// [Compiler::finish_with_value](crate::compiler::Compiler::finish_with_value).
+    pub(crate) fn pop_result(&mut self) -> Result<Value, VmError> {
+        self.stack.pop()
     }
 
     /// Reset the VM's internal state.
     pub fn reset(&mut self) {
+        self.close_upvalues(0);
         self.stack.clear();
+        self.closure = None;
+        self.constructing = false;
         self.ip = 0;
+        self.base = 0;
+        self.handlers.clear();
+        self.frames.clear();
+        self.thrown = None;
+        self.yielded = None;
+        self.error_span = None;
     }
 
     /// Execute a [Chunk].
-    pub fn execute(&mut self, chunk: Rc<Chunk>) -> Result<(), VmError> {
+    pub fn execute(&mut self, chunk: Rc<Chunk>) -> Result<(), Error> {
         self.chunk = chunk;
-        self.run()
+
+        let result = self.run().map_err(|VmError(err)| {
+            let span = self.error_span.take().or_else(|| self.failed_at());
+
+            match span {
+                Some(span) => Error::new(err).at(span),
+                None => Error::new(err),
+            }
+        });
+
+        if result.is_err() {
+            self.thrown = None;
+        }
+
+        result
+    }
+
+    /// Where the instruction that just failed was compiled from.
+    fn failed_at(&self) -> Option<Span> {
+        self.chunk.span(self.ip.checked_sub(1)?)
     }
 
     #[cfg_attr(feature = "bench", inline(never))]
@@ -85,104 +360,3368 @@ impl Vm {
         self.stack.push(value.into_value());
     }
 
+    /// Read the property `name` of an instance: a field, the value of a
+    /// getter, or a method bound to the instance.
+    pub(crate) fn get_property(
+        &mut self,
+        instance: Rc<Instance>,
+        name: &str,
+    ) -> Result<Value, VmError> {
+        if instance.class.field_slot(name).is_some() {
+            Ok(instance.get_field(name).unwrap_or(Value::Nil))
+        } else if let Some(getter) = instance.class.get_getter(name) {
+            self.invoke(instance, getter, Vec::new())
+        } else if let Some(method) = instance.class.get_method(name) {
+            Ok(Value::Method(Rc::new(BoundMethod::new(instance, method))))
+        } else {
+            let hint = did_you_mean(name, instance.class.property_names());
+
+            Err(VmError::new(
+                ErrorCode::UndefinedProperty,
+                format!(
+                    "'{}' has no property named '{name}'{hint}",
+                    instance.class.name
+                ),
+            ))
+        }
+    }
+
+    /// Assign `value` to the property `name` of an instance, through its
+    /// setter if it has one and no field of that name.
+    pub(crate) fn set_property(
+        &mut self,
+        instance: Rc<Instance>,
+        name: &str,
+        value: Value,
+    ) -> Result<(), VmError> {
+        match instance.class.get_setter(name) {
+            Some(setter) if instance.class.field_slot(name).is_none() => {
+                self.invoke(instance, setter, vec![value])?;
+
+                Ok(())
+            }
+            _ => instance
+                .set_field(name, value)
+                .map_err(|err| VmError::new(ErrorCode::UndefinedProperty, err)),
+        }
+    }
+
     fn op_get_prop(&mut self, index: usize) -> Result<(), VmError> {
-        let instance = self.stack.pop()?;
+        let chunk = Rc::clone(&self.chunk);
+        let name = &*chunk.variables[index];
+
+        match self.stack.pop()? {
+            Value::Instance(instance) => {
+                let value = self.get_property(instance, name)?;
+
+                self.stack.push(value);
+            }
+            Value::Class(class) => match class.get_static(name) {
+                Some(value) => self.stack.push(value),
+                None => {
+                    let hint = did_you_mean(name, class.statics.borrow().keys());
+
+                    return Err(VmError::new(
+                        ErrorCode::UndefinedProperty,
+                        format!("'{}' has no static method named '{name}'{hint}", class.name),
+                    ));
+                }
+            },
+            Value::Module(module) => match module.exports.get(name) {
+                Some(global) if self.state.contains(global) => {
+                    let value = self.state.get::<Value>(global).map_err(VmError)?;
 
-        if let Value::Instance(instance) = instance {
-            let name = &*self.chunk.variables[index];
-            let bound = Instance::bind(instance, name);
+                    self.stack.push(value);
+                }
+                _ => {
+                    let hint = did_you_mean(name, module.exports.keys());
 
-            self.stack.push(Value::Method(Rc::new(bound)));
+                    return Err(VmError::new(
+                        ErrorCode::UndefinedProperty,
+                        format!(
+                            "module '{}' has no export named '{name}'{hint}",
+                            module.name
+                        ),
+                    ));
+                }
+            },
+            value => {
+                return Err(VmError::new(
+                    ErrorCode::NoProperties,
+                    format!("'{value}' has no properties"),
+                ))
+            }
         }
 
         Ok(())
     }
 
-    fn op_call(&mut self) -> Result<(), VmError> {
+    fn op_set_prop(&mut self, index: usize) -> Result<(), VmError> {
+        let chunk = Rc::clone(&self.chunk);
+        let name = &*chunk.variables[index];
+        let value = self.stack.pop()?;
+
         match self.stack.pop()? {
+            Value::Instance(instance) => self.set_property(instance, name, value.clone())?,
+            Value::Class(class) => {
+                let code = match class.static_fields.borrow().get(name) {
+                    Some(_) => ErrorCode::AssignToConstant,
+                    None => ErrorCode::UndefinedProperty,
+                };
+
+                class
+                    .set_static_field(name, value.clone())
+                    .map_err(|err| VmError::new(code, err))?;
+            }
+            Value::Module(module) => {
+                return Err(VmError::new(
+                    ErrorCode::InvalidAssignment,
+                    format!(
+                        "cannot assign to '{name}' exported by module '{}'",
+                        module.name
+                    ),
+                ))
+            }
+            value => {
+                return Err(VmError::new(
+                    ErrorCode::NoProperties,
+                    format!("'{value}' has no properties"),
+                ))
+            }
+        }
+
+        self.stack.push(value);
+
+        Ok(())
+    }
+
+    fn op_call(&mut self, argc: usize) -> Result<(), VmError> {
+        let base = self.stack.len() - argc - 1;
+
+        match self.stack.get(base)?.clone() {
             Value::Class(class) => {
                 let instance = Class::instance(class);
+
+                // The arguments of a call to a class go to its `__init`
+                // method, if it has one.
+                match instance.class.get_method("__init") {
+                    Some(Method::Script(init)) => {
+                        let frames = self.frames.len();
+
+                        self.stack.set(base, Value::Instance(Rc::clone(&instance)));
+                        self.call_script(init, base, argc)?;
+
+                        // The `__init` runs in a frame of its own like any
+                        // other call, which returns the instance.
+                        if self.frames.len() > frames {
+                            self.constructing = true;
+
+                            return Ok(());
+                        }
+                    }
+                    Some(init) => {
+                        let args = self.stack.split_off(base + 1);
+
+                        self.invoke(Rc::clone(&instance), init, args)?;
+                    }
+                    None => check_arity(&instance.class.name, 0, false, argc)?,
+                }
+
+                self.stack.truncate(base);
                 self.stack.push(Value::Instance(instance));
             }
-            Value::Method(bound) => {
-                let method = bound.as_ref();
-                let result = BoundMethod::call(method, Vec::new());
+            Value::Method(bound) => match &bound.function {
+                Method::Native(fun) => {
+                    let args = self.stack.split_off(base + 1);
+                    let result = self.call_native(
+                        || format!("{}.{}", bound.receiver.class.name, fun.name),
+                        args,
+                        |_, args| BoundMethod::call(bound.as_ref(), args).map_err(native_error),
+                    )?;
+
+                    self.stack.truncate(base);
+                    self.stack.push(result);
+                }
+                Method::Script(fun) => {
+                    self.stack
+                        .set(base, Value::Instance(bound.receiver.clone()));
+                    self.call_script(fun.clone(), base, argc)?;
+                }
+            },
+            Value::Function(fun) => {
+                let mut args = self.stack.split_off(base + 1);
 
+                if let Some(params) = &fun.params {
+                    check_arity(&fun.name, params.required(), params.variadic, args.len())?;
+
+                    if params.variadic {
+                        let rest = args.split_off(params.required());
+                        args.push(Value::list(rest));
+                    }
+                }
+
+                let result = self.call_native(
+                    || fun.name.to_string(),
+                    args,
+                    |vm, args| match &fun.inner {
+                        Native::Plain(inner) => inner(args).map_err(native_error),
+                        Native::Reentrant(inner) => inner(vm, args),
+                    },
+                )?;
+
+                self.stack.truncate(base);
                 self.stack.push(result);
             }
-            value => return Err(VmError(format!("'{value}' is not callable"))),
+            Value::ScriptFun(fun) => self.call_script(fun, base, argc)?,
+            value => {
+                return Err(VmError::new(
+                    ErrorCode::NotCallable,
+                    format!("'{value}' is not callable"),
+                ))
+            }
         }
 
         Ok(())
     }
 
-    fn op_class(&mut self, index: usize) -> Result<(), VmError> {
-        let name = &*self.chunk.variables[index];
-        let class = Class::new(name);
+    /// Reorder named arguments into parameter order, then call the function.
+    fn op_call_named(&mut self, argc: usize, names: usize) -> Result<(), VmError> {
+        let names = match &self.chunk.constants[names] {
+            Value::List(names) => names
+                .borrow()
+                .iter()
+                .map(Value::to_string)
+                .collect::<Vec<_>>(),
+            _ => {
+                return Err(VmError::new(
+                    ErrorCode::Internal,
+                    "expected a list of argument names",
+                ))
+            }
+        };
+        let base = self.stack.len() - argc - 1;
 
-        self.stack.push(Value::Class(class));
+        let (name, params, variadic) = match self.stack.get(base)? {
+            Value::ScriptFun(fun) => (fun.name.clone(), fun.params.clone(), fun.variadic),
+            Value::Method(bound) => match &bound.function {
+                Method::Script(fun) => (fun.name.clone(), fun.params.clone(), fun.variadic),
+                Method::Native(fun) => {
+                    return Err(VmError::new(
+                        ErrorCode::NamedArgument,
+                        format!("'{}' does not accept named arguments", fun.name),
+                    ))
+                }
+            },
+            Value::Function(fun) => match &fun.params {
+                Some(params) => (fun.name.clone(), params.names.clone(), params.variadic),
+                None => {
+                    return Err(VmError::new(
+                        ErrorCode::NamedArgument,
+                        format!("'{}' does not accept named arguments", fun.name),
+                    ))
+                }
+            },
+            value => {
+                return Err(VmError::new(
+                    ErrorCode::NamedArgument,
+                    format!("'{value}' does not accept named arguments"),
+                ))
+            }
+        };
 
-        Ok(())
-    } 
+        // A rest parameter can't be passed by name, so it is left empty.
+        let required = params.len() - variadic as usize;
+        let mut named = self.stack.split_off(base + 1 + argc - names.len());
+        let mut args = self
+            .stack
+            .split_off(base + 1)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
 
-    pub fn run(&mut self) -> Result<(), VmError> {
-        loop {
-            let inst = *self.get_next_inst();
+        if args.len() > required {
+            return Err(VmError::new(
+                ErrorCode::NamedArgument,
+                format!(
+                "'{name}' expected at most {required} positional arguments before named arguments"
+            ),
+            ));
+        }
 
-            match inst {
-                Instruction::Push { value } => self.op_push(value),
-                Instruction::Pop => {
-                    self.stack.pop()?;
-                },
-                Instruction::Class { index } => self.op_class(index)?,
-                Instruction::Call => self.op_call()?,
-                Instruction::LoadNil => {
-                    self.stack.push(Value::Nil);
+        args.resize(required, None);
+
+        for (arg, value) in names.iter().zip(named.drain(..)) {
+            let slot = params[..required]
+                .iter()
+                .position(|param| **param == *arg)
+                .ok_or_else(|| {
+                    let hint = did_you_mean(arg, &params[..required]);
+
+                    VmError::new(
+                        ErrorCode::NamedArgument,
+                        format!("'{name}' has no parameter named '{arg}'{hint}"),
+                    )
+                })?;
+
+            if args[slot].replace(value).is_some() {
+                return Err(VmError::new(
+                    ErrorCode::NamedArgument,
+                    format!("'{name}' got multiple values for '{arg}'"),
+                ));
+            }
+        }
+
+        for (param, arg) in params.iter().zip(args) {
+            let arg = arg.ok_or_else(|| {
+                VmError::new(
+                    ErrorCode::NamedArgument,
+                    format!("'{name}' is missing argument '{param}'"),
+                )
+            })?;
+
+            self.stack.push(arg);
+        }
+
+        self.op_call(required)
+    }
+
+    /// Call `callee` with `args` from outside of a script, such as from the
+    /// host once a script has run, and return its result.
+    pub fn call(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, VmError> {
+        let result = self.call_from_native(callee, args);
+
+        if result.is_err() {
+            self.reset();
+        }
+
+        result
+    }
+
+    /// Call `callee` with `args` from inside a native function made with
+    /// [Function::reentrant](crate::value::Function::reentrant) and return its
+    /// result. An error leaves the VM as it is, so the native can return it
+    /// to be raised in the script that called the native.
+    pub fn call_from_native(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, VmError> {
+        let argc = args.len();
+
+        self.stack.push(callee);
+
+        for arg in args {
+            self.stack.push(arg);
+        }
+
+        let frames = self.frames.len();
+
+        self.op_call(argc)?;
+        self.finish_call(frames)?;
+        self.stack.pop()
+    }
+
+    /// Call a method on `receiver` and return its result.
+    pub(crate) fn invoke(
+        &mut self,
+        receiver: Rc<Instance>,
+        method: Method,
+        args: Vec<Value>,
+    ) -> Result<Value, VmError> {
+        match method {
+            Method::Native(fun) => self.call_native(
+                || format!("{}.{}", receiver.class.name, fun.name),
+                args,
+                |_, args| Ok((fun.fun)(Rc::clone(&receiver), args)),
+            ),
+            Method::Script(fun) => {
+                let base = self.stack.len();
+                let argc = args.len();
+
+                self.stack.push(Value::Instance(receiver));
+
+                for arg in args {
+                    self.stack.push(arg);
                 }
-                Instruction::GetProperty { index } => self.op_get_prop(index)?,
-                Instruction::GetGlobal { index } => {
-                    let name = &*self.chunk.variables[index];
-                    let value = self.state.get::<Value>(name).map_err(VmError)?;
 
-                    self.stack.push(value);
+                let frames = self.frames.len();
+
+                self.call_script(fun, base, argc)?;
+                self.finish_call(frames)?;
+                self.stack.pop()
+            }
+        }
+    }
+
+    /// Call the property `index` names of the value below `argc` arguments.
+    /// A method of an instance is called with the instance as its receiver
+    /// directly, rather than through a [BoundMethod].
+    fn op_call_method(&mut self, index: usize, hash: Hash, argc: usize) -> Result<(), VmError> {
+        let base = self.stack.len() - argc - 1;
+        let method = match self.stack.get(base)? {
+            Value::Instance(instance) => instance
+                .class
+                .call_target(hash, &self.chunk.variables[index])
+                .map(|method| (Rc::clone(instance), method)),
+            _ => None,
+        };
+
+        match method {
+            Some((_, Method::Script(fun))) => self.call_script(fun, base, argc),
+            Some((receiver, Method::Native(fun))) => {
+                let args = self.stack.split_off(base + 1);
+                let result = self.call_native(
+                    || format!("{}.{}", receiver.class.name, fun.name),
+                    args,
+                    |_, args| Ok((fun.fun)(Rc::clone(&receiver), args)),
+                )?;
+
+                self.stack.truncate(base);
+                self.stack.push(result);
+
+                Ok(())
+            }
+            // Fields holding functions, getters, static methods and module
+            // exports are looked up and called like any other value.
+            None => {
+                let args = self.stack.split_off(base + 1);
+
+                self.op_get_prop(index)?;
+
+                for arg in args {
+                    self.stack.push(arg);
                 }
-                Instruction::Halt => break,
-                Instruction::DefineGlobal { index } => self.define_global(index)?,
-                Instruction::SetGlobal { .. } => todo!(),
-            };
+
+                self.op_call(argc)
+            }
+        }
+    }
+
+    /// Start a call of a script function whose receiver and arguments start
+    /// at `base`. The function runs in a new frame, once the instruction
+    /// making the call returns to the dispatch loop, while a generator or
+    /// async function is pushed instead.
+    fn call_script(&mut self, fun: Rc<ScriptFun>, base: usize, argc: usize) -> Result<(), VmError> {
+        check_arity(&fun.name, fun.arity, fun.variadic, argc)?;
+
+        if fun.variadic {
+            let rest = self.stack.split_off(base + 1 + fun.arity);
+            self.stack.push(Value::list(rest));
         }
 
-        Ok(())
+        if fun.generator {
+            let slots = self.stack.split_off(base);
+
+            self.stack
+                .push(Value::Generator(Rc::new(Generator::new(fun, slots))));
+
+            return Ok(());
+        }
+
+        if fun.is_async {
+            // An async function runs like a generator that suspends at each
+            // `await` of a pending promise, until it settles its own.
+            let slots = self.stack.split_off(base);
+            let generator = Rc::new(Generator::new(fun, slots));
+            let promise = Promise::new();
+
+            self.stack.push(Value::Promise(Rc::clone(&promise)));
+
+            return self.step(generator, promise);
+        }
+
+        self.push_frame(fun, 0, base)
     }
 
-    fn define_global(&mut self, index: usize) -> Result<(), VmError> {
-        let name = &*self.chunk.variables[index];
-        let value = self.stack.pop()?;
+    /// Run the frame a call from outside the dispatch loop pushed, if it
+    /// pushed one when there were `frames`, until the call returns.
+    fn finish_call(&mut self, frames: usize) -> Result<(), VmError> {
+        if self.frames.len() == frames {
+            return Ok(());
+        }
+
+        let result = self.run();
 
-        self.state.add(name, value);
+        self.unwind(frames);
+
+        result
+    }
+
+    /// Save the running function's state and start running `fun` from `ip`,
+    /// with its receiver at `base`.
+    fn push_frame(&mut self, fun: Rc<ScriptFun>, ip: usize, base: usize) -> Result<(), VmError> {
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(VmError::new(
+                ErrorCode::StackOverflow,
+                format!(
+                    "'{}' called with {MAX_FRAMES} calls already running",
+                    fun.name
+                ),
+            ));
+        }
+
+        let frame = Frame {
+            chunk: mem::replace(&mut self.chunk, fun.chunk.clone()),
+            ip: mem::replace(&mut self.ip, ip),
+            base: mem::replace(&mut self.base, base),
+            closure: self.closure.replace(fun),
+            constructing: mem::take(&mut self.constructing),
+        };
+
+        self.frames.push(frame);
 
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    //use std::rc::Rc;
+    /// Go back to the function that called the running one, returning where
+    /// the running one got to.
+    fn pop_frame(&mut self) -> usize {
+        let frame = self.frames.pop().expect("a frame to return to");
 
-    use crate::{compiler::Compiler, lexer::Lexer, parser::Parser, scope::State};
+        self.chunk = frame.chunk;
+        self.base = frame.base;
+        self.closure = frame.closure;
+        self.constructing = frame.constructing;
 
-    //use super::Vm;
+        mem::replace(&mut self.ip, frame.ip)
+    }
 
-    #[test]
-    fn test_vm() {
-        let ast = Parser::new(Lexer::tokenize("1 + 2")).parse().unwrap();
-        let mut scope = State::new();
+    /// Pop the frames above the first `frames`, such as those of the calls
+    /// an error is raised through.
+    fn unwind(&mut self, frames: usize) {
+        while self.frames.len() > frames {
+            self.pop_frame();
+        }
+    }
 
-        let mut compiler = Compiler::new(&mut scope);
-        let _chunk = compiler.run(ast).unwrap();
+    /// The names of the script functions being run, outermost first, for
+    /// the stack traces of errors.
+    fn calls(&self) -> Vec<Box<str>> {
+        self.frames
+            .iter()
+            .map(|frame| &frame.closure)
+            .chain([&self.closure])
+            .flatten()
+            .map(|fun| fun.name.clone())
+            .collect()
+    }
 
-        //let mut vm = Vm::new(Rc::new(chunk));
+    /// The upvalue at `index` of the running closure.
+    fn upvalue(&self, index: usize) -> Result<Rc<RefCell<Upvalue>>, VmError> {
+        self.closure
+            .as_ref()
+            .and_then(|fun| fun.upvalues.get(index))
+            .cloned()
+            .ok_or_else(|| VmError::new(ErrorCode::Internal, format!("no upvalue {index}")))
+    }
 
-        //vm.execute().unwrap();
+    /// The upvalue capturing the stack slot `slot`, shared with the closures
+    /// that already captured it.
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let open = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot));
+
+        if let Some(upvalue) = open {
+            return Rc::clone(upvalue);
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    /// Move the values of the locals from the stack slot `from` up that
+    /// closures captured into their upvalues, and return the upvalues with
+    /// the slots they captured.
+    fn close_upvalues(&mut self, from: usize) -> Vec<(usize, Rc<RefCell<Upvalue>>)> {
+        if self.open_upvalues.is_empty() {
+            return Vec::new();
+        }
+
+        let (closing, open): (Vec<_>, _) = mem::take(&mut self.open_upvalues)
+            .into_iter()
+            .partition(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(slot) if slot >= from));
+
+        self.open_upvalues = open;
+
+        closing
+            .into_iter()
+            .map(|upvalue| {
+                let slot = match *upvalue.borrow() {
+                    Upvalue::Open(slot) => slot,
+                    Upvalue::Closed(_) => unreachable!("only open upvalues are closed"),
+                };
+                let value = self.stack.get(slot).cloned().unwrap_or(Value::Nil);
+
+                *upvalue.borrow_mut() = Upvalue::Closed(value);
+                (slot, upvalue)
+            })
+            .collect()
+    }
+
+    /// Push a closure of the function constant at `index`.
+    fn op_make_closure(&mut self, index: usize) -> Result<(), VmError> {
+        let Value::ScriptFun(fun) = &self.chunk.constants[index] else {
+            return Err(VmError::new(
+                ErrorCode::Internal,
+                "closures can only be made from functions",
+            ));
+        };
+        let fun = Rc::clone(fun);
+        let upvalues = fun
+            .captures
+            .iter()
+            .map(|capture| {
+                if capture.local {
+                    Ok(self.capture_upvalue(self.base + capture.index))
+                } else {
+                    self.upvalue(capture.index)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        self.stack.push(Value::ScriptFun(Rc::new(ScriptFun {
+            upvalues,
+            ..(*fun).clone()
+        })));
+
+        Ok(())
+    }
+
+    /// Run `generator` until its next `yield`, returning the value it yields,
+    /// or `None` once it has returned.
+    pub fn resume(&mut self, generator: &Generator) -> Result<Option<Value>, VmError> {
+        match self.resume_generator(generator)? {
+            Resumed::Yielded(value) => Ok(Some(value)),
+            Resumed::Returned(_) => Ok(None),
+        }
+    }
+
+    fn resume_generator(&mut self, generator: &Generator) -> Result<Resumed, VmError> {
+        let state = mem::replace(&mut *generator.state.borrow_mut(), GeneratorState::Running);
+        let (ip, slots, handlers, upvalues) = match state {
+            GeneratorState::Suspended {
+                ip,
+                slots,
+                handlers,
+                upvalues,
+            } => (ip, slots, handlers, upvalues),
+            GeneratorState::Running => {
+                return Err(VmError::new(
+                    ErrorCode::NotIterable,
+                    format!("generator '{}' is already running", generator.fun.name),
+                ))
+            }
+            GeneratorState::Done => {
+                *generator.state.borrow_mut() = GeneratorState::Done;
+
+                return Ok(Resumed::Returned(Value::Nil));
+            }
+        };
+
+        let base = self.stack.len();
+        let floor = self.handlers.len();
+
+        for slot in slots {
+            self.stack.push(slot);
+        }
+
+        // Closures may have changed the captured locals while the generator
+        // was suspended.
+        for (slot, upvalue) in upvalues {
+            let value = mem::replace(&mut *upvalue.borrow_mut(), Upvalue::Open(base + slot));
+
+            if let Upvalue::Closed(value) = value {
+                self.stack.set(base + slot, value);
+            }
+
+            self.open_upvalues.push(upvalue);
+        }
+
+        let frames = self.frames.len();
+
+        self.handlers
+            .extend(handlers.into_iter().map(|(ip, stack)| Handler {
+                ip,
+                stack: base + stack,
+                frames: frames + 1,
+            }));
+
+        let result = self
+            .push_frame(Rc::clone(&generator.fun), ip, base)
+            .and_then(|_| self.run_above(floor, frames + 1));
+        let handlers = self.handlers.split_off(floor);
+
+        let (state, value) = match (result, self.yielded.take()) {
+            (Ok(()), Some(value)) => {
+                let ip = self.pop_frame();
+                let upvalues = self
+                    .close_upvalues(base)
+                    .into_iter()
+                    .map(|(slot, upvalue)| (slot - base, upvalue))
+                    .collect();
+                let state = GeneratorState::Suspended {
+                    ip,
+                    slots: self.stack.split_off(base),
+                    handlers: handlers
+                        .into_iter()
+                        .map(|handler| (handler.ip, handler.stack - base))
+                        .collect(),
+                    upvalues,
+                };
+
+                (state, Ok(Resumed::Yielded(value)))
+            }
+            (result, _) => {
+                let result = result.and_then(|_| self.stack.pop()).map(Resumed::Returned);
+
+                self.unwind(frames);
+                self.close_upvalues(base);
+                self.stack.truncate(base);
+
+                (GeneratorState::Done, result)
+            }
+        };
+
+        *generator.state.borrow_mut() = state;
+
+        value
+    }
+
+    /// Run an async function's `generator` until it awaits a pending
+    /// promise or settles its own `promise`.
+    fn step(&mut self, generator: Rc<Generator>, promise: Rc<Promise>) -> Result<(), VmError> {
+        match self.resume_generator(&generator) {
+            Ok(Resumed::Yielded(Value::Promise(waiting))) => self.tasks.push(Task {
+                generator,
+                promise,
+                waiting,
+            }),
+            Ok(Resumed::Yielded(_)) => {
+                return Err(VmError::new(
+                    ErrorCode::Internal,
+                    "expected an async function to await a promise",
+                ))
+            }
+            Ok(Resumed::Returned(value)) => promise.resolve(value),
+            // Nothing can see the promise reject, so report the error instead.
+            Err(err) if Rc::strong_count(&promise) == 1 => return Err(err),
+            Err(err) => promise.reject(self.thrown.take().unwrap_or(Value::String(err.0))),
+        }
+
+        Ok(())
+    }
+
+    /// Resume the async functions whose awaited promises have settled, until
+    /// all of those left are waiting on pending promises, and return how
+    /// many are.
+    ///
+    /// Hosts call this after settling the promises returned by their native
+    /// functions. An error is returned if an async function fails and its
+    /// promise has been dropped, so nothing could observe the rejection.
+    pub fn poll(&mut self) -> Result<usize, VmError> {
+        loop {
+            let (ready, waiting): (Vec<_>, _) = mem::take(&mut self.tasks)
+                .into_iter()
+                .partition(|task| !task.waiting.is_pending());
+
+            self.tasks = waiting;
+
+            if ready.is_empty() {
+                return Ok(self.tasks.len());
+            }
+
+            let mut ready = ready.into_iter();
+
+            while let Some(task) = ready.next() {
+                if let Err(err) = self.step(task.generator, task.promise) {
+                    self.tasks.extend(ready);
+
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Stop the async functions waiting on pending promises, rejecting
+    /// their promises with `error`, and return how many there were.
+    pub fn cancel_tasks(&mut self, error: Value) -> usize {
+        let tasks = mem::take(&mut self.tasks);
+
+        for task in &tasks {
+            task.promise.reject(error.clone());
+        }
+
+        tasks.len()
+    }
+
+    /// Replace the value on top of the stack with what it resolves to, and
+    /// return whether the function has to suspend until it does.
+    fn op_await(&mut self) -> Result<bool, VmError> {
+        let Value::Promise(promise) = self.stack.peek(0)? else {
+            return Ok(false);
+        };
+
+        match promise.state() {
+            PromiseState::Pending => {
+                // Leave the promise on the stack and run this instruction
+                // again once it settles.
+                self.yielded = Some(Value::Promise(Rc::clone(promise)));
+                self.ip -= 1;
+
+                Ok(true)
+            }
+            PromiseState::Resolved(value) => {
+                self.stack.pop()?;
+                self.stack.push(value);
+
+                Ok(false)
+            }
+            PromiseState::Rejected(error) => {
+                self.stack.pop()?;
+                self.stack.push(error);
+                self.op_throw()?;
+
+                Ok(false)
+            }
+        }
+    }
+
+    fn op_return(&mut self) -> Result<(), VmError> {
+        let value = match self.stack.pop()? {
+            _ if self.constructing => self.stack.get(self.base)?.clone(),
+            value => value,
+        };
+
+        self.close_upvalues(self.base);
+        self.stack.truncate(self.base);
+        self.stack.push(value);
+
+        Ok(())
+    }
+
+    fn op_map(&mut self, len: usize) -> Result<(), VmError> {
+        let mut map = Map::new();
+        let mut entries = self.stack.split_off(self.stack.len() - len * 2).into_iter();
+
+        while let (Some(key), Some(value)) = (entries.next(), entries.next()) {
+            match key {
+                Value::String(key) => map.insert(key, value),
+                key => {
+                    return Err(VmError::new(
+                        ErrorCode::InvalidMapKey,
+                        format!("'{key}' cannot be used as a map key"),
+                    ))
+                }
+            };
+        }
+
+        self.stack.push(Value::map(map));
+
+        Ok(())
+    }
+
+    fn op_extend(&mut self) -> Result<(), VmError> {
+        let value = self.stack.pop()?;
+
+        match (self.stack.peek(0)?.clone(), value) {
+            (Value::List(list), value) => {
+                let items = self.spread(value)?;
+
+                list.borrow_mut().extend(items);
+            }
+            (Value::Map(map), Value::Map(entries)) => {
+                let mut map = map.borrow_mut();
+
+                for (key, value) in entries.borrow().iter() {
+                    map.insert(key, value.clone());
+                }
+            }
+            (Value::Map(_), value) => {
+                return Err(VmError::new(
+                    ErrorCode::TypeMismatch,
+                    format!("cannot spread {} '{value}' into a map", value.type_name()),
+                ))
+            }
+            _ => {
+                return Err(VmError::new(
+                    ErrorCode::Internal,
+                    "expected a list or map to extend",
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn op_field(&mut self, index: usize) -> Result<(), VmError> {
+        let name = &*self.chunk.variables[index];
+
+        match self.stack.peek(0)? {
+            Value::Class(class) => {
+                class.add_field(name);
+
+                Ok(())
+            }
+            _ => Err(VmError::new(
+                ErrorCode::TypeMismatch,
+                format!("cannot define field '{name}'"),
+            )),
+        }
+    }
+
+    fn op_method(&mut self, index: usize, kind: FunKind) -> Result<(), VmError> {
+        let fun = self.stack.pop()?;
+        // Methods are named by their declaration rather than the function,
+        // which a decorator may have replaced.
+        let name = self.chunk.variables[index].clone();
+
+        match (self.stack.peek(0)?, fun) {
+            (Value::Class(class), fun @ (Value::ScriptFun(_) | Value::Function(_)))
+                if kind == FunKind::Static =>
+            {
+                class.statics.borrow_mut().insert(name, fun);
+            }
+            (Value::Class(class), Value::ScriptFun(fun)) => {
+                let methods = match kind {
+                    FunKind::Getter => &class.getters,
+                    FunKind::Setter => &class.setters,
+                    _ => &class.methods,
+                };
+
+                methods.borrow_mut().insert(name, Method::Script(fun));
+                class.forget_calls();
+            }
+            (_, fun) => {
+                return Err(VmError::new(
+                    ErrorCode::TypeMismatch,
+                    format!("cannot define method '{name}' as '{fun}'"),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn op_get_field(&mut self, slot: usize) -> Result<(), VmError> {
+        let value = match self.stack.pop()? {
+            Value::Instance(instance) => instance.fields().get(slot).cloned().ok_or_else(|| {
+                VmError::new(
+                    ErrorCode::UndefinedProperty,
+                    format!("'{}' has no field at position {slot}", instance.class.name),
+                )
+            })?,
+            value => {
+                return Err(VmError::new(
+                    ErrorCode::NoProperties,
+                    format!("'{value}' has no fields"),
+                ))
+            }
+        };
+
+        self.stack.push(value);
+
+        Ok(())
+    }
+
+    fn op_class(&mut self, index: usize) -> Result<(), VmError> {
+        let name = &*self.chunk.variables[index];
+        let class = Class::new(name);
+
+        self.stack.push(Value::Class(class));
+
+        Ok(())
+    } 
+
+    /// Run the current chunk until it returns or halts.
+    ///
+    /// Errors raised inside a try block entered by this call are caught by
+    /// its handler, including those raised by the functions it calls.
+    pub fn run(&mut self) -> Result<(), VmError> {
+        self.run_to(self.frames.len())
+    }
+
+    /// Run until the function running with `frames` frames below it returns.
+    fn run_to(&mut self, frames: usize) -> Result<(), VmError> {
+        let handlers = self.handlers.len();
+        let result = self.run_above(handlers, frames);
+
+        self.handlers.truncate(handlers);
+
+        result
+    }
+
+    /// Run until the function running with `frames` frames below it returns,
+    /// catching errors with the try blocks entered past the first `handlers`.
+    fn run_above(&mut self, handlers: usize, frames: usize) -> Result<(), VmError> {
+        if self.runs >= MAX_NESTED_RUNS {
+            let name = self.closure.as_ref().map_or("<script>", |fun| &fun.name);
+            let err = VmError::new(
+                ErrorCode::StackOverflow,
+                format!(
+                    "'{name}' called with {MAX_NESTED_RUNS} getters, setters, iterators and \
+                     native functions already running"
+                ),
+            );
+
+            self.unwind(frames);
+
+            return Err(err);
+        }
+
+        self.runs += 1;
+
+        let result = loop {
+            match self.dispatch(frames) {
+                Err(err) if self.handlers.len() > handlers => self.catch(err),
+                Err(err) => {
+                    if self.error_span.is_none() {
+                        self.error_span = self.failed_at();
+                    }
+
+                    self.unwind(frames);
+
+                    break Err(err);
+                }
+                result => break result,
+            }
+        };
+
+        self.runs -= 1;
+
+        result
+    }
+
+    /// Unwind to the innermost try block and push the error for its handler.
+    fn catch(&mut self, err: VmError) {
+        let handler = self.handlers.pop().expect("a try block to catch the error");
+        let value = self.thrown.take().unwrap_or(Value::String(err.0));
+
+        self.unwind(handler.frames);
+        self.error_span = None;
+        self.close_upvalues(handler.stack);
+        self.stack.truncate(handler.stack);
+        self.stack.push(value);
+        self.ip = handler.ip;
+    }
+
+    fn op_throw(&mut self) -> Result<(), VmError> {
+        let value = self.stack.pop()?;
+        let err = match &value {
+            Value::String(message) => VmError::new(ErrorCode::Uncaught, message),
+            Value::Instance(error) if error.is_instance_of(&self.error_class) => {
+                exception::fill_stack(error, &self.calls());
+                VmError::new(ErrorCode::Uncaught, exception::describe(error))
+            }
+            value => VmError::new(ErrorCode::Uncaught, value),
+        };
+
+        self.thrown = Some(value);
+
+        Err(err)
+    }
+
+    /// Run instructions until the function running with `frames` frames
+    /// below it returns, or the running generator yields or awaits.
+    fn dispatch(&mut self, frames: usize) -> Result<(), VmError> {
+        loop {
+            let inst = *self.get_next_inst();
+
+            match inst {
+                Instruction::Push { value } => self.op_push(value),
+                Instruction::Pop => {
+                    self.stack.pop()?;
+                },
+                Instruction::Swap => {
+                    let b = self.stack.pop()?;
+                    let a = self.stack.pop()?;
+
+                    self.stack.push(b);
+                    self.stack.push(a);
+                }
+                Instruction::Dup { depth } => {
+                    let value = self.stack.peek(depth)?.clone();
+
+                    self.stack.push(value);
+                }
+                Instruction::Unpack { len } => {
+                    let value = self.stack.pop()?;
+                    let items = self.spread(value)?;
+
+                    if items.len() != len {
+                        return Err(VmError::new(
+                            ErrorCode::TypeMismatch,
+                            format!("expected {len} values to unpack but got {}", items.len()),
+                        ));
+                    }
+
+                    for item in items {
+                        self.stack.push(item);
+                    }
+                }
+                Instruction::GetKey { index } => {
+                    let key = &*self.chunk.variables[index];
+                    let value = match self.stack.pop()? {
+                        Value::Map(map) => map.borrow().get(key).cloned().ok_or_else(|| {
+                            VmError::new(
+                                ErrorCode::UndefinedProperty,
+                                format!("map has no key '{key}' to unpack"),
+                            )
+                        })?,
+                        value => {
+                            return Err(VmError::new(
+                                ErrorCode::TypeMismatch,
+                                format!("expected a map to unpack but got '{value}'"),
+                            ))
+                        }
+                    };
+
+                    self.stack.push(value);
+                }
+                Instruction::Constant { index } => {
+                    self.stack.push(self.chunk.constants[index].clone());
+                }
+                Instruction::Class { index } => self.op_class(index)?,
+                Instruction::Field { index } => self.op_field(index)?,
+                Instruction::Version { version } => match self.stack.peek(0)? {
+                    Value::Class(class) => class.version.set(version),
+                    _ => {
+                        return Err(VmError::new(
+                            ErrorCode::Internal,
+                            "expected a class to set the version of",
+                        ))
+                    }
+                },
+                Instruction::Doc { index } => {
+                    match (self.stack.peek(0)?, &self.chunk.constants[index]) {
+                        (Value::Class(class), Value::String(doc)) => {
+                            *class.doc.borrow_mut() = Some(doc.as_str().into());
+                        }
+                        _ => {
+                            return Err(VmError::new(
+                                ErrorCode::Internal,
+                                "expected a class to set the docstring of",
+                            ))
+                        }
+                    }
+                }
+                Instruction::Implement => {
+                    let t = self.stack.pop()?;
+
+                    match (self.stack.peek(0)?, t) {
+                        (Value::Class(class), Value::Trait(t)) => class
+                            .implement(t)
+                            .map_err(|err| VmError::new(ErrorCode::TraitNotImplemented, err))?,
+                        (Value::Class(class), Value::Class(base)) => class
+                            .extend(base)
+                            .map_err(|err| VmError::new(ErrorCode::TypeMismatch, err))?,
+                        (_, t) => {
+                            return Err(VmError::new(
+                                ErrorCode::TypeMismatch,
+                                format!("'{t}' is not a trait or class"),
+                            ))
+                        }
+                    }
+                }
+                Instruction::Mixin => {
+                    let mixin = self.stack.pop()?;
+
+                    match (self.stack.peek(0)?, mixin) {
+                        (Value::Class(class), Value::Class(mixin)) => class
+                            .mix_in(&mixin)
+                            .map_err(|err| VmError::new(ErrorCode::TypeMismatch, err))?,
+                        (_, mixin) => {
+                            return Err(VmError::new(
+                                ErrorCode::TypeMismatch,
+                                format!("'{mixin}' is not a class"),
+                            ))
+                        }
+                    }
+                }
+                Instruction::Method { index } => self.op_method(index, FunKind::Method)?,
+                Instruction::StaticMethod { index } => self.op_method(index, FunKind::Static)?,
+                Instruction::StaticField { index, constant } => {
+                    let value = self.stack.pop()?;
+                    let name = &*self.chunk.variables[index];
+
+                    match self.stack.peek(0)? {
+                        Value::Class(class) => class.add_static_field(name, value, constant),
+                        _ => {
+                            return Err(VmError::new(
+                                ErrorCode::TypeMismatch,
+                                format!("cannot define static field '{name}'"),
+                            ))
+                        }
+                    }
+                }
+                Instruction::Getter { index } => self.op_method(index, FunKind::Getter)?,
+                Instruction::Setter { index } => self.op_method(index, FunKind::Setter)?,
+                Instruction::Call { argc } => self.op_call(argc)?,
+                Instruction::CallMethod { index, hash, argc } => {
+                    self.op_call_method(index, hash, argc as usize)?
+                }
+                Instruction::CallNamed { argc, names } => self.op_call_named(argc, names)?,
+                Instruction::Return => {
+                    self.op_return()?;
+
+                    if self.frames.len() == frames {
+                        return Ok(());
+                    }
+
+                    self.pop_frame();
+                }
+                Instruction::LoadNil => {
+                    self.stack.push(Value::Nil);
+                }
+                Instruction::List { len } => {
+                    let items = self.stack.split_off(self.stack.len() - len);
+
+                    self.stack.push(Value::list(items));
+                }
+                Instruction::Map { len } => self.op_map(len)?,
+                Instruction::Object { len } => {
+                    let fields = self.stack.split_off(self.stack.len() - len);
+                    let Value::Class(class) = self.stack.pop()? else {
+                        return Err(VmError::new(
+                            ErrorCode::Internal,
+                            "expected a class to make an object of",
+                        ));
+                    };
+                    let instance = Instance::new(class);
+
+                    *instance.fields_mut() = fields;
+                    self.stack.push(Value::Instance(instance));
+                }
+                Instruction::Extend => self.op_extend()?,
+                Instruction::CallSpread => {
+                    let args = self.spread(self.stack.peek(0)?.clone())?;
+                    let argc = args.len();
+
+                    self.stack.pop()?;
+
+                    for arg in args {
+                        self.stack.push(arg);
+                    }
+
+                    self.op_call(argc)?;
+                }
+                Instruction::GetProperty { index } => self.op_get_prop(index)?,
+                Instruction::SetProperty { index } => self.op_set_prop(index)?,
+                Instruction::GetGlobal { slot } => {
+                    let value = self.global(slot)?.clone();
+
+                    self.stack.push(value);
+                }
+                Instruction::GetLocal { slot } => {
+                    let value = self.stack.get(self.base + slot)?.clone();
+
+                    self.stack.push(value);
+                }
+                Instruction::MakeClosure { index } => self.op_make_closure(index)?,
+                Instruction::GetUpvalue { index } => {
+                    let value = match &*self.upvalue(index)?.borrow() {
+                        Upvalue::Open(slot) => self.stack.get(*slot)?.clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+
+                    self.stack.push(value);
+                }
+                Instruction::SetUpvalue { index } => {
+                    let value = self.stack.peek(0)?.clone();
+
+                    match &mut *self.upvalue(index)?.borrow_mut() {
+                        Upvalue::Open(slot) => self.stack.set(*slot, value),
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                Instruction::CloseUpvalue { slot } => {
+                    self.close_upvalues(self.base + slot);
+                }
+                Instruction::GetField { slot } => self.op_get_field(slot)?,
+                Instruction::Add
+                | Instruction::Sub
+                | Instruction::Mul
+                | Instruction::Div
+                | Instruction::Rem => {
+                    let b = self.stack.pop()?;
+                    let a = self.stack.pop()?;
+
+                    self.stack.push(arithmetic(inst, a, b)?);
+                }
+                Instruction::AddLocals { a, b } => {
+                    let a = self.stack.get(self.base + a)?.clone();
+                    let b = self.stack.get(self.base + b)?.clone();
+
+                    self.stack.push(arithmetic(Instruction::Add, a, b)?);
+                }
+                Instruction::Neg | Instruction::Not => {
+                    let value = self.stack.pop()?;
+
+                    self.stack.push(unary(inst, value)?);
+                }
+                Instruction::Equal
+                | Instruction::NotEqual
+                | Instruction::Less
+                | Instruction::LessEqual
+                | Instruction::Greater
+                | Instruction::GreaterEqual => {
+                    let b = self.stack.pop()?;
+                    let a = self.stack.pop()?;
+
+                    self.stack.push(binary(inst, a, b)?);
+                }
+                Instruction::Range { step } => {
+                    let step = if step {
+                        self.stack.pop()?
+                    } else {
+                        Value::Integer(1)
+                    };
+                    let end = self.stack.pop()?;
+                    let start = self.stack.pop()?;
+
+                    let range = match (start, end, step) {
+                        (Value::Integer(start), Value::Integer(end), Value::Integer(step)) => {
+                            iter::range(start, end, step)
+                                .map_err(|err| VmError::new(ErrorCode::Arithmetic, err))?
+                        }
+                        (start, end, step) => {
+                            return Err(VmError::new(
+                                ErrorCode::TypeMismatch,
+                                format!("a range needs integers, not '{start}..{end} step {step}'"),
+                            ))
+                        }
+                    };
+
+                    self.stack.push(range);
+                }
+                Instruction::IsInstance => {
+                    let class = self.stack.pop()?;
+                    let value = self.stack.pop()?;
+
+                    let is_instance = match (value, class) {
+                        (Value::Instance(instance), Value::Class(class)) => {
+                            instance.is_instance_of(&class)
+                        }
+                        (Value::Instance(instance), Value::Trait(t)) => {
+                            instance.class.implements_trait(&t)
+                        }
+                        (_, Value::Class(_) | Value::Trait(_)) => false,
+                        (_, class) => {
+                            return Err(VmError::new(
+                                ErrorCode::TypeMismatch,
+                                format!("'{class}' is not a class or trait"),
+                            ))
+                        }
+                    };
+
+                    self.stack.push(Value::Bool(is_instance));
+                }
+                Instruction::TypeOf => {
+                    let value = self.stack.pop()?;
+
+                    self.stack.push(Value::String(value.type_name().into()));
+                }
+                Instruction::Jump { offset } => self.ip += offset,
+                Instruction::JumpIfFalse { offset } => {
+                    if self.stack.pop()?.is_falsey() {
+                        self.ip += offset;
+                    }
+                }
+                Instruction::JumpIfFalseOrPop { offset } => {
+                    if self.stack.peek(0)?.is_falsey() {
+                        self.ip += offset;
+                    } else {
+                        self.stack.pop()?;
+                    }
+                }
+                Instruction::JumpIfTrueOrPop { offset } => {
+                    if self.stack.peek(0)?.is_falsey() {
+                        self.stack.pop()?;
+                    } else {
+                        self.ip += offset;
+                    }
+                }
+                Instruction::JumpIfTrue { offset } => {
+                    if !self.stack.pop()?.is_falsey() {
+                        self.ip += offset;
+                    }
+                }
+                Instruction::Loop { offset } => self.ip -= offset,
+                Instruction::JumpTable { index } => {
+                    let value = self.stack.pop()?;
+
+                    self.ip += self.chunk.tables[index].offset(&value);
+                }
+                Instruction::Try { offset } => self.handlers.push(Handler {
+                    ip: self.ip + offset,
+                    stack: self.stack.len(),
+                    frames: self.frames.len(),
+                }),
+                Instruction::EndTry => {
+                    self.handlers.pop();
+                }
+                Instruction::Throw => self.op_throw()?,
+                Instruction::Iter => {
+                    let value = self.stack.pop()?;
+                    let iterable = self.iterable(value)?;
+
+                    self.stack.push(iterable);
+                    self.stack.push(Value::Integer(0));
+                }
+                Instruction::ForNext { slot, offset } => match self.for_next(self.base + slot)? {
+                    Some(value) => self.stack.push(value),
+                    None => self.ip += offset,
+                },
+                Instruction::Yield => {
+                    self.yielded = Some(self.stack.pop()?);
+
+                    return Ok(());
+                }
+                Instruction::CheckType { ty, what } => {
+                    let value = self.stack.peek(0)?;
+                    let ty = &self.chunk.variables[ty];
+
+                    if !value.has_type(ty) {
+                        return Err(VmError::new(
+                            ErrorCode::TypeMismatch,
+                            format!(
+                                "expected {} to be {ty} but got {} '{value}'",
+                                self.chunk.variables[what],
+                                value.type_name()
+                            ),
+                        ));
+                    }
+                }
+                Instruction::Await => {
+                    if self.op_await()? {
+                        return Ok(());
+                    }
+                }
+                Instruction::Halt => break,
+                Instruction::DefineGlobal { slot } => {
+                    let value = self.stack.pop()?;
+
+                    self.state.set_slot(slot, value);
+                }
+                Instruction::SetGlobal { slot } => {
+                    self.global(slot)?;
+                    self.state.set_slot(slot, self.stack.peek(0)?.clone());
+                }
+                Instruction::SetLocal { slot } => {
+                    let value = self.stack.peek(0)?.clone();
+
+                    self.stack.set(self.base + slot, value);
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Get the value a for loop iterates over for `value`, calling its
+    /// `__iter` method if it is an instance.
+    pub(crate) fn iterable(&mut self, value: Value) -> Result<Value, VmError> {
+        let value = match value {
+            Value::Instance(instance) => match instance.class.get_method("__iter") {
+                Some(method) => self.invoke(instance, method, Vec::new())?,
+                None => Value::Instance(instance),
+            },
+            value => value,
+        };
+
+        match value {
+            Value::List(_)
+            | Value::Map(_)
+            | Value::String(_)
+            | Value::Bytes(_)
+            | Value::Generator(_)
+            | Value::Iter(_) => Ok(value),
+            value => Err(VmError::new(
+                ErrorCode::NotIterable,
+                format!("{} '{value}' is not iterable", value.type_name()),
+            )),
+        }
+    }
+
+    /// Get the next value of the iterable at stack `slot`, advancing the
+    /// cursor in the slot above it. Lists produce their items, maps their
+    /// keys, strings their characters and bytes their values.
+    fn for_next(&mut self, slot: usize) -> Result<Option<Value>, VmError> {
+        let Value::Integer(cursor) = *self.stack.get(slot + 1)? else {
+            return Err(VmError::new(ErrorCode::Internal, "expected a loop cursor"));
+        };
+        let mut cursor = cursor as usize;
+        let iterable = match self.stack.get(slot)? {
+            Value::Generator(generator) => Value::Generator(Rc::clone(generator)),
+            Value::Iter(iter) => Value::Iter(Rc::clone(iter)),
+            iterable => {
+                let next = next_in(iterable, &mut cursor)?;
+
+                self.stack.set(slot + 1, Value::Integer(cursor as i64));
+
+                return Ok(next);
+            }
+        };
+
+        self.next_item(&iterable, &mut cursor)
+    }
+
+    /// Get the next value of `iterable`, which came from [Vm::iterable],
+    /// advancing `cursor` past it for the iterables that don't keep track of
+    /// where they are themselves.
+    pub(crate) fn next_item(
+        &mut self,
+        iterable: &Value,
+        cursor: &mut usize,
+    ) -> Result<Option<Value>, VmError> {
+        match iterable {
+            Value::Generator(generator) => self.resume(generator),
+            Value::Iter(iter) => iter.next(self),
+            iterable => next_in(iterable, cursor),
+        }
+    }
+
+    /// Collect the values a for loop over `value` would go through.
+    fn spread(&mut self, value: Value) -> Result<Vec<Value>, VmError> {
+        let slot = self.stack.len();
+        let iterable = self.iterable(value)?;
+        let mut items = Vec::new();
+
+        self.stack.push(iterable);
+        self.stack.push(Value::Integer(0));
+
+        while let Some(item) = self.for_next(slot)? {
+            items.push(item);
+        }
+
+        self.stack.truncate(slot);
+
+        Ok(items)
+    }
+
+    /// The names of the globals scripts can refer to directly, leaving out
+    /// those namespaced by modules.
+    fn script_globals(&self) -> impl Iterator<Item = &str> {
+        self.state.names().filter(|name| !name.contains("::"))
+    }
+
+    /// The value of the global in `slot`, failing if it isn't defined.
+    fn global(&self, slot: usize) -> Result<&Value, VmError> {
+        self.state.get_slot(slot).ok_or_else(|| {
+            let name = self.state.slot_name(slot);
+
+            VmError::new(
+                ErrorCode::UndefinedVariable,
+                format!(
+                    "undefined variable '{name}'{}",
+                    did_you_mean(name, self.script_globals())
+                ),
+            )
+        })
+    }
+}
+
+/// Get the value at `cursor` in a list, map, string or bytes, advancing the
+/// cursor past it.
+fn next_in(iterable: &Value, cursor: &mut usize) -> Result<Option<Value>, VmError> {
+    let (next, advance) = match iterable {
+        Value::List(list) => (list.borrow().get(*cursor).cloned(), 1),
+        Value::Map(map) => (
+            map.borrow()
+                .entry(*cursor)
+                .map(|(key, _)| Value::String(key.into())),
+            1,
+        ),
+        Value::String(string) => match string.get(*cursor..).and_then(|s| s.chars().next()) {
+            Some(c) => (Some(Value::String(c.into())), c.len_utf8()),
+            None => (None, 0),
+        },
+        Value::Bytes(bytes) => (bytes.get(*cursor).map(|&b| Value::Integer(b.into())), 1),
+        _ => return Err(VmError::new(ErrorCode::Internal, "expected an iterable")),
+    };
+
+    *cursor += advance;
+
+    Ok(next)
+}
+
+/// Wrap the error message of a native function.
+fn native_error(err: String) -> VmError {
+    VmError::new(ErrorCode::Native, err)
+}
+
+/// Check that a function taking `arity` arguments, or at least `arity` if it
+/// is variadic, can be called with `argc` arguments.
+fn check_arity(name: &str, arity: usize, variadic: bool, argc: usize) -> Result<(), VmError> {
+    match (variadic, argc) {
+        (false, argc) if argc != arity => Err(VmError::new(
+            ErrorCode::WrongArity,
+            format!("'{name}' expected {arity} arguments but got {argc}"),
+        )),
+        (true, argc) if argc < arity => Err(VmError::new(
+            ErrorCode::WrongArity,
+            format!("'{name}' expected at least {arity} arguments but got {argc}"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Apply the binary instruction `inst` to `a` and `b`, which is an
+/// equality test, a comparison or arithmetic.
+pub(crate) fn binary(inst: Instruction, a: Value, b: Value) -> Result<Value, VmError> {
+    match inst {
+        Instruction::Equal => Ok(Value::Bool(equal(&a, &b))),
+        Instruction::NotEqual => Ok(Value::Bool(!equal(&a, &b))),
+        Instruction::Less
+        | Instruction::LessEqual
+        | Instruction::Greater
+        | Instruction::GreaterEqual => compare(inst, &a, &b),
+        _ => arithmetic(inst, a, b),
+    }
+}
+
+/// Whether `a` and `b` are equal, where integers and floats are compared
+/// by their values.
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Integer(_), Value::Float(_)) | (Value::Float(_), Value::Integer(_)) => {
+            as_float(a) == as_float(b)
+        }
+        _ => a == b,
+    }
+}
+
+/// Apply the comparison instruction `inst` to `a` and `b`, which have to be
+/// two numbers or two strings. Strings are compared by their bytes.
+fn compare(inst: Instruction, a: &Value, b: &Value) -> Result<Value, VmError> {
+    let ordering = match (a, b) {
+        (Value::Integer(x), Value::Integer(y)) => Some(x.cmp(y)),
+        (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+            as_float(a).partial_cmp(&as_float(b))
+        }
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => {
+            return Err(VmError::new(
+                ErrorCode::TypeMismatch,
+                format!(
+                    "unsupported operand types for {}: {} and {}",
+                    symbol(inst),
+                    a.type_name(),
+                    b.type_name()
+                ),
+            ))
+        }
+    };
+
+    // NaN is neither less than, equal to nor greater than anything.
+    Ok(Value::Bool(ordering.is_some_and(|ordering| match inst {
+        Instruction::Less => ordering.is_lt(),
+        Instruction::LessEqual => ordering.is_le(),
+        Instruction::Greater => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    })))
+}
+
+/// Apply the arithmetic instruction `inst` to `a` and `b`.
+///
+/// Integers stay integers, and become floats when mixed with floats.
+pub(crate) fn arithmetic(inst: Instruction, a: Value, b: Value) -> Result<Value, VmError> {
+    let overflow = || {
+        VmError::new(
+            ErrorCode::Arithmetic,
+            format!("integer overflow in {a} {} {b}", symbol(inst)),
+        )
+    };
+
+    match (inst, &a, &b) {
+        (Instruction::Div | Instruction::Rem, Value::Integer(_), Value::Integer(0)) => {
+            Err(VmError::new(
+                ErrorCode::Arithmetic,
+                format!("{a} {} 0 divides by zero", symbol(inst)),
+            ))
+        }
+        (_, Value::Integer(x), Value::Integer(y)) => match inst {
+            Instruction::Add => x.checked_add(*y),
+            Instruction::Sub => x.checked_sub(*y),
+            Instruction::Mul => x.checked_mul(*y),
+            Instruction::Div => x.checked_div(*y),
+            _ => x.checked_rem(*y),
+        }
+        .map(Value::Integer)
+        .ok_or_else(overflow),
+        (_, Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+            let (x, y) = (as_float(&a), as_float(&b));
+
+            Ok(Value::Float(match inst {
+                Instruction::Add => x + y,
+                Instruction::Sub => x - y,
+                Instruction::Mul => x * y,
+                Instruction::Div => x / y,
+                _ => x % y,
+            }))
+        }
+        (Instruction::Add, Value::String(x), Value::String(y)) => {
+            Ok(Value::String(format!("{x}{y}")))
+        }
+        (Instruction::Mul, Value::String(s), Value::Integer(n))
+        | (Instruction::Mul, Value::Integer(n), Value::String(s)) => {
+            let count = usize::try_from(*n).map_err(|_| {
+                VmError::new(
+                    ErrorCode::Arithmetic,
+                    format!("can't repeat a string {n} times"),
+                )
+            })?;
+
+            match s.len().checked_mul(count) {
+                Some(len) if len <= MAX_STRING_LEN => {}
+                _ => {
+                    return Err(VmError::new(
+                        ErrorCode::Arithmetic,
+                        format!(
+                            "repeating a string {n} times is longer than {MAX_STRING_LEN} bytes"
+                        ),
+                    ))
+                }
+            }
+
+            Ok(Value::String(s.repeat(count)))
+        }
+        _ => Err(VmError::new(
+            ErrorCode::TypeMismatch,
+            format!(
+                "unsupported operand types for {}: {} and {}",
+                symbol(inst),
+                a.type_name(),
+                b.type_name()
+            ),
+        )),
+    }
+}
+
+/// Apply the unary instruction `inst`, [Instruction::Neg] or
+/// [Instruction::Not], to `value`.
+pub(crate) fn unary(inst: Instruction, value: Value) -> Result<Value, VmError> {
+    match (inst, &value) {
+        (Instruction::Not, _) => Ok(Value::Bool(value.is_falsey())),
+        (_, Value::Integer(v)) => v.checked_neg().map(Value::Integer).ok_or_else(|| {
+            VmError::new(
+                ErrorCode::Arithmetic,
+                format!("integer overflow in -({value})"),
+            )
+        }),
+        (_, Value::Float(v)) => Ok(Value::Float(-v)),
+        _ => Err(VmError::new(
+            ErrorCode::TypeMismatch,
+            format!(
+                "unsupported operand type for unary -: {}",
+                value.type_name()
+            ),
+        )),
+    }
+}
+
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Integer(v) => *v as f64,
+        Value::Float(v) => *v,
+        _ => f64::NAN,
+    }
+}
+
+/// The operator an arithmetic or comparison instruction is compiled from.
+fn symbol(inst: Instruction) -> &'static str {
+    match inst {
+        Instruction::Add => "+",
+        Instruction::Sub => "-",
+        Instruction::Mul => "*",
+        Instruction::Div => "/",
+        Instruction::Less => "<",
+        Instruction::LessEqual => "<=",
+        Instruction::Greater => ">",
+        Instruction::GreaterEqual => ">=",
+        _ => "%",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        compiler::Compiler,
+        lexer::Lexer,
+        parser::Parser,
+        scope::State,
+        value::{Class, Function, Promise, PromiseState, Value},
+        Thrush,
+    };
+
+    use super::{MAX_FRAMES, MAX_NESTED_RUNS, MAX_STRING_LEN};
+
+    //use super::Vm;
+
+    #[test]
+    fn test_vm() {
+        let ast = Parser::new(Lexer::tokenize("1 + 2")).parse().unwrap();
+        let mut scope = State::new();
+
+        let mut compiler = Compiler::new(&mut scope);
+        let _chunk = compiler.run(ast).unwrap();
+
+        //let mut vm = Vm::new(Rc::new(chunk));
+
+        //vm.execute().unwrap();
+    }
+
+    #[test]
+    fn test_static_method() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("class Math {\n    static fun id(x) {\n        return x\n    }\n}")
+            .unwrap();
+        thrush.exec("var x = Math.id(7)").unwrap();
+
+        assert_eq!(thrush.globals().get::<i64>("x"), Ok(7));
+        assert!(thrush.exec("Math.missing()").is_err());
+    }
+
+    #[test]
+    fn test_script_method() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("class Bird {\n    fun me() {\n        return self\n    }\n}")
+            .unwrap();
+        thrush
+            .exec("var bird = Bird()\nvar me = bird.me()")
+            .unwrap();
+
+        let bird = thrush.globals().get::<Value>("bird").unwrap();
+
+        assert_eq!(thrush.globals().get::<Value>("me"), Ok(bird));
+        assert!(thrush.exec("Bird.me()").is_err());
+    }
+
+    #[test]
+    fn test_accessors() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "class Box {\n    var inner\n    get size() {\n        return self.inner\n    }\n    set size(v) {\n        self.inner = v\n    }\n}",
+            )
+            .unwrap();
+        thrush
+            .exec("var b = Box()\nvar assigned = b.size = 3\nvar size = b.size")
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<i64>("assigned"), Ok(3));
+        assert_eq!(thrush.globals().get::<i64>("size"), Ok(3));
+    }
+
+    #[test]
+    fn test_native_getter() {
+        let mut thrush = Thrush::new();
+        let class = Class::new("Point");
+
+        class.add_getter("x", |_, _| Value::Integer(4));
+        thrush.globals().add("Point", Value::Class(class));
+
+        thrush.exec("var x = Point().x").unwrap();
+
+        assert_eq!(thrush.globals().get::<i64>("x"), Ok(4));
+        assert!(thrush.exec("Point().x = 1").is_err());
+    }
+
+    #[test]
+    fn test_global_slots() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("var limit = 3\nfun over(n) {\n    return n - limit\n}")
+            .unwrap();
+
+        let slot = thrush.globals().slot("limit");
+
+        thrush.globals().remove("limit");
+
+        assert!(!thrush.globals().contains("limit"));
+        assert_eq!(
+            thrush
+                .exec("var missing = over(5)")
+                .map_err(|err| err.to_string()),
+            Err("[E0001] line 3:16: undefined variable 'limit'".into())
+        );
+
+        thrush.globals().add("limit", 1);
+        thrush.exec("var left = over(5)").unwrap();
+
+        assert_eq!(thrush.globals().slot("limit"), slot);
+        assert_eq!(thrush.globals().get::<i64>("left"), Ok(4));
+    }
+
+    #[test]
+    fn test_native_function() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .globals()
+            .add_function("count", |args| Ok(Value::Integer(args.len() as i64)));
+        thrush
+            .globals()
+            .add_function("fail", |_| Err("no dice".into()));
+        thrush
+            .exec("var n = count(1, \"two\", nil)\nvar f = count\nvar m = f()")
+            .unwrap();
+
+        assert!(thrush
+            .exec("fail()")
+            .unwrap_err()
+            .to_string()
+            .contains("no dice"));
+
+        assert_eq!(thrush.globals().get::<i64>("n"), Ok(3));
+        assert_eq!(thrush.globals().get::<i64>("m"), Ok(0));
+        assert!(thrush.globals().get::<Rc<Function>>("f").is_ok());
+    }
+
+    #[test]
+    fn test_variadic() {
+        let mut thrush = Thrush::new();
+        let join = Function::new("join", |args| match args.as_slice() {
+            [Value::String(sep), Value::List(parts)] => Ok(Value::String(
+                parts
+                    .borrow()
+                    .iter()
+                    .map(Value::to_string)
+                    .collect::<Vec<_>>()
+                    .join(sep),
+            )),
+            _ => Err("unexpected arguments".into()),
+        });
+
+        thrush
+            .globals()
+            .add("join", join.with_params(&["sep", "...parts"]));
+        thrush
+            .exec("fun rest(first, ...others) {\n    return others\n}")
+            .unwrap();
+        thrush
+            .exec("var none = rest(1)\nvar some = rest(1, 2, 3)\nvar joined = join(\"-\", 1, 2)")
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<Vec<i64>>("none"), Ok(vec![]));
+        assert_eq!(thrush.globals().get::<Vec<i64>>("some"), Ok(vec![2, 3]));
+        assert_eq!(thrush.globals().get::<String>("joined"), Ok("1-2".into()));
+        assert!(thrush.exec("rest()").is_err());
+        assert!(thrush.exec("join()").is_err());
+    }
+
+    #[test]
+    fn test_named_args() {
+        let mut thrush = Thrush::new();
+        let rect = Function::new("rect", |args| Ok(Value::list(args)));
+
+        thrush
+            .globals()
+            .add("rect", rect.with_params(&["x", "y", "w", "h"]));
+        thrush
+            .exec("fun sub(a, b) {\n    return [a, b]\n}\nvar s = sub(b: 1, a: 2)")
+            .unwrap();
+        thrush.exec("var r = rect(1, 2, h: 4, w: 3)").unwrap();
+
+        assert_eq!(thrush.globals().get::<Vec<i64>>("s"), Ok(vec![2, 1]));
+        assert_eq!(thrush.globals().get::<Vec<i64>>("r"), Ok(vec![1, 2, 3, 4]));
+        assert!(thrush.exec("rect(1, 2, 3, x: 4)").is_err());
+        assert!(thrush.exec("rect(x: 1)").is_err());
+        assert!(thrush.exec("path.join(x: 1)").is_err());
+        assert!(thrush.exec("fun f(a) {}\nf(b: 1)").is_err());
+    }
+
+    #[test]
+    fn test_match() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "class Point {\n    var x\n    var y\n}\nfun describe(v) {\n    match v {\n        1 => return \"one\"\n        \"x\" => return \"ex\"\n        Point(0, y) => {\n            return y\n        }\n        Point(x, _) => return x\n        other => return [other]\n    }\n}",
+            )
+            .unwrap();
+        thrush
+            .exec("var p = Point()\np.x = 0\np.y = 5\nvar q = Point()\nq.x = 3")
+            .unwrap();
+        thrush
+            .exec("var r = [describe(1), describe(\"x\"), describe(p), describe(q), describe(nil)]")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("r").unwrap().to_string(),
+            "[\"one\", \"ex\", 5, 3, [nil]]"
+        );
+
+        thrush
+            .exec("var got = nil\nmatch [2] {\n    1 => got = 1,\n    x => got = x\n}")
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<Vec<i64>>("got"), Ok(vec![2]));
+    }
+
+    #[test]
+    fn test_switch() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "fun dense(n) {\n    switch n {\n        0 => return \"stop\"\n        1, 2 => return \"walk\"\n        4 => {\n            return \"run\"\n        }\n        _ => return \"idle\"\n    }\n}\nfun sparse(s) {\n    switch s {\n        \"up\", \"jump\" => return 1\n        100 => return 2\n    }\n    return 0\n}",
+            )
+            .unwrap();
+        thrush
+            .exec("var r = [dense(0), dense(2), dense(3), dense(4), dense(9), dense(\"x\"), sparse(\"jump\"), sparse(100), sparse(1)]")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("r").unwrap().to_string(),
+            "[\"stop\", \"walk\", \"idle\", \"run\", \"idle\", \"idle\", 1, 2, 0]"
+        );
+
+        thrush
+            .exec("var got = nil\nswitch 7 {\n    5, 6, 7 => got = 7\n}")
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<i64>("got"), Ok(7));
+        assert!(thrush
+            .exec("switch 1 {\n    1 => nil\n    1 => nil\n}")
+            .is_err());
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut thrush = Thrush::new();
+        let nested = |depth| format!("var x = {}1{}", "[".repeat(depth), "]".repeat(depth));
+
+        thrush.exec(&nested(200)).unwrap();
+        thrush.set_max_depth(50);
+
+        assert!(thrush.exec(&nested(40)).is_ok());
+        assert!(thrush
+            .exec(&nested(60))
+            .unwrap_err()
+            .to_string()
+            .starts_with("[E0403]"));
+        assert!(thrush.exec(&"[".repeat(100)).is_err());
+    }
+
+    #[test]
+    fn test_call_frames() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "fun count(n) {
+    match n {
+        0 => return 0
+        _ => return 1 + count(n - 1)
+    }
+}
+class Node {
+    var next
+    fun depth() {
+        match self.next {
+            nil => return 1
+            next => return 1 + next.depth()
+        }
+    }
+}
+var deep = count(5000)
+var head = nil
+for _ in range(3000) {
+    var node = Node()
+    node.next = head
+    head = node
+}
+var length = head.depth()
+fun forever(n) { return forever(n + 1) }
+var overflow = nil
+try { forever(0) } catch (e) { overflow = e }
+var after = count(10)",
+            )
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("deep"), "5000");
+        assert_eq!(global("length"), "3000");
+        assert_eq!(
+            global("overflow"),
+            format!("[E0104] 'forever' called with {MAX_FRAMES} calls already running")
+        );
+        assert_eq!(global("after"), "10");
+        assert_eq!(
+            thrush.exec("forever(0)").map_err(|err| err.to_string()),
+            Err(format!(
+                "[E0104] line 24:37: 'forever' called with {MAX_FRAMES} calls already running"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_nested_runs() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                r#"class Loop {
+    get x() {
+        return self.x
+    }
+}
+class Forever {
+    fun __iter() {
+        for item in self {}
+        return []
+    }
+}
+var caught = nil
+try {
+    Loop().x
+} catch (e) {
+    caught = e
+}"#,
+            )
+            .unwrap();
+
+        let caught = thrush.globals().get::<String>("caught").unwrap();
+        let nested = format!(
+            "called with {MAX_NESTED_RUNS} getters, setters, iterators and native functions \
+             already running"
+        );
+
+        assert!(caught.starts_with("[E0104] 'x' "), "{caught}");
+        assert!(caught.ends_with(&nested), "{caught}");
+
+        let err = thrush
+            .exec("for item in Forever() {}")
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.starts_with("[E0104] "), "{err}");
+        assert!(err.ends_with(&nested), "{err}");
+        assert_eq!(thrush.exec("var after = Loop() is Loop"), Ok(()));
+    }
+
+    #[test]
+    fn test_constructor_frames() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                r#"class Node {
+    var next
+    fun __init(n) {
+        if n > 0 {
+            self.next = Node(n - 1)
+        }
+    }
+}
+var links = 0
+var node = Node(1000)
+while node != nil {
+    links = links + 1
+    node = node.next
+}
+class Boxed {
+    var value
+    fun __init(value) {
+        self.value = value
+        return 5
+    }
+}
+var boxed = Boxed(1).value"#,
+            )
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("links"), "1001");
+        assert_eq!(global("boxed"), "1");
+
+        let err = thrush.exec("Node(20000)").unwrap_err().to_string();
+
+        assert!(err.starts_with("[E0104] "), "{err}");
+        assert!(err.ends_with(&format!(
+            "'__init' called with {MAX_FRAMES} calls already running"
+        )));
+    }
+
+    #[test]
+    fn test_constructor() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "class Point {
+    var x
+    var y
+    fun __init(x, y) {
+        self.x = x
+        self.y = y
+        return 0
+    }
+}
+class Empty {}
+var p = Point(1, 2)
+var sum = p.x + p.y
+var is_point = p is Point",
+            )
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<i64>("sum"), Ok(3));
+        assert_eq!(thrush.globals().get::<bool>("is_point"), Ok(true));
+        assert_eq!(
+            thrush.exec("Point(1)").map_err(|err| err.to_string()),
+            Err("[E0102] line 1:7: '__init' expected 2 arguments but got 1".into())
+        );
+        assert_eq!(
+            thrush.exec("Empty(1)").map_err(|err| err.to_string()),
+            Err("[E0102] line 1:7: 'Empty' expected 0 arguments but got 1".into())
+        );
+    }
+
+    #[test]
+    fn test_const() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("const LIVES = 3\nconst NAMES = [\"a\"]\nfun f(LIVES) {\n    return LIVES\n}\nvar x = [LIVES, f(1), NAMES]")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("x").unwrap().to_string(),
+            "[3, 1, [\"a\"]]"
+        );
+        assert_eq!(thrush.globals().get::<i64>("LIVES"), Ok(3));
+        assert!(thrush
+            .exec("LIVES = 2")
+            .unwrap_err()
+            .to_string()
+            .starts_with("[E0007]"));
+        assert!(thrush.exec("var NAMES = nil").is_err());
+        assert!(thrush.exec("const A = 1\nA = 2").is_err());
+        assert!(thrush.exec("const B = 1\nself").is_err());
+        assert!(thrush.exec("const B = 2").is_ok());
+    }
+
+    #[test]
+    fn test_let() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("let speed = 2\nlet names = [\"a\"]\nfun f() {\n    return speed * 2\n}\nvar x = [speed, f(), names]")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("x").unwrap().to_string(),
+            "[2, 4, [\"a\"]]"
+        );
+        assert_eq!(
+            thrush.exec("speed = 3").unwrap_err().to_string(),
+            "[E0007] line 1:1: cannot assign to 'speed', which is declared with let"
+        );
+        assert!(thrush.exec("let names = []").is_err());
+        assert!(thrush.exec("const speed = 1").is_err());
+        assert!(thrush.exec("let a = 1\na = 2").is_err());
+        assert!(thrush.exec("let b").is_err());
+        assert_eq!(thrush.globals().get::<i64>("speed"), Ok(2));
+    }
+
+    #[test]
+    fn test_decorators() {
+        let mut thrush = Thrush::new();
+        let routes = Rc::new(RefCell::new(Vec::new()));
+        let registered = Rc::clone(&routes);
+
+        thrush.globals().add_function("route", move |args| {
+            registered.borrow_mut().push(args[0].to_string());
+            Ok(args[0].clone())
+        });
+        thrush
+            .exec("fun tripled(f) { return fun(x) { return x * 3 } }\nfun named(f) { return \"replaced\" }\n@named\n@tripled\nfun f(x) { return x }\n@route fun home() {}\nclass C {\n    @tripled fun m(x) { return x }\n    @route\n    static fun s() {}\n}\nvar x = [f, C().m(2)]")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("x").unwrap().to_string(),
+            "[\"replaced\", 6]"
+        );
+        assert_eq!(*routes.borrow(), ["<fun home>", "<fun s>"]);
+        assert_eq!(
+            thrush
+                .exec("class D {\n    @named fun m() {}\n}")
+                .unwrap_err()
+                .to_string(),
+            "[E0204] line 2:6: cannot define method 'm' as 'replaced'"
+        );
+    }
+
+    #[test]
+    fn test_anonymous_function() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "var double = fun(x) { return x * 2 }\nfun twice(f, x) {\n    var g = fun(y) { return y + 1 }\n    return f(g(x))\n}\nvar x = twice(double, 20)\nvar name = double",
+            )
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<i64>("x"), Ok(42));
+        assert_eq!(
+            thrush.globals().get::<Value>("name").unwrap().to_string(),
+            "<fun anonymous>"
+        );
+    }
+
+    #[test]
+    fn test_increment() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("class Counter {\n    var n\n}\nvar c = Counter()\nc.n = 1.5\nvar i = 1\ni++\n++i\nvar j = i--\nc.n++\nvar total = 0\nfor x in [1, 2, 3] {\n    total++\n}\nvar k = 5\nvar sum = k++ + ++k\nvar made = 0\nfun make() {\n    made++\n    return c\n}\nmake().n++\nvar old = make().n--\nfun count() {\n    var n = 1\n    var m = n++\n    return m * 10 + n\n}\nvar local = count()")
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<i64>("i"), Ok(2));
+        assert_eq!(thrush.globals().get::<i64>("j"), Ok(3));
+        assert_eq!(thrush.globals().get::<i64>("total"), Ok(3));
+        assert_eq!(thrush.globals().get::<i64>("k"), Ok(7));
+        assert_eq!(thrush.globals().get::<i64>("sum"), Ok(12));
+        assert_eq!(thrush.globals().get::<i64>("made"), Ok(2));
+        assert_eq!(thrush.globals().get::<f64>("old"), Ok(3.5));
+        assert_eq!(thrush.globals().get::<i64>("local"), Ok(12));
+        assert_eq!(
+            thrush
+                .exec("var n = c.n")
+                .map(|()| thrush.globals().get::<f64>("n")),
+            Ok(Ok(2.5))
+        );
+        assert!(thrush.exec("var s = \"a\"\ns++").is_err());
+    }
+
+    #[test]
+    fn test_trait() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("trait Drawable {\n    fun draw(canvas)\n}\nclass Sprite : Drawable {\n    fun draw(canvas) { return \"sprite\" }\n}\nclass Rock {}\nvar sprite = Sprite()\nvar drawable = sprite is Drawable\nvar rock = Rock() is Drawable\nvar number = 1 is Drawable\nvar kind = typeof Drawable\nfun render(d: Drawable) { return d.draw(nil) }\nvar drawn = render(sprite)")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("drawable"), "true");
+        assert_eq!(global("rock"), "false");
+        assert_eq!(global("number"), "false");
+        assert_eq!(global("kind"), "trait");
+        assert_eq!(global("Drawable"), "<trait Drawable>");
+        assert_eq!(global("drawn"), "sprite");
+
+        let Ok(Value::Class(sprite)) = thrush.globals().get::<Value>("Sprite") else {
+            panic!("expected a class");
+        };
+
+        assert!(sprite.implements("Drawable"));
+        assert_eq!(
+            thrush.exec("class Blank : Drawable {}").map_err(|err| err.to_string()),
+            Err(
+                "[E0208] line 1:1: class 'Blank' is missing the method 'draw' required by trait 'Drawable'"
+                    .into()
+            )
+        );
+
+        let err = thrush
+            .exec("var x = 1\nclass Wrong : Drawable {\n    fun draw() {\n        return x + 1\n    }\n}")
+            .unwrap_err().to_string();
+
+        assert!(err.starts_with("[E0208] line 2:1: "), "{err}");
+        assert!(err.contains("must take 1 parameters"), "{err}");
+        assert!(thrush
+            .exec("var shape = 1\nclass Odd : shape {}")
+            .unwrap_err()
+            .to_string()
+            .contains("is not a trait or class"));
+        assert!(thrush.exec("render(Rock())").is_err());
+    }
+
+    #[test]
+    fn test_mixin() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("trait Logs {\n    fun log(message)\n}\nclass Logger {\n    var logged\n    fun log(message) {\n        self.logged = message\n        return self.name()\n    }\n    get last() { return self.logged }\n}\nclass Service with Logger : Logs {\n    fun name() { return \"service\" }\n}\nvar service = Service()\nvar name = service.log(\"started\")\nvar last = service.last\nvar logs = service is Logs")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("name"), "service");
+        assert_eq!(global("last"), "started");
+        assert_eq!(global("logs"), "true");
+
+        // Classes declared by an earlier script can be mixed in too.
+        thrush
+            .exec("class Job with Logger {\n    fun name() { return \"job\" }\n}\nvar job = Job().log(\"queued\")")
+            .unwrap();
+        assert_eq!(thrush.globals().get::<String>("job"), Ok("job".into()));
+
+        assert_eq!(
+            thrush
+                .exec("class Loud with Logger {\n    fun log(message) {}\n}")
+                .map_err(|err| err.to_string()),
+            Err("[E0204] 'log' from mixin 'Logger' conflicts with the one in 'Loud'".into())
+        );
+        assert!(thrush
+            .exec("class Echo {\n    fun log(message) {}\n}\nclass Both with Logger, Echo {}")
+            .unwrap_err()
+            .to_string()
+            .contains("'log' from mixin 'Echo' conflicts with the one in 'Logger'"));
+        assert_eq!(
+            thrush
+                .exec("class Early with Later {}\nclass Later {}")
+                .map_err(|err| err.to_string()),
+            Err("[E0001] mixin 'Later' must be a class declared before 'Early'".into())
+        );
+    }
+
+    #[test]
+    fn test_static_field() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("class Counter {\n    static var count = 0\n    static const MAX = 5 * 2\n    fun init() { Counter.count = Counter.count + 1 }\n    static fun limit() { return Counter.MAX - Counter.count }\n}\nCounter().init()\nCounter().init()\nvar count = Counter.count\nvar left = Counter.limit()")
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<i64>("count"), Ok(2));
+        assert_eq!(thrush.globals().get::<i64>("left"), Ok(8));
+        assert_eq!(
+            thrush
+                .exec("Counter.MAX = 1")
+                .map_err(|err| err.to_string()),
+            Err("[E0007] line 1:15: cannot assign to constant 'Counter.MAX'".into())
+        );
+        assert_eq!(
+            thrush
+                .exec("Counter.total = 1")
+                .map_err(|err| err.to_string()),
+            Err("[E0201] line 1:17: 'Counter' has no static field named 'total'".into())
+        );
+        assert!(thrush.exec("Counter.limit = 1").is_err());
+    }
+
+    #[test]
+    fn test_if() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("var calls = 0\nfun heavy() {\n    calls = calls + 1\n    return 10\n}\nfun pick(a, b) {\n    return if a { \"a\" } else if b { \"b\" } else { \"c\" }\n}\nvar x = if true { 1 } else { 2 }\nvar y = { heavy(); calls + 1 }\nvar none = if false { 1 }\nvar picks = [pick(true, false), pick(false, true), pick(false, nil)]\nvar sum = 1 + if false { 1 } else { { heavy() } }\nvar looped = if true {\n    var total = 0\n    for i in [1, 2, 3] { total = total + i }\n    total\n}\nif calls {\n    calls = calls + 10\n} else {\n    calls = 0\n}")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("x"), "1");
+        assert_eq!(global("y"), "2");
+        assert_eq!(global("none"), "nil");
+        assert_eq!(global("picks"), "[\"a\", \"b\", \"c\"]");
+        assert_eq!(global("sum"), "11");
+        assert_eq!(global("looped"), "6");
+        assert_eq!(global("calls"), "12");
+        assert_eq!(
+            thrush
+                .exec("var list = [1, { for i in [] {}\n 2 }]")
+                .map_err(|err| err.to_string()),
+            Err(
+                "[E0401] line 1:16: a for loop can't be used in a block inside a larger expression"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_call_method() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "class Greeter {
+    var greet
+    static fun make() { return Greeter() }
+    get shout() { return fun(name) { return name + \"!\" } }
+    fun hello(name) { return \"hello \" + name }
+}
+var g = Greeter.make()
+g.greet = fun(name) { return \"hi \" + name }
+var method = g.hello(\"a\")
+var field = g.greet(\"b\")
+var getter = g.shout(\"c\")
+var joined = path.join(\"a\", \"b\")",
+            )
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<String>(name).unwrap();
+
+        assert_eq!(global("method"), "hello a");
+        assert_eq!(global("field"), "hi b");
+        assert_eq!(global("getter"), "c!");
+        assert_eq!(global("joined"), "a/b");
+        assert_eq!(
+            thrush.exec("g.hello()").map_err(|err| err.to_string()),
+            Err("[E0102] line 1:1: 'hello' expected 1 arguments but got 0".into())
+        );
+        assert_eq!(
+            thrush.exec("g.helo(1)").map_err(|err| err.to_string()),
+            Err(
+                "[E0201] line 1:8: 'Greeter' has no property named 'helo'; did you mean 'hello'?"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_closures() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "fun counter() {
+    var count = 0
+    return fun() {
+        count = count + 1
+        return count
+    }
+}
+var next = counter()
+next()
+var second = next()
+var fresh = counter()()
+fun nested() {
+    var total = 1
+    var add = fun(x) {
+        return fun() {
+            total = total + x
+            return total
+        }
+    }
+    add(2)()
+    add(3)()
+    return total
+}
+var sum = nested()
+fun loops() {
+    var made = []
+    for i in [1, 2, 3] {
+        made = [...made, fun() { return i * 10 }]
+    }
+    return made
+}
+var fa = nil
+var fb = nil
+var fc = nil
+fa, fb, fc = loops()
+var thirty = fc()
+var ten = fa()
+fun gen() {
+    var n = 1
+    yield fun() { n = n + 10 }
+    yield n
+}
+var resumed = nil
+var first = true
+for v in gen() {
+    if first {
+        v()
+    } else {
+        resumed = v
+    }
+    first = false
+}
+class Box {
+    var size
+    fun grower() {
+        return fun(by) { self.size = self.size + by }
+    }
+}
+var box = Box()
+box.size = 1
+box.grower()(4)
+var size = box.size",
+            )
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap();
+
+        assert_eq!(global("second"), Value::Integer(2));
+        assert_eq!(global("fresh"), Value::Integer(1));
+        assert_eq!(global("sum"), Value::Integer(6));
+        assert_eq!(global("thirty"), Value::Integer(30));
+        assert_eq!(global("ten"), Value::Integer(10));
+        assert_eq!(global("resumed"), Value::Integer(11));
+        assert_eq!(global("size"), Value::Integer(5));
+    }
+
+    #[test]
+    fn test_destructure_params() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("fun magnitude((x, y)) { return x * x + y * y }\nfun configure({host, port}, scale) {\n    return [host, port * scale]\n}\nvar area = magnitude([3, 4])\nvar config = configure({port: 80, host: \"local\", debug: true}, 2)\nvar swap = fun((a, b)) { return [b, a] }\nvar swapped = swap([1, 2])")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("area"), "25");
+        assert_eq!(global("config"), "[\"local\", 160]");
+        assert_eq!(global("swapped"), "[2, 1]");
+        assert!(thrush
+            .exec("magnitude([1, 2, 3])")
+            .unwrap_err()
+            .to_string()
+            .contains("expected 2 values to unpack but got 3"));
+        assert_eq!(
+            thrush
+                .exec("configure({host: \"local\"}, 1)")
+                .map_err(|err| err.to_string()),
+            Err("[E0201] line 1:28: map has no key 'port' to unpack".into())
+        );
+        assert!(thrush
+            .exec("configure(1, 1)")
+            .unwrap_err()
+            .to_string()
+            .contains("expected a map to unpack but got '1'"));
+    }
+
+    #[test]
+    fn test_object() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("var base = 10\nfun make(n) {\n    return object {\n        var n = n + base\n        var label\n        fun add(x) { return self.n + x }\n        get twice() { return self.n * 2 }\n    }\n}\nvar a = make(1)\nvar b = make(2)\nvar sum = a.add(5)\nvar twice = b.twice\nb.label = \"b\"\nvar label = b.label\nvar none = a.label\nvar kind = typeof a")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("sum"), "16");
+        assert_eq!(global("twice"), "24");
+        assert_eq!(global("label"), "b");
+        assert_eq!(global("none"), "nil");
+        assert_eq!(global("kind"), "object");
+        assert_eq!(global("a"), "<instance object>");
+        assert!(thrush
+            .exec("var o = object { var x = 1 }\no.y = 2")
+            .unwrap_err()
+            .to_string()
+            .contains("has no field named 'y'"));
+    }
+
+    #[test]
+    fn test_multiple_assignment() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("class Point {\n    var x\n}\nvar p = Point()\nvar a = 1\nvar b = 2\na, b = b, a\nfun point() { return [3, 4] }\nvar x = 0\nvar y = 0\nx, y = point()\nfun swap() {\n    var l = \"l\"\n    var r = \"r\"\n    l, r = r, l\n    return l + r\n}\nvar swapped = swap()\np.x, a = a, 5")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("a"), "5");
+        assert_eq!(global("b"), "1");
+        assert_eq!(global("x"), "3");
+        assert_eq!(global("y"), "4");
+        assert_eq!(global("swapped"), "rl");
+        assert_eq!(
+            thrush
+                .exec("var px = p.x")
+                .map(|()| thrush.globals().get::<i64>("px")),
+            Ok(Ok(2))
+        );
+        assert!(thrush
+            .exec("x, y = [1, 2, 3]")
+            .unwrap_err()
+            .to_string()
+            .contains("expected 2 values to unpack but got 3"));
+    }
+
+    #[test]
+    fn test_spread() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("fun add(a, b, c) { return a + b + c }\nvar xs = [2, 3]\nvar sum = add(1, ...xs)\nvar list = [0, ...xs, 4, ...\"ab\", ...[]]\nvar empty = [...[]]\nvar base = {a: 1, b: 2}\nvar map = {z: 0, ...base, b: 3}\nfun count(...rest) { return rest }\nvar rest = count(...xs, 9)")
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("sum"), "6");
+        assert_eq!(global("list"), "[0, 2, 3, 4, \"a\", \"b\"]");
+        assert_eq!(global("empty"), "[]");
+        assert_eq!(global("map"), "{z: 0, a: 1, b: 3}");
+        assert_eq!(global("rest"), "[2, 3, 9]");
+        assert_eq!(global("xs"), "[2, 3]");
+
+        assert!(thrush
+            .exec("var bad = add(...1)")
+            .unwrap_err()
+            .to_string()
+            .starts_with("[E0206]"));
+        assert!(thrush
+            .exec("var bad = {...[1]}")
+            .unwrap_err()
+            .to_string()
+            .contains("cannot spread list '[1]' into a map"));
+        assert!(thrush.exec("add(...xs, c: 1)").is_err());
+        assert!(thrush.exec("var bad = add(...[1])").is_err());
+    }
+
+    #[test]
+    fn test_modules() {
+        let mut thrush = Thrush::new();
+        let loads = Rc::new(std::cell::Cell::new(0));
+        let counter = loads.clone();
+
+        thrush.set_module_loader(move |path| {
+            counter.set(counter.get() + 1);
+
+            match path {
+                "math/vec" => Ok("import \"math/util\"\nvar hidden = 1\nexport const ZERO = 0\nexport fun twice(x) {\n    return util.pair(x)\n}\nexport class Vec {\n    var x\n}".into()),
+                "math/util" => Ok("export fun pair(x) {\n    return [x, x, hidden]\n}\nvar hidden = \"util\"".into()),
+                "a" => Ok("import \"b\"".into()),
+                "b" => Ok("import \"a\"".into()),
+                _ => Err("not found".into()),
+            }
+        });
+
+        thrush
+            .exec("var hidden = \"main\"\nimport \"math/vec\"\nimport \"math/vec\" as v\nvar r = [vec.twice(2), v.ZERO, vec.Vec(), hidden]")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("r").unwrap().to_string(),
+            "[[2, 2, \"util\"], 0, <instance Vec>, \"main\"]"
+        );
+        assert_eq!(loads.get(), 2);
+
+        thrush.exec("import \"math/vec\" as again").unwrap();
+
+        assert_eq!(loads.get(), 2);
+        assert!(thrush
+            .exec("vec.hidden")
+            .unwrap_err()
+            .to_string()
+            .starts_with("[E0201] line 1:1: module 'math/vec' has no export named 'hidden'"));
+        assert!(thrush.exec("vec.ZERO = 1").is_err());
+        assert!(thrush
+            .exec("import \"a\"")
+            .unwrap_err()
+            .to_string()
+            .starts_with("[E0501]"));
+        assert!(thrush.exec("import \"missing\"").is_err());
+    }
+
+    #[test]
+    fn test_try() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "var events = []
+fun fail(msg) {
+    throw msg
+}
+fun cleanup() {
+    try {
+        return \"done\"
+    } finally {
+        events = [events, \"cleanup\"]
+    }
+}
+var caught = nil
+try {
+    var partial = [1, fail(\"boom\")]
+} catch (e) {
+    caught = e
+}
+var runtime = nil
+try {
+    var quotient = 1 / 0
+} catch (e) {
+    runtime = e
+}
+var result = cleanup()
+var nested = nil
+try {
+    try {
+        throw 1
+    } finally {
+        events = [events, \"inner\"]
+    }
+} catch (e) {
+    nested = e
+}
+var rethrown = nil
+try {
+    try { throw \"a\" } catch (e) { throw [e, \"b\"] } finally { events = [events, \"f\"] }
+} catch (e) {
+    rethrown = e
+}",
+            )
+            .unwrap();
+
+        let global = |thrush: &mut Thrush, name| thrush.globals().get::<Value>(name).unwrap();
+
+        assert_eq!(thrush.globals().get::<String>("caught"), Ok("boom".into()));
+        assert!(thrush
+            .globals()
+            .get::<String>("runtime")
+            .unwrap()
+            .starts_with("[E0205] 1 / 0 divides by zero"));
+        assert_eq!(thrush.globals().get::<String>("result"), Ok("done".into()));
+        assert_eq!(thrush.globals().get::<i64>("nested"), Ok(1));
+        assert_eq!(
+            global(&mut thrush, "rethrown").to_string(),
+            "[\"a\", \"b\"]"
+        );
+        assert_eq!(
+            global(&mut thrush, "events").to_string(),
+            "[[[[], \"cleanup\"], \"inner\"], \"f\"]"
+        );
+        assert!(!thrush.globals().contains("partial"));
+        assert_eq!(
+            thrush.exec("throw \"lost\"").map_err(|err| err.to_string()),
+            Err("[E0302] line 1:7: lost".into())
+        );
+        assert_eq!(
+            thrush.exec("fail(2)").map_err(|err| err.to_string()),
+            Err("[E0302] line 3:11: 2".into())
+        );
+
+        assert_eq!(
+            thrush
+                .exec("try {\n    missing()\n} catch (e) {\n    caught = e\n}")
+                .map_err(|err| err.to_string()),
+            Err("[E0001] line 2:5: undefined variable 'missing'".into())
+        );
+    }
+
+    #[test]
+    fn test_loops() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "var log = []
+var first = true
+while true {
+    log = [log, \"visit\"]
+    match first {
+        true => {
+            first = false
+            continue
+        }
+        _ => {}
+    }
+    break
+}
+outer: while true {
+    while true {
+        try {
+            break outer
+        } finally {
+            log = [log, \"finally\"]
+        }
+    }
+    log = [log, \"skipped\"]
+}
+var running = true
+while running {
+    running = false
+}",
+            )
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("log").unwrap().to_string(),
+            "[[[[], \"visit\"], \"visit\"], \"finally\"]"
+        );
+        assert!(thrush
+            .exec("break")
+            .unwrap_err()
+            .to_string()
+            .starts_with("[E0008] cannot break outside of a loop"));
+        assert_eq!(
+            thrush.exec("outer: while true {\n    continue outr\n}").map_err(|err| err.to_string()),
+            Err(
+                "[E0008] line 1:14: no loop labeled 'outr' encloses this continue; did you mean 'outer'?"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_for() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "var total = 0
+var text = \"\"
+outer: for x in [1, 2, 3, 4, 5] {
+    match x {
+        2 => {
+            continue
+        }
+        4 => {
+            break outer
+        }
+        _ => {}
+    }
+    for c in \"hé\" {
+        text = text + c
+    }
+    total = total + x
+}
+for key in {a: 1, b: 2} {
+    text = text + key
+}
+for byte in [65] {
+    total = total + byte
+}",
+            )
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<i64>("total"), Ok(69));
+        assert_eq!(thrush.globals().get::<String>("text"), Ok("héhéab".into()));
+
+        let err = thrush.exec("for x in 10 {}").unwrap_err().to_string();
+
+        assert!(err.contains("int '10' is not iterable"));
+    }
+
+    #[test]
+    fn test_generators() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "fun doubled(items) {
+    for item in items {
+        yield item * 2
+    }
+    return
+    yield 100
+}
+fun guarded() {
+    try {
+        yield 1
+        throw \"boom\"
+    } catch (e) {
+        yield e
+    }
+}
+class Bag {
+    var items
+    fun __iter() {
+        yield \"start\"
+        for item in doubled(self.items) {
+            yield item
+        }
+    }
+}
+var bag = Bag()
+bag.items = [1, 2, 3]
+var log = []
+for x in bag {
+    log = [log, x]
+}
+for x in guarded() {
+    log = [log, x]
+}
+var gen = doubled([5])
+var kind = typeof gen
+var sum = 0
+for x in gen {
+    sum = sum + x
+}
+for x in gen {
+    sum = sum + x
+}",
+            )
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("log").unwrap().to_string(),
+            "[[[[[[[], \"start\"], 2], 4], 6], 1], \"boom\"]"
+        );
+        assert_eq!(
+            thrush.globals().get::<String>("kind"),
+            Ok("generator".into())
+        );
+        assert_eq!(thrush.globals().get::<i64>("sum"), Ok(10));
+
+        let err = thrush
+            .exec("fun inner() {\n    for x in gen {}\n    yield 1\n}\ngen = inner()\nfor x in gen {}")
+            .unwrap_err().to_string();
+
+        assert!(err.contains("generator 'inner' is already running"));
+        assert!(thrush
+            .exec("yield 1")
+            .unwrap_err()
+            .to_string()
+            .contains("cannot yield from top-level code"));
+    }
+
+    #[test]
+    fn test_async() {
+        let mut thrush = Thrush::new();
+        let requests = Rc::new(RefCell::new(Vec::new()));
+        let pending = Rc::clone(&requests);
+
+        thrush.globals().add_function("fetch", move |args| {
+            let promise = Promise::new();
+
+            pending
+                .borrow_mut()
+                .push((args[0].to_string(), Rc::clone(&promise)));
+
+            Ok(Value::Promise(promise))
+        });
+
+        thrush
+            .exec(
+                "var log = []
+async fun get(url) {
+    log = [log, url]
+    return await fetch(url)
+}
+async fun main() {
+    var a = await get(\"a\")
+    var b = await get(\"b\")
+    log = [log, a + b]
+    try {
+        await get(\"c\")
+    } catch (e) {
+        log = [log, e]
+    }
+    return await 1
+}
+var done = main()
+var kind = typeof done",
+            )
+            .unwrap();
+
+        let settle = |thrush: &mut Thrush, state: PromiseState| {
+            let (_, promise) = requests.borrow_mut().remove(0);
+
+            match state {
+                PromiseState::Resolved(value) => promise.resolve(value),
+                PromiseState::Rejected(error) => promise.reject(error),
+                PromiseState::Pending => {}
+            }
+
+            thrush.poll()
+        };
+
+        assert_eq!(thrush.poll(), Ok(2));
+        assert_eq!(
+            settle(
+                &mut thrush,
+                PromiseState::Resolved(Value::String("x".into()))
+            ),
+            Ok(2)
+        );
+        assert_eq!(
+            settle(
+                &mut thrush,
+                PromiseState::Resolved(Value::String("y".into()))
+            ),
+            Ok(2)
+        );
+        assert_eq!(
+            settle(
+                &mut thrush,
+                PromiseState::Rejected(Value::String("timeout".into()))
+            ),
+            Ok(0)
+        );
+        assert_eq!(
+            thrush.globals().get::<Value>("log").unwrap().to_string(),
+            "[[[[[[], \"a\"], \"b\"], \"xy\"], \"c\"], \"timeout\"]"
+        );
+        assert_eq!(thrush.globals().get::<String>("kind"), Ok("promise".into()));
+
+        let Ok(Value::Promise(done)) = thrush.globals().get::<Value>("done") else {
+            panic!("expected a promise");
+        };
+
+        assert_eq!(done.state(), PromiseState::Resolved(Value::Integer(1)));
+
+        // A failure nothing awaits is reported by the poll that resumed it.
+        thrush
+            .exec("async fun lost() {\n    await fetch(\"d\")\n    throw \"lost\"\n}\nlost()")
+            .unwrap();
+
+        let err = settle(&mut thrush, PromiseState::Resolved(Value::Nil)).unwrap_err();
+
+        assert!(err.contains("lost"));
+        assert!(thrush
+            .exec("await 1")
+            .unwrap_err()
+            .to_string()
+            .contains("cannot await outside of an async function"));
+        assert!(thrush
+            .exec("async fun g() {\n    yield 1\n}")
+            .unwrap_err()
+            .to_string()
+            .contains("cannot yield from an async function"));
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .globals()
+            .add_function("launch", |_| panic!("launched"));
+        thrush.enable_audit();
+        thrush.enable_dry_run(|name, args| match name {
+            "launch" => Ok(Value::String(format!("dry run of {}", args[0]))),
+            "path.join" => Ok(Value::String("mocked".into())),
+            _ => Err(format!("no mock for '{name}'")),
+        });
+        thrush
+            .exec("var result = launch(3)\nvar joined = path.join(\"a\", \"b\")")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<String>("result"),
+            Ok("dry run of 3".into())
+        );
+        assert_eq!(
+            thrush.globals().get::<String>("joined"),
+            Ok("mocked".into())
+        );
+        assert_eq!(thrush.audit_log().len(), 2);
+        assert_eq!(
+            thrush.exec("path.ext(\"a.txt\")").unwrap_err().to_string(),
+            "[E0301] line 1:10: no mock for 'path.ext'"
+        );
+
+        thrush.disable_dry_run();
+        thrush.exec("joined = path.join(\"a\", \"b\")").unwrap();
+
+        assert_eq!(thrush.globals().get::<String>("joined"), Ok("a/b".into()));
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let mut thrush = Thrush::new();
+        let error = |thrush: &mut Thrush, script| thrush.exec(script).unwrap_err().to_string();
+
+        thrush
+            .exec(
+                "class Point {}
+fun add(a: Int, b: Number) -> Number {
+    return a + b
+}
+fun origin(p: Point, ...rest: List) -> Any {
+    return rest
+}
+fun nothing() -> Nil {}
+var x: Float = add(1, 2.5)
+let p: Point = Point()
+var rest = origin(p, 1, 2)
+nothing()",
+            )
+            .unwrap();
+
+        assert_eq!(thrush.globals().get::<f64>("x"), Ok(3.5));
+        assert_eq!(
+            error(&mut thrush, "add(1.5, 2)"),
+            "[E0204] line 1:10: expected parameter 'a' of 'add' to be Int but got float '1.5'"
+        );
+        assert_eq!(
+            error(&mut thrush, "origin(1)"),
+            "[E0204] line 1:8: expected parameter 'p' of 'origin' to be Point but got int '1'"
+        );
+        assert_eq!(
+            error(
+                &mut thrush,
+                "fun name() -> String {\n    return 1\n}\nname()"
+            ),
+            "[E0204] line 2:12: expected the return value of 'name' to be String but got int '1'"
+        );
+        assert_eq!(
+            error(&mut thrush, "fun missing() -> Int {}\nmissing()"),
+            "[E0204] line 2:1: expected the return value of 'missing' to be Int but got nil 'nil'"
+        );
+        assert_eq!(
+            error(&mut thrush, "var y: Bool = 1"),
+            "[E0204] line 1:15: expected 'y' to be Bool but got int '1'"
+        );
+    }
+
+    #[test]
+    fn test_typeof_is() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "class Door {
+    var open
+}
+class Key {}
+var door = Door()
+var types = [typeof 1, typeof 1.5, typeof \"a\", typeof [], typeof {}, typeof nil]
+var more = [typeof door, typeof Door, typeof typeof door, typeof door.open]
+var checks = [door is Door, door is Key, 1 is Door]",
+            )
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("types").unwrap().to_string(),
+            "[\"int\", \"float\", \"string\", \"list\", \"map\", \"nil\"]"
+        );
+        assert_eq!(
+            thrush.globals().get::<Value>("more").unwrap().to_string(),
+            "[\"Door\", \"class\", \"string\", \"nil\"]"
+        );
+        assert_eq!(
+            thrush.globals().get::<Value>("checks").unwrap().to_string(),
+            "[true, false, false]"
+        );
+        assert!(thrush
+            .exec("var bad = door is 2")
+            .unwrap_err()
+            .to_string()
+            .starts_with("[E0204] line 1:19: '2' is not a class"));
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "var calls = 0
+fun touch(v) {
+    calls = calls + 1
+    return v
+}
+var compared = [1 < 2, 2 <= 2, 3 > 4, 2.5 >= 2, \"ab\" < \"b\", 0.0 / 0.0 < 1]
+var equal = [1 == 1.0, \"a\" != \"a\", [1, 2] == [1, 2], nil == false]
+var logic = [false || \"b\", 1 && 2, nil && touch(1), 1 || touch(2), touch(0) && touch(3)]
+var mixed = 1 + 1 == 2 && 1 < 2 || false
+fun order(a, b) {
+    return if a < b { -1 } else if a > b { 1 } else { 0 }
+}
+var orders = [order(1, 2), order(2, 1), order(3, 3)]",
+            )
+            .unwrap();
+
+        let mut global = |name: &str| thrush.globals().get::<Value>(name).unwrap().to_string();
+
+        assert_eq!(global("compared"), "[true, true, false, true, true, false]");
+        assert_eq!(global("equal"), "[true, false, true, false]");
+        assert_eq!(global("logic"), "[\"b\", 2, nil, 1, 3]");
+        assert_eq!(global("calls"), "2");
+        assert_eq!(global("mixed"), "true");
+        assert_eq!(global("orders"), "[-1, 1, 0]");
+        assert_eq!(
+            thrush
+                .exec("var x = 1 < \"2\"")
+                .map_err(|err| err.to_string()),
+            Err("[E0204] line 1:13: unsupported operand types for <: int and string".into())
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec(
+                "var numbers = [1 + 2 * 3, 7 / 2, 7 % 4 - 1, 1 + 0.5, 3 / 2.0]
+var strings = [\"ab\" + \"cd\", \"ab\" * 3, 2 * \"-\", \"x\" * 0]",
+            )
+            .unwrap();
+
+        assert_eq!(
+            thrush
+                .globals()
+                .get::<Value>("numbers")
+                .unwrap()
+                .to_string(),
+            "[7, 3, 2, 1.5, 1.5]"
+        );
+        assert_eq!(
+            thrush
+                .globals()
+                .get::<Value>("strings")
+                .unwrap()
+                .to_string(),
+            "[\"abcd\", \"ababab\", \"--\", \"\"]"
+        );
+
+        let error = |thrush: &mut Thrush, script| thrush.exec(script).unwrap_err().to_string();
+
+        assert_eq!(
+            error(&mut thrush, "var x = \"lives: \" + 3"),
+            "[E0204] line 1:21: unsupported operand types for +: string and int"
+        );
+        assert_eq!(
+            error(&mut thrush, "var x = [1] * 2"),
+            "[E0204] line 1:15: unsupported operand types for *: list and int"
+        );
+        assert_eq!(
+            error(&mut thrush, "var x = 1 / 0"),
+            "[E0205] line 1:13: 1 / 0 divides by zero"
+        );
+        assert_eq!(
+            error(&mut thrush, "var x = 9223372036854775807 + 1"),
+            "[E0205] line 1:31: integer overflow in 9223372036854775807 + 1"
+        );
+        assert_eq!(
+            error(&mut thrush, "var x = \"ab\" * (0 - 2)"),
+            "[E0205] line 1:17: can't repeat a string -2 times"
+        );
+        assert_eq!(
+            error(&mut thrush, "var x = \"a\" * 10000000000000"),
+            format!("[E0205] line 1:15: repeating a string 10000000000000 times is longer than {MAX_STRING_LEN} bytes")
+        );
+        assert_eq!(
+            error(&mut thrush, "var x = \"ab\" * 9223372036854775807"),
+            format!("[E0205] line 1:16: repeating a string 9223372036854775807 times is longer than {MAX_STRING_LEN} bytes")
+        );
+    }
+
+    #[test]
+    fn test_unary() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("const N = -2\nvar n = 3\nvar values = [-n, -(1 + 2) * N, -1.5, - -n, +n, !nil, !0, !!\"\"]")
+            .unwrap();
+
+        assert_eq!(
+            thrush.globals().get::<Value>("values").unwrap().to_string(),
+            "[-3, 6, -1.5, 3, 3, true, false, true]"
+        );
+
+        let error = |thrush: &mut Thrush, script| thrush.exec(script).unwrap_err().to_string();
+
+        assert_eq!(
+            error(&mut thrush, "var x = -\"a\""),
+            "[E0204] line 1:10: unsupported operand type for unary -: string"
+        );
+        assert_eq!(
+            error(&mut thrush, "var x = +[]"),
+            "[E0204] line 1:10: expected the operand of unary + to be Number but got list '[]'"
+        );
+        assert_eq!(
+            error(&mut thrush, "var x = -(0 - 9223372036854775807 - 1)"),
+            "[E0205] line 1:11: integer overflow in -(-9223372036854775808)"
+        );
     }
 }
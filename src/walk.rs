@@ -0,0 +1,309 @@
+//! Going through the lists, maps and instances inside a value from the host,
+//! with [`Value::walk`].
+//!
+//! ```
+//! use thrush::{value::Value, walk::{Visit, ValueVisitor, Segment}};
+//!
+//! /// Replace every float with its integer part.
+//! struct Truncate;
+//!
+//! impl ValueVisitor for Truncate {
+//!     fn visit(&mut self, _: &[Segment], value: &Value) -> Visit {
+//!         match value {
+//!             Value::Float(v) => Visit::Replace(Value::Integer(*v as i64)),
+//!             _ => Visit::Walk,
+//!         }
+//!     }
+//! }
+//!
+//! let mut value = Value::list(vec![Value::Float(1.5), Value::list(vec![Value::Float(2.5)])]);
+//!
+//! assert!(value.walk(&mut Truncate));
+//! assert_eq!(value.to_string(), "[1, [2]]");
+//! ```
+//!
+//! Each list, map and instance is walked once, however many times it
+//! appears, so a walk over a value that contains itself terminates. Values
+//! are taken out of their container before they are visited and put back
+//! after, so a visitor is free to read or change the container it is in.
+
+use std::{collections::HashSet, fmt, rc::Rc};
+
+use crate::value::Value;
+
+/// One step of the path from the value being walked to a value inside it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// An item of a list.
+    Index(usize),
+    /// The value of a key in a map.
+    Key(Box<str>),
+    /// A declared field of an instance.
+    Field(Box<str>),
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Segment::Index(index) => write!(f, "[{index}]"),
+            Segment::Key(key) => write!(f, "[{key:?}]"),
+            Segment::Field(name) => write!(f, ".{name}"),
+        }
+    }
+}
+
+/// What a walk does after visiting a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Visit {
+    /// Go on to the values inside this one.
+    Walk,
+    /// Leave out the values inside this one.
+    Skip,
+    /// Put a value in this one's place, without walking it.
+    Replace(Value),
+    /// End the walk.
+    Stop,
+}
+
+/// Called by [`Value::walk`] on each value it reaches.
+pub trait ValueVisitor {
+    /// Visit a value before the values inside it, where `path` leads to it
+    /// from the value being walked.
+    fn visit(&mut self, path: &[Segment], value: &Value) -> Visit;
+
+    /// Called after the values inside a list, map or instance have been
+    /// walked.
+    fn leave(&mut self, _path: &[Segment], _value: &Value) {}
+
+    /// Called instead of [`visit`](Self::visit) on a list, map or instance
+    /// that has already been reached along another path, or is one of the
+    /// values it is inside.
+    fn revisit(&mut self, _path: &[Segment], _value: &Value) {}
+}
+
+impl Value {
+    /// Walk this value and every value inside it, depth first, calling
+    /// `visitor` on each. Returns `false` if the visitor stopped the walk.
+    pub fn walk<V: ValueVisitor>(&mut self, visitor: &mut V) -> bool {
+        Walker {
+            visitor,
+            path: Vec::new(),
+            seen: HashSet::new(),
+        }
+        .walk(self)
+    }
+}
+
+struct Walker<'a, V> {
+    visitor: &'a mut V,
+    path: Vec<Segment>,
+    /// The addresses of the lists, maps and instances already reached.
+    seen: HashSet<*const ()>,
+}
+
+impl<V: ValueVisitor> Walker<'_, V> {
+    fn walk(&mut self, value: &mut Value) -> bool {
+        let address = match value {
+            Value::List(list) => Some(Rc::as_ptr(list) as *const ()),
+            Value::Map(map) => Some(Rc::as_ptr(map) as *const ()),
+            Value::Instance(instance) => Some(Rc::as_ptr(instance) as *const ()),
+            _ => None,
+        };
+
+        if let Some(address) = address {
+            if !self.seen.insert(address) {
+                self.visitor.revisit(&self.path, value);
+                return true;
+            }
+        }
+
+        match self.visitor.visit(&self.path, value) {
+            Visit::Walk => {}
+            Visit::Skip => return true,
+            Visit::Replace(replacement) => {
+                *value = replacement;
+                return true;
+            }
+            Visit::Stop => return false,
+        }
+
+        let walked = match &*value {
+            Value::List(list) => {
+                let mut index = 0;
+
+                // Go by index, since the visitor can add or remove items.
+                loop {
+                    let Some(mut item) = list.borrow().get(index).cloned() else {
+                        break true;
+                    };
+
+                    if !self.walk_in(Segment::Index(index), &mut item) {
+                        break false;
+                    }
+
+                    if let Some(slot) = list.borrow_mut().get_mut(index) {
+                        *slot = item;
+                    }
+
+                    index += 1;
+                }
+            }
+            Value::Map(map) => {
+                let keys = map
+                    .borrow()
+                    .keys()
+                    .map(Box::from)
+                    .collect::<Vec<Box<str>>>();
+
+                keys.into_iter().all(|key| {
+                    let Some(mut item) = map.borrow().get(&key).cloned() else {
+                        return true;
+                    };
+
+                    if !self.walk_in(Segment::Key(key.clone()), &mut item) {
+                        return false;
+                    }
+
+                    if map.borrow().contains_key(&key) {
+                        map.borrow_mut().insert(key, item);
+                    }
+
+                    true
+                })
+            }
+            Value::Instance(instance) => {
+                let names = instance.class.fields.borrow().clone();
+
+                names.into_iter().enumerate().all(|(slot, name)| {
+                    let Some(mut field) = instance.fields().get(slot).cloned() else {
+                        return true;
+                    };
+
+                    if !self.walk_in(Segment::Field(name), &mut field) {
+                        return false;
+                    }
+
+                    if let Some(slot) = instance.fields_mut().get_mut(slot) {
+                        *slot = field;
+                    }
+
+                    true
+                })
+            }
+            _ => return true,
+        };
+
+        if walked {
+            self.visitor.leave(&self.path, value);
+        }
+
+        walked
+    }
+
+    fn walk_in(&mut self, segment: Segment, value: &mut Value) -> bool {
+        self.path.push(segment);
+
+        let walked = self.walk(value);
+
+        self.path.pop();
+
+        walked
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::{Segment, ValueVisitor, Visit};
+    use crate::{
+        value::{Map, Value},
+        Thrush,
+    };
+
+    /// Records the path to every value it visits, and hides strings.
+    #[derive(Default)]
+    struct Redact {
+        paths: Vec<String>,
+        revisited: usize,
+    }
+
+    impl ValueVisitor for Redact {
+        fn visit(&mut self, path: &[Segment], value: &Value) -> Visit {
+            self.paths
+                .push(path.iter().map(ToString::to_string).collect());
+
+            match value {
+                Value::String(_) => Visit::Replace(Value::String("***".into())),
+                Value::Integer(13) => Visit::Stop,
+                _ => Visit::Walk,
+            }
+        }
+
+        fn revisit(&mut self, _: &[Segment], _: &Value) {
+            self.revisited += 1;
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let mut thrush = Thrush::new();
+
+        thrush
+            .exec("class User {\n    var name\n    var tags\n}\nvar user = User()\nuser.name = \"ada\"\nuser.tags = [\"a\"]\nvar tags = user.tags\nvar data = {users: [user], tags: tags, count: 1}")
+            .unwrap();
+
+        let mut data = thrush.globals().get::<Value>("data").unwrap();
+        let mut redact = Redact::default();
+
+        assert!(data.walk(&mut redact));
+        assert_eq!(
+            redact.paths,
+            [
+                "",
+                "[\"users\"]",
+                "[\"users\"][0]",
+                "[\"users\"][0].name",
+                "[\"users\"][0].tags",
+                "[\"users\"][0].tags[0]",
+                "[\"count\"]",
+            ]
+        );
+        assert_eq!(redact.revisited, 1);
+        assert_eq!(
+            thrush
+                .exec("var name = user.name")
+                .map(|()| thrush.globals().get::<String>("name")),
+            Ok(Ok("***".into()))
+        );
+
+        let mut stopped = Value::list(vec![Value::Integer(13), Value::String("kept".into())]);
+
+        assert!(!stopped.walk(&mut Redact::default()));
+        assert_eq!(stopped.to_string(), "[13, \"kept\"]");
+
+        let mut root = Value::String("root".into());
+
+        assert!(root.walk(&mut Redact::default()));
+        assert_eq!(root, Value::String("***".into()));
+
+        let looped = Value::map(Map::new());
+        let Value::Map(entries) = &looped else {
+            unreachable!()
+        };
+
+        entries.borrow_mut().insert("me", looped.clone());
+
+        let mut redact = Redact::default();
+
+        assert!(looped.clone().walk(&mut redact));
+        assert_eq!(redact.paths, [""]);
+        assert_eq!(redact.revisited, 1);
+        assert!(matches!(
+            entries.borrow().get("me"),
+            Some(Value::Map(me)) if Rc::ptr_eq(me, entries)
+        ));
+
+        entries.borrow_mut().remove("me");
+    }
+}